@@ -11,3 +11,12 @@ pub const PENDING_USER_TIMEOUT_NS: u64 = 15 * 60 * 1_000_000_000; // 15 Minutes
 // Pool and trial canister constants
 pub const DEFAULT_POOL_TARGET_SIZE: u32 = 5;
 pub const TRIAL_CANISTER_EXPIRY_NS: u64 = 60 * 60 * 1_000_000_000; // 1 hour in nanoseconds
+
+// gpt_user rollout constants
+pub const DEFAULT_UPGRADE_BATCH_SIZE: u32 = 10;
+pub const MAX_UPGRADE_BATCH_SIZE: u32 = 100;
+pub const DEFAULT_UPGRADE_BATCH_INTERVAL_SECS: u64 = 60;
+pub const MAX_USER_WASM_SIZE_BYTES: u64 = 10 * 1024 * 1024; // Same-subnet install_code payload limit
+
+// Provider backends a model may fall back to after its primary one
+pub const MAX_FALLBACK_PROVIDERS: usize = 4;
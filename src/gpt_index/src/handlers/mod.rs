@@ -5,6 +5,7 @@ pub mod node;
 pub mod upgrade;
 pub mod user;
 pub mod user_canister;
+pub mod user_canister_upgrade;
 
 pub use governance::*;
 pub use liveness::*;
@@ -13,3 +14,4 @@ pub use node::*;
 pub use upgrade::*;
 pub use user::*;
 pub use user_canister::*;
+pub use user_canister_upgrade::*;
@@ -1,3 +1,4 @@
+use crate::config::MAX_FALLBACK_PROVIDERS;
 use crate::handlers::governance::verify_manager;
 use crate::storage::{CandidWrapper, MODELS};
 use gpt_types::{
@@ -52,6 +53,25 @@ fn validate_model(model: &Model) -> CanisterResult<()> {
         }
     }
 
+    if let Some(fallbacks) = &model.fallback_providers {
+        if fallbacks.len() > MAX_FALLBACK_PROVIDERS {
+            return Err(CanisterError::InvalidInput(format!(
+                "A model can have at most {} fallback providers.",
+                MAX_FALLBACK_PROVIDERS
+            )));
+        }
+        let incomplete = fallbacks.iter().any(|backend| {
+            backend.provider.trim().is_empty()
+                || backend.provider_model.trim().is_empty()
+                || backend.provider_endpoint.trim().is_empty()
+        });
+        if incomplete {
+            return Err(CanisterError::InvalidInput(
+                "Fallback providers need a provider, model and endpoint.".to_string(),
+            ));
+        }
+    }
+
     Ok(())
 }
 
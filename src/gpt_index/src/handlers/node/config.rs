@@ -29,6 +29,7 @@ pub fn get_node_config(req: GetNodeConfigRequest) -> GetNodeConfigResult {
                     hostname: node.hostname,
                     model_id: node.model_id,
                     encrypted_api_key: node.encrypted_api_key,
+                    encrypted_fallback_api_keys: node.encrypted_fallback_api_keys,
                 })
             }
         }
@@ -52,6 +52,14 @@ pub fn create_node(req: CreateIndexNodeRequest) -> CreateIndexNodeResult {
         if wrapper.0.status == ModelStatus::Paused {
             return Err(CanisterError::InvalidInput("Model is paused.".to_string()));
         }
+        let fallback_count = wrapper.0.fallback_providers.as_ref().map_or(0, Vec::len);
+        let key_count = req.encrypted_fallback_api_keys.as_ref().map_or(0, Vec::len);
+        if key_count > fallback_count {
+            return Err(CanisterError::InvalidInput(format!(
+                "Model has {} fallback providers but {} fallback keys were given.",
+                fallback_count, key_count
+            )));
+        }
         Ok(())
     });
     model_check?;
@@ -85,6 +93,7 @@ pub fn create_node(req: CreateIndexNodeRequest) -> CreateIndexNodeResult {
         hostname: req.hostname.trim().to_string(),
         model_id: req.model_id.clone(),
         encrypted_api_key: req.encrypted_api_key.clone(),
+        encrypted_fallback_api_keys: req.encrypted_fallback_api_keys.clone(),
 
         // Initial state is Inactive until registration
         lifecycle_status: NodeLifecycleStatus::Inactive,
@@ -156,6 +156,7 @@ pub fn post_upgrade_handler() {
 
     crate::setup_rebalancer_timer();
     crate::handlers::liveness::setup_liveness_timer();
+    crate::handlers::user_canister_upgrade::resume_rollout_timer();
 
     ic_cdk::println!("post_upgrade_handler completed.");
 }
@@ -1,9 +1,11 @@
 use crate::config;
 use crate::handlers::governance::verify_manager;
+use crate::handlers::user_canister_upgrade::{
+    current_user_wasm, module_hash, record_installed_module,
+};
 use crate::storage::{
     AVAILABLE_CANISTERS, CANISTER_POOL, CandidWrapper, StorablePrincipal, TRIAL_EXPIRIES,
 };
-use crate::wasm_assets::GPT_USER_WASM;
 use candid::{Encode, Principal};
 use gpt_types::api::common::{CanisterPoolEntry, CanisterPoolState};
 use gpt_types::api::{
@@ -64,6 +66,8 @@ pub async fn create_empty_canister_for_pool() -> CanisterResult<Principal> {
         canister_id,
         time_created: ic_cdk::api::time(),
         state: CanisterPoolState::Available,
+        module_hash: None,
+        upgrade_state: None,
     };
 
     CANISTER_POOL.with(|pool| {
@@ -84,6 +88,8 @@ pub async fn create_empty_canister_for_pool() -> CanisterResult<Principal> {
 }
 
 /// Installs gpt_user WASM on a canister (reinstall mode, wipes any previous state).
+/// Uses the current runtime-uploaded module if a rollout has completed, otherwise the
+/// compile-time build.
 pub async fn install_user_wasm(canister_id: Principal) -> CanisterResult<()> {
     let parent_principal = canister_self();
     let init_arg_bytes =
@@ -94,10 +100,13 @@ pub async fn install_user_wasm(canister_id: Principal) -> CanisterResult<()> {
         canister_id
     );
 
+    let wasm_module = current_user_wasm();
+    let installed_hash = module_hash(&wasm_module);
+
     let install_args = InstallCodeArgs {
         mode: CanisterInstallMode::Reinstall,
         canister_id,
-        wasm_module: GPT_USER_WASM.to_vec(),
+        wasm_module,
         arg: init_arg_bytes,
     };
 
@@ -107,6 +116,8 @@ pub async fn install_user_wasm(canister_id: Principal) -> CanisterResult<()> {
         CanisterError::CallError(error_str)
     })?;
 
+    record_installed_module(canister_id, Some(installed_hash));
+
     ic_cdk::println!("WASM installed successfully on canister: {}", canister_id);
     Ok(())
 }
@@ -151,6 +162,8 @@ pub async fn return_canister_to_pool(canister_id: Principal) {
         if let Some(wrapper) = pool_map.get(&key) {
             let mut entry = wrapper.0.clone();
             entry.state = CanisterPoolState::Available;
+            entry.module_hash = None;
+            entry.upgrade_state = None;
             pool_map.insert(key, CandidWrapper(entry));
         }
     });
@@ -0,0 +1,895 @@
+use crate::config;
+use crate::handlers::governance::verify_manager;
+use crate::storage::{
+    CANISTER_POOL, CONFIG, CandidWrapper, StorablePrincipal, StoredUserWasm, USER_WASMS,
+    WASM_SLOT_CURRENT, WASM_SLOT_PREVIOUS, WASM_SLOT_STAGED, WASM_SLOT_UPLOAD,
+};
+use crate::wasm_assets::GPT_USER_WASM;
+use candid::{Encode, Principal};
+use gpt_types::api::common::{CanisterPoolEntry, CanisterPoolState, CanisterUpgradeState};
+use gpt_types::api::{
+    BeginUserWasmUploadRequest, BeginUserWasmUploadResponse, BeginUserWasmUploadResult,
+    CommitUserWasmUploadRequest, CommitUserWasmUploadResponse, CommitUserWasmUploadResult,
+    GetStorageSchemaVersionResponse, GetStorageSchemaVersionResult,
+    GetUserCanisterUpgradeStatusRequest, GetUserCanisterUpgradeStatusResponse,
+    GetUserCanisterUpgradeStatusResult, HaltUserCanisterUpgradeRequest,
+    HaltUserCanisterUpgradeResponse, HaltUserCanisterUpgradeResult,
+    RollbackUserCanisterUpgradeRequest, RollbackUserCanisterUpgradeResponse,
+    RollbackUserCanisterUpgradeResult, StartUserCanisterUpgradeRequest,
+    StartUserCanisterUpgradeResponse, StartUserCanisterUpgradeResult, UpgradeRollout,
+    UpgradeRolloutStatus, UploadUserWasmChunkRequest, UploadUserWasmChunkResponse,
+    UploadUserWasmChunkResult,
+};
+use gpt_types::error::{CanisterError, CanisterResult};
+use ic_cdk::call::Call;
+use ic_cdk::management_canister::{
+    CanisterInstallMode, InstallCodeArgs, StartCanisterArgs, StopCanisterArgs, install_code,
+    start_canister, stop_canister,
+};
+use ic_cdk_macros::{query, update};
+use ic_cdk_timers::{TimerId, clear_timer, set_timer};
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::time::Duration;
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+thread_local! {
+    // Heap-only rollout scheduling state. Re-armed from the stored rollout in post_upgrade.
+    static ROLLOUT_TIMER: RefCell<Option<TimerId>> = const { RefCell::new(None) };
+    static BATCH_IN_FLIGHT: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn module_hash(wasm: &[u8]) -> Vec<u8> {
+    Sha256::digest(wasm).to_vec()
+}
+
+fn get_wasm_slot(slot: &str) -> Option<StoredUserWasm> {
+    USER_WASMS.with(|w| w.borrow().get(&slot.to_string()).map(|s| s.0))
+}
+
+fn set_wasm_slot(slot: &str, stored: StoredUserWasm) {
+    USER_WASMS.with(|w| {
+        w.borrow_mut()
+            .insert(slot.to_string(), CandidWrapper(stored));
+    });
+}
+
+/// Returns the module newly assigned canisters are installed with: the last completed
+/// runtime rollout, or the compile-time build if none has completed yet.
+pub fn current_user_wasm() -> Vec<u8> {
+    get_wasm_slot(WASM_SLOT_CURRENT)
+        .map(|s| s.wasm)
+        .unwrap_or_else(|| GPT_USER_WASM.to_vec())
+}
+
+fn get_rollout() -> Option<UpgradeRollout> {
+    CONFIG.with(|c| c.borrow().get().0.upgrade_rollout.clone())
+}
+
+fn set_rollout(rollout: UpgradeRollout) {
+    CONFIG.with(|c| {
+        let mut wrapper = c.borrow().get().clone();
+        wrapper.0.upgrade_rollout = Some(rollout);
+        c.borrow_mut()
+            .set(wrapper)
+            .expect("Failed to update config");
+    });
+}
+
+fn is_rollout_active(rollout: &UpgradeRollout) -> bool {
+    matches!(
+        rollout.status,
+        UpgradeRolloutStatus::Running | UpgradeRolloutStatus::RollingBack
+    )
+}
+
+fn get_active_rollout() -> Option<UpgradeRollout> {
+    get_rollout().filter(is_rollout_active)
+}
+
+fn update_pool_entry<F>(canister_id: Principal, f: F)
+where
+    F: FnOnce(&mut gpt_types::api::common::CanisterPoolEntry),
+{
+    CANISTER_POOL.with(|pool| {
+        let mut pool_map = pool.borrow_mut();
+        let key = StorablePrincipal(canister_id);
+        if let Some(wrapper) = pool_map.get(&key) {
+            let mut entry = wrapper.0.clone();
+            f(&mut entry);
+            pool_map.insert(key, CandidWrapper(entry));
+        }
+    });
+}
+
+/// Records the module an index install just put on a canister.
+pub fn record_installed_module(canister_id: Principal, hash: Option<Vec<u8>>) {
+    update_pool_entry(canister_id, |entry| {
+        entry.module_hash = hash;
+        entry.upgrade_state = None;
+    });
+}
+
+fn schedule_rollout_tick(delay: Duration) {
+    ROLLOUT_TIMER.with(|t| {
+        if let Some(previous) = t.borrow_mut().take() {
+            clear_timer(previous);
+        }
+        let timer_id = set_timer(delay, || {
+            ROLLOUT_TIMER.with(|t| t.borrow_mut().take());
+            ic_cdk::futures::spawn(rollout_tick());
+        });
+        *t.borrow_mut() = Some(timer_id);
+    });
+}
+
+/// Re-arms the rollout timer after an index upgrade if a rollout was still in progress.
+pub fn resume_rollout_timer() {
+    if let Some(rollout) = get_active_rollout() {
+        ic_cdk::println!(
+            "Resuming gpt_user rollout ({:?}) after index upgrade.",
+            rollout.status
+        );
+        schedule_rollout_tick(Duration::from_secs(rollout.batch_interval_secs));
+    }
+}
+
+#[update]
+pub fn begin_user_wasm_upload(req: BeginUserWasmUploadRequest) -> BeginUserWasmUploadResult {
+    verify_manager()?;
+
+    if req.total_size == 0 || req.total_size > config::MAX_USER_WASM_SIZE_BYTES {
+        return Err(CanisterError::InvalidInput(format!(
+            "Module size must be between 1 and {} bytes.",
+            config::MAX_USER_WASM_SIZE_BYTES
+        )));
+    }
+    if req.module_hash.len() != 32 {
+        return Err(CanisterError::InvalidInput(
+            "Module hash must be a 32-byte SHA-256 digest.".to_string(),
+        ));
+    }
+
+    set_wasm_slot(
+        WASM_SLOT_UPLOAD,
+        StoredUserWasm {
+            wasm: Vec::new(),
+            module_hash: req.module_hash,
+            total_size: req.total_size,
+            updated_at: ic_cdk::api::time(),
+        },
+    );
+
+    ic_cdk::println!(
+        "Started gpt_user module upload ({} bytes expected).",
+        req.total_size
+    );
+    Ok(BeginUserWasmUploadResponse)
+}
+
+#[update]
+pub fn upload_user_wasm_chunk(req: UploadUserWasmChunkRequest) -> UploadUserWasmChunkResult {
+    verify_manager()?;
+
+    let mut upload = get_wasm_slot(WASM_SLOT_UPLOAD).ok_or_else(|| {
+        CanisterError::InvalidInput("No module upload in progress.".to_string())
+    })?;
+
+    let received = upload.wasm.len() as u64 + req.chunk.len() as u64;
+    if received > upload.total_size {
+        return Err(CanisterError::InvalidInput(format!(
+            "Chunk exceeds the declared module size ({} > {} bytes).",
+            received, upload.total_size
+        )));
+    }
+
+    upload.wasm.extend_from_slice(&req.chunk);
+    upload.updated_at = ic_cdk::api::time();
+    set_wasm_slot(WASM_SLOT_UPLOAD, upload);
+
+    Ok(UploadUserWasmChunkResponse {
+        received_bytes: received,
+    })
+}
+
+#[update]
+pub fn commit_user_wasm_upload(_req: CommitUserWasmUploadRequest) -> CommitUserWasmUploadResult {
+    verify_manager()?;
+
+    if get_active_rollout().is_some() {
+        return Err(CanisterError::InvalidInput(
+            "Cannot replace the staged module while a rollout is in progress.".to_string(),
+        ));
+    }
+
+    let upload = get_wasm_slot(WASM_SLOT_UPLOAD).ok_or_else(|| {
+        CanisterError::InvalidInput("No module upload in progress.".to_string())
+    })?;
+
+    if upload.wasm.len() as u64 != upload.total_size {
+        return Err(CanisterError::InvalidInput(format!(
+            "Upload incomplete: received {} of {} bytes.",
+            upload.wasm.len(),
+            upload.total_size
+        )));
+    }
+    if !upload.wasm.starts_with(WASM_MAGIC) && !upload.wasm.starts_with(GZIP_MAGIC) {
+        return Err(CanisterError::InvalidInput(
+            "Upload is neither a Wasm module nor gzip-compressed.".to_string(),
+        ));
+    }
+
+    let actual_hash = module_hash(&upload.wasm);
+    if actual_hash != upload.module_hash {
+        return Err(CanisterError::InvalidInput(format!(
+            "Module hash mismatch: expected {}, got {}.",
+            hex::encode(&upload.module_hash),
+            hex::encode(&actual_hash)
+        )));
+    }
+
+    let size = upload.total_size;
+    USER_WASMS.with(|w| {
+        let mut slots = w.borrow_mut();
+        slots.remove(&WASM_SLOT_UPLOAD.to_string());
+        slots.insert(WASM_SLOT_STAGED.to_string(), CandidWrapper(upload));
+    });
+
+    ic_cdk::println!(
+        "Staged gpt_user module {} ({} bytes).",
+        hex::encode(&actual_hash),
+        size
+    );
+    Ok(CommitUserWasmUploadResponse {
+        module_hash: actual_hash,
+        size,
+    })
+}
+
+/// Starts rolling the staged module out to every assigned canister. A halted rollout of the
+/// same module resumes where it stopped, retrying canisters whose upgrade failed.
+#[update]
+pub fn start_user_canister_upgrade(
+    req: StartUserCanisterUpgradeRequest,
+) -> StartUserCanisterUpgradeResult {
+    verify_manager()?;
+
+    let existing = get_rollout();
+    if existing.as_ref().is_some_and(is_rollout_active) {
+        return Err(CanisterError::InvalidInput(
+            "A rollout is already in progress.".to_string(),
+        ));
+    }
+
+    let staged = get_wasm_slot(WASM_SLOT_STAGED).ok_or_else(|| {
+        CanisterError::InvalidInput("No staged gpt_user module to roll out.".to_string())
+    })?;
+
+    let current_wasm = current_user_wasm();
+    let current_hash = module_hash(&current_wasm);
+    if staged.module_hash == current_hash {
+        return Err(CanisterError::InvalidInput(
+            "Staged module is already the current module.".to_string(),
+        ));
+    }
+
+    let batch_size = req
+        .batch_size
+        .unwrap_or(config::DEFAULT_UPGRADE_BATCH_SIZE)
+        .clamp(1, config::MAX_UPGRADE_BATCH_SIZE);
+    let batch_interval_secs = req
+        .batch_interval_secs
+        .unwrap_or(config::DEFAULT_UPGRADE_BATCH_INTERVAL_SECS);
+
+    // Keep the module being replaced so a rollback can reinstall it.
+    set_wasm_slot(
+        WASM_SLOT_PREVIOUS,
+        StoredUserWasm {
+            total_size: current_wasm.len() as u64,
+            wasm: current_wasm,
+            module_hash: current_hash.clone(),
+            updated_at: ic_cdk::api::time(),
+        },
+    );
+
+    let target = staged.module_hash.clone();
+    let now = ic_cdk::api::time();
+    let canisters_queued = CANISTER_POOL.with(|pool| {
+        let mut pool_map = pool.borrow_mut();
+        let queued: Vec<_> = pool_map
+            .iter()
+            .filter(|(_, w)| {
+                matches!(w.0.state, CanisterPoolState::Assigned { .. })
+                    && w.0.module_hash.as_ref() != Some(&target)
+            })
+            .collect();
+
+        for (key, wrapper) in &queued {
+            let mut entry = wrapper.0.clone();
+            entry.upgrade_state = Some(CanisterUpgradeState::Pending);
+            pool_map.insert(key.clone(), CandidWrapper(entry));
+        }
+        queued.len() as u32
+    });
+
+    let resumed = existing.filter(|r| r.target_module_hash == target);
+
+    let rollout = UpgradeRollout {
+        target_module_hash: target,
+        previous_module_hash: current_hash,
+        batch_size,
+        batch_interval_secs,
+        status: UpgradeRolloutStatus::Running,
+        batches_run: resumed.as_ref().map_or(0, |r| r.batches_run),
+        previous_schema_version: resumed.as_ref().and_then(|r| r.previous_schema_version),
+        target_schema_version: resumed.as_ref().and_then(|r| r.target_schema_version),
+        started_at: now,
+        updated_at: now,
+    };
+    set_rollout(rollout.clone());
+    schedule_rollout_tick(Duration::ZERO);
+
+    ic_cdk::println!(
+        "Started gpt_user rollout of {}: {} canisters queued, batch size {}, interval {}s.",
+        hex::encode(&rollout.target_module_hash),
+        canisters_queued,
+        batch_size,
+        batch_interval_secs
+    );
+
+    Ok(StartUserCanisterUpgradeResponse {
+        rollout,
+        canisters_queued,
+    })
+}
+
+/// Stops the running rollout or rollback after the batch currently in flight.
+#[update]
+pub fn halt_user_canister_upgrade(
+    _req: HaltUserCanisterUpgradeRequest,
+) -> HaltUserCanisterUpgradeResult {
+    verify_manager()?;
+
+    let mut rollout = get_active_rollout()
+        .ok_or_else(|| CanisterError::InvalidInput("No rollout in progress.".to_string()))?;
+
+    rollout.status = UpgradeRolloutStatus::Halted {
+        reason: "Halted by manager.".to_string(),
+    };
+    rollout.updated_at = ic_cdk::api::time();
+    set_rollout(rollout);
+
+    ROLLOUT_TIMER.with(|t| {
+        if let Some(timer_id) = t.borrow_mut().take() {
+            clear_timer(timer_id);
+        }
+    });
+
+    ic_cdk::println!("gpt_user rollout halted by manager.");
+    Ok(HaltUserCanisterUpgradeResponse)
+}
+
+/// Reinstalls the previous module (in upgrade mode, keeping stable memory) on every canister
+/// that already runs the rollout's target. Refused unless the previous module is known to
+/// read the storage schema the upgraded canisters were migrated to.
+#[update]
+pub fn rollback_user_canister_upgrade(
+    _req: RollbackUserCanisterUpgradeRequest,
+) -> RollbackUserCanisterUpgradeResult {
+    verify_manager()?;
+
+    let mut rollout =
+        get_rollout().ok_or_else(|| CanisterError::InvalidInput("No rollout to roll back.".to_string()))?;
+
+    match rollout.status {
+        UpgradeRolloutStatus::Halted { .. } | UpgradeRolloutStatus::Completed => {}
+        _ => {
+            return Err(CanisterError::InvalidInput(
+                "Only a halted or completed rollout can be rolled back.".to_string(),
+            ));
+        }
+    }
+
+    let previous = get_wasm_slot(WASM_SLOT_PREVIOUS)
+        .filter(|p| p.module_hash == rollout.previous_module_hash)
+        .ok_or_else(|| {
+            CanisterError::Other("Previous gpt_user module is no longer stored.".to_string())
+        })?;
+
+    let canisters_queued = CANISTER_POOL.with(|pool| {
+        pool.borrow()
+            .iter()
+            .filter(|(_, w)| w.0.module_hash.as_ref() == Some(&rollout.target_module_hash))
+            .count() as u32
+    });
+    if canisters_queued > 0 {
+        check_rollback_schema(
+            rollout.previous_schema_version,
+            rollout.target_schema_version,
+        )?;
+    }
+
+    rollout.status = UpgradeRolloutStatus::RollingBack;
+    rollout.updated_at = ic_cdk::api::time();
+    set_rollout(rollout);
+    schedule_rollout_tick(Duration::ZERO);
+
+    ic_cdk::println!(
+        "Rolling back {} canisters to gpt_user module {}.",
+        canisters_queued,
+        hex::encode(&previous.module_hash)
+    );
+    Ok(RollbackUserCanisterUpgradeResponse { canisters_queued })
+}
+
+#[query]
+pub fn get_user_canister_upgrade_status(
+    _req: GetUserCanisterUpgradeStatusRequest,
+) -> GetUserCanisterUpgradeStatusResult {
+    verify_manager()?;
+
+    let (mut pending, mut upgraded, mut failed, mut rolled_back) = (0u32, 0u32, 0u32, 0u32);
+    CANISTER_POOL.with(|pool| {
+        for (_, wrapper) in pool.borrow().iter() {
+            match wrapper.0.upgrade_state {
+                Some(CanisterUpgradeState::Pending) => pending += 1,
+                Some(CanisterUpgradeState::Upgraded { .. }) => upgraded += 1,
+                Some(CanisterUpgradeState::Failed { .. }) => failed += 1,
+                Some(CanisterUpgradeState::RolledBack { .. }) => rolled_back += 1,
+                None => {}
+            }
+        }
+    });
+
+    let current_module_hash = get_wasm_slot(WASM_SLOT_CURRENT)
+        .map(|s| s.module_hash)
+        .unwrap_or_else(|| module_hash(GPT_USER_WASM));
+
+    Ok(GetUserCanisterUpgradeStatusResponse {
+        rollout: get_rollout(),
+        current_module_hash,
+        staged_module_hash: get_wasm_slot(WASM_SLOT_STAGED).map(|s| s.module_hash),
+        pending,
+        upgraded,
+        failed,
+        rolled_back,
+    })
+}
+
+async fn rollout_tick() {
+    let Some(rollout) = get_active_rollout() else {
+        ic_cdk::println!("Rollout tick: no active rollout, stopping.");
+        return;
+    };
+
+    if BATCH_IN_FLIGHT.with(|f| f.replace(true)) {
+        ic_cdk::println!("Rollout tick: previous batch still in flight, retrying later.");
+        schedule_rollout_tick(Duration::from_secs(rollout.batch_interval_secs));
+        return;
+    }
+
+    run_rollout_batch(rollout).await;
+    BATCH_IN_FLIGHT.with(|f| f.set(false));
+}
+
+/// Upgrades the next batch of canisters (or rolls them back) and schedules the following
+/// tick. Any failure in the batch halts the rollout.
+async fn run_rollout_batch(mut rollout: UpgradeRollout) {
+    let rolling_back = rollout.status == UpgradeRolloutStatus::RollingBack;
+    let slot = if rolling_back {
+        WASM_SLOT_PREVIOUS
+    } else {
+        WASM_SLOT_STAGED
+    };
+
+    let Some(module) = get_wasm_slot(slot) else {
+        halt_rollout(
+            rollout,
+            format!("gpt_user module slot '{}' is empty.", slot),
+            ic_cdk::api::time(),
+        );
+        return;
+    };
+
+    let batch = select_batch(&rollout);
+
+    if batch.is_empty() {
+        finish_rollout(rollout, rolling_back, ic_cdk::api::time());
+        return;
+    }
+
+    ic_cdk::println!(
+        "Rollout batch {}: {} {} canisters.",
+        rollout.batches_run + 1,
+        if rolling_back { "rolling back" } else { "upgrading" },
+        batch.len()
+    );
+
+    let mut failures = 0usize;
+    for entry in &batch {
+        let canister_id = entry.canister_id;
+        let result = if rolling_back {
+            roll_back_user_canister(canister_id, rollout.previous_schema_version, &module.wasm)
+                .await
+        } else {
+            // A canister still on the previous module tells which schema a rollback can
+            // return to; an upgraded one tells which schema it was migrated to.
+            if rollout.previous_schema_version.is_none()
+                && entry.module_hash.as_ref() == Some(&rollout.previous_module_hash)
+            {
+                rollout.previous_schema_version = storage_schema_version(canister_id)
+                    .await
+                    .map(|v| v.supported_version)
+                    .inspect_err(|e| ic_cdk::println!("WARN: {}", e))
+                    .ok();
+            }
+            let result = upgrade_user_canister(canister_id, &module.wasm).await;
+            if result.is_ok() {
+                match storage_schema_version(canister_id).await {
+                    Ok(v) => {
+                        rollout.target_schema_version =
+                            rollout.target_schema_version.max(Some(v.stored_version))
+                    }
+                    Err(e) => ic_cdk::println!("WARN: {}", e),
+                }
+            }
+            result
+        };
+        let at = ic_cdk::api::time();
+        match result {
+            Ok(()) => update_pool_entry(canister_id, |entry| {
+                entry.module_hash = Some(module.module_hash.clone());
+                entry.upgrade_state = Some(if rolling_back {
+                    CanisterUpgradeState::RolledBack { at }
+                } else {
+                    CanisterUpgradeState::Upgraded { at }
+                });
+            }),
+            Err(e) => {
+                failures += 1;
+                ic_cdk::println!("ERROR: Rollout failed for {}: {:?}", canister_id, e);
+                update_pool_entry(canister_id, |entry| {
+                    entry.upgrade_state = Some(CanisterUpgradeState::Failed {
+                        at,
+                        error: e.to_string(),
+                    });
+                });
+            }
+        }
+    }
+
+    // A manager may have halted the rollout while this batch was running.
+    let Some(latest) = get_rollout() else { return };
+    if latest.status != rollout.status {
+        ic_cdk::println!("Rollout status changed during batch; not scheduling another.");
+        // Keep what the batch learned about schema versions for a later rollback.
+        set_rollout(UpgradeRollout {
+            previous_schema_version: rollout.previous_schema_version,
+            target_schema_version: rollout.target_schema_version,
+            ..latest
+        });
+        return;
+    }
+
+    rollout.batches_run += 1;
+    rollout.updated_at = ic_cdk::api::time();
+
+    if failures > 0 {
+        let reason = format!(
+            "{} of {} canisters failed in batch {}.",
+            failures,
+            batch.len(),
+            rollout.batches_run
+        );
+        halt_rollout(rollout, reason, ic_cdk::api::time());
+        return;
+    }
+
+    let interval = Duration::from_secs(rollout.batch_interval_secs);
+    set_rollout(rollout);
+    schedule_rollout_tick(interval);
+}
+
+/// Picks the next canisters to upgrade: assigned ones not yet on the target that have not
+/// failed in this rollout. A rollback picks every canister on the target.
+fn select_batch(rollout: &UpgradeRollout) -> Vec<CanisterPoolEntry> {
+    let rolling_back = rollout.status == UpgradeRolloutStatus::RollingBack;
+    let target = &rollout.target_module_hash;
+    CANISTER_POOL.with(|pool| {
+        pool.borrow()
+            .iter()
+            .map(|(_, w)| w.0)
+            .filter(|entry| {
+                if rolling_back {
+                    entry.module_hash.as_ref() == Some(target)
+                } else {
+                    matches!(entry.state, CanisterPoolState::Assigned { .. })
+                        && entry.module_hash.as_ref() != Some(target)
+                        && !matches!(
+                            entry.upgrade_state,
+                            Some(CanisterUpgradeState::Failed { .. })
+                        )
+                }
+            })
+            .take(rollout.batch_size as usize)
+            .collect()
+    })
+}
+
+/// Asks a gpt_user canister which storage schema it is on and which its module supports.
+async fn storage_schema_version(
+    canister_id: Principal,
+) -> CanisterResult<GetStorageSchemaVersionResponse> {
+    let response = Call::unbounded_wait(canister_id, "get_storage_schema_version")
+        .await
+        .map_err(|e| {
+            CanisterError::CallError(format!(
+                "Failed to read the schema version of {}: {}",
+                canister_id, e
+            ))
+        })?;
+    let result: GetStorageSchemaVersionResult = response
+        .candid()
+        .map_err(|e| CanisterError::CallError(format!("Decoding error: {}", e)))?;
+    result
+}
+
+/// A module can only be reinstalled over storage whose schema it supports; an older module
+/// would skip the newer migrations and misread the records.
+fn check_rollback_schema(previous: Option<u32>, stored: Option<u32>) -> CanisterResult<()> {
+    match (previous, stored) {
+        (Some(previous), Some(stored)) if stored <= previous => Ok(()),
+        (Some(previous), Some(stored)) => Err(CanisterError::InvalidInput(format!(
+            "Canisters store schema version {}, which the previous module (schema version {}) \
+             cannot read.",
+            stored, previous
+        ))),
+        _ => Err(CanisterError::InvalidInput(
+            "The storage schema versions of this rollout are unknown; a rollback is only \
+             valid between modules with the same schema."
+                .to_string(),
+        )),
+    }
+}
+
+async fn roll_back_user_canister(
+    canister_id: Principal,
+    previous_schema_version: Option<u32>,
+    wasm: &[u8],
+) -> CanisterResult<()> {
+    let stored = storage_schema_version(canister_id).await?.stored_version;
+    check_rollback_schema(previous_schema_version, Some(stored))?;
+    upgrade_user_canister(canister_id, wasm).await
+}
+
+async fn upgrade_user_canister(canister_id: Principal, wasm: &[u8]) -> CanisterResult<()> {
+    // Stop first so no calls are in flight while the module is swapped.
+    stop_canister(&StopCanisterArgs { canister_id })
+        .await
+        .map_err(|e| {
+            CanisterError::CallError(format!("Failed to stop {}: {}", canister_id, e))
+        })?;
+
+    let install_args = InstallCodeArgs {
+        mode: CanisterInstallMode::Upgrade(None),
+        canister_id,
+        wasm_module: wasm.to_vec(),
+        arg: Encode!().expect("BUG: Failed to encode empty upgrade argument"),
+    };
+    let install_result = install_code(&install_args).await.map_err(|e| {
+        CanisterError::CallError(format!("Failed to upgrade {}: {}", canister_id, e))
+    });
+
+    // Restart regardless of the outcome; a failed upgrade leaves the old module in place.
+    let start_result = start_canister(&StartCanisterArgs { canister_id })
+        .await
+        .map_err(|e| {
+            CanisterError::CallError(format!("Failed to restart {}: {}", canister_id, e))
+        });
+
+    install_result.and(start_result)
+}
+
+fn halt_rollout(mut rollout: UpgradeRollout, reason: String, now: u64) {
+    ic_cdk::println!("Halting gpt_user rollout: {}", reason);
+    rollout.status = UpgradeRolloutStatus::Halted { reason };
+    rollout.updated_at = now;
+    set_rollout(rollout);
+}
+
+fn finish_rollout(mut rollout: UpgradeRollout, rolling_back: bool, now: u64) {
+    // Newly assigned canisters get whatever the rollout left in place.
+    let promoted_slot = if rolling_back {
+        WASM_SLOT_PREVIOUS
+    } else {
+        WASM_SLOT_STAGED
+    };
+    if let Some(module) = get_wasm_slot(promoted_slot) {
+        set_wasm_slot(WASM_SLOT_CURRENT, module);
+    }
+
+    rollout.status = if rolling_back {
+        UpgradeRolloutStatus::RolledBack
+    } else {
+        UpgradeRolloutStatus::Completed
+    };
+    rollout.updated_at = now;
+
+    ic_cdk::println!(
+        "gpt_user rollout finished ({:?}) after {} batches.",
+        rollout.status,
+        rollout.batches_run
+    );
+    set_rollout(rollout);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREVIOUS: &[u8] = &[1; 32];
+    const TARGET: &[u8] = &[2; 32];
+
+    fn rollout(status: UpgradeRolloutStatus, batch_size: u32) -> UpgradeRollout {
+        UpgradeRollout {
+            target_module_hash: TARGET.to_vec(),
+            previous_module_hash: PREVIOUS.to_vec(),
+            batch_size,
+            batch_interval_secs: 60,
+            status,
+            batches_run: 0,
+            previous_schema_version: None,
+            target_schema_version: None,
+            started_at: 1,
+            updated_at: 1,
+        }
+    }
+
+    fn add_canister(
+        id: u8,
+        assigned: bool,
+        module_hash: &[u8],
+        upgrade_state: Option<CanisterUpgradeState>,
+    ) -> Principal {
+        let canister_id = Principal::from_slice(&[id]);
+        let state = if assigned {
+            CanisterPoolState::Assigned {
+                owner: Principal::anonymous(),
+                expires_at: None,
+            }
+        } else {
+            CanisterPoolState::Available
+        };
+        CANISTER_POOL.with(|pool| {
+            pool.borrow_mut().insert(
+                StorablePrincipal(canister_id),
+                CandidWrapper(CanisterPoolEntry {
+                    canister_id,
+                    time_created: 0,
+                    state,
+                    module_hash: Some(module_hash.to_vec()),
+                    upgrade_state,
+                }),
+            )
+        });
+        canister_id
+    }
+
+    fn stored_wasm(hash: &[u8]) -> StoredUserWasm {
+        StoredUserWasm {
+            wasm: hash.to_vec(),
+            module_hash: hash.to_vec(),
+            total_size: hash.len() as u64,
+            updated_at: 0,
+        }
+    }
+
+    fn ids(batch: &[CanisterPoolEntry]) -> Vec<Principal> {
+        batch.iter().map(|entry| entry.canister_id).collect()
+    }
+
+    #[test]
+    fn test_batch_skips_unassigned_upgraded_and_failed_canisters() {
+        let pending = add_canister(1, true, PREVIOUS, Some(CanisterUpgradeState::Pending));
+        add_canister(2, false, PREVIOUS, None);
+        add_canister(
+            3,
+            true,
+            TARGET,
+            Some(CanisterUpgradeState::Upgraded { at: 1 }),
+        );
+        let failed = CanisterUpgradeState::Failed {
+            at: 1,
+            error: "trapped".to_string(),
+        };
+        add_canister(4, true, PREVIOUS, Some(failed));
+        let never_queued = add_canister(5, true, PREVIOUS, None);
+
+        let batch = select_batch(&rollout(UpgradeRolloutStatus::Running, 10));
+        assert_eq!(ids(&batch), vec![pending, never_queued]);
+    }
+
+    #[test]
+    fn test_batch_is_capped_at_the_batch_size() {
+        for id in 1..=5 {
+            add_canister(id, true, PREVIOUS, Some(CanisterUpgradeState::Pending));
+        }
+        assert_eq!(
+            select_batch(&rollout(UpgradeRolloutStatus::Running, 2)).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_rollback_batch_takes_every_canister_on_the_target() {
+        add_canister(1, true, PREVIOUS, None);
+        let upgraded = add_canister(
+            2,
+            true,
+            TARGET,
+            Some(CanisterUpgradeState::Upgraded { at: 1 }),
+        );
+        let unassigned = add_canister(3, false, TARGET, None);
+
+        let batch = select_batch(&rollout(UpgradeRolloutStatus::RollingBack, 10));
+        assert_eq!(ids(&batch), vec![upgraded, unassigned]);
+    }
+
+    #[test]
+    fn test_halting_stores_the_reason() {
+        halt_rollout(
+            rollout(UpgradeRolloutStatus::Running, 10),
+            "1 of 2 canisters failed in batch 1.".to_string(),
+            5,
+        );
+
+        let halted = get_rollout().unwrap();
+        assert_eq!(
+            halted.status,
+            UpgradeRolloutStatus::Halted {
+                reason: "1 of 2 canisters failed in batch 1.".to_string()
+            }
+        );
+        assert_eq!(halted.updated_at, 5);
+        assert!(get_active_rollout().is_none());
+    }
+
+    #[test]
+    fn test_finishing_promotes_the_staged_module() {
+        set_wasm_slot(WASM_SLOT_STAGED, stored_wasm(TARGET));
+        set_wasm_slot(WASM_SLOT_PREVIOUS, stored_wasm(PREVIOUS));
+
+        finish_rollout(rollout(UpgradeRolloutStatus::Running, 10), false, 5);
+
+        assert_eq!(
+            get_rollout().unwrap().status,
+            UpgradeRolloutStatus::Completed
+        );
+        assert_eq!(current_user_wasm(), TARGET);
+    }
+
+    #[test]
+    fn test_finishing_a_rollback_promotes_the_previous_module() {
+        set_wasm_slot(WASM_SLOT_STAGED, stored_wasm(TARGET));
+        set_wasm_slot(WASM_SLOT_PREVIOUS, stored_wasm(PREVIOUS));
+
+        finish_rollout(rollout(UpgradeRolloutStatus::RollingBack, 10), true, 5);
+
+        assert_eq!(
+            get_rollout().unwrap().status,
+            UpgradeRolloutStatus::RolledBack
+        );
+        assert_eq!(current_user_wasm(), PREVIOUS);
+    }
+
+    #[test]
+    fn test_rollback_needs_a_module_that_reads_the_stored_schema() {
+        assert!(check_rollback_schema(Some(6), Some(6)).is_ok());
+        assert!(check_rollback_schema(Some(6), Some(5)).is_ok());
+        assert!(check_rollback_schema(Some(5), Some(6)).is_err());
+        assert!(check_rollback_schema(None, Some(6)).is_err());
+        assert!(check_rollback_schema(Some(6), None).is_err());
+    }
+}
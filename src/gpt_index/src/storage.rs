@@ -1,6 +1,6 @@
 use candid::{CandidType, Deserialize, Principal};
 use gpt_types::{
-    api::common::CanisterPoolEntry,
+    api::{UpgradeRollout, common::CanisterPoolEntry},
     domain::{Model, Node, User, node::AttestationRequirements},
 };
 use ic_stable_structures::{
@@ -20,6 +20,7 @@ const MEMORY_ID_NODES: MemoryId = MemoryId::new(2);
 const MEMORY_ID_MODELS: MemoryId = MemoryId::new(3);
 const MEMORY_ID_USER_CANISTERS: MemoryId = MemoryId::new(4);
 const MEMORY_ID_MANAGERS: MemoryId = MemoryId::new(5);
+const MEMORY_ID_USER_WASMS: MemoryId = MemoryId::new(6);
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -91,6 +92,29 @@ pub struct GlobalConfig {
     pub next_node_id: u64,
     pub attestation_requirements: Option<AttestationRequirements>,
     pub pool_target_size: u32,
+    /// The latest gpt_user rollout, kept after it finishes for inspection
+    pub upgrade_rollout: Option<UpgradeRollout>,
+}
+
+// --- gpt_user Module Slots ---
+
+/// Chunks received so far for an upload that has not been committed yet
+pub const WASM_SLOT_UPLOAD: &str = "upload";
+/// Committed upload, the target of the next or running rollout
+pub const WASM_SLOT_STAGED: &str = "staged";
+/// Installed on newly assigned canisters (falls back to the compile-time build)
+pub const WASM_SLOT_CURRENT: &str = "current";
+/// Module a rollback reinstalls
+pub const WASM_SLOT_PREVIOUS: &str = "previous";
+
+#[derive(CandidType, Deserialize, Default, Clone)]
+pub struct StoredUserWasm {
+    #[serde(with = "serde_bytes")]
+    pub wasm: Vec<u8>,
+    /// SHA-256 of the complete module (expected value while uploading)
+    pub module_hash: Vec<u8>,
+    pub total_size: u64,
+    pub updated_at: u64,
 }
 
 // --- Storage Definition ---
@@ -138,6 +162,12 @@ thread_local! {
         )
     );
 
+    pub static USER_WASMS: RefCell<StableBTreeMap<String, CandidWrapper<StoredUserWasm>, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_USER_WASMS))
+        )
+    );
+
     // Derived Indexes (Heap Memory - Rebuilt on Upgrade)
 
     pub static NODE_OWNER_INDEX: RefCell<BTreeMap<Principal, BTreeSet<u64>>> = const { RefCell::new(BTreeMap::new()) };
@@ -0,0 +1,23 @@
+use crate::core::state::SharedState;
+use axum::{
+    extract::State,
+    http::{StatusCode, header::CONTENT_TYPE},
+    response::IntoResponse,
+};
+use tracing::error;
+
+/// Serves the node's metrics in the Prometheus text format.
+pub async fn metrics_handler(State(state): State<SharedState>) -> impl IntoResponse {
+    match state.metrics.encode() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            body,
+        )
+            .into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to encode metrics.");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -1,3 +1,4 @@
+pub mod metrics;
 pub mod routes;
 pub mod status;
 pub mod websocket;
@@ -1,13 +1,13 @@
 use super::message::{forward_stream_to_socket, try_receive_initial_request};
 use crate::{
-    core::job::processor::handle_conversation_job, clients::ai_provider::BROADCAST_CHANNEL_CAPACITY,
-    core::state::SharedState, core::job::types::StreamedResponse,
+    core::job::processor::handle_conversation_job,
+    core::job::stream::{JobStream, find_job_stream},
+    core::state::SharedState,
 };
 use axum::extract::ws::WebSocket;
 use futures::SinkExt;
 use std::net::SocketAddr;
-use std::sync::atomic::Ordering;
-use tokio::sync::broadcast;
+use std::sync::{Arc, atomic::Ordering};
 use tracing::{Instrument, debug, error, info, info_span, warn};
 
 pub async fn handle_socket_connection(mut socket: WebSocket, addr: SocketAddr, state: SharedState) {
@@ -40,12 +40,19 @@ pub async fn handle_socket_connection(mut socket: WebSocket, addr: SocketAddr, s
     );
 
     async move {
+        if let Some(last_seq) = request.last_seq {
+            resume_job_stream(&mut socket, &state, &stream_key, request.job_id, last_seq).await;
+            let _ = socket.close().await;
+            return;
+        }
+
         info!(
             user_canister = %request.user_canister_id,
             "Client initiated job via WebSocket"
         );
 
-        let (mut receiver, should_spawn_job) = subscribe_to_job_stream(&state, &stream_key).await;
+        let (stream, should_spawn_job) = subscribe_to_job_stream(&state, &stream_key).await;
+        let subscription = stream.subscribe(None);
 
         let chat_key_result =
             handle_conversation_job(request.clone(), &state, stream_key.clone()).await;
@@ -68,7 +75,15 @@ pub async fn handle_socket_connection(mut socket: WebSocket, addr: SocketAddr, s
             }
         };
 
-        forward_stream_to_socket(&mut socket, &mut receiver, &stream_key, &chat_key).await;
+        stream.set_chat_key(chat_key.clone());
+        forward_stream_to_socket(
+            &mut socket,
+            &stream,
+            subscription,
+            request.job_id,
+            &chat_key,
+        )
+        .await;
 
         if let Err(e) = socket.close().await {
             debug!(
@@ -82,18 +97,50 @@ pub async fn handle_socket_connection(mut socket: WebSocket, addr: SocketAddr, s
     .await
 }
 
-async fn subscribe_to_job_stream(
-    state: &SharedState,
-    stream_key: &str,
-) -> (broadcast::Receiver<StreamedResponse>, bool) {
+async fn subscribe_to_job_stream(state: &SharedState, stream_key: &str) -> (Arc<JobStream>, bool) {
     let mut job_streams_lock = state.job_streams.lock().await;
-    if let Some(sender) = job_streams_lock.get(stream_key) {
+    if let Some(stream) = job_streams_lock.get(stream_key) {
         info!("Client subscribing to existing job stream.");
-        (sender.subscribe(), false)
+        (stream.clone(), false)
     } else {
-        info!("No existing stream found. Creating new broadcast channel.");
-        let (tx, rx) = broadcast::channel(BROADCAST_CHANNEL_CAPACITY);
-        job_streams_lock.insert(stream_key.to_string(), tx);
-        (rx, true)
+        info!("No existing stream found. Creating new job stream.");
+        let stream = Arc::new(JobStream::new());
+        job_streams_lock.insert(stream_key.to_string(), stream.clone());
+        (stream, true)
+    }
+}
+
+/// Reattaches a reconnecting client to its job's stream, replaying what it missed
+/// after `last_seq`. The job is not claimed again.
+async fn resume_job_stream(
+    socket: &mut WebSocket,
+    state: &SharedState,
+    stream_key: &str,
+    job_id: u64,
+    last_seq: u64,
+) {
+    let Some(stream) = find_job_stream(state, stream_key).await else {
+        info!(
+            last_seq,
+            "No stream to resume; it finished or never started on this node."
+        );
+        return;
+    };
+    // Without the chat key the job was never claimed, so there is nothing to resume.
+    let Some(chat_key) = stream.chat_key().map(<[u8]>::to_vec) else {
+        info!(last_seq, "Stream is not resumable yet.");
+        return;
+    };
+
+    let subscription = stream.subscribe(Some(last_seq));
+    if subscription.finished && subscription.replay.is_empty() {
+        info!(last_seq, "Client already received the whole stream.");
+        return;
     }
+    info!(
+        last_seq,
+        replayed = subscription.replay.len(),
+        "Client resumed job stream."
+    );
+    forward_stream_to_socket(socket, &stream, subscription, job_id, &chat_key).await;
 }
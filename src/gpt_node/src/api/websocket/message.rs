@@ -1,4 +1,5 @@
-use crate::api::websocket::types::ConversationRequest;
+use crate::api::websocket::types::{ClientMessage, ConversationRequest};
+use crate::core::job::stream::{JobStream, StreamFrame, Subscription};
 use crate::core::job::types::StreamedResponse;
 use aes_gcm::{
     Aes256Gcm, Nonce,
@@ -22,28 +23,51 @@ const GCM_NONCE_SIZE: usize = 12;
 
 pub(super) async fn forward_stream_to_socket(
     socket: &mut WebSocket,
-    receiver: &mut broadcast::Receiver<StreamedResponse>,
-    stream_key: &str,
+    stream: &JobStream,
+    subscription: Subscription,
+    job_id: u64,
     chat_key: &[u8],
 ) {
     let last_message_was_final = Arc::new(AtomicBool::new(false));
     let mut lag_errors = 0;
+    let Subscription {
+        replay,
+        mut receiver,
+        ..
+    } = subscription;
 
     // Initialize cipher with the job-specific symmetric chat key
     let key = aes_gcm::Key::<Aes256Gcm>::from_slice(chat_key);
     let cipher = Aes256Gcm::new(key);
 
+    // Frames a reconnecting client missed go out before the live stream.
+    for frame in &replay {
+        match send_frame(frame, socket, &last_message_was_final, &cipher).await {
+            Ok(true) => {
+                info!("Final message replayed for stream. Breaking forward loop.");
+                return;
+            }
+            Ok(false) => {}
+            Err(_) => return,
+        }
+    }
+    let last_replayed_seq = replay.last().map_or(0, |frame| frame.seq);
+
     loop {
         tokio::select! {
             biased;
             client_msg = socket.next() => {
-                if handle_client_message(client_msg, socket, stream_key).await.is_err() {
+                if handle_client_message(client_msg, socket, stream, job_id, &cipher).await.is_err() {
                     info!("Client disconnected for stream. Breaking forward loop.");
                     break;
                 }
             },
             broadcast_msg = receiver.recv() => {
-                match handle_broadcast_message(broadcast_msg, socket, &last_message_was_final, stream_key, &cipher).await {
+                // Skip live frames that were already replayed.
+                if matches!(&broadcast_msg, Ok(frame) if frame.seq <= last_replayed_seq) {
+                    continue;
+                }
+                match handle_broadcast_message(broadcast_msg, socket, &last_message_was_final, &cipher).await {
                     Ok(is_final) => {
                         lag_errors = 0;
                         if is_final {
@@ -173,9 +197,22 @@ pub(super) async fn send_error_and_close(
 pub(super) async fn handle_client_message(
     msg_result: Option<Result<WsMessage, axum::Error>>,
     socket: &mut WebSocket,
-    _stream_key: &str,
+    stream: &JobStream,
+    job_id: u64,
+    cipher: &Aes256Gcm,
 ) -> Result<(), ()> {
     match msg_result {
+        Some(Ok(WsMessage::Text(base64_ciphertext))) => {
+            match decrypt_client_message(base64_ciphertext.as_str(), cipher) {
+                Ok(ClientMessage::Cancel { job_id: cancelled }) if cancelled == job_id => {
+                    info!("Client cancelled the job.");
+                    stream.cancel();
+                }
+                Ok(other) => warn!(message = ?other, "Ignoring client message for another job."),
+                Err(e) => warn!(error = %e, "Ignoring unreadable client message."),
+            }
+            Ok(())
+        }
         Some(Ok(WsMessage::Close(_))) => {
             info!("Close message received from client for stream key.");
             Err(())
@@ -196,44 +233,19 @@ pub(super) async fn handle_client_message(
             info!("Client socket closed for stream key.");
             Err(())
         }
-        // We ignore other text/binary messages from client during streaming
+        // We ignore binary messages from client during streaming
         _ => Ok(()),
     }
 }
 
 async fn handle_broadcast_message(
-    msg_result: Result<StreamedResponse, broadcast::error::RecvError>,
+    msg_result: Result<StreamFrame, broadcast::error::RecvError>,
     socket: &mut WebSocket,
     last_message_was_final: &Arc<AtomicBool>,
-    _stream_key: &str,
     cipher: &Aes256Gcm,
 ) -> Result<bool, bool> {
     match msg_result {
-        Ok(update) => {
-            let is_final = update.is_complete || update.error_status.is_some();
-            last_message_was_final.store(is_final, Ordering::Relaxed);
-
-            let json_update = serde_json::to_string(&update).unwrap();
-
-            // Encrypt the JSON payload
-            let encrypted_b64 = match encrypt_response(&json_update, cipher) {
-                Ok(s) => s,
-                Err(e) => {
-                    error!("Encryption failed for stream chunk: {}", e);
-                    return Err(false); // Terminate stream on crypto failure
-                }
-            };
-
-            if socket
-                .send(WsMessage::Text(encrypted_b64.into()))
-                .await
-                .is_err()
-            {
-                warn!("Failed to send update to client for stream key.");
-                return Err(false);
-            }
-            Ok(is_final)
-        }
+        Ok(frame) => send_frame(&frame, socket, last_message_was_final, cipher).await,
         Err(broadcast::error::RecvError::Closed) => {
             info!("Broadcast channel closed for stream key.");
             if !last_message_was_final.load(Ordering::Relaxed) {
@@ -252,6 +264,54 @@ async fn handle_broadcast_message(
     }
 }
 
+/// Encrypts and sends one frame. Returns whether it was the final one.
+async fn send_frame(
+    frame: &StreamFrame,
+    socket: &mut WebSocket,
+    last_message_was_final: &Arc<AtomicBool>,
+    cipher: &Aes256Gcm,
+) -> Result<bool, bool> {
+    let is_final = frame.is_final();
+    last_message_was_final.store(is_final, Ordering::Relaxed);
+
+    let json_update = serde_json::to_string(frame).unwrap();
+
+    // Encrypt the JSON payload
+    let encrypted_b64 = match encrypt_response(&json_update, cipher) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Encryption failed for stream chunk: {}", e);
+            return Err(false); // Terminate stream on crypto failure
+        }
+    };
+
+    if socket
+        .send(WsMessage::Text(encrypted_b64.into()))
+        .await
+        .is_err()
+    {
+        warn!("Failed to send update to client for stream key.");
+        return Err(false);
+    }
+    Ok(is_final)
+}
+
+/// Decrypts a base64 `[nonce][ciphertext]` client message sealed with the chat key.
+fn decrypt_client_message(
+    base64_ciphertext: &str,
+    cipher: &Aes256Gcm,
+) -> anyhow::Result<ClientMessage> {
+    let payload = STANDARD.decode(base64_ciphertext)?;
+    if payload.len() < GCM_NONCE_SIZE {
+        return Err(anyhow::anyhow!("Client message too short for IV"));
+    }
+    let (nonce_bytes, ciphertext) = payload.split_at(GCM_NONCE_SIZE);
+    let json = cipher
+        .decrypt(Nonce::from_slice(nonce_bytes), ciphertext)
+        .map_err(|e| anyhow::anyhow!("Decryption error: {}", e))?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
 fn encrypt_response(json: &str, cipher: &Aes256Gcm) -> anyhow::Result<String> {
     let mut nonce_bytes = [0u8; GCM_NONCE_SIZE];
     rand::thread_rng().fill_bytes(&mut nonce_bytes);
@@ -269,3 +329,22 @@ fn encrypt_response(json: &str, cipher: &Aes256Gcm) -> anyhow::Result<String> {
 
     Ok(STANDARD.encode(final_payload))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt_cancel_message() {
+        let cipher = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(&[7u8; 32]));
+        let sealed = encrypt_response(r#"{"type":"cancel","jobId":"42"}"#, &cipher).unwrap();
+        assert_eq!(
+            decrypt_client_message(&sealed, &cipher).unwrap(),
+            ClientMessage::Cancel { job_id: 42 }
+        );
+
+        // A message sealed with another key is rejected.
+        let other = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(&[8u8; 32]));
+        assert!(decrypt_client_message(&sealed, &other).is_err());
+    }
+}
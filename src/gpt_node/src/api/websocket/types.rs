@@ -27,4 +27,24 @@ pub struct ConversationRequest {
     #[serde(deserialize_with = "deserialize_u64_from_string_or_number")]
     pub job_id: u64,
     pub user_canister_id: String,
+    /// Last sequence number the client received. Set when reconnecting, to resume the
+    /// job's stream instead of starting the job.
+    #[serde(default)]
+    pub last_seq: Option<u64>,
+}
+
+/// Control message a client sends on an open stream, encrypted with the chat key.
+/// Only holders of the chat key can produce one, which is what authenticates it.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ClientMessage {
+    /// Stops the job's generation, keeping the text produced so far.
+    Cancel {
+        #[serde(deserialize_with = "deserialize_u64_from_string_or_number")]
+        job_id: u64,
+    },
 }
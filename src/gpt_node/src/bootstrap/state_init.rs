@@ -1,7 +1,9 @@
 use crate::{
     Args,
+    clients::ai_provider::{backend::provider_targets, tokenizer::TokenCounter},
     core::state::{AppState, SharedState},
     core::metrics::Metrics,
+    core::sensitive::SecretString,
 };
 use age::Decryptor;
 use age::x25519::Identity as X25519Identity;
@@ -16,10 +18,8 @@ use std::{
     io::Read,
     iter,
     num::NonZeroU32,
-    sync::{
-        Arc,
-        atomic::{AtomicBool, AtomicU64},
-    },
+    path::Path,
+    sync::{Arc, atomic::AtomicBool},
     time::SystemTime,
 };
 use tokio::sync::{Mutex, Semaphore};
@@ -51,42 +51,46 @@ pub(super) fn initialize_shared_state(
     );
 
     info!("Decrypting Provider API Key...");
-    let encrypted_key_b64 = &node_config.encrypted_api_key;
-    let encrypted_key_bytes = STANDARD
-        .decode(encrypted_key_b64)
-        .map_err(|e| NodeError::Configuration(format!("Failed to base64-decode API key: {}", e)))?;
-
-    let decryptor = Decryptor::new(&encrypted_key_bytes[..])
-        .map_err(|e| NodeError::Configuration(format!("Failed to initialize decryptor: {}", e)))?;
-
-    let mut decrypted_reader = decryptor
-        .decrypt(iter::once(&host_x25519_identity as &dyn age::Identity))
-        .map_err(|e| {
-            NodeError::Configuration(format!(
-                "Failed to decrypt API key: {}. The node attempted to decrypt using Host Identity: {}. Ensure this matches the identity configured in the Index.",
-                e, host_identity_public
-            ))
-        })?;
-
-    let mut api_key = String::new();
-    decrypted_reader.read_to_string(&mut api_key).map_err(|e| {
-        NodeError::Configuration(format!("Failed to read decrypted key into string: {}", e))
-    })?;
-
+    let api_key = decrypt_api_key(
+        &node_config.encrypted_api_key,
+        &host_x25519_identity,
+        &host_identity_public,
+    )?;
     if api_key.is_empty() {
         return Err(NodeError::Configuration(
             "Decrypted API key is empty.".to_string(),
         ));
     }
-
     info!("Successfully decrypted Provider API Key.");
 
+    // An empty entry means the node has no key for that fallback backend.
+    let fallback_keys = node_config
+        .encrypted_fallback_api_keys
+        .iter()
+        .flatten()
+        .map(|encrypted| {
+            if encrypted.is_empty() {
+                Ok(SecretString::default())
+            } else {
+                decrypt_api_key(encrypted, &host_x25519_identity, &host_identity_public)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
     let provider_config = OpenAIConfig::new()
-        .with_api_key(api_key)
+        .with_api_key(api_key.expose())
         .with_api_base(endpoint);
 
     let openai_client = OpenAIClient::with_config(provider_config);
 
+    let provider_targets = provider_targets(&model_details, api_key, fallback_keys);
+    info!(
+        backends = provider_targets.len(),
+        "Provider backends configured."
+    );
+
+    let token_counter = TokenCounter::for_model(&model_details, Path::new(&args.tokenizer_dir));
+
     let rate_limiter = args.rpm.map(|rpm| {
         Arc::new(RateLimiter::direct(Quota::per_minute(
             NonZeroU32::new(rpm).unwrap_or_else(|| NonZeroU32::new(1).unwrap()),
@@ -94,18 +98,13 @@ pub(super) fn initialize_shared_state(
     });
     let request_semaphore = Arc::new(Semaphore::new(args.concurrency.unwrap_or(100000) as usize));
 
-    let metrics = Arc::new(Metrics {
-        requests_total: AtomicU64::new(0),
-        requests_succeeded: AtomicU64::new(0),
-        requests_failed: AtomicU64::new(0),
-        tokens_processed: AtomicU64::new(0),
-        current_active_requests: AtomicU64::new(0),
-        avg_response_time_ms: AtomicU64::new(0),
-        total_response_time_ms: AtomicU64::new(0),
-        peak_concurrent_requests: AtomicU64::new(0),
-        rpm_limit: AtomicU64::new(args.rpm.unwrap_or(0) as u64),
-        concurrency_limit: AtomicU64::new(args.concurrency.unwrap_or(0) as u64),
-    });
+    let metrics = Arc::new(Metrics::new(
+        &node_config.model_id,
+        &model_details.provider,
+        args.rpm.unwrap_or(0) as u64,
+        args.concurrency.unwrap_or(0) as u64,
+    ));
+    Metrics::install(metrics.clone());
 
     Ok(Arc::new(AppState {
         node_id,
@@ -119,12 +118,45 @@ pub(super) fn initialize_shared_state(
         node_x25519_identity,
         node_public_key,
         openai_client,
+        provider_http_client: reqwest::Client::new(),
+        provider_targets,
+        token_counter,
         agent,
         request_semaphore,
         rate_limiter,
         job_streams: Arc::new(Mutex::new(HashMap::new())),
+        retired_streams: Arc::new(Mutex::new(HashMap::new())),
         metrics,
         shutdown: Arc::new(AtomicBool::new(false)),
         is_draining: Arc::new(AtomicBool::new(false)),
     }))
 }
+
+/// Decrypts a base64 age ciphertext addressed to the host identity.
+fn decrypt_api_key(
+    encrypted_b64: &str,
+    host_identity: &X25519Identity,
+    host_identity_public: &age::x25519::Recipient,
+) -> Result<SecretString, NodeError> {
+    let encrypted_key_bytes = STANDARD
+        .decode(encrypted_b64)
+        .map_err(|e| NodeError::Configuration(format!("Failed to base64-decode API key: {}", e)))?;
+
+    let decryptor = Decryptor::new(&encrypted_key_bytes[..])
+        .map_err(|e| NodeError::Configuration(format!("Failed to initialize decryptor: {}", e)))?;
+
+    let mut decrypted_reader = decryptor
+        .decrypt(iter::once(host_identity as &dyn age::Identity))
+        .map_err(|e| {
+            NodeError::Configuration(format!(
+                "Failed to decrypt API key: {}. The node attempted to decrypt using Host Identity: {}. Ensure this matches the identity configured in the Index.",
+                e, host_identity_public
+            ))
+        })?;
+
+    let mut api_key = String::new();
+    decrypted_reader.read_to_string(&mut api_key).map_err(|e| {
+        NodeError::Configuration(format!("Failed to read decrypted key into string: {}", e))
+    })?;
+    Ok(SecretString::new(api_key))
+}
@@ -0,0 +1,665 @@
+//! Adapter for the native Anthropic Messages API.
+//!
+//! ## Differences from OpenAI
+//! - **Endpoint**: `POST {endpoint}/messages` (e.g. `https://api.anthropic.com/v1/messages`)
+//! - **Auth**: `x-api-key` plus a pinned `anthropic-version` header
+//! - **System prompt**: top-level `system` field rather than a message
+//! - **Messages**: content blocks; tool calls are `tool_use` blocks on assistant turns and
+//!   tool outputs are `tool_result` blocks on user turns
+//! - **Token Limit**: `max_tokens` (required)
+//! - **Reasoning**: `thinking` with a token budget; temperature must be left unset
+//! - **Structured output**: no schema option; the schema is added to the system prompt
+//! - **Streaming**: typed SSE events (`message_start`, `content_block_start`,
+//!   `content_block_delta`, `content_block_stop`, `message_delta`, `message_stop`,
+//!   `ping`, `error`); usage is split between `message_start` and `message_delta`
+
+use super::{
+    ChatRequest, ProviderAdapter, ProviderHttpRequest, StreamDelta, StreamParser, endpoint_url,
+    response_format_instructions, tool_call_as_text, tool_result_as_text,
+};
+use crate::{
+    clients::ai_provider::{
+        extended_usage::{AnthropicUsageExtension, ExtendedTokenUsage, ProviderUsageExtension},
+        sse::SseEvent,
+    },
+    core::error::{NodeError, OpenAIError},
+    core::job::types::TokenizedMessage,
+    core::sensitive::SecretString,
+};
+use async_openai::error::ApiError;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use gpt_types::domain::message::TokenUsage;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Smallest thinking budget the API accepts.
+const MIN_THINKING_BUDGET_TOKENS: u32 = 1024;
+
+pub struct AnthropicAdapter;
+
+impl ProviderAdapter for AnthropicAdapter {
+    fn name(&self) -> &'static str {
+        "Anthropic"
+    }
+
+    fn build_request(
+        &self,
+        request: &ChatRequest,
+        endpoint: &str,
+        api_key: &SecretString,
+    ) -> Result<ProviderHttpRequest, NodeError> {
+        Ok(ProviderHttpRequest {
+            url: endpoint_url(endpoint, "/messages"),
+            headers: vec![
+                ("x-api-key", api_key.expose().to_string()),
+                ("anthropic-version", ANTHROPIC_VERSION.to_string()),
+            ],
+            body: build_messages_request(request),
+        })
+    }
+
+    fn stream_parser(&self) -> Box<dyn StreamParser> {
+        Box::new(AnthropicStreamParser::default())
+    }
+
+    fn map_error(&self, status: u16, body: &str) -> NodeError {
+        match serde_json::from_str::<ErrorEnvelope>(body) {
+            Ok(envelope) => api_error(Some(status), envelope.error),
+            Err(_) => api_error(
+                Some(status),
+                ErrorBody {
+                    r#type: None,
+                    message: format!("HTTP {}", status),
+                },
+            ),
+        }
+    }
+}
+
+fn build_messages_request(request: &ChatRequest) -> Value {
+    let has_tools = !request.tools.is_empty();
+
+    // History system messages cannot be interleaved; fold them into the system prompt.
+    let mut system = request.system_prompt.clone();
+    let mut messages: Vec<(&'static str, Vec<Value>)> = Vec::new();
+    for msg in &request.messages {
+        let (role, blocks) = match msg.role.as_str() {
+            "system" => {
+                if !system.is_empty() {
+                    system.push_str("\n\n");
+                }
+                system.push_str(&msg.content);
+                continue;
+            }
+            "user" => ("user", user_blocks(msg, request.supports_images)),
+            "assistant" => ("assistant", assistant_blocks(msg, has_tools)),
+            "tool" => match tool_result_block(msg, has_tools) {
+                Some(block) => ("user", vec![block]),
+                None => continue,
+            },
+            _ => continue,
+        };
+        if blocks.is_empty() {
+            continue;
+        }
+        // Roles must alternate, and parallel tool results belong in a single user turn.
+        match messages.last_mut() {
+            Some((last_role, last_blocks)) if *last_role == role => last_blocks.extend(blocks),
+            _ => messages.push((role, blocks)),
+        }
+    }
+
+    if let Some(format) = &request.response_format {
+        if !system.is_empty() {
+            system.push_str("\n\n");
+        }
+        system.push_str(&response_format_instructions(format));
+    }
+
+    let mut body = Map::new();
+    body.insert("model".into(), json!(request.model));
+    body.insert("max_tokens".into(), json!(request.max_completion_tokens));
+    body.insert("stream".into(), json!(true));
+    if !system.is_empty() {
+        body.insert("system".into(), json!(system));
+    }
+    body.insert(
+        "messages".into(),
+        Value::Array(
+            messages
+                .into_iter()
+                .map(|(role, content)| json!({ "role": role, "content": content }))
+                .collect(),
+        ),
+    );
+
+    if has_tools {
+        let tools: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.parameters,
+                })
+            })
+            .collect();
+        body.insert("tools".into(), Value::Array(tools));
+        body.insert("tool_choice".into(), json!({ "type": "auto" }));
+    }
+
+    match thinking_budget(request) {
+        Some(budget_tokens) => {
+            body.insert(
+                "thinking".into(),
+                json!({ "type": "enabled", "budget_tokens": budget_tokens }),
+            );
+        }
+        // Reasoning models run with their default temperature, as in the OpenAI adapter.
+        None if !request.is_reasoning_model => {
+            body.insert(
+                "temperature".into(),
+                json!(request.temperature.clamp(0.0, 1.0)),
+            );
+        }
+        None => {}
+    }
+
+    info!(
+        model = %request.model,
+        messages_count = body["messages"].as_array().map_or(0, |m| m.len()),
+        tools_count = request.tools.len(),
+        max_tokens = request.max_completion_tokens,
+        thinking = body.contains_key("thinking"),
+        "Final Anthropic request constructed."
+    );
+    Value::Object(body)
+}
+
+/// Thinking budget for the job's reasoning effort, if extended thinking applies.
+fn thinking_budget(request: &ChatRequest) -> Option<u32> {
+    if !request.is_reasoning_model {
+        return None;
+    }
+    // Continuing a tool loop with thinking enabled requires replaying the signed thinking
+    // blocks of the previous turn, which are not stored. Answer that turn without thinking.
+    if request.messages.last().is_some_and(|m| m.role == "tool") {
+        return None;
+    }
+    let budget = match request.reasoning_effort.as_deref()?.to_lowercase().as_str() {
+        "low" => MIN_THINKING_BUDGET_TOKENS,
+        "high" => 16_384,
+        _ => 4_096,
+    };
+    // The budget must leave room for the answer within max_tokens.
+    let budget = budget.min(request.max_completion_tokens / 2);
+    (budget >= MIN_THINKING_BUDGET_TOKENS).then_some(budget)
+}
+
+fn user_blocks(msg: &TokenizedMessage, supports_images: bool) -> Vec<Value> {
+    let mut blocks = Vec::new();
+    if supports_images {
+        for attachment in msg.attachments.iter().flatten() {
+            blocks.push(json!({
+                "type": "image",
+                "source": {
+                    "type": "base64",
+                    "media_type": attachment.mime_type,
+                    "data": STANDARD.encode(&attachment.data),
+                },
+            }));
+        }
+    }
+    if !msg.content.trim().is_empty() {
+        blocks.push(json!({ "type": "text", "text": msg.content }));
+    }
+    blocks
+}
+
+fn assistant_blocks(msg: &TokenizedMessage, has_tools: bool) -> Vec<Value> {
+    let mut blocks = Vec::new();
+    if !msg.content.trim().is_empty() {
+        blocks.push(json!({ "type": "text", "text": msg.content }));
+    }
+    for call in msg.tool_calls.iter().flatten() {
+        // The API rejects tool_use blocks when no tools are declared; keep the call as
+        // context instead, the way tool_choice "none" does for OpenAI providers.
+        if !has_tools {
+            blocks.push(json!({
+                "type": "text",
+                "text": tool_call_as_text(call),
+            }));
+            continue;
+        }
+        let input = serde_json::from_str::<Value>(&call.function.arguments)
+            .ok()
+            .filter(Value::is_object)
+            .unwrap_or_else(|| json!({}));
+        blocks.push(json!({
+            "type": "tool_use",
+            "id": call.id,
+            "name": call.function.name,
+            "input": input,
+        }));
+    }
+    blocks
+}
+
+fn tool_result_block(msg: &TokenizedMessage, has_tools: bool) -> Option<Value> {
+    let Some(tool_call_id) = &msg.tool_call_id else {
+        warn!("A 'tool' role message was found without a tool_call_id. Skipping.");
+        return None;
+    };
+    if !has_tools {
+        return Some(json!({
+            "type": "text",
+            "text": tool_result_as_text(&msg.content),
+        }));
+    }
+    Some(json!({
+        "type": "tool_result",
+        "tool_use_id": tool_call_id,
+        "content": msg.content,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart {
+        message: MessageStart,
+    },
+    ContentBlockStart {
+        index: u32,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: u32,
+        delta: BlockDelta,
+    },
+    ContentBlockStop {
+        index: u32,
+    },
+    MessageDelta {
+        delta: MessageDeltaBody,
+        #[serde(default)]
+        usage: Option<Usage>,
+    },
+    MessageStop,
+    Error {
+        error: ErrorBody,
+    },
+    /// `ping` and event types added after this adapter was written.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageStart {
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        #[serde(default)]
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+    },
+    Thinking {
+        #[serde(default)]
+        thinking: String,
+    },
+    /// `redacted_thinking` and other blocks that carry nothing for the user.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    /// `signature_delta` and future delta types.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageDeltaBody {
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Usage {
+    #[serde(default)]
+    input_tokens: Option<u32>,
+    #[serde(default)]
+    output_tokens: Option<u32>,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<u32>,
+    #[serde(default)]
+    cache_read_input_tokens: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    r#type: Option<String>,
+    message: String,
+}
+
+#[derive(Debug, Default)]
+struct AnthropicStreamParser {
+    usage: Option<Usage>,
+    stop_reason: Option<String>,
+    /// Tool blocks that have not received any input yet.
+    pending_tool_inputs: HashSet<u32>,
+}
+
+impl AnthropicStreamParser {
+    fn merge_usage(&mut self, update: Usage) {
+        let usage = self.usage.get_or_insert_with(Usage::default);
+        // `message_delta` counts are cumulative, so later values replace earlier ones.
+        usage.input_tokens = update.input_tokens.or(usage.input_tokens);
+        usage.output_tokens = update.output_tokens.or(usage.output_tokens);
+        usage.cache_creation_input_tokens = update
+            .cache_creation_input_tokens
+            .or(usage.cache_creation_input_tokens);
+        usage.cache_read_input_tokens = update
+            .cache_read_input_tokens
+            .or(usage.cache_read_input_tokens);
+    }
+}
+
+impl StreamParser for AnthropicStreamParser {
+    fn parse_event(&mut self, event: &SseEvent) -> Result<Vec<StreamDelta>, NodeError> {
+        let parsed: StreamEvent = serde_json::from_str(&event.data).map_err(|e| {
+            warn!(
+                json_error = %e,
+                content_len = event.data.len(),
+                "Anthropic sent an unexpected event payload (content redacted)"
+            );
+            OpenAIError::JSONDeserialize(e, event.data.clone())
+        })?;
+
+        let deltas = match parsed {
+            StreamEvent::MessageStart { message } => {
+                if let Some(usage) = message.usage {
+                    self.merge_usage(usage);
+                }
+                Vec::new()
+            }
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => match content_block {
+                ContentBlock::Text { text } if !text.is_empty() => vec![StreamDelta::Text(text)],
+                ContentBlock::Thinking { thinking } if !thinking.is_empty() => {
+                    vec![StreamDelta::Reasoning(thinking)]
+                }
+                ContentBlock::ToolUse { id, name } => {
+                    self.pending_tool_inputs.insert(index);
+                    vec![StreamDelta::ToolCall {
+                        index,
+                        id: Some(id),
+                        name: Some(name),
+                        arguments: None,
+                    }]
+                }
+                _ => Vec::new(),
+            },
+            StreamEvent::ContentBlockDelta { index, delta } => match delta {
+                BlockDelta::TextDelta { text } => vec![StreamDelta::Text(text)],
+                BlockDelta::ThinkingDelta { thinking } => vec![StreamDelta::Reasoning(thinking)],
+                BlockDelta::InputJsonDelta { partial_json } => {
+                    self.pending_tool_inputs.remove(&index);
+                    vec![StreamDelta::ToolCall {
+                        index,
+                        id: None,
+                        name: None,
+                        arguments: Some(partial_json),
+                    }]
+                }
+                BlockDelta::Other => Vec::new(),
+            },
+            StreamEvent::ContentBlockStop { index } => {
+                // Calls to tools without parameters stream no input at all.
+                if self.pending_tool_inputs.remove(&index) {
+                    vec![StreamDelta::ToolCall {
+                        index,
+                        id: None,
+                        name: None,
+                        arguments: Some("{}".to_string()),
+                    }]
+                } else {
+                    Vec::new()
+                }
+            }
+            StreamEvent::MessageDelta { delta, usage } => {
+                if let Some(usage) = usage {
+                    self.merge_usage(usage);
+                }
+                match delta.stop_reason {
+                    Some(reason) => {
+                        self.stop_reason = Some(reason.clone());
+                        vec![StreamDelta::Finish(reason)]
+                    }
+                    None => Vec::new(),
+                }
+            }
+            StreamEvent::MessageStop => vec![StreamDelta::Done],
+            StreamEvent::Error { error } => return Err(api_error(None, error)),
+            StreamEvent::Other => Vec::new(),
+        };
+        Ok(deltas)
+    }
+
+    fn usage(&self) -> Option<ExtendedTokenUsage> {
+        let usage = self.usage.as_ref()?;
+        // `input_tokens` excludes cached prompt tokens; report the full prompt size.
+        let prompt_tokens = usage.input_tokens.unwrap_or(0)
+            + usage.cache_creation_input_tokens.unwrap_or(0)
+            + usage.cache_read_input_tokens.unwrap_or(0);
+        let completion_tokens = usage.output_tokens.unwrap_or(0);
+        Some(ExtendedTokenUsage::with_extension(
+            TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+                served_by: None,
+            },
+            ProviderUsageExtension::Anthropic(AnthropicUsageExtension {
+                cache_creation_input_tokens: usage.cache_creation_input_tokens,
+                cache_read_input_tokens: usage.cache_read_input_tokens,
+                stop_reason: self.stop_reason.clone(),
+            }),
+        ))
+    }
+}
+
+/// Expresses an Anthropic error in the OpenAI error shape that severity, retry and
+/// message status mapping already understand, keyed by HTTP status code.
+fn api_error(status: Option<u16>, error: ErrorBody) -> NodeError {
+    let status = status.or(match error.r#type.as_deref() {
+        Some("invalid_request_error") => Some(400),
+        Some("authentication_error") => Some(401),
+        Some("billing_error") => Some(402),
+        Some("permission_error") => Some(403),
+        Some("not_found_error") => Some(404),
+        Some("request_too_large") => Some(413),
+        Some("rate_limit_error") => Some(429),
+        Some("api_error") => Some(500),
+        Some("overloaded_error") => Some(529),
+        _ => None,
+    });
+    let code = if error.message.contains("prompt is too long") {
+        Some("context_length_exceeded".to_string())
+    } else {
+        status.map(|s| s.to_string())
+    };
+    NodeError::Provider(OpenAIError::ApiError(ApiError {
+        message: error.message,
+        r#type: error.r#type,
+        param: None,
+        code,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ai_provider::adapter::ResponseFormat;
+    use crate::clients::ai_provider::adapter::test_support::{message, request, tool_loop};
+
+    #[test]
+    fn test_request_uses_system_field_and_tool_blocks() {
+        let mut messages = vec![message("system", "Use metric units.")];
+        messages.extend(tool_loop());
+        let body = build_messages_request(&request(messages, true));
+
+        assert_eq!(body["system"], "Be brief.\n\nUse metric units.");
+        assert_eq!(body["max_tokens"], 8192);
+        assert_eq!(body["tools"][0]["input_schema"]["type"], "object");
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["role"], "assistant");
+        assert_eq!(messages[1]["content"][0]["type"], "tool_use");
+        assert_eq!(messages[1]["content"][0]["input"]["city"], "Paris");
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][0]["type"], "tool_result");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "call_1");
+    }
+
+    #[test]
+    fn test_tool_history_is_flattened_without_tools() {
+        let body = build_messages_request(&request(tool_loop(), false));
+
+        assert!(body.get("tools").is_none());
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages[1]["content"][0]["type"], "text");
+        assert_eq!(messages[2]["content"][0]["type"], "text");
+    }
+
+    #[test]
+    fn test_response_schema_is_added_to_system_prompt() {
+        let mut req = request(vec![message("user", "Where?")], false);
+        req.response_format = Some(ResponseFormat {
+            name: "place".to_string(),
+            schema: json!({ "type": "object", "required": ["city"] }),
+        });
+        let body = build_messages_request(&req);
+
+        let system = body["system"].as_str().unwrap();
+        assert!(system.starts_with(&req.system_prompt));
+        assert!(system.contains("\"required\":[\"city\"]"));
+    }
+
+    #[test]
+    fn test_thinking_replaces_temperature_for_reasoning_models() {
+        let mut req = request(vec![message("user", "Hi")], false);
+        req.is_reasoning_model = true;
+        req.reasoning_effort = Some("high".to_string());
+        let body = build_messages_request(&req);
+        assert_eq!(body["thinking"]["budget_tokens"], 4096);
+        assert!(body.get("temperature").is_none());
+
+        // Continuing a tool loop runs without thinking.
+        let mut req = request(tool_loop(), true);
+        req.is_reasoning_model = true;
+        req.reasoning_effort = Some("high".to_string());
+        assert!(build_messages_request(&req).get("thinking").is_none());
+    }
+
+    #[test]
+    fn test_parser_maps_blocks_and_usage() {
+        let mut parser = AnthropicStreamParser::default();
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":12,"cache_read_input_tokens":4,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Hmm"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig"}}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"now","input":{}}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"ping"}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":30}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+        let deltas: Vec<StreamDelta> = events
+            .iter()
+            .flat_map(|data| {
+                parser
+                    .parse_event(&SseEvent {
+                        event: None,
+                        data: data.to_string(),
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        assert_eq!(deltas[0], StreamDelta::Reasoning("Hmm".to_string()));
+        assert_eq!(
+            deltas[2],
+            StreamDelta::ToolCall {
+                index: 1,
+                id: None,
+                name: None,
+                arguments: Some("{}".to_string()),
+            }
+        );
+        assert_eq!(deltas.last(), Some(&StreamDelta::Done));
+
+        let usage = parser.usage().unwrap();
+        assert_eq!(usage.base.prompt_tokens, 16);
+        assert_eq!(usage.base.completion_tokens, 30);
+        assert_eq!(usage.base.total_tokens, 46);
+    }
+
+    #[test]
+    fn test_errors_map_to_status_codes() {
+        let overloaded = AnthropicAdapter.map_error(
+            529,
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+        );
+        assert!(matches!(
+            overloaded,
+            NodeError::Provider(OpenAIError::ApiError(ref e)) if e.code.as_deref() == Some("529")
+        ));
+
+        let mut parser = AnthropicStreamParser::default();
+        let err = parser
+            .parse_event(&SseEvent {
+                event: Some("error".to_string()),
+                data: r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 300000 tokens"}}"#.to_string(),
+            })
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            NodeError::Provider(OpenAIError::ApiError(ref e))
+                if e.code.as_deref() == Some("context_length_exceeded")
+        ));
+    }
+}
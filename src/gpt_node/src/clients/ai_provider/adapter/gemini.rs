@@ -0,0 +1,773 @@
+//! Adapter for the native Google Gemini `generateContent` API.
+//!
+//! ## Differences from OpenAI
+//! - **Endpoint**: `POST {endpoint}/models/{model}:streamGenerateContent?alt=sse`
+//!   (e.g. `https://generativelanguage.googleapis.com/v1beta`)
+//! - **Auth**: `x-goog-api-key` header
+//! - **System prompt**: top-level `systemInstruction`
+//! - **Messages**: `contents` with `user`/`model` roles made of parts; images are
+//!   `inlineData`, tool calls are `functionCall` parts and tool outputs are
+//!   `functionResponse` parts matched to their call by function name
+//! - **Token Limit**: `generationConfig.maxOutputTokens`
+//! - **Reasoning**: `generationConfig.thinkingConfig` with a token budget
+//! - **Structured output**: `generationConfig.responseJsonSchema`; with function
+//!   declarations, which a JSON response type cannot be combined with, the schema goes
+//!   into the system instruction instead
+//! - **Streaming**: every SSE event is a complete `GenerateContentResponse`; there is
+//!   no terminal marker, and `usageMetadata` is cumulative
+//! - **Safety**: blocked prompts and responses arrive as `promptFeedback.blockReason`
+//!   or a `finishReason` rather than an HTTP error
+
+use super::{
+    ChatRequest, ProviderAdapter, ProviderHttpRequest, StreamDelta, StreamParser, endpoint_url,
+    response_format_instructions, tool_call_as_text, tool_result_as_text,
+};
+use crate::{
+    clients::ai_provider::{
+        extended_usage::{ExtendedTokenUsage, GeminiUsageExtension, ProviderUsageExtension},
+        sse::SseEvent,
+    },
+    core::error::{NodeError, OpenAIError},
+    core::job::types::TokenizedMessage,
+    core::sensitive::SecretString,
+};
+use async_openai::error::ApiError;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use gpt_types::domain::message::TokenUsage;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// Placeholder Google documents for replaying function calls whose thought signature
+/// was not kept; newer models reject signature-less calls in history otherwise.
+const SKIP_THOUGHT_SIGNATURE: &str = "skip_thought_signature_validator";
+
+pub struct GeminiAdapter;
+
+impl ProviderAdapter for GeminiAdapter {
+    fn name(&self) -> &'static str {
+        "Gemini"
+    }
+
+    fn build_request(
+        &self,
+        request: &ChatRequest,
+        endpoint: &str,
+        api_key: &SecretString,
+    ) -> Result<ProviderHttpRequest, NodeError> {
+        let model = request
+            .model
+            .strip_prefix("models/")
+            .unwrap_or(&request.model);
+        Ok(ProviderHttpRequest {
+            url: endpoint_url(
+                endpoint,
+                &format!("/models/{}:streamGenerateContent?alt=sse", model),
+            ),
+            headers: vec![("x-goog-api-key", api_key.expose().to_string())],
+            body: build_generate_content_request(request),
+        })
+    }
+
+    fn stream_parser(&self) -> Box<dyn StreamParser> {
+        Box::new(GeminiStreamParser::default())
+    }
+
+    fn map_error(&self, status: u16, body: &str) -> NodeError {
+        match serde_json::from_str::<ErrorEnvelope>(body) {
+            Ok(envelope) => api_error(Some(status), envelope.into_error()),
+            Err(_) => api_error(
+                Some(status),
+                ErrorBody {
+                    code: None,
+                    message: format!("HTTP {}", status),
+                    status: None,
+                },
+            ),
+        }
+    }
+}
+
+fn build_generate_content_request(request: &ChatRequest) -> Value {
+    let has_tools = !request.tools.is_empty();
+
+    // Function responses are matched to calls by name, so remember which call ids
+    // belong to which function.
+    let call_names: HashMap<&str, &str> = request
+        .messages
+        .iter()
+        .flat_map(|m| m.tool_calls.iter().flatten())
+        .map(|call| (call.id.as_str(), call.function.name.as_str()))
+        .collect();
+
+    let mut system = request.system_prompt.clone();
+    let mut contents: Vec<(&'static str, Vec<Value>)> = Vec::new();
+    for msg in &request.messages {
+        let (role, parts) = match msg.role.as_str() {
+            "system" => {
+                if !system.is_empty() {
+                    system.push_str("\n\n");
+                }
+                system.push_str(&msg.content);
+                continue;
+            }
+            "user" => ("user", user_parts(msg, request.supports_images)),
+            "assistant" => ("model", model_parts(msg, has_tools)),
+            "tool" => match function_response_part(msg, has_tools, &call_names) {
+                Some(part) => ("user", vec![part]),
+                None => continue,
+            },
+            _ => continue,
+        };
+        if parts.is_empty() {
+            continue;
+        }
+        // Parallel function responses must share one turn, directly after the calls.
+        match contents.last_mut() {
+            Some((last_role, last_parts)) if *last_role == role => last_parts.extend(parts),
+            _ => contents.push((role, parts)),
+        }
+    }
+
+    let native_response_format = request.response_format.as_ref().filter(|_| !has_tools);
+    if let Some(format) = request.response_format.as_ref().filter(|_| has_tools) {
+        if !system.is_empty() {
+            system.push_str("\n\n");
+        }
+        system.push_str(&response_format_instructions(format));
+    }
+
+    let mut body = Map::new();
+    if !system.is_empty() {
+        body.insert(
+            "systemInstruction".into(),
+            json!({ "parts": [{ "text": system }] }),
+        );
+    }
+    body.insert(
+        "contents".into(),
+        Value::Array(
+            contents
+                .into_iter()
+                .map(|(role, parts)| json!({ "role": role, "parts": parts }))
+                .collect(),
+        ),
+    );
+
+    if has_tools {
+        let declarations: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters,
+                })
+            })
+            .collect();
+        body.insert(
+            "tools".into(),
+            json!([{ "functionDeclarations": declarations }]),
+        );
+        body.insert(
+            "toolConfig".into(),
+            json!({ "functionCallingConfig": { "mode": "AUTO" } }),
+        );
+    }
+
+    let mut generation_config = Map::new();
+    generation_config.insert(
+        "maxOutputTokens".into(),
+        json!(request.max_completion_tokens),
+    );
+    if request.is_reasoning_model {
+        if let Some(budget) = thinking_budget(request) {
+            generation_config.insert(
+                "thinkingConfig".into(),
+                json!({ "thinkingBudget": budget, "includeThoughts": true }),
+            );
+        }
+    } else {
+        // Reasoning models run with their default temperature, as in the OpenAI adapter.
+        generation_config.insert(
+            "temperature".into(),
+            json!(request.temperature.clamp(0.0, 2.0)),
+        );
+    }
+    if let Some(format) = native_response_format {
+        generation_config.insert("responseMimeType".into(), json!("application/json"));
+        generation_config.insert("responseJsonSchema".into(), format.schema.clone());
+    }
+    body.insert("generationConfig".into(), Value::Object(generation_config));
+
+    info!(
+        model = %request.model,
+        contents_count = body["contents"].as_array().map_or(0, |c| c.len()),
+        tools_count = request.tools.len(),
+        max_output_tokens = request.max_completion_tokens,
+        thinking = body["generationConfig"].get("thinkingConfig").is_some(),
+        "Final Gemini request constructed."
+    );
+    Value::Object(body)
+}
+
+/// Thinking budget for the job's reasoning effort; without one the model decides.
+fn thinking_budget(request: &ChatRequest) -> Option<u32> {
+    let budget = match request.reasoning_effort.as_deref()?.to_lowercase().as_str() {
+        "low" => 1_024,
+        "high" => 24_576,
+        _ => 8_192,
+    };
+    // Thoughts count against maxOutputTokens; leave room for the answer.
+    Some(budget.min(request.max_completion_tokens / 2))
+}
+
+fn user_parts(msg: &TokenizedMessage, supports_images: bool) -> Vec<Value> {
+    let mut parts = Vec::new();
+    if supports_images {
+        for attachment in msg.attachments.iter().flatten() {
+            parts.push(json!({
+                "inlineData": {
+                    "mimeType": attachment.mime_type,
+                    "data": STANDARD.encode(&attachment.data),
+                },
+            }));
+        }
+    }
+    if !msg.content.trim().is_empty() {
+        parts.push(json!({ "text": msg.content }));
+    }
+    parts
+}
+
+fn model_parts(msg: &TokenizedMessage, has_tools: bool) -> Vec<Value> {
+    let mut parts = Vec::new();
+    if !msg.content.trim().is_empty() {
+        parts.push(json!({ "text": msg.content }));
+    }
+    for call in msg.tool_calls.iter().flatten() {
+        // Function calls are rejected when no functions are declared.
+        if !has_tools {
+            parts.push(json!({ "text": tool_call_as_text(call) }));
+            continue;
+        }
+        let args = serde_json::from_str::<Value>(&call.function.arguments)
+            .ok()
+            .filter(Value::is_object)
+            .unwrap_or_else(|| json!({}));
+        parts.push(json!({
+            "functionCall": { "name": call.function.name, "args": args },
+            "thoughtSignature": SKIP_THOUGHT_SIGNATURE,
+        }));
+    }
+    parts
+}
+
+fn function_response_part(
+    msg: &TokenizedMessage,
+    has_tools: bool,
+    call_names: &HashMap<&str, &str>,
+) -> Option<Value> {
+    let Some(tool_call_id) = &msg.tool_call_id else {
+        warn!("A 'tool' role message was found without a tool_call_id. Skipping.");
+        return None;
+    };
+    if !has_tools {
+        return Some(json!({ "text": tool_result_as_text(&msg.content) }));
+    }
+    let Some(name) = call_names.get(tool_call_id.as_str()) else {
+        warn!(
+            tool_call_id = %tool_call_id,
+            "Tool result does not match any earlier tool call. Skipping."
+        );
+        return None;
+    };
+    // `response` must be an object; wrap plain-text tool output.
+    let response = serde_json::from_str::<Value>(&msg.content)
+        .ok()
+        .filter(Value::is_object)
+        .unwrap_or_else(|| json!({ "content": msg.content }));
+    Some(json!({
+        "functionResponse": { "name": name, "response": response },
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    #[serde(default)]
+    prompt_feedback: Option<PromptFeedback>,
+    #[serde(default)]
+    usage_metadata: Option<UsageMetadata>,
+    /// Errors raised after the stream started arrive as an event.
+    #[serde(default)]
+    error: Option<ErrorBody>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    #[serde(default)]
+    content: Option<Content>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+    #[serde(default)]
+    finish_message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Content {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Part {
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    thought: bool,
+    #[serde(default)]
+    function_call: Option<FunctionCallPart>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionCallPart {
+    #[serde(default)]
+    id: Option<String>,
+    name: String,
+    #[serde(default)]
+    args: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    #[serde(default)]
+    block_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: Option<u32>,
+    #[serde(default)]
+    candidates_token_count: Option<u32>,
+    #[serde(default)]
+    thoughts_token_count: Option<u32>,
+    #[serde(default)]
+    cached_content_token_count: Option<u32>,
+    #[serde(default)]
+    total_token_count: Option<u32>,
+}
+
+/// Google APIs return a single error object, some endpoints wrapped in an array.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ErrorEnvelope {
+    Single { error: ErrorBody },
+    List(Vec<ErrorEnvelope>),
+}
+
+impl ErrorEnvelope {
+    fn into_error(self) -> ErrorBody {
+        match self {
+            ErrorEnvelope::Single { error } => error,
+            ErrorEnvelope::List(list) => list
+                .into_iter()
+                .next()
+                .map(ErrorEnvelope::into_error)
+                .unwrap_or(ErrorBody {
+                    code: None,
+                    message: "Empty error response".to_string(),
+                    status: None,
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    code: Option<u16>,
+    #[serde(default)]
+    message: String,
+    /// gRPC status name, e.g. `RESOURCE_EXHAUSTED`.
+    #[serde(default)]
+    status: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct GeminiStreamParser {
+    usage: Option<UsageMetadata>,
+    finish_reason: Option<String>,
+    /// Each function call part is a complete call, so every one gets its own index.
+    tool_call_count: u32,
+}
+
+impl StreamParser for GeminiStreamParser {
+    fn parse_event(&mut self, event: &SseEvent) -> Result<Vec<StreamDelta>, NodeError> {
+        let parsed: GenerateContentResponse = serde_json::from_str(&event.data).map_err(|e| {
+            warn!(
+                json_error = %e,
+                content_len = event.data.len(),
+                "Gemini sent an unexpected event payload (content redacted)"
+            );
+            OpenAIError::JSONDeserialize(e, event.data.clone())
+        })?;
+
+        if let Some(error) = parsed.error {
+            return Err(api_error(None, error));
+        }
+        // Counts are cumulative, so the latest report replaces earlier ones.
+        if let Some(usage) = parsed.usage_metadata {
+            self.usage = Some(usage);
+        }
+        if let Some(reason) = parsed.prompt_feedback.and_then(|f| f.block_reason) {
+            return Err(blocked_error(
+                format!("Prompt blocked by provider ({})", reason),
+                Some("content_policy_violation"),
+            ));
+        }
+
+        let mut deltas = Vec::new();
+        // Only one candidate is requested.
+        let Some(candidate) = parsed.candidates.into_iter().next() else {
+            return Ok(deltas);
+        };
+        for part in candidate.content.map(|c| c.parts).unwrap_or_default() {
+            if let Some(call) = part.function_call {
+                let index = self.tool_call_count;
+                self.tool_call_count += 1;
+                let arguments = call.args.unwrap_or_else(|| json!({})).to_string();
+                deltas.push(StreamDelta::ToolCall {
+                    index,
+                    id: Some(
+                        call.id
+                            .unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple())),
+                    ),
+                    name: Some(call.name),
+                    arguments: Some(arguments),
+                });
+            } else if let Some(text) = part.text.filter(|t| !t.is_empty()) {
+                deltas.push(if part.thought {
+                    StreamDelta::Reasoning(text)
+                } else {
+                    StreamDelta::Text(text)
+                });
+            }
+        }
+
+        if let Some(reason) = candidate.finish_reason {
+            if let Some(err) = finish_reason_error(&reason, candidate.finish_message) {
+                return Err(err);
+            }
+            if reason != "FINISH_REASON_UNSPECIFIED" {
+                let reason = reason.to_lowercase();
+                self.finish_reason = Some(reason.clone());
+                deltas.push(StreamDelta::Finish(reason));
+            }
+        }
+        Ok(deltas)
+    }
+
+    fn usage(&self) -> Option<ExtendedTokenUsage> {
+        let usage = self.usage.as_ref()?;
+        let prompt_tokens = usage.prompt_token_count.unwrap_or(0);
+        // `candidatesTokenCount` excludes thinking, which is billed as output.
+        let completion_tokens =
+            usage.candidates_token_count.unwrap_or(0) + usage.thoughts_token_count.unwrap_or(0);
+        Some(ExtendedTokenUsage::with_extension(
+            TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: usage
+                    .total_token_count
+                    .unwrap_or(prompt_tokens + completion_tokens),
+                served_by: None,
+            },
+            ProviderUsageExtension::Gemini(GeminiUsageExtension {
+                thoughts_token_count: usage.thoughts_token_count,
+                cached_content_token_count: usage.cached_content_token_count,
+                finish_reason: self.finish_reason.clone(),
+            }),
+        ))
+    }
+}
+
+/// Error for finish reasons that mean the response was withheld or unusable.
+/// `STOP` and `MAX_TOKENS` end the stream normally.
+fn finish_reason_error(reason: &str, finish_message: Option<String>) -> Option<NodeError> {
+    let code = match reason {
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => {
+            Some("content_policy_violation")
+        }
+        "MALFORMED_FUNCTION_CALL" | "UNEXPECTED_TOOL_CALL" | "LANGUAGE" => Some("400"),
+        // Unclassified stops surface as unknown provider errors.
+        "OTHER" => None,
+        _ => return None,
+    };
+    let message =
+        finish_message.unwrap_or_else(|| format!("Response stopped by provider ({})", reason));
+    Some(blocked_error(message, code))
+}
+
+fn blocked_error(message: String, code: Option<&str>) -> NodeError {
+    NodeError::Provider(OpenAIError::ApiError(ApiError {
+        message,
+        r#type: Some("blocked".to_string()),
+        param: None,
+        code: code.map(str::to_string),
+    }))
+}
+
+/// Expresses a Gemini error in the OpenAI error shape that severity, retry and
+/// message status mapping already understand, keyed by HTTP status code.
+fn api_error(status: Option<u16>, error: ErrorBody) -> NodeError {
+    let status = status.or(error.code).or(match error.status.as_deref() {
+        Some("INVALID_ARGUMENT") | Some("FAILED_PRECONDITION") => Some(400),
+        Some("UNAUTHENTICATED") => Some(401),
+        Some("PERMISSION_DENIED") => Some(403),
+        Some("NOT_FOUND") => Some(404),
+        Some("RESOURCE_EXHAUSTED") => Some(429),
+        Some("INTERNAL") => Some(500),
+        Some("UNAVAILABLE") => Some(503),
+        Some("DEADLINE_EXCEEDED") => Some(504),
+        _ => None,
+    });
+    // Gemini rejects bad keys and oversized prompts with a plain 400.
+    let code = if error.message.contains("API key not valid") {
+        Some("invalid_api_key".to_string())
+    } else if error
+        .message
+        .contains("exceeds the maximum number of tokens")
+    {
+        Some("context_length_exceeded".to_string())
+    } else {
+        status.map(|s| s.to_string())
+    };
+    NodeError::Provider(OpenAIError::ApiError(ApiError {
+        message: error.message,
+        r#type: error.status,
+        param: None,
+        code,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ai_provider::adapter::ResponseFormat;
+    use crate::clients::ai_provider::adapter::test_support::{message, request, tool_loop};
+    use gpt_types::domain::message::ImageAttachment;
+
+    fn parse_all(parser: &mut GeminiStreamParser, events: &[&str]) -> Vec<StreamDelta> {
+        events
+            .iter()
+            .flat_map(|data| {
+                parser
+                    .parse_event(&SseEvent {
+                        event: None,
+                        data: data.to_string(),
+                    })
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_request_maps_contents_images_and_function_parts() {
+        let mut user = message("user", "What is this?");
+        user.attachments = Some(vec![ImageAttachment {
+            mime_type: "image/png".to_string(),
+            data: vec![1, 2, 3],
+        }]);
+        let mut messages = vec![message("system", "Use metric units."), user];
+        messages.extend(tool_loop());
+        let req = request(messages, true);
+        let http = GeminiAdapter
+            .build_request(
+                &req,
+                "https://generativelanguage.googleapis.com/v1beta/",
+                &SecretString::new("key"),
+            )
+            .unwrap();
+        assert_eq!(
+            http.url,
+            "https://generativelanguage.googleapis.com/v1beta/models/test-model:streamGenerateContent?alt=sse"
+        );
+
+        let body = http.body;
+        assert_eq!(
+            body["systemInstruction"]["parts"][0]["text"],
+            "Be brief.\n\nUse metric units."
+        );
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 8192);
+        assert_eq!(
+            body["tools"][0]["functionDeclarations"][0]["name"],
+            "get_weather"
+        );
+
+        let contents = body["contents"].as_array().unwrap();
+        // Consecutive user messages share a turn.
+        assert_eq!(contents.len(), 3);
+        assert_eq!(
+            contents[0]["parts"][0]["inlineData"]["mimeType"],
+            "image/png"
+        );
+        assert_eq!(contents[0]["parts"][0]["inlineData"]["data"], "AQID");
+        assert_eq!(contents[1]["role"], "model");
+        assert_eq!(
+            contents[1]["parts"][0]["functionCall"]["args"]["city"],
+            "Paris"
+        );
+        assert_eq!(contents[2]["role"], "user");
+        let response = &contents[2]["parts"][0]["functionResponse"];
+        assert_eq!(response["name"], "get_weather");
+        assert_eq!(response["response"]["content"], "Sunny");
+    }
+
+    #[test]
+    fn test_tool_history_is_flattened_without_tools() {
+        let body = build_generate_content_request(&request(tool_loop(), false));
+
+        assert!(body.get("tools").is_none());
+        let contents = body["contents"].as_array().unwrap();
+        assert!(contents[1]["parts"][0]["text"].is_string());
+        assert!(contents[2]["parts"][0]["text"].is_string());
+    }
+
+    #[test]
+    fn test_thinking_replaces_temperature_for_reasoning_models() {
+        let mut req = request(vec![message("user", "Hi")], false);
+        assert_eq!(body_config(&req)["temperature"], json!(0.7f32));
+
+        req.is_reasoning_model = true;
+        req.reasoning_effort = Some("high".to_string());
+        let config = body_config(&req);
+        assert_eq!(config["thinkingConfig"]["thinkingBudget"], 4096);
+        assert!(config.get("temperature").is_none());
+    }
+
+    fn body_config(req: &ChatRequest) -> Value {
+        build_generate_content_request(req)["generationConfig"].clone()
+    }
+
+    #[test]
+    fn test_response_schema_is_native_unless_tools_are_declared() {
+        let schema = json!({ "type": "object", "required": ["city"] });
+        let format = ResponseFormat {
+            name: "place".to_string(),
+            schema: schema.clone(),
+        };
+        let mut req = request(vec![message("user", "Where?")], false);
+        req.response_format = Some(format.clone());
+        let config = body_config(&req);
+        assert_eq!(config["responseMimeType"], "application/json");
+        assert_eq!(config["responseJsonSchema"], schema);
+
+        let mut req = request(tool_loop(), true);
+        req.response_format = Some(format);
+        let body = build_generate_content_request(&req);
+        assert!(body["generationConfig"].get("responseMimeType").is_none());
+        let system = body["systemInstruction"]["parts"][0]["text"].as_str().unwrap();
+        assert!(system.contains("\"required\":[\"city\"]"));
+    }
+
+    #[test]
+    fn test_parser_maps_parts_and_usage() {
+        let mut parser = GeminiStreamParser::default();
+        let deltas = parse_all(
+            &mut parser,
+            &[
+                r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Plan","thought":true}]}}],"usageMetadata":{"promptTokenCount":10}}"#,
+                r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Checking."},{"functionCall":{"name":"now","args":{}}}]}}]}"#,
+                r#"{"candidates":[{"content":{"role":"model","parts":[{"functionCall":{"name":"get_weather","args":{"city":"Paris"}}}]},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":10,"candidatesTokenCount":7,"thoughtsTokenCount":5,"totalTokenCount":22}}"#,
+            ],
+        );
+
+        assert_eq!(deltas[0], StreamDelta::Reasoning("Plan".to_string()));
+        assert_eq!(deltas[1], StreamDelta::Text("Checking.".to_string()));
+        assert!(matches!(
+            &deltas[3],
+            StreamDelta::ToolCall { index: 1, id: Some(id), arguments: Some(args), .. }
+                if id.starts_with("call_") && args == "{\"city\":\"Paris\"}"
+        ));
+        assert_eq!(
+            deltas.last(),
+            Some(&StreamDelta::Finish("stop".to_string()))
+        );
+
+        let usage = parser.usage().unwrap();
+        assert_eq!(usage.base.prompt_tokens, 10);
+        assert_eq!(usage.base.completion_tokens, 12);
+        assert_eq!(usage.base.total_tokens, 22);
+    }
+
+    fn error_code(err: NodeError) -> Option<String> {
+        match err {
+            NodeError::Provider(OpenAIError::ApiError(e)) => e.code,
+            other => panic!("Expected an API error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_safety_blocks_and_finish_reasons_map_to_errors() {
+        let mut parser = GeminiStreamParser::default();
+        let blocked = parser.parse_event(&SseEvent {
+            event: None,
+            data: r#"{"promptFeedback":{"blockReason":"PROHIBITED_CONTENT"}}"#.to_string(),
+        });
+        assert_eq!(
+            error_code(blocked.unwrap_err()).as_deref(),
+            Some("content_policy_violation")
+        );
+
+        let stopped = parser.parse_event(&SseEvent {
+            event: None,
+            data: r#"{"candidates":[{"finishReason":"SAFETY"}]}"#.to_string(),
+        });
+        assert_eq!(
+            error_code(stopped.unwrap_err()).as_deref(),
+            Some("content_policy_violation")
+        );
+
+        let malformed = parser.parse_event(&SseEvent {
+            event: None,
+            data: r#"{"candidates":[{"finishReason":"MALFORMED_FUNCTION_CALL"}]}"#.to_string(),
+        });
+        assert_eq!(error_code(malformed.unwrap_err()).as_deref(), Some("400"));
+
+        let truncated = parse_all(
+            &mut parser,
+            &[
+                r#"{"candidates":[{"content":{"parts":[{"text":"Hi"}]},"finishReason":"MAX_TOKENS"}]}"#,
+            ],
+        );
+        assert_eq!(
+            truncated.last(),
+            Some(&StreamDelta::Finish("max_tokens".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_http_errors_map_to_codes() {
+        let bad_key = GeminiAdapter.map_error(
+            400,
+            r#"{"error":{"code":400,"message":"API key not valid. Please pass a valid API key.","status":"INVALID_ARGUMENT"}}"#,
+        );
+        assert_eq!(error_code(bad_key).as_deref(), Some("invalid_api_key"));
+
+        let exhausted = GeminiAdapter.map_error(
+            429,
+            r#"[{"error":{"code":429,"message":"Quota exceeded","status":"RESOURCE_EXHAUSTED"}}]"#,
+        );
+        assert_eq!(error_code(exhausted).as_deref(), Some("429"));
+    }
+}
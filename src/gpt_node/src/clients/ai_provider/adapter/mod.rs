@@ -0,0 +1,220 @@
+//! Provider adapters.
+//!
+//! An adapter translates the provider-neutral [`ChatRequest`] into the provider's HTTP
+//! request, parses its SSE stream into [`StreamDelta`]s, extracts usage and maps error
+//! responses to `NodeError`s. The transport itself (sending, retries, SSE decoding and
+//! broadcasting) is shared and lives in `stream_handler`.
+
+mod anthropic;
+mod gemini;
+mod openai;
+
+pub use anthropic::AnthropicAdapter;
+pub use gemini::GeminiAdapter;
+pub use openai::OpenAICompatibleAdapter;
+
+use super::extended_usage::ExtendedTokenUsage;
+use super::provider::Provider;
+use super::sse::SseEvent;
+use crate::core::error::NodeError;
+use crate::core::job::types::{TokenizedMessage, ToolCall};
+use crate::core::sensitive::SecretString;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// A chat request after context truncation, independent of any provider API.
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub model: String,
+    pub system_prompt: String,
+    pub messages: Vec<TokenizedMessage>,
+    pub tools: Vec<ToolDefinition>,
+    pub max_completion_tokens: u32,
+    pub temperature: f32,
+    pub reasoning_effort: Option<String>,
+    pub is_reasoning_model: bool,
+    pub supports_images: bool,
+    /// Job-level `extra_body_json` fields, merged over the adapter's request body.
+    pub extra_fields: HashMap<String, Value>,
+    /// JSON Schema the response text should match.
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// A job's response schema, parsed.
+#[derive(Debug, Clone)]
+pub struct ResponseFormat {
+    pub name: String,
+    pub schema: Value,
+}
+
+/// A tool offered to the model, with its JSON schema already validated.
+#[derive(Debug, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+/// Everything needed to open a provider stream.
+#[derive(Debug)]
+pub struct ProviderHttpRequest {
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Value,
+}
+
+/// A provider-neutral piece of a streamed response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamDelta {
+    Text(String),
+    /// Chain-of-thought text, tracked for metrics but never returned to the user.
+    Reasoning(String),
+    /// A fragment of the tool call at `index`; fields present are appended.
+    ToolCall {
+        index: u32,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: Option<String>,
+    },
+    Finish(String),
+    /// The provider signalled the end of the stream.
+    Done,
+}
+
+pub trait ProviderAdapter: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn build_request(
+        &self,
+        request: &ChatRequest,
+        endpoint: &str,
+        api_key: &SecretString,
+    ) -> Result<ProviderHttpRequest, NodeError>;
+
+    /// Returns a fresh parser for one response stream.
+    fn stream_parser(&self) -> Box<dyn StreamParser>;
+
+    /// Maps a non-success HTTP response to an error.
+    fn map_error(&self, status: u16, body: &str) -> NodeError;
+}
+
+/// Stateful parser for a single response stream.
+pub trait StreamParser: Send {
+    fn parse_event(&mut self, event: &SseEvent) -> Result<Vec<StreamDelta>, NodeError>;
+
+    /// Usage reported so far, if the provider sent any.
+    fn usage(&self) -> Option<ExtendedTokenUsage>;
+}
+
+/// Picks the adapter for a provider backend from its provider name, falling back to the
+/// endpoint URL for models registered before provider names were meaningful.
+pub fn adapter_for_backend(
+    provider_name: &str,
+    provider_endpoint: &str,
+) -> Box<dyn ProviderAdapter> {
+    let provider = provider_name.to_lowercase();
+    let endpoint = provider_endpoint.to_lowercase();
+    let is_anthropic = provider == "anthropic" || endpoint.contains("api.anthropic.com");
+    // Gemini models registered against the OpenAI-compatible shim keep using it.
+    let is_gemini = (provider == "gemini"
+        || provider == "google"
+        || endpoint.contains("generativelanguage.googleapis.com"))
+        && !endpoint.trim_end_matches('/').ends_with("/openai");
+    if is_anthropic {
+        Box::new(AnthropicAdapter)
+    } else if is_gemini {
+        Box::new(GeminiAdapter)
+    } else {
+        Box::new(OpenAICompatibleAdapter::new(Provider::from_model(
+            provider_name,
+            provider_endpoint,
+        )))
+    }
+}
+
+fn endpoint_url(endpoint: &str, path: &str) -> String {
+    format!("{}{}", endpoint.trim_end_matches('/'), path)
+}
+
+/// Renders a historical tool call as text for requests that declare no tools, which
+/// native APIs reject tool call blocks in.
+fn tool_call_as_text(call: &ToolCall) -> String {
+    format!(
+        "[Called tool {} with {}]",
+        call.function.name, call.function.arguments
+    )
+}
+
+fn tool_result_as_text(content: &str) -> String {
+    format!("[Tool result: {}]", content)
+}
+
+/// System prompt addition for providers without a native schema option.
+fn response_format_instructions(format: &ResponseFormat) -> String {
+    format!(
+        "Respond only with a JSON value that matches the following JSON Schema, without \
+         code fences or any other text.\n{}",
+        format.schema
+    )
+}
+
+/// Requests shared by the adapter and transport tests.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::{ChatRequest, ToolDefinition};
+    use crate::core::job::types::{FunctionCall, TokenizedMessage, ToolCall};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    pub fn message(role: &str, content: &str) -> TokenizedMessage {
+        TokenizedMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            token_count: 0,
+            attachments: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    /// A request for `test-model`, offering a `get_weather` tool if `with_tools`.
+    pub fn request(messages: Vec<TokenizedMessage>, with_tools: bool) -> ChatRequest {
+        ChatRequest {
+            model: "test-model".to_string(),
+            system_prompt: "Be brief.".to_string(),
+            messages,
+            tools: if with_tools {
+                vec![ToolDefinition {
+                    name: "get_weather".to_string(),
+                    description: "Weather lookup".to_string(),
+                    parameters: json!({ "type": "object" }),
+                }]
+            } else {
+                Vec::new()
+            },
+            max_completion_tokens: 8192,
+            temperature: 0.7,
+            reasoning_effort: None,
+            is_reasoning_model: false,
+            supports_images: true,
+            extra_fields: HashMap::new(),
+            response_format: None,
+        }
+    }
+
+    /// A question, the assistant's `get_weather` call and its result.
+    pub fn tool_loop() -> Vec<TokenizedMessage> {
+        let mut assistant = message("assistant", "");
+        assistant.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            _type: "function".to_string(),
+            function: FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: "{\"city\":\"Paris\"}".to_string(),
+            },
+        }]);
+        let mut result = message("tool", "Sunny");
+        result.tool_call_id = Some("call_1".to_string());
+        vec![message("user", "Weather in Paris?"), assistant, result]
+    }
+}
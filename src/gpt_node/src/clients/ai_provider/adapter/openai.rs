@@ -0,0 +1,430 @@
+//! Adapter for OpenAI-compatible chat completions APIs.
+//!
+//! Covers every provider in [`Provider`]; their differences are handled by
+//! `Provider::get_request_config` and `usage_parser`.
+
+use super::{
+    ChatRequest, ProviderAdapter, ProviderHttpRequest, StreamDelta, StreamParser, endpoint_url,
+};
+use crate::{
+    clients::ai_provider::{
+        extended_usage::ExtendedTokenUsage,
+        provider::Provider,
+        resilient_types::{ProviderErrorBody, ResilientChatCompletionStreamResponse},
+        sse::SseEvent,
+        types::ExtendedChatCompletionRequest,
+        usage_parser::{extract_reasoning_content, parse_extended_usage_with_reasoning},
+    },
+    core::error::{NodeError, OpenAIError},
+    core::job::types::TokenizedMessage,
+    core::sensitive::SecretString,
+};
+use async_openai::{
+    error::ApiError,
+    types::chat::{
+        ChatCompletionMessageToolCall, ChatCompletionMessageToolCalls,
+        ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+        ChatCompletionRequestMessageContentPartImage,
+        ChatCompletionRequestMessageContentPartImageArgs,
+        ChatCompletionRequestMessageContentPartTextArgs, ChatCompletionRequestSystemMessageArgs,
+        ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs,
+        ChatCompletionStreamOptions, ChatCompletionTool, ChatCompletionToolChoiceOption,
+        ChatCompletionTools, CreateChatCompletionRequestArgs, FunctionCall, FunctionObjectArgs,
+        ImageDetail, ImageUrlArgs, ReasoningEffort, ResponseFormat, ResponseFormatJsonSchema,
+        ToolChoiceOptions,
+    },
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use gpt_types::domain::message::ImageAttachment;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+pub struct OpenAICompatibleAdapter {
+    provider: Provider,
+}
+
+impl OpenAICompatibleAdapter {
+    pub fn new(provider: Provider) -> Self {
+        Self { provider }
+    }
+}
+
+impl ProviderAdapter for OpenAICompatibleAdapter {
+    fn name(&self) -> &'static str {
+        self.provider.name()
+    }
+
+    fn build_request(
+        &self,
+        request: &ChatRequest,
+        endpoint: &str,
+        api_key: &SecretString,
+    ) -> Result<ProviderHttpRequest, NodeError> {
+        let body = build_chat_completion_request(self.provider, request)?;
+        let body = serde_json::to_value(&body).map_err(|e| {
+            NodeError::Other(format!("Failed to serialize provider request: {}", e))
+        })?;
+        Ok(ProviderHttpRequest {
+            url: endpoint_url(endpoint, "/chat/completions"),
+            headers: vec![("authorization", format!("Bearer {}", api_key.expose()))],
+            body,
+        })
+    }
+
+    fn stream_parser(&self) -> Box<dyn StreamParser> {
+        Box::new(OpenAIStreamParser {
+            provider: self.provider,
+            last_response_with_usage: None,
+            reasoning_len: 0,
+        })
+    }
+
+    fn map_error(&self, status: u16, body: &str) -> NodeError {
+        let (message, r#type, code) = match serde_json::from_str::<ProviderErrorBody>(body) {
+            Ok(ProviderErrorBody::Standard { error }) => {
+                let code = error.code.map(|code| match code {
+                    Value::String(s) => s,
+                    other => other.to_string(),
+                });
+                (error.message, error.r#type, code)
+            }
+            Ok(ProviderErrorBody::FastAPI { detail }) => {
+                let msgs: Vec<String> = detail.into_iter().map(|d| d.msg).collect();
+                (msgs.join("; "), None, None)
+            }
+            Ok(ProviderErrorBody::Simple { detail }) => (detail, None, None),
+            Err(_) => (format!("HTTP {}", status), None, None),
+        };
+        NodeError::Provider(OpenAIError::ApiError(ApiError {
+            message,
+            r#type,
+            param: None,
+            // The HTTP status stands in for providers that omit an error code.
+            code: Some(code.unwrap_or_else(|| status.to_string())),
+        }))
+    }
+}
+
+struct OpenAIStreamParser {
+    provider: Provider,
+    last_response_with_usage: Option<ResilientChatCompletionStreamResponse>,
+    reasoning_len: usize,
+}
+
+impl StreamParser for OpenAIStreamParser {
+    fn parse_event(&mut self, event: &SseEvent) -> Result<Vec<StreamDelta>, NodeError> {
+        if event.data.trim() == "[DONE]" {
+            return Ok(vec![StreamDelta::Done]);
+        }
+        let response: ResilientChatCompletionStreamResponse = serde_json::from_str(&event.data)
+            .map_err(|e| {
+                warn!(
+                    provider = %self.provider.name(),
+                    json_error = %e,
+                    content_len = event.data.len(),
+                    "Provider sent invalid JSON or error payload (content redacted)"
+                );
+                OpenAIError::JSONDeserialize(e, event.data.clone())
+            })?;
+
+        let mut deltas = Vec::new();
+
+        // Extract reasoning content (DeepInfra/Kimi thinking models)
+        if let Some(reasoning) = extract_reasoning_content(&response) {
+            self.reasoning_len += reasoning.len();
+            deltas.push(StreamDelta::Reasoning(reasoning));
+        }
+
+        // service_tier is captured but ignored (tolerated via ResilientChatCompletionStreamResponse)
+
+        for choice in &response.choices {
+            if let Some(content) = choice.delta.content.as_ref().filter(|c| !c.is_empty()) {
+                deltas.push(StreamDelta::Text(content.clone()));
+            }
+            for tc_delta in choice.delta.tool_calls.iter().flatten() {
+                let function = tc_delta.function.as_ref();
+                deltas.push(StreamDelta::ToolCall {
+                    index: tc_delta.index,
+                    id: tc_delta.id.clone(),
+                    name: function.and_then(|f| f.name.clone()),
+                    arguments: function.and_then(|f| f.arguments.clone()),
+                });
+            }
+            if let Some(reason) = &choice.finish_reason {
+                deltas.push(StreamDelta::Finish(reason.clone()));
+            }
+        }
+
+        // Store response if it contains usage data (for final extraction)
+        if response.usage.is_some() {
+            self.last_response_with_usage = Some(response);
+        }
+        Ok(deltas)
+    }
+
+    fn usage(&self) -> Option<ExtendedTokenUsage> {
+        self.last_response_with_usage.as_ref().and_then(|response| {
+            parse_extended_usage_with_reasoning(response, self.provider, self.reasoning_len)
+        })
+    }
+}
+
+fn build_chat_completion_request(
+    provider: Provider,
+    request: &ChatRequest,
+) -> Result<ExtendedChatCompletionRequest, NodeError> {
+    let is_reasoning_model = request.is_reasoning_model;
+
+    // Get provider-specific configuration
+    let provider_config = provider.get_request_config(
+        is_reasoning_model,
+        request.reasoning_effort.as_deref(),
+        request.max_completion_tokens,
+    );
+
+    let mut all_messages = Vec::new();
+
+    if !request.system_prompt.is_empty() {
+        let system_msg = ChatCompletionRequestSystemMessageArgs::default()
+            .content(request.system_prompt.clone())
+            .build()?;
+        all_messages.push(ChatCompletionRequestMessage::System(system_msg));
+    }
+
+    for msg in &request.messages {
+        let chat_msg = match msg.role.as_str() {
+            "user" => Some(build_user_message(msg, request.supports_images)?),
+            "assistant" => build_assistant_message(msg)?,
+            "tool" => build_tool_message(msg)?,
+            "system" => {
+                let system_msg = ChatCompletionRequestSystemMessageArgs::default()
+                    .content(msg.content.clone())
+                    .build()?;
+                Some(ChatCompletionRequestMessage::System(system_msg))
+            }
+            _ => None,
+        };
+        if let Some(msg) = chat_msg {
+            all_messages.push(msg);
+        }
+    }
+
+    let mut openai_tools = Vec::new();
+    for tool in &request.tools {
+        let function_object = FunctionObjectArgs::default()
+            .name(&tool.name)
+            .description(tool.description.clone())
+            .parameters(tool.parameters.clone())
+            // Use strict mode if possible/desired, defaulted false or derived from tool config if expanded.
+            // Assuming defaults for now.
+            .build()?;
+
+        openai_tools.push(ChatCompletionTool {
+            function: function_object,
+        });
+    }
+
+    let mut req_builder = CreateChatCompletionRequestArgs::default();
+    req_builder
+        .model(request.model.clone())
+        .messages(all_messages)
+        .stream(true);
+
+    // Provider-specific stream options (Mistral doesn't support stream_options)
+    if provider_config.supports_stream_options {
+        req_builder.stream_options(ChatCompletionStreamOptions {
+            include_usage: Some(true),
+            include_obfuscation: None,
+        });
+    }
+
+    // Handle reasoning effort - only OpenAI uses the builder method directly
+    // Mistral and Cerebras use extra_fields (handled by provider_config)
+    if is_reasoning_model
+        && provider == Provider::OpenAI
+        && let Some(effort_str) = &request.reasoning_effort
+    {
+        let effort = match effort_str.to_lowercase().as_str() {
+            "low" => ReasoningEffort::Low,
+            "medium" => ReasoningEffort::Medium,
+            "high" => ReasoningEffort::High,
+            _ => ReasoningEffort::Medium, // Default
+        };
+        req_builder.reasoning_effort(effort);
+    }
+
+    // Set token limit - only if NOT using max_tokens via extra_fields (Mistral)
+    // For Mistral, max_tokens is added to extra_fields by provider_config
+    if !provider_config.use_max_tokens {
+        req_builder.max_completion_tokens(request.max_completion_tokens);
+    }
+
+    // Temperature for non-reasoning models (reasoning models typically don't support temperature)
+    if !is_reasoning_model {
+        req_builder.temperature(request.temperature);
+    }
+
+    if !openai_tools.is_empty() {
+        let mut final_tools = Vec::new();
+        let mut tool_names = Vec::new();
+
+        for tool in openai_tools {
+            tool_names.push(tool.function.name.clone());
+            // async-openai 0.32.2 wraps tools in ChatCompletionTools enum
+            final_tools.push(ChatCompletionTools::Function(tool));
+        }
+
+        info!(?tool_names, "Attached tools and set tool_choice to 'auto'.");
+        req_builder
+            .tools(final_tools)
+            .tool_choice(ChatCompletionToolChoiceOption::Mode(
+                ToolChoiceOptions::Auto,
+            ));
+    } else {
+        // When no tools are provided, explicitly set tool_choice to "none"
+        // This prevents the model from attempting tool calls even if
+        // conversation history contains previous tool interactions
+        info!("No tools provided, setting tool_choice to 'none'.");
+        req_builder.tool_choice(ChatCompletionToolChoiceOption::Mode(
+            ToolChoiceOptions::None,
+        ));
+    }
+
+    // Non-strict: strict mode only accepts a subset of JSON Schema. The response is
+    // validated against the full schema afterwards.
+    if let Some(format) = &request.response_format {
+        req_builder.response_format(ResponseFormat::JsonSchema {
+            json_schema: ResponseFormatJsonSchema {
+                description: None,
+                name: format.name.clone(),
+                schema: Some(format.schema.clone()),
+                strict: None,
+            },
+        });
+    }
+
+    let standard_req = req_builder.build()?;
+
+    // Provider-specific fields (e.g., max_tokens for Mistral, reasoning_format for Cerebras)
+    let extra_fields = provider_config.extra_fields;
+
+    info!(
+        model = %standard_req.model,
+        provider = %provider.name(),
+        messages_count = standard_req.messages.len(),
+        tools_count = standard_req.tools.as_ref().map_or(0, |t| t.len()),
+        has_response_format = standard_req.response_format.is_some(),
+        max_completion_tokens = ?standard_req.max_completion_tokens,
+        temperature = ?standard_req.temperature,
+        stream_options = ?standard_req.stream_options,
+        extra_fields_count = extra_fields.len(),
+        extra_fields_keys = ?extra_fields.keys().collect::<Vec<_>>(),
+        "Final provider request constructed."
+    );
+    debug!(
+        model = %standard_req.model,
+        messages_count = standard_req.messages.len(),
+        "Provider request details."
+    );
+
+    Ok(ExtendedChatCompletionRequest {
+        standard_request: standard_req,
+        extra_fields,
+    })
+}
+
+fn build_user_message(
+    msg: &TokenizedMessage,
+    model_supports_images: bool,
+) -> Result<ChatCompletionRequestMessage, NodeError> {
+    let attachments = msg.attachments.as_deref().unwrap_or_default();
+    if model_supports_images && !attachments.is_empty() {
+        let mut content_parts = Vec::new();
+        content_parts.push(
+            ChatCompletionRequestMessageContentPartTextArgs::default()
+                .text(msg.content.clone())
+                .build()?
+                .into(),
+        );
+        for attachment in attachments {
+            content_parts.push(build_image_part(attachment)?.into());
+        }
+        Ok(ChatCompletionRequestUserMessageArgs::default()
+            .content(content_parts)
+            .build()?
+            .into())
+    } else {
+        Ok(ChatCompletionRequestUserMessageArgs::default()
+            .content(msg.content.clone())
+            .build()?
+            .into())
+    }
+}
+
+fn build_assistant_message(
+    msg: &TokenizedMessage,
+) -> Result<Option<ChatCompletionRequestMessage>, NodeError> {
+    if let Some(tool_calls) = &msg.tool_calls {
+        let mut builder = ChatCompletionRequestAssistantMessageArgs::default();
+
+        let api_tool_calls: Vec<ChatCompletionMessageToolCalls> = tool_calls
+            .iter()
+            .map(|tc| {
+                // ChatCompletionMessageToolCall in 0.32.2 does not have `type` field
+                let tool_call = ChatCompletionMessageToolCall {
+                    id: tc.id.clone(),
+                    function: FunctionCall {
+                        name: tc.function.name.clone(),
+                        arguments: tc.function.arguments.clone(),
+                    },
+                };
+                ChatCompletionMessageToolCalls::Function(tool_call)
+            })
+            .collect();
+
+        builder.tool_calls(api_tool_calls);
+
+        if !msg.content.trim().is_empty() {
+            builder.content(msg.content.clone());
+        }
+
+        let assistant_message = builder.build()?;
+        Ok(Some(assistant_message.into()))
+    } else if !msg.content.trim().is_empty() {
+        let assistant_message = ChatCompletionRequestAssistantMessageArgs::default()
+            .content(msg.content.clone())
+            .build()?;
+        Ok(Some(assistant_message.into()))
+    } else {
+        Ok(None)
+    }
+}
+
+fn build_tool_message(
+    msg: &TokenizedMessage,
+) -> Result<Option<ChatCompletionRequestMessage>, NodeError> {
+    if let Some(tool_call_id) = &msg.tool_call_id {
+        let tool_message = ChatCompletionRequestToolMessageArgs::default()
+            .content(msg.content.clone())
+            .tool_call_id(tool_call_id.clone())
+            .build()?;
+        Ok(Some(tool_message.into()))
+    } else {
+        warn!("A 'tool' role message was found without a tool_call_id. Skipping.");
+        Ok(None)
+    }
+}
+
+fn build_image_part(
+    attachment: &ImageAttachment,
+) -> Result<ChatCompletionRequestMessageContentPartImage, OpenAIError> {
+    let b64 = STANDARD.encode(&attachment.data);
+    let image_url = format!("data:{};base64,{}", attachment.mime_type, b64);
+    let built_image_url = ImageUrlArgs::default()
+        .url(image_url)
+        .detail(ImageDetail::High)
+        .build()?;
+    ChatCompletionRequestMessageContentPartImageArgs::default()
+        .image_url(built_image_url)
+        .build()
+}
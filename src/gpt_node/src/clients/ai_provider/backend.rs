@@ -0,0 +1,140 @@
+//! The provider backends a node sends its model's requests to.
+//!
+//! A model names a primary provider and optionally an ordered list of fallback
+//! providers. The node holds one API key per backend; fallbacks it has no key for are
+//! left out.
+
+use super::adapter::{ProviderAdapter, adapter_for_backend};
+use crate::core::sensitive::SecretString;
+use gpt_types::domain::{Model, message::ServedBy};
+
+/// A provider backend together with this node's key for it.
+pub struct ProviderTarget {
+    /// Position in the model's backend list: 0 for the primary provider
+    pub backend_index: u32,
+    pub provider: String,
+    pub provider_model: String,
+    pub endpoint: String,
+    pub(super) api_key: SecretString,
+    pub(super) adapter: Box<dyn ProviderAdapter>,
+}
+
+impl ProviderTarget {
+    pub fn new(
+        backend_index: u32,
+        provider: &str,
+        provider_model: &str,
+        endpoint: &str,
+        api_key: SecretString,
+    ) -> Self {
+        Self {
+            backend_index,
+            provider: provider.to_string(),
+            provider_model: provider_model.to_string(),
+            endpoint: endpoint.to_string(),
+            api_key,
+            adapter: adapter_for_backend(provider, endpoint),
+        }
+    }
+
+    pub fn served_by(&self) -> ServedBy {
+        ServedBy {
+            backend_index: self.backend_index,
+            provider: self.provider.clone(),
+            provider_model: self.provider_model.clone(),
+        }
+    }
+}
+
+/// The backends of `model` in failover order: the primary provider, then each
+/// fallback provider whose key in `fallback_keys` is non-empty.
+pub fn provider_targets(
+    model: &Model,
+    api_key: SecretString,
+    fallback_keys: Vec<SecretString>,
+) -> Vec<ProviderTarget> {
+    let mut targets = vec![ProviderTarget::new(
+        0,
+        &model.provider,
+        &model.provider_model,
+        &model.provider_endpoint,
+        api_key,
+    )];
+    let fallbacks = model.fallback_providers.iter().flatten();
+    for (index, (backend, key)) in fallbacks.zip(fallback_keys).enumerate() {
+        if key.is_empty() {
+            continue;
+        }
+        targets.push(ProviderTarget::new(
+            index as u32 + 1,
+            &backend.provider,
+            &backend.provider_model,
+            &backend.provider_endpoint,
+            key,
+        ));
+    }
+    targets
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use gpt_types::domain::{ModelStatus, ProviderBackend};
+
+    /// A Llama model served by Groq, with `fallbacks` as its fallback providers.
+    pub(crate) fn model(fallbacks: Vec<ProviderBackend>) -> Model {
+        Model {
+            model_id: "llama".to_string(),
+            name: "Llama".to_string(),
+            description: String::new(),
+            max_context: 8192,
+            max_output: 1024,
+            input_token_price: 0.0,
+            output_token_price: 0.0,
+            maker: "meta".to_string(),
+            provider: "groq".to_string(),
+            provider_model: "llama-3.3-70b".to_string(),
+            provider_endpoint: "https://api.groq.com/openai/v1".to_string(),
+            max_image_attachments: 0,
+            max_tools: 0,
+            aa_score: None,
+            release_date: None,
+            status: ModelStatus::Active,
+            extra_body_json: None,
+            is_reasoning: false,
+            is_embedding: false,
+            is_featured: false,
+            fallback_providers: Some(fallbacks),
+        }
+    }
+
+    fn backend(provider: &str, endpoint: &str) -> ProviderBackend {
+        ProviderBackend {
+            provider: provider.to_string(),
+            provider_model: format!("{}-llama", provider),
+            provider_endpoint: endpoint.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_targets_skip_fallbacks_without_keys() {
+        let model = model(vec![
+            backend("together", "https://api.together.xyz/v1"),
+            backend("fireworks", "https://api.fireworks.ai/inference/v1"),
+            backend("deepinfra", "https://api.deepinfra.com/v1/openai"),
+        ]);
+        let targets = provider_targets(
+            &model,
+            SecretString::new("primary"),
+            vec![SecretString::new("k1"), SecretString::default()],
+        );
+
+        let served: Vec<(u32, &str)> = targets
+            .iter()
+            .map(|t| (t.backend_index, t.provider.as_str()))
+            .collect();
+        assert_eq!(served, [(0, "groq"), (1, "together")]);
+        assert_eq!(targets[1].provider_model, "together-llama");
+        assert_eq!(targets[1].served_by().backend_index, 1);
+    }
+}
@@ -1,43 +1,42 @@
+use super::tokenizer::TokenCounter;
 use crate::core::job::types::{MessageData, TokenizedMessage};
+use tracing::info;
 
-const TOKENS_PER_IMAGE: u32 = 1000;
 const TOKENS_PER_TOOL_CALL: u32 = 10;
 
-fn count_tokens(text: &str) -> u32 {
-    ((text.chars().count() as f64) / 4.0).ceil() as u32
+/// Tokens `msg` takes up in the prompt: its text, images and tool calls.
+pub(crate) fn message_tokens(msg: &MessageData, counter: &TokenCounter) -> u32 {
+    let text_token_count = counter.count_text(&msg.content);
+    let image_token_count = msg.attachments.as_ref().map_or(0, |images| {
+        images.iter().map(|image| counter.count_image(image)).sum()
+    });
+    let tool_calls_token_count = msg.tool_calls.as_ref().map_or(0, |calls| {
+        calls
+            .iter()
+            .map(|call| {
+                TOKENS_PER_TOOL_CALL
+                    + counter.count_text(&call.function.name)
+                    + counter.count_text(&call.function.arguments)
+            })
+            .sum()
+    });
+    text_token_count + image_token_count + tool_calls_token_count
 }
 
 pub(super) fn prepare_context(
     messages: &[MessageData],
     max_context_tokens: u32,
+    counter: &TokenCounter,
 ) -> Vec<TokenizedMessage> {
     let tokenized: Vec<TokenizedMessage> = messages
         .iter()
-        .map(|msg| {
-            let text_token_count = count_tokens(&msg.content);
-            let image_token_count = msg
-                .attachments
-                .as_ref()
-                .map_or(0, |v| (v.len() as u32) * TOKENS_PER_IMAGE);
-            let tool_calls_token_count = msg.tool_calls.as_ref().map_or(0, |calls| {
-                calls
-                    .iter()
-                    .map(|call| {
-                        TOKENS_PER_TOOL_CALL
-                            + count_tokens(&call.function.name)
-                            + count_tokens(&call.function.arguments)
-                    })
-                    .sum()
-            });
-
-            TokenizedMessage {
-                role: msg.role.clone(),
-                content: msg.content.clone(),
-                token_count: text_token_count + image_token_count + tool_calls_token_count,
-                attachments: msg.attachments.clone(),
-                tool_calls: msg.tool_calls.clone(),
-                tool_call_id: msg.tool_call_id.clone(),
-            }
+        .map(|msg| TokenizedMessage {
+            role: msg.role.clone(),
+            content: msg.content.clone(),
+            token_count: message_tokens(msg, counter),
+            attachments: msg.attachments.clone(),
+            tool_calls: msg.tool_calls.clone(),
+            tool_call_id: msg.tool_call_id.clone(),
         })
         .collect();
 
@@ -72,6 +71,12 @@ pub(super) fn prepare_context(
     kept_messages.reverse();
     final_messages.extend(kept_messages);
 
+    info!(
+        initial_tokens = total_tokens,
+        final_tokens = current_tokens,
+        "Context truncated to fit token limit."
+    );
+
     final_messages
 }
 
@@ -85,6 +90,7 @@ mod tests {
     impl Default for MessageData {
         fn default() -> Self {
             MessageData {
+                message_id: 0,
                 role: "user".to_string(),
                 content: "".to_string(),
                 attachments: None,
@@ -108,7 +114,7 @@ mod tests {
                 ..Default::default()
             },
         ];
-        let prepared = prepare_context(&messages, 100);
+        let prepared = prepare_context(&messages, 100, &TokenCounter::heuristic());
         assert_eq!(prepared.len(), 2);
     }
 
@@ -135,7 +141,7 @@ mod tests {
         ];
         // Total tokens: 16 + 15 + 18 = 49.
         // Limit of 34 should keep system (16) and the most recent message (18). Total = 34.
-        let prepared = prepare_context(&messages, 34);
+        let prepared = prepare_context(&messages, 34, &TokenCounter::heuristic());
         assert_eq!(prepared.len(), 2);
         assert_eq!(prepared[0].role, "system");
         assert!(prepared[1].content.contains("second, most recent"));
@@ -162,7 +168,7 @@ mod tests {
         ];
         // Total tokens: 4 + 4 + 4 = 12. Limit is 8.
         // Should keep the last two messages.
-        let prepared = prepare_context(&messages, 8);
+        let prepared = prepare_context(&messages, 8, &TokenCounter::heuristic());
         assert_eq!(prepared.len(), 2);
         assert!(prepared[0].content.contains("Old message 2"));
         assert!(prepared[1].content.contains("Recent message"));
@@ -187,7 +193,7 @@ mod tests {
             },
         ];
         // Total tokens: 3 + 6 + 1000 = 1009. Limit = 1007.
-        let prepared = prepare_context(&messages, 1007);
+        let prepared = prepare_context(&messages, 1007, &TokenCounter::heuristic());
         assert_eq!(
             prepared.len(),
             1,
@@ -219,7 +225,7 @@ mod tests {
             },
         ];
         // Total tokens: 7 + 20 = 27. Limit is 21. Should keep only the tool call message.
-        let prepared = prepare_context(&messages, 21);
+        let prepared = prepare_context(&messages, 21, &TokenCounter::heuristic());
         assert_eq!(
             prepared.len(),
             1,
@@ -231,7 +237,7 @@ mod tests {
     #[test]
     fn test_prepare_context_empty_input() {
         let messages = vec![];
-        let prepared = prepare_context(&messages, 100);
+        let prepared = prepare_context(&messages, 100, &TokenCounter::heuristic());
         assert!(prepared.is_empty());
     }
 
@@ -249,7 +255,7 @@ mod tests {
                 ..Default::default()
             },
         ];
-        let prepared = prepare_context(&messages, 5); // Limit is less than system prompt
+        let prepared = prepare_context(&messages, 5, &TokenCounter::heuristic()); // Limit is less than system prompt
         assert_eq!(prepared.len(), 1);
         assert_eq!(prepared[0].role, "system");
     }
@@ -268,7 +274,7 @@ mod tests {
                 ..Default::default()
             }, // 9 tokens
         ];
-        let prepared = prepare_context(&messages, 5); // Limit allows system but not user message
+        let prepared = prepare_context(&messages, 5, &TokenCounter::heuristic()); // Limit allows system but not user message
         assert_eq!(prepared.len(), 1);
         assert_eq!(prepared[0].role, "system");
     }
@@ -1,21 +1,26 @@
 //! Embedding request handler.
 //!
 //! This module handles embedding model requests, using the same streaming infrastructure
-//! as chat completions but calling the embeddings API instead.
+//! as chat completions but calling the embeddings API instead. Embedding batch jobs
+//! are split into requests sized for the provider.
 
 use crate::{
-    core::error::NodeError,
+    core::error::{NodeError, map_node_error_to_message_status},
+    core::job::stream::get_or_create_job_stream,
     core::job::types::{OpenAIRequest, StreamedResponse},
     core::state::AppState,
 };
 use async_openai::types::embeddings::CreateEmbeddingRequestArgs;
 use gpt_types::domain::message::TokenUsage;
-use std::sync::atomic::Ordering;
-use tokio::sync::broadcast;
+use std::{ops::Range, sync::atomic::Ordering, time::Instant};
 use tracing::{info, instrument, warn};
 
+use super::provider::Provider;
+use super::tokenizer::TokenCounter;
 use super::types::AIResponse;
-use super::BROADCAST_CHANNEL_CAPACITY;
+
+/// Most estimated input tokens sent in one request of an embedding batch.
+const MAX_BATCH_REQUEST_TOKENS: u32 = 100_000;
 
 /// Process an embedding request.
 ///
@@ -46,15 +51,12 @@ pub async fn process_embedding_request(
     }
 
     // Acquire semaphore permit
-    let _permit_guard = state
-        .request_semaphore
-        .clone()
-        .acquire_owned()
+    let _permit_guard = super::acquire_request_permit(state)
         .await
         .map_err(|e| NodeError::Other(format!("Failed to acquire semaphore: {}", e)))?;
 
-    // Get or create broadcast sender
-    let tx = get_broadcast_sender(state, &stream_key).await;
+    // Get or create the job stream
+    let tx = get_or_create_job_stream(state, &stream_key).await;
 
     // Extract the last user message as embedding input
     let input_text = request
@@ -87,12 +89,22 @@ pub async fn process_embedding_request(
         .map_err(|e| NodeError::Configuration(format!("Failed to build embedding request: {}", e)))?;
 
     // Execute embedding request (non-streaming)
+    let started = Instant::now();
     let response = state
         .openai_client
         .embeddings()
         .create(embedding_request)
         .await
-        .map_err(NodeError::Provider)?;
+        .map_err(NodeError::Provider);
+    let provider = state.metrics.primary_provider();
+    state
+        .metrics
+        .observe_request_duration(provider, started.elapsed());
+    let response = response.inspect_err(|e| {
+        state
+            .metrics
+            .record_provider_error(provider, &map_node_error_to_message_status(e));
+    })?;
 
     // Extract embedding and usage
     let embedding = response
@@ -106,6 +118,7 @@ pub async fn process_embedding_request(
         prompt_tokens: response.usage.prompt_tokens,
         completion_tokens: 0, // Embeddings don't have completion tokens
         total_tokens: response.usage.total_tokens,
+        served_by: None,
     });
 
     info!(
@@ -144,20 +157,226 @@ pub async fn process_embedding_request(
     Ok(AIResponse::Embedding(embedding, usage))
 }
 
-async fn get_broadcast_sender(
+/// Process an embedding batch job.
+///
+/// The inputs are sent in consecutive requests of at most the provider's input limit
+/// and [`MAX_BATCH_REQUEST_TOKENS`] estimated tokens. The rate limit and concurrency
+/// permit taken by `process_request` cover all of them. Returns one vector per input
+/// in input order, with the usage of every request added up, and broadcasts the
+/// vectors as a JSON array.
+///
+/// Uses skip_all to prevent logging of input text content.
+#[instrument(skip_all, fields(stream_key = %stream_key, input_count = inputs.len()))]
+pub async fn process_embedding_batch_request(
+    inputs: Vec<String>,
+    stream_key: String,
     state: &AppState,
-    stream_key: &str,
-) -> broadcast::Sender<StreamedResponse> {
-    let mut job_streams_lock = state.job_streams.lock().await;
-    job_streams_lock
-        .entry(stream_key.to_string())
-        .or_insert_with(|| {
-            info!(
-                "Creating broadcast sender for embedding stream key: {}",
-                stream_key
-            );
-            let (new_tx, _) = broadcast::channel::<StreamedResponse>(BROADCAST_CHANNEL_CAPACITY);
-            new_tx
-        })
-        .clone()
+) -> Result<AIResponse, NodeError> {
+    let tx = get_or_create_job_stream(state, &stream_key).await;
+
+    if inputs.is_empty() || inputs.iter().any(|input| input.is_empty()) {
+        return Err(NodeError::Other(
+            "Embedding batch has an empty input.".to_string(),
+        ));
+    }
+
+    let primary = state
+        .provider_targets
+        .first()
+        .ok_or_else(|| NodeError::Configuration("No provider backend configured.".to_string()))?;
+    let max_inputs =
+        Provider::from_model(&primary.provider, &primary.endpoint).max_embedding_inputs();
+    let ranges = batch_ranges(
+        &inputs,
+        max_inputs,
+        MAX_BATCH_REQUEST_TOKENS,
+        &state.token_counter,
+    );
+    info!(
+        stream_key = %stream_key,
+        request_count = ranges.len(),
+        max_inputs,
+        "Processing embedding batch"
+    );
+
+    let provider = state.metrics.primary_provider();
+    let mut embeddings = Vec::with_capacity(inputs.len());
+    let mut usage = TokenUsage::default();
+    for range in ranges {
+        let expected = range.len();
+        let embedding_request = CreateEmbeddingRequestArgs::default()
+            .model(&state.provider_model)
+            .input(inputs[range].to_vec())
+            .build()
+            .map_err(|e| NodeError::Other(format!("Failed to build embedding request: {}", e)))?;
+
+        let started = Instant::now();
+        let response = state
+            .openai_client
+            .embeddings()
+            .create(embedding_request)
+            .await
+            .map_err(NodeError::Provider);
+        state
+            .metrics
+            .observe_request_duration(provider, started.elapsed());
+        let response = response.inspect_err(|e| {
+            state
+                .metrics
+                .record_provider_error(provider, &map_node_error_to_message_status(e));
+        })?;
+
+        if response.data.len() != expected {
+            return Err(NodeError::Other(format!(
+                "Provider returned {} embeddings for {} inputs.",
+                response.data.len(),
+                expected
+            )));
+        }
+        let mut data = response.data;
+        data.sort_by_key(|embedding| embedding.index);
+        embeddings.extend(data.into_iter().map(|embedding| embedding.embedding));
+        usage.prompt_tokens += response.usage.prompt_tokens;
+        usage.total_tokens += response.usage.total_tokens;
+    }
+
+    info!(
+        stream_key = %stream_key,
+        embedding_count = embeddings.len(),
+        prompt_tokens = usage.prompt_tokens,
+        "Embedding batch completed successfully"
+    );
+
+    let embeddings_json = serde_json::to_string(&embeddings)
+        .map_err(|e| NodeError::Other(format!("Failed to serialize embeddings: {}", e)))?;
+    let usage = Some(usage);
+    let final_response = StreamedResponse {
+        text: embeddings_json,
+        is_complete: true,
+        error_status: None,
+        usage: usage.clone(),
+    };
+    if let Err(e) = tx.send(final_response) {
+        warn!(
+            stream_key = %stream_key,
+            error = %e,
+            "Failed to broadcast embedding batch response (no listeners?)"
+        );
+    }
+
+    state
+        .metrics
+        .requests_succeeded
+        .fetch_add(1, Ordering::Relaxed);
+
+    Ok(AIResponse::Embeddings(embeddings, usage))
+}
+
+/// Embeds the query of a retrieval job with `provider_model`, the provider's name
+/// for the model the job's files were embedded with. The request goes to the
+/// primary provider, waits for the rate limiter and holds a concurrency permit, but
+/// is not streamed to the client.
+#[instrument(skip_all, fields(provider_model = %provider_model))]
+pub async fn embed_query(
+    state: &AppState,
+    provider_model: &str,
+    query: String,
+) -> Result<Vec<f32>, NodeError> {
+    if query.trim().is_empty() {
+        return Err(NodeError::Other(
+            "The message to retrieve passages for is empty.".to_string(),
+        ));
+    }
+    if let Some(limiter) = &state.rate_limiter {
+        limiter.until_ready().await;
+    }
+    let _permit = super::acquire_request_permit(state)
+        .await
+        .map_err(|e| NodeError::Other(format!("Failed to acquire semaphore: {}", e)))?;
+
+    let embedding_request = CreateEmbeddingRequestArgs::default()
+        .model(provider_model)
+        .input(query)
+        .build()
+        .map_err(|e| NodeError::Other(format!("Failed to build embedding request: {}", e)))?;
+
+    let provider = state.metrics.primary_provider();
+    let started = Instant::now();
+    let response = state
+        .openai_client
+        .embeddings()
+        .create(embedding_request)
+        .await
+        .map_err(NodeError::Provider);
+    state
+        .metrics
+        .observe_request_duration(provider, started.elapsed());
+    let response = response.inspect_err(|e| {
+        state
+            .metrics
+            .record_provider_error(provider, &map_node_error_to_message_status(e));
+    })?;
+
+    info!(
+        prompt_tokens = response.usage.prompt_tokens,
+        "Embedded the retrieval query"
+    );
+    response
+        .data
+        .into_iter()
+        .next()
+        .map(|embedding| embedding.embedding)
+        .ok_or_else(|| NodeError::Other("No embedding data returned from provider.".to_string()))
+}
+
+/// Splits `inputs` into consecutive ranges of at most `max_inputs` inputs and, unless
+/// a single input exceeds it, `max_tokens` estimated tokens.
+fn batch_ranges(
+    inputs: &[String],
+    max_inputs: usize,
+    max_tokens: u32,
+    token_counter: &TokenCounter,
+) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut tokens = 0u32;
+    for (index, input) in inputs.iter().enumerate() {
+        let input_tokens = token_counter.count_text(input);
+        if index > start
+            && (index - start == max_inputs || tokens.saturating_add(input_tokens) > max_tokens)
+        {
+            ranges.push(start..index);
+            start = index;
+            tokens = 0;
+        }
+        tokens = tokens.saturating_add(input_tokens);
+    }
+    if start < inputs.len() {
+        ranges.push(start..inputs.len());
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches_respect_input_and_token_limits() {
+        let counter = TokenCounter::heuristic();
+        let inputs: Vec<String> = (0..5).map(|_| "word ".repeat(20)).collect();
+        let tokens = counter.count_text(&inputs[0]);
+
+        assert_eq!(
+            batch_ranges(&inputs, 2, u32::MAX, &counter),
+            vec![0..2, 2..4, 4..5]
+        );
+        assert_eq!(
+            batch_ranges(&inputs, 10, tokens * 3, &counter),
+            vec![0..3, 3..5]
+        );
+        // An input over the token budget is still sent, on its own.
+        assert_eq!(batch_ranges(&inputs, 10, tokens - 1, &counter).len(), 5);
+        assert!(batch_ranges(&[], 10, tokens, &counter).is_empty());
+    }
 }
@@ -57,6 +57,28 @@ pub struct DeepInfraUsageExtension {
     pub reasoning_content_length: usize,
 }
 
+/// Anthropic-specific prompt caching and stop data.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct AnthropicUsageExtension {
+    /// Prompt tokens written to the cache by this request.
+    pub cache_creation_input_tokens: Option<u32>,
+    /// Prompt tokens served from the cache.
+    pub cache_read_input_tokens: Option<u32>,
+    /// Why generation stopped (e.g. `end_turn`, `tool_use`, `max_tokens`).
+    pub stop_reason: Option<String>,
+}
+
+/// Gemini-specific thinking, caching and stop data.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct GeminiUsageExtension {
+    /// Thinking tokens, counted within completion_tokens.
+    pub thoughts_token_count: Option<u32>,
+    /// Prompt tokens served from the context cache.
+    pub cached_content_token_count: Option<u32>,
+    /// Why generation stopped (e.g. `stop`, `max_tokens`).
+    pub finish_reason: Option<String>,
+}
+
 /// Provider-specific usage extension data.
 #[derive(Debug, Serialize, Clone)]
 pub enum ProviderUsageExtension {
@@ -70,6 +92,10 @@ pub enum ProviderUsageExtension {
     Cerebras(CerebrasUsageExtension),
     /// DeepInfra-specific data.
     DeepInfra(DeepInfraUsageExtension),
+    /// Anthropic-specific caching data.
+    Anthropic(AnthropicUsageExtension),
+    /// Gemini-specific thinking and caching data.
+    Gemini(GeminiUsageExtension),
 }
 
 impl Default for ProviderUsageExtension {
@@ -1,44 +1,57 @@
 //! AI provider client module.
 //!
-//! This module handles communication with AI providers (OpenAI and compatible APIs).
-//! It includes:
-//! - Request building with provider-specific configurations
-//! - SSE stream handling with provider-specific response parsing
+//! This module handles communication with AI providers (OpenAI-compatible APIs and
+//! Anthropic). It includes:
+//! - Provider adapters for request building, stream parsing and error mapping
+//! - Failover between a model's provider backends
+//! - SSE stream handling shared by all adapters
 //! - Extended usage extraction for provider-specific metrics
-//! - Broadcast channel management for WebSocket streaming
+//! - Response schema validation and repair for structured output
+//! - Summarization calls for long conversation histories
 
-mod context;
+mod adapter;
+pub(crate) mod backend;
+pub(crate) mod context;
 mod embedding_handler;
 mod extended_usage;
 mod provider;
 mod request_builder;
 pub(crate) mod resilient_types;
+mod sse;
 mod stream_handler;
+mod structured_output;
+mod summarizer;
+#[cfg(test)]
+mod test_support;
+pub(crate) mod tokenizer;
 mod types;
 mod usage_parser;
 
 use crate::{
     core::error::{map_node_error_to_message_status, NodeError},
+    core::job::stream::{get_or_create_job_stream, JobStream},
     core::job::types::{OpenAIRequest, StreamedResponse},
     core::state::AppState,
 };
+pub use embedding_handler::embed_query;
+pub use summarizer::summarize_transcript;
 pub use types::AIResponse;
 
-use provider::Provider;
 use std::{sync::atomic::Ordering, time::Instant};
-use tokio::sync::broadcast;
+use tokio::sync::{AcquireError, OwnedSemaphorePermit};
 use tracing::{debug, error, info, instrument, warn};
 
 pub const BROADCAST_CHANNEL_CAPACITY: usize = 100;
-const TOKEN_ESTIMATION_FACTOR: f64 = 4.0;
 
 /// Processes an AI provider request (OpenAI or compatible endpoint).
 ///
 /// This function:
-/// 1. Detects the provider from the model endpoint
-/// 2. Builds a provider-specific request
-/// 3. Handles streaming responses with provider-specific parsing
-/// 4. Returns the final response with usage information
+/// 1. Builds a provider-neutral request for the model
+/// 2. Sends it to the model's provider backends in failover order, each encoding it
+///    with its own adapter
+/// 3. Handles streaming responses with the adapter's parser
+/// 4. Validates the text against the job's response schema, if any, requesting repairs
+/// 5. Returns the final response with usage information, including the backend used
 ///
 /// Uses skip_all to prevent logging of message content and response data.
 #[instrument(skip_all, fields(stream_key = %stream_key, provider_model = %state.provider_model))]
@@ -63,7 +76,7 @@ pub async fn process_request(
         limiter.until_ready().await;
     }
 
-    let _permit_guard = match state.request_semaphore.clone().acquire_owned().await {
+    let _permit_guard = match acquire_request_permit(state).await {
         Ok(permit) => {
             debug!("Semaphore permit acquired for stream key: {}", stream_key);
             permit
@@ -76,7 +89,7 @@ pub async fn process_request(
         }
     };
 
-    let tx = get_broadcast_sender(state, &stream_key).await;
+    let tx = get_or_create_job_stream(state, &stream_key).await;
 
     // Detect provider from endpoint for provider-specific handling
     let model_details = state.get_model_details().await.map_err(|e| {
@@ -91,63 +104,76 @@ pub async fn process_request(
             model_id = %model_details.model_id,
             "Routing to embedding handler for embedding model."
         );
-        return embedding_handler::process_embedding_request(request, stream_key, state).await;
+        return match request.embedding_inputs {
+            Some(inputs) => {
+                embedding_handler::process_embedding_batch_request(inputs, stream_key, state)
+                    .await
+            }
+            None => embedding_handler::process_embedding_request(request, stream_key, state).await,
+        };
     }
 
-    let provider = Provider::from_endpoint(&model_details.provider_endpoint);
-    info!(
-        provider = %provider.name(),
-        endpoint = %model_details.provider_endpoint,
-        stream_key = %stream_key,
-        "Detected AI provider for stream handling."
-    );
-
-    let openai_request = match request_builder::build_request(
+    let chat_request = match request_builder::build_request(
         &request,
-        state,
+        &model_details,
+        &state.provider_model,
+        &state.token_counter,
         custom_prompt,
-        &stream_key,
-        request.extra_body_json.clone(),
-    )
-    .await
-    {
+    ) {
         Ok(req) => req,
         Err(e) => {
             return broadcast_and_return_error(e, &tx, state, &stream_key).await;
         }
     };
 
-    // Pass provider to stream handler for provider-specific SSE parsing
-    let result = stream_handler::handle_stream(
-        &state.openai_client,
-        openai_request,
-        &stream_key,
-        tx,
-        provider,
-    )
-    .await;
+    let validator = match chat_request.response_format.as_ref() {
+        Some(format) => match structured_output::SchemaValidator::new(&format.schema) {
+            Ok(validator) => Some(validator),
+            Err(e) => return broadcast_and_return_error(e, &tx, state, &stream_key).await,
+        },
+        None => None,
+    };
+
+    let result = match &validator {
+        Some(validator) => {
+            structured_output::handle_structured_stream(
+                &state.provider_http_client,
+                &state.provider_targets,
+                chat_request,
+                validator,
+                &state.token_counter,
+                &stream_key,
+                &tx,
+                &state.metrics,
+            )
+            .await
+        }
+        None => {
+            stream_handler::handle_stream(
+                &state.provider_http_client,
+                &state.provider_targets,
+                chat_request,
+                &stream_key,
+                &tx,
+                &state.metrics,
+            )
+            .await
+        }
+    };
 
     update_final_metrics(state, request_start_time, &result, &stream_key);
 
     result
 }
 
-async fn get_broadcast_sender(
-    state: &AppState,
-    stream_key: &str,
-) -> broadcast::Sender<StreamedResponse> {
-    let mut job_streams_lock = state.job_streams.lock().await;
-    job_streams_lock
-        .entry(stream_key.to_string())
-        .or_insert_with(|| {
-            info!(
-                "Creating NEW broadcast sender for stream key: {}",
-                stream_key
-            );
-            let (new_tx, _) = broadcast::channel::<StreamedResponse>(BROADCAST_CHANNEL_CAPACITY);
-            new_tx
-        })
-        .clone()
+/// Waits for a concurrency permit, recording how long the wait took.
+async fn acquire_request_permit(state: &AppState) -> Result<OwnedSemaphorePermit, AcquireError> {
+    let waiting_since = Instant::now();
+    let permit = state.request_semaphore.clone().acquire_owned().await;
+    state
+        .metrics
+        .observe_semaphore_wait(waiting_since.elapsed());
+    permit
 }
 
 fn update_peak_concurrency(state: &AppState) {
@@ -191,13 +217,17 @@ fn update_final_metrics(
                 .fetch_add(1, Ordering::Relaxed);
 
             let estimated_tokens = match response {
-                AIResponse::Text(text, _) => {
-                    (text.len() as f64 / TOKEN_ESTIMATION_FACTOR).ceil() as u64
+                AIResponse::Text(text, _) | AIResponse::Cancelled(text, _) => {
+                    state.token_counter.count_text(text) as u64
                 }
                 AIResponse::Embedding(embedding, _) => {
                     // Embeddings are fixed-size vectors, estimate based on dimensions
                     (embedding.len() as u64) / 4
                 }
+                AIResponse::Embeddings(embeddings, _) => embeddings
+                    .iter()
+                    .map(|embedding| (embedding.len() as u64) / 4)
+                    .sum(),
                 _ => 0,
             };
 
@@ -245,7 +275,7 @@ fn update_final_metrics(
 
 async fn broadcast_and_return_error<T>(
     err: NodeError,
-    tx: &broadcast::Sender<StreamedResponse>,
+    tx: &JobStream,
     state: &AppState,
     stream_key: &str,
 ) -> Result<T, NodeError> {
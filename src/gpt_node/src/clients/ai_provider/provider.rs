@@ -10,6 +10,7 @@
 //! - **Stream Options**: Supports `stream_options.include_usage: true`
 //! - **Reasoning**: `reasoning_effort` (low/medium/high) for o-series models
 //! - **Tool Calling**: Standard `tools`, `tool_choice` (auto/none/required)
+//! - **Embeddings**: Up to 2048 inputs per request
 //! - **Endpoint**: `https://api.openai.com/v1`
 //!
 //! ### Mistral AI
@@ -77,6 +78,21 @@ pub enum Provider {
 }
 
 impl Provider {
+    /// Resolve the provider from the model's configured provider name, falling back to
+    /// endpoint detection for names that are not recognised.
+    pub fn from_model(provider: &str, endpoint: &str) -> Self {
+        match provider.to_lowercase().as_str() {
+            "openai" => Provider::OpenAI,
+            "mistral" => Provider::Mistral,
+            "cerebras" => Provider::Cerebras,
+            "groq" => Provider::Groq,
+            "deepinfra" => Provider::DeepInfra,
+            "openrouter" => Provider::OpenRouter,
+            "xai" => Provider::XAI,
+            _ => Provider::from_endpoint(endpoint),
+        }
+    }
+
     /// Detect provider from endpoint URL (case-insensitive).
     pub fn from_endpoint(endpoint: &str) -> Self {
         let endpoint_lower = endpoint.to_lowercase();
@@ -109,6 +125,15 @@ impl Provider {
             Provider::XAI => "xAI",
         }
     }
+
+    /// Most inputs sent in one embeddings request. Providers that do not document a
+    /// limit get a conservative default.
+    pub fn max_embedding_inputs(&self) -> usize {
+        match self {
+            Provider::OpenAI => 2048,
+            _ => 128,
+        }
+    }
 }
 
 /// Provider-specific request configuration.
@@ -205,6 +205,7 @@ impl From<ResilientUsage> for TokenUsage {
             prompt_tokens: val.prompt_tokens,
             completion_tokens: val.completion_tokens,
             total_tokens: val.total_tokens,
+            served_by: None,
         }
     }
 }
@@ -227,9 +228,7 @@ pub enum ProviderErrorBody {
 #[derive(Debug, Deserialize)]
 pub struct StandardErrorDetail {
     pub message: String,
-    #[allow(dead_code)]
     pub r#type: Option<String>,
-    #[allow(dead_code)]
     pub code: Option<Value>, // Code can be string or int
 }
 
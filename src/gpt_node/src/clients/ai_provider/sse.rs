@@ -0,0 +1,124 @@
+//! Incremental decoder for `text/event-stream` response bodies.
+//!
+//! Provider streams arrive in arbitrary network chunks, so events and even UTF-8
+//! sequences may be split across reads. Bytes are buffered until a full line is available.
+
+/// A single server-sent event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// Value of the `event:` field, if the provider sent one.
+    pub event: Option<String>,
+    /// Concatenated `data:` lines, joined with `\n`.
+    pub data: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Option<String>,
+}
+
+impl SseDecoder {
+    /// Feeds a chunk of the response body and returns every event it completed.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if let Some(event) = self.process_line(line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flushes an event left unterminated when the body ended.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let rest = std::mem::take(&mut self.buffer);
+        let rest = String::from_utf8_lossy(&rest);
+        let rest = rest.trim_end_matches('\r');
+        if !rest.is_empty() {
+            self.process_line(rest);
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // Comment, used by some providers as a keep-alive.
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            },
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        self.data.take().map(|data| SseEvent { event, data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_split_across_chunks() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.push(b"event: message_start\nda").is_empty());
+        let events = decoder.push(b"ta: {\"a\":1}\n\ndata: [DONE]\n\n");
+
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("message_start".to_string()),
+                    data: "{\"a\":1}".to_string(),
+                },
+                SseEvent {
+                    event: None,
+                    data: "[DONE]".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_crlf_comments_and_multiline_data() {
+        let mut decoder = SseDecoder::default();
+        let events = decoder.push(b": keep-alive\r\n\r\ndata: one\r\ndata: two\r\n\r\n");
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "one\ntwo");
+    }
+
+    #[test]
+    fn test_finish_flushes_unterminated_event() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.push("data: caf\u{e9}".as_bytes()).is_empty());
+        assert_eq!(decoder.finish().unwrap().data, "caf\u{e9}");
+        assert!(decoder.finish().is_none());
+    }
+}
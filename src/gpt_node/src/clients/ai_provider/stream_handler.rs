@@ -1,130 +1,311 @@
 //! SSE stream handler for AI provider responses.
 //!
-//! This module processes streaming chat completion responses from various AI providers.
-//! It handles provider-specific fields and extracts extended usage information.
+//! This module sends chat requests built by a `ProviderAdapter` and processes the
+//! streamed response. Parsing is delegated to the adapter's `StreamParser`; this module
+//! aggregates text and tool calls and broadcasts progress to WebSocket clients.
 
 use crate::{
     clients::ai_provider::{
-        resilient_types::ResilientChatCompletionStreamResponse,
-        types::ExtendedChatCompletionRequest, AIResponse,
+        AIResponse,
+        adapter::{ChatRequest, ProviderAdapter, StreamDelta},
+        backend::ProviderTarget,
+        sse::SseDecoder,
     },
-    core::error::{map_node_error_to_message_status, NodeError},
-    core::job::types::StreamedResponse,
+    core::error::{NodeError, OpenAIError, map_node_error_to_message_status},
+    core::job::{stream::JobStream, types::StreamedResponse},
+    core::metrics::Metrics,
+    core::sensitive::SecretString,
 };
-use async_openai::{
-    config::OpenAIConfig,
-    error::OpenAIError,
-    types::chat::{
-        ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk, FinishReason,
-        FunctionCall,
-    },
-    Client,
-};
-use futures::{Stream, StreamExt};
+use async_openai::types::chat::{ChatCompletionMessageToolCall, FunctionCall};
 use gpt_types::domain::message::TokenUsage;
 use std::{
     collections::BTreeMap,
-    pin::Pin,
     time::{Duration, Instant},
 };
-use tokio::sync::broadcast;
 use tracing::{debug, error, info, instrument, warn};
 
 use super::extended_usage::ExtendedTokenUsage;
-use super::provider::Provider;
-use super::usage_parser::{extract_reasoning_content, parse_extended_usage_with_reasoning};
 
 const MAX_STREAMING_RETRIES: u32 = 3;
 const STREAMING_RETRY_DELAY_MS: u64 = 1000;
 
-type ChatResponseStream =
-    Pin<Box<dyn Stream<Item = Result<ResilientChatCompletionStreamResponse, OpenAIError>> + Send>>;
-
 /// Handle streaming response from AI provider.
 ///
 /// # Arguments
-/// * `client` - OpenAI-compatible client
-/// * `req` - Extended chat completion request
+/// * `client` - HTTP client used for provider requests
+/// * `targets` - The model's provider backends in failover order
+/// * `request` - Provider-neutral chat request
 /// * `stream_key` - Unique identifier for this stream (for logging/broadcasting)
-/// * `tx` - Broadcast sender for streaming responses to WebSocket clients
-/// * `provider` - Detected AI provider for provider-specific parsing
+/// * `tx` - Job stream that carries the responses to WebSocket clients
+/// * `metrics` - Node metrics that receive latency, throughput and error observations
+///
+/// A backend whose stream cannot be established after retries, or whose stream fails
+/// before the first token, is given up on for the next one, provided the error is
+/// retryable. Once a token has been emitted the job stays on that backend, since it
+/// may already have reached the client.
+///
+/// When the request has a response format, the final update of a text response is
+/// not sent; the caller sends it once the text has been validated.
 ///
 /// Uses skip_all to prevent logging of request content and response text.
-#[instrument(skip_all, fields(stream_key = %stream_key, provider = %provider.name()))]
+#[instrument(skip_all, fields(stream_key = %stream_key))]
 pub(super) async fn handle_stream(
-    client: &Client<OpenAIConfig>,
-    req: ExtendedChatCompletionRequest,
+    client: &reqwest::Client,
+    targets: &[ProviderTarget],
+    mut request: ChatRequest,
     stream_key: &str,
-    tx: broadcast::Sender<StreamedResponse>,
-    provider: Provider,
+    tx: &JobStream,
+    metrics: &Metrics,
 ) -> Result<AIResponse, NodeError> {
-    info!(
-        stream_key,
-        provider = %provider.name(),
-        "Initializing provider stream."
-    );
-    let stream = initialize_stream_with_retry(client, req, stream_key).await?;
-    info!(stream_key, "Stream initialized, beginning processing.");
-    process_stream(stream, stream_key.to_string(), tx, provider).await
+    let started = Instant::now();
+    let defer_final_text = request.response_format.is_some();
+    for (position, target) in targets.iter().enumerate() {
+        let adapter = target.adapter.as_ref();
+        info!(
+            stream_key,
+            provider = %adapter.name(),
+            backend_index = target.backend_index,
+            "Initializing provider stream."
+        );
+        request.model = target.provider_model.clone();
+        let initialized = tokio::select! {
+            biased;
+            _ = tx.cancelled() => {
+                info!(stream_key, "Job cancelled before the provider stream started.");
+                send_final(tx, cancelled_payload(String::new(), None), stream_key);
+                return Ok(AIResponse::Cancelled(String::new(), None));
+            }
+            initialized = initialize_stream_with_retry(
+                client,
+                adapter,
+                &target.endpoint,
+                &target.api_key,
+                &request,
+                stream_key,
+            ) => initialized,
+        };
+        if let Err(e) = &initialized {
+            metrics.record_provider_error(&target.provider, &map_node_error_to_message_status(e));
+        }
+        let response = match initialized {
+            Ok(response) => response,
+            Err(e) if position + 1 < targets.len() && is_retryable_node_error(&e) => {
+                warn!(
+                    stream_key,
+                    backend_index = target.backend_index,
+                    provider = %target.provider,
+                    error = %e,
+                    "Provider backend unavailable, failing over to the next backend."
+                );
+                continue;
+            }
+            Err(e) => {
+                metrics.observe_request_duration(&target.provider, started.elapsed());
+                return Err(e);
+            }
+        };
+        info!(stream_key, "Stream initialized, beginning processing.");
+        let end = process_stream(
+            response,
+            target,
+            stream_key,
+            tx,
+            metrics,
+            started,
+            defer_final_text,
+            position + 1 < targets.len(),
+        )
+        .await;
+        let mut result = match end {
+            StreamEnd::Finished(result) => result,
+            StreamEnd::FailedBeforeOutput(e) => {
+                metrics
+                    .record_provider_error(&target.provider, &map_node_error_to_message_status(&e));
+                warn!(
+                    stream_key,
+                    backend_index = target.backend_index,
+                    provider = %target.provider,
+                    error = %e,
+                    "Provider stream failed before the first token, failing over to the next backend."
+                );
+                continue;
+            }
+        };
+        if let Err(e) = &result {
+            metrics.record_provider_error(&target.provider, &map_node_error_to_message_status(e));
+        }
+        metrics.observe_request_duration(&target.provider, started.elapsed());
+        if let Ok(
+            AIResponse::Text(_, usage)
+            | AIResponse::ToolCall(_, usage)
+            | AIResponse::Cancelled(_, usage),
+        ) = &mut result
+        {
+            usage.get_or_insert_with(TokenUsage::default).served_by = Some(target.served_by());
+        }
+        return result;
+    }
+    Err(NodeError::Configuration(
+        "No provider backend is configured.".to_string(),
+    ))
+}
+
+/// How a provider stream ended.
+enum StreamEnd {
+    /// The stream ended with this result, and its final update has been sent.
+    Finished(Result<AIResponse, NodeError>),
+    /// The stream failed with a retryable error before any text or tool call arrived.
+    /// Nothing has been sent, so another backend can take over.
+    FailedBeforeOutput(NodeError),
 }
 
-#[instrument(skip_all, fields(stream_key = %stream_key, provider = %provider.name()))]
+/// Reads the provider stream of `target`. `started` is when the request was first
+/// sent, which time-to-first-token is measured from. Unless `may_fail_over` is set,
+/// every stream ends with `StreamEnd::Finished`.
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all, fields(stream_key = %stream_key, provider = %target.provider))]
 async fn process_stream(
-    mut stream: ChatResponseStream,
-    stream_key: String,
-    tx: broadcast::Sender<StreamedResponse>,
-    provider: Provider,
-) -> Result<AIResponse, NodeError> {
+    mut response: reqwest::Response,
+    target: &ProviderTarget,
+    stream_key: &str,
+    tx: &JobStream,
+    metrics: &Metrics,
+    started: Instant,
+    defer_final_text: bool,
+    may_fail_over: bool,
+) -> StreamEnd {
+    let adapter = target.adapter.as_ref();
     let start_time = Instant::now();
+    let mut first_token_at: Option<Instant> = None;
+    let mut parser = adapter.stream_parser();
+    let mut decoder = SseDecoder::default();
     let mut full_response_text = String::new();
-    let mut reasoning_text = String::new(); // Accumulate reasoning content (DeepInfra/Kimi)
+    let mut reasoning_len = 0usize; // Reasoning content is tracked but never returned
     let mut final_node_error: Option<NodeError> = None;
     let mut tool_calls_aggregator: BTreeMap<u32, ChatCompletionMessageToolCall> = BTreeMap::new();
-    let mut final_finish_reason: Option<FinishReason> = None;
-    let mut last_response_with_usage: Option<ResilientChatCompletionStreamResponse> = None;
-    let mut chunk_count = 0;
-
-    while let Some(chunk_result) = stream.next().await {
-        chunk_count += 1;
-        match handle_stream_chunk(
-            chunk_result,
-            &mut full_response_text,
-            &mut reasoning_text,
-            &mut tool_calls_aggregator,
-            &tx,
-            &stream_key,
-            &mut last_response_with_usage,
-            provider,
-        ) {
-            Ok(Some(finish_reason)) => {
-                final_finish_reason = Some(finish_reason);
+    let mut final_finish_reason: Option<String> = None;
+    let mut event_count = 0;
+    let mut done = false;
+    let mut cancelled = false;
+
+    while !done {
+        // Dropping the response on cancellation closes the connection, which stops
+        // the provider from generating further.
+        let chunk = tokio::select! {
+            biased;
+            _ = tx.cancelled() => {
+                info!(stream_key, "Job cancelled, stopping provider stream.");
+                cancelled = true;
+                break;
+            }
+            chunk = response.chunk() => chunk,
+        };
+        let events = match chunk {
+            Ok(Some(bytes)) => decoder.push(&bytes),
+            Ok(None) => {
+                done = true;
+                decoder.finish().into_iter().collect()
             }
-            Ok(None) => continue,
             Err(e) => {
-                final_node_error = Some(e);
+                error!(
+                    stream_key,
+                    provider = %adapter.name(),
+                    error = %e,
+                    "Error receiving chunk from provider stream."
+                );
+                final_node_error = Some(NodeError::Provider(OpenAIError::Reqwest(e)));
                 break;
             }
+        };
+
+        for event in events {
+            event_count += 1;
+            let deltas = match parser.parse_event(&event) {
+                Ok(deltas) => deltas,
+                Err(e) => {
+                    error!(
+                        stream_key,
+                        provider = %adapter.name(),
+                        error = %e,
+                        "Provider stream reported an error."
+                    );
+                    final_node_error = Some(e);
+                    done = true;
+                    break;
+                }
+            };
+            for delta in deltas {
+                match delta {
+                    StreamDelta::Text(text) => {
+                        if first_token_at.is_none() {
+                            first_token_at = Some(Instant::now());
+                            metrics
+                                .observe_time_to_first_token(&target.provider, started.elapsed());
+                        }
+                        full_response_text.push_str(&text);
+                        let streamed_response = StreamedResponse {
+                            text: full_response_text.clone(),
+                            is_complete: false,
+                            error_status: None,
+                            usage: None,
+                        };
+                        // Use send, ignoring error if no receivers (optimistic broadcast)
+                        let _ = tx.send(streamed_response);
+                    }
+                    StreamDelta::Reasoning(reasoning) => {
+                        reasoning_len += reasoning.len();
+                        debug!(
+                            stream_key,
+                            reasoning_chunk_len = reasoning.len(),
+                            "Captured reasoning content chunk."
+                        );
+                    }
+                    StreamDelta::ToolCall {
+                        index,
+                        id,
+                        name,
+                        arguments,
+                    } => {
+                        let entry = tool_calls_aggregator.entry(index).or_insert_with(|| {
+                            ChatCompletionMessageToolCall {
+                                id: String::new(),
+                                function: FunctionCall {
+                                    name: String::new(),
+                                    arguments: String::new(),
+                                },
+                            }
+                        });
+                        if let Some(id) = id {
+                            entry.id = id;
+                        }
+                        if let Some(name) = name {
+                            entry.function.name.push_str(&name);
+                        }
+                        if let Some(arguments) = arguments {
+                            entry.function.arguments.push_str(&arguments);
+                        }
+                    }
+                    StreamDelta::Finish(reason) => final_finish_reason = Some(reason),
+                    StreamDelta::Done => done = true,
+                }
+            }
         }
     }
 
-    // Parse extended usage from the last response that contained usage data
-    let final_extended_usage: Option<ExtendedTokenUsage> =
-        last_response_with_usage.as_ref().and_then(|response| {
-            parse_extended_usage_with_reasoning(response, provider, reasoning_text.len())
-        });
-
+    let final_extended_usage: Option<ExtendedTokenUsage> = parser.usage();
     let elapsed = start_time.elapsed();
 
     // Log detailed metrics including provider-specific data
     info!(
         stream_key,
         duration_ms = elapsed.as_millis(),
-        chunks_processed = chunk_count,
+        events_processed = event_count,
         finish_reason = ?final_finish_reason,
         has_error = final_node_error.is_some(),
+        cancelled,
         has_usage = final_extended_usage.is_some(),
-        reasoning_content_len = reasoning_text.len(),
-        provider = %provider.name(),
+        reasoning_content_len = reasoning_len,
+        provider = %adapter.name(),
         "Stream processing finished."
     );
 
@@ -132,7 +313,7 @@ async fn process_stream(
     if let Some(ref usage) = final_extended_usage {
         debug!(
             stream_key,
-            provider = %provider.name(),
+            provider = %adapter.name(),
             prompt_tokens = usage.base.prompt_tokens,
             completion_tokens = usage.base.completion_tokens,
             total_tokens = usage.base.total_tokens,
@@ -143,6 +324,32 @@ async fn process_stream(
 
     // Convert to base TokenUsage for backward compatibility
     let final_usage: Option<TokenUsage> = final_extended_usage.map(|eu| eu.base);
+    if final_node_error.is_none()
+        && let Some(usage) = &final_usage
+    {
+        let generating_since = first_token_at.unwrap_or(start_time);
+        metrics.observe_tokens_per_second(
+            &target.provider,
+            usage.completion_tokens,
+            generating_since.elapsed(),
+        );
+    }
+
+    if cancelled {
+        send_final(
+            tx,
+            cancelled_payload(full_response_text.clone(), final_usage.clone()),
+            stream_key,
+        );
+        return StreamEnd::Finished(Ok(AIResponse::Cancelled(full_response_text, final_usage)));
+    }
+
+    let emitted = first_token_at.is_some() || !tool_calls_aggregator.is_empty();
+    if let Some(err) =
+        final_node_error.take_if(|err| may_fail_over && !emitted && is_retryable_node_error(err))
+    {
+        return StreamEnd::FailedBeforeOutput(err);
+    }
 
     let final_payload = if let Some(ref err) = final_node_error {
         StreamedResponse {
@@ -160,23 +367,12 @@ async fn process_stream(
         }
     };
 
-    // Ensure final message is sent even if previous chunks failed to send
-    if tx.receiver_count() > 0 {
-        if let Err(e) = tx.send(final_payload) {
-            warn!(
-                stream_key,
-                error = %e,
-                "Final broadcast failed (client likely disconnected)."
-            );
-        } else {
-            debug!(
-                stream_key,
-                "Final completion/error message broadcast successfully."
-            );
-        }
+    let is_text_response = final_node_error.is_none() && tool_calls_aggregator.is_empty();
+    if !(defer_final_text && is_text_response) {
+        send_final(tx, final_payload, stream_key);
     }
 
-    if let Some(err) = final_node_error {
+    StreamEnd::Finished(if let Some(err) = final_node_error {
         Err(err)
     } else if !tool_calls_aggregator.is_empty() {
         let final_tool_calls: Vec<ChatCompletionMessageToolCall> =
@@ -194,180 +390,104 @@ async fn process_stream(
             "No tool calls. Returning final aggregated text as AIResponse::Text."
         );
         Ok(AIResponse::Text(full_response_text, final_usage))
-    }
+    })
 }
 
-/// Map string finish reasons from resilient response to strict enum.
-fn parse_finish_reason(reason: Option<String>) -> Option<FinishReason> {
-    match reason?.as_str() {
-        "stop" => Some(FinishReason::Stop),
-        "length" => Some(FinishReason::Length),
-        "tool_calls" => Some(FinishReason::ToolCalls),
-        "content_filter" => Some(FinishReason::ContentFilter),
-        "function_call" => Some(FinishReason::FunctionCall),
-        _ => None,
+/// Final update for a cancelled generation: the partial text, marked complete.
+fn cancelled_payload(text: String, usage: Option<TokenUsage>) -> StreamedResponse {
+    StreamedResponse {
+        text,
+        is_complete: true,
+        error_status: None,
+        usage,
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn handle_stream_chunk(
-    chunk_result: Result<ResilientChatCompletionStreamResponse, OpenAIError>,
-    full_response_text: &mut String,
-    reasoning_text: &mut String,
-    tool_calls_aggregator: &mut BTreeMap<u32, ChatCompletionMessageToolCall>,
-    tx: &broadcast::Sender<StreamedResponse>,
-    stream_key: &str,
-    last_response_with_usage: &mut Option<ResilientChatCompletionStreamResponse>,
-    provider: Provider,
-) -> Result<Option<FinishReason>, NodeError> {
-    match chunk_result {
-        Ok(response) => {
-            // Store response if it contains usage data (for final extraction)
-            if response.usage.is_some() {
-                *last_response_with_usage = Some(response.clone());
-            }
-
-            // Extract reasoning content (DeepInfra/Kimi thinking models)
-            if let Some(reasoning) = extract_reasoning_content(&response) {
-                reasoning_text.push_str(&reasoning);
-                debug!(
-                    stream_key,
-                    provider = %provider.name(),
-                    reasoning_chunk_len = reasoning.len(),
-                    "Captured reasoning content chunk."
-                );
-            }
-
-            // service_tier is captured but ignored (tolerated via ResilientChatCompletionStreamResponse)
-
-            let mut finish_reason = None;
-            for choice in response.choices {
-                if let Some(content) = choice.delta.content.as_ref().filter(|c| !c.is_empty()) {
-                    full_response_text.push_str(content);
-                    let streamed_response = StreamedResponse {
-                        text: full_response_text.clone(),
-                        is_complete: false,
-                        error_status: None,
-                        usage: None,
-                    };
-                    // Use send, ignoring error if no receivers (optimistic broadcast)
-                    let _ = tx.send(streamed_response);
-                }
-
-                if let Some(tool_calls_delta) = &choice.delta.tool_calls {
-                    process_tool_calls_delta(tool_calls_delta, tool_calls_aggregator);
-                }
-
-                if let Some(reason) = parse_finish_reason(choice.finish_reason) {
-                    finish_reason = Some(reason);
-                }
-            }
-            Ok(finish_reason)
-        }
-        Err(e) => {
-            error!(
-                stream_key,
-                provider = %provider.name(),
-                error = %e,
-                "Error receiving chunk from provider stream."
-            );
-            // Log error details but redact potentially sensitive content
-            if let OpenAIError::JSONDeserialize(ref json_err, ref _content) = e {
-                warn!(
-                    stream_key,
-                    json_error = %json_err,
-                    content_len = _content.len(),
-                    "Provider sent invalid JSON or error payload (content redacted)"
-                );
-            }
-            let node_err = NodeError::from(e);
-            Err(node_err)
-        }
-    }
-}
-
-fn process_tool_calls_delta(
-    tool_calls_delta: &[ChatCompletionMessageToolCallChunk],
-    tool_calls_aggregator: &mut BTreeMap<u32, ChatCompletionMessageToolCall>,
-) {
-    for tc_delta in tool_calls_delta {
-        let entry = tool_calls_aggregator
-            .entry(tc_delta.index)
-            .or_insert_with(|| ChatCompletionMessageToolCall {
-                id: tc_delta.id.clone().unwrap_or_default(),
-                function: FunctionCall {
-                    name: "".to_string(),
-                    arguments: "".to_string(),
-                },
-            });
-
-        if let Some(id) = &tc_delta.id {
-            entry.id.clone_from(id);
-        }
-        if let Some(function) = &tc_delta.function {
-            if let Some(name) = &function.name {
-                entry.function.name.push_str(name);
-            }
-            if let Some(args) = &function.arguments {
-                entry.function.arguments.push_str(args);
-            }
-        }
+/// Sends the final update of a stream.
+pub(super) fn send_final(tx: &JobStream, final_payload: StreamedResponse, stream_key: &str) {
+    // Ensure final message is sent even if previous chunks failed to send
+    // Always sent, so a client that reconnects later can still replay it.
+    if let Err(e) = tx.send(final_payload) {
+        warn!(
+            stream_key,
+            error = %e,
+            "Final broadcast failed (client likely disconnected)."
+        );
+    } else {
+        debug!(
+            stream_key,
+            "Final completion/error message broadcast successfully."
+        );
     }
 }
 
 async fn initialize_stream_with_retry(
-    client: &Client<OpenAIConfig>,
-    req: ExtendedChatCompletionRequest,
+    client: &reqwest::Client,
+    adapter: &dyn ProviderAdapter,
+    endpoint: &str,
+    api_key: &SecretString,
+    request: &ChatRequest,
     stream_key: &str,
-) -> Result<ChatResponseStream, NodeError> {
+) -> Result<reqwest::Response, NodeError> {
+    let provider_request = adapter.build_request(request, endpoint, api_key)?;
+
+    // Merge job-specific extra_body_json (user/model overrides take precedence)
+    let mut body = provider_request.body;
+    if let Some(fields) = body.as_object_mut() {
+        fields.extend(request.extra_fields.clone());
+    }
+
     for attempt in 1..=MAX_STREAMING_RETRIES {
         debug!(
             attempt,
             max_retries = MAX_STREAMING_RETRIES,
             stream_key,
-            "Creating OpenAI streaming request"
+            "Creating provider streaming request"
         );
 
-        match client
-            .chat()
-            .create_stream_byot::<ExtendedChatCompletionRequest, ResilientChatCompletionStreamResponse>(
-                req.clone(),
-            )
-            .await
-        {
-            Ok(stream) => {
+        let mut http_request = client.post(&provider_request.url).json(&body);
+        for (name, value) in &provider_request.headers {
+            http_request = http_request.header(*name, value);
+        }
+
+        let node_err = match http_request.send().await {
+            Ok(response) if response.status().is_success() => {
                 info!(
                     attempt,
                     stream_key, "Successfully established provider stream"
                 );
-                return Ok(stream);
+                return Ok(response);
             }
-            Err(e) => {
-                let node_err = NodeError::from(e);
-                if attempt >= MAX_STREAMING_RETRIES || !is_retryable_node_error(&node_err) {
-                    let reason = if !is_retryable_node_error(&node_err) {
-                        "non-retryable error"
-                    } else {
-                        "max retries reached"
-                    };
-                    error!(
-                        stream_key,
-                        error = %node_err,
-                        reason,
-                        "Failing stream initialization"
-                    );
-                    return Err(node_err);
-                }
-                warn!(
-                    stream_key,
-                    attempt,
-                    error = %node_err,
-                    delay_ms = STREAMING_RETRY_DELAY_MS,
-                    "Retryable error initializing stream, retrying..."
-                );
-                tokio::time::sleep(Duration::from_millis(STREAMING_RETRY_DELAY_MS)).await;
+            Ok(response) => {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                adapter.map_error(status, &body)
             }
+            Err(e) => NodeError::Provider(OpenAIError::Reqwest(e)),
+        };
+
+        if attempt >= MAX_STREAMING_RETRIES || !is_retryable_node_error(&node_err) {
+            let reason = if !is_retryable_node_error(&node_err) {
+                "non-retryable error"
+            } else {
+                "max retries reached"
+            };
+            error!(
+                stream_key,
+                error = %node_err,
+                reason,
+                "Failing stream initialization"
+            );
+            return Err(node_err);
         }
+        warn!(
+            stream_key,
+            attempt,
+            error = %node_err,
+            delay_ms = STREAMING_RETRY_DELAY_MS,
+            "Retryable error initializing stream, retrying..."
+        );
+        tokio::time::sleep(Duration::from_millis(STREAMING_RETRY_DELAY_MS)).await;
     }
     unreachable!("Loop should have returned or errored.")
 }
@@ -400,3 +520,376 @@ fn is_retryable_node_error(err: &NodeError) -> bool {
         _ => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ai_provider::adapter::test_support::{message, request};
+    use crate::clients::ai_provider::adapter::{
+        AnthropicAdapter, GeminiAdapter, OpenAICompatibleAdapter,
+    };
+    use crate::clients::ai_provider::provider::Provider;
+    use crate::clients::ai_provider::test_support::{mock_provider, target, with_broadcasts};
+    use axum::http::StatusCode;
+    use gpt_types::error::{MessageErrorStatus, ProviderErrorType};
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    fn chat_request() -> ChatRequest {
+        let mut request = request(vec![message("user", "Hi")], false);
+        request.extra_fields = HashMap::from([("top_k".to_string(), Value::from(5))]);
+        request
+    }
+
+    async fn run(
+        adapter: Box<dyn ProviderAdapter>,
+        endpoint: &str,
+    ) -> (Result<AIResponse, NodeError>, Vec<StreamedResponse>) {
+        run_targets(&[target(0, adapter, endpoint)]).await
+    }
+
+    async fn run_targets(
+        targets: &[ProviderTarget],
+    ) -> (Result<AIResponse, NodeError>, Vec<StreamedResponse>) {
+        run_on_stream(targets, JobStream::new()).await
+    }
+
+    async fn run_on_stream(
+        targets: &[ProviderTarget],
+        stream: JobStream,
+    ) -> (Result<AIResponse, NodeError>, Vec<StreamedResponse>) {
+        with_broadcasts(
+            &stream,
+            handle_stream(
+                &reqwest::Client::new(),
+                targets,
+                chat_request(),
+                "test-stream",
+                &stream,
+                &Metrics::new("test-model", "test", 0, 0),
+            ),
+        )
+        .await
+    }
+
+    const ANTHROPIC_TEXT_STREAM: &str = "event: message_start\n\
+data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"usage\":{\"input_tokens\":10,\"output_tokens\":1}}}\n\n\
+event: content_block_start\n\
+data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n\
+event: ping\n\
+data: {\"type\":\"ping\"}\n\n\
+event: content_block_delta\n\
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n\
+event: content_block_delta\n\
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\" there\"}}\n\n\
+event: content_block_stop\n\
+data: {\"type\":\"content_block_stop\",\"index\":0}\n\n\
+event: message_delta\n\
+data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":3}}\n\n\
+event: message_stop\n\
+data: {\"type\":\"message_stop\"}\n\n";
+
+    /// Anthropic reports overload as an error event after a successful response.
+    const ANTHROPIC_OVERLOADED_STREAM: &str = "event: message_start\n\
+data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_3\",\"usage\":{\"input_tokens\":5}}}\n\n\
+event: error\n\
+data: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n";
+
+    const ANTHROPIC_TOOL_STREAM: &str = "event: message_start\n\
+data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_2\",\"usage\":{\"input_tokens\":20,\"output_tokens\":1}}}\n\n\
+event: content_block_start\n\
+data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"get_weather\",\"input\":{}}}\n\n\
+event: content_block_delta\n\
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"city\\\": \"}}\n\n\
+event: content_block_delta\n\
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"\\\"Paris\\\"}\"}}\n\n\
+event: content_block_stop\n\
+data: {\"type\":\"content_block_stop\",\"index\":0}\n\n\
+event: message_delta\n\
+data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\"},\"usage\":{\"output_tokens\":15}}\n\n\
+event: message_stop\n\
+data: {\"type\":\"message_stop\"}\n\n";
+
+    #[tokio::test]
+    async fn test_anthropic_text_stream() {
+        let (endpoint, captured) =
+            mock_provider("/v1/messages", &[(StatusCode::OK, ANTHROPIC_TEXT_STREAM)]).await;
+        let (result, broadcasts) = run(Box::new(AnthropicAdapter), &endpoint).await;
+
+        match result.unwrap() {
+            AIResponse::Text(text, usage) => {
+                assert_eq!(text, "Hello there");
+                let usage = usage.unwrap();
+                assert_eq!(usage.prompt_tokens, 10);
+                assert_eq!(usage.completion_tokens, 3);
+                assert_eq!(usage.served_by.unwrap().backend_index, 0);
+            }
+            _ => panic!("Expected a text response"),
+        }
+        let last = broadcasts.last().unwrap();
+        assert!(last.is_complete);
+        assert_eq!(last.text, "Hello there");
+
+        let (headers, body) = captured.lock().unwrap().pop().unwrap();
+        assert_eq!(headers["x-api-key"], "sk-test");
+        assert_eq!(headers["anthropic-version"], "2023-06-01");
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["top_k"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_tool_use_stream() {
+        let (endpoint, _) =
+            mock_provider("/v1/messages", &[(StatusCode::OK, ANTHROPIC_TOOL_STREAM)]).await;
+        let (result, _) = run(Box::new(AnthropicAdapter), &endpoint).await;
+
+        match result.unwrap() {
+            AIResponse::ToolCall(calls, usage) => {
+                assert_eq!(calls.len(), 1);
+                assert_eq!(calls[0].id, "toolu_1");
+                assert_eq!(calls[0].function.name, "get_weather");
+                assert_eq!(calls[0].function.arguments, "{\"city\": \"Paris\"}");
+                assert_eq!(usage.unwrap().completion_tokens, 15);
+            }
+            _ => panic!("Expected a tool call response"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_error_response_is_not_retried() {
+        let (endpoint, _) = mock_provider(
+            "/v1/messages",
+            &[(
+                StatusCode::UNAUTHORIZED,
+                r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#,
+            )],
+        )
+        .await;
+        let (result, _) = run(Box::new(AnthropicAdapter), &endpoint).await;
+
+        let err = result.err().unwrap();
+        assert_eq!(err.severity(), crate::core::error::ErrorSeverity::Terminal);
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_mid_stream_error() {
+        let (endpoint, _) = mock_provider(
+            "/v1/messages",
+            &[(StatusCode::OK, ANTHROPIC_OVERLOADED_STREAM)],
+        )
+        .await;
+        let (result, broadcasts) = run(Box::new(AnthropicAdapter), &endpoint).await;
+
+        assert!(result.is_err());
+        assert!(broadcasts.last().unwrap().error_status.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_stream() {
+        let (endpoint, captured) = mock_provider(
+            "/v1/chat/completions",
+            &[(
+                StatusCode::OK,
+                "data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":null}]}\n\n\
+data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}],\"usage\":{\"prompt_tokens\":4,\"completion_tokens\":1,\"total_tokens\":5}}\n\n\
+data: [DONE]\n\n",
+            )],
+        )
+        .await;
+        let adapter = OpenAICompatibleAdapter::new(Provider::OpenAI);
+        let (result, _) = run(Box::new(adapter), &endpoint).await;
+
+        match result.unwrap() {
+            AIResponse::Text(text, usage) => {
+                assert_eq!(text, "Hi");
+                assert_eq!(usage.unwrap().total_tokens, 5);
+            }
+            _ => panic!("Expected a text response"),
+        }
+        let (headers, body) = captured.lock().unwrap().pop().unwrap();
+        assert_eq!(headers["authorization"], "Bearer sk-test");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+
+    #[tokio::test]
+    async fn test_gemini_text_stream() {
+        let (endpoint, captured) = mock_provider(
+            "/v1/models/test-model:streamGenerateContent",
+            &[(
+                StatusCode::OK,
+                "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hello\"}]}}],\"usageMetadata\":{\"promptTokenCount\":8}}\r\n\r\n\
+data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\" there\"}]},\"finishReason\":\"STOP\"}],\"usageMetadata\":{\"promptTokenCount\":8,\"candidatesTokenCount\":2,\"totalTokenCount\":10}}\r\n\r\n",
+            )],
+        )
+        .await;
+        let (result, broadcasts) = run(Box::new(GeminiAdapter), &endpoint).await;
+
+        match result.unwrap() {
+            AIResponse::Text(text, usage) => {
+                assert_eq!(text, "Hello there");
+                assert_eq!(usage.unwrap().total_tokens, 10);
+            }
+            _ => panic!("Expected a text response"),
+        }
+        assert!(broadcasts.last().unwrap().is_complete);
+
+        let (headers, body) = captured.lock().unwrap().pop().unwrap();
+        assert_eq!(headers["x-goog-api-key"], "sk-test");
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "Be brief.");
+        assert_eq!(body["contents"][0]["role"], "user");
+    }
+
+    #[tokio::test]
+    async fn test_gemini_safety_block() {
+        let (endpoint, _) = mock_provider(
+            "/v1/models/test-model:streamGenerateContent",
+            &[(
+                StatusCode::OK,
+                "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Sure\"}]}}]}\r\n\r\n\
+data: {\"candidates\":[{\"finishReason\":\"SAFETY\",\"safetyRatings\":[{\"category\":\"HARM_CATEGORY_DANGEROUS_CONTENT\",\"probability\":\"HIGH\",\"blocked\":true}]}]}\r\n\r\n",
+            )],
+        )
+        .await;
+        let (result, broadcasts) = run(Box::new(GeminiAdapter), &endpoint).await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            broadcasts.last().unwrap().error_status,
+            Some(MessageErrorStatus::ProviderError(
+                ProviderErrorType::ContentPolicyViolation
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_next_backend_before_streaming() {
+        let (unavailable, _) = mock_provider(
+            "/v1/messages",
+            &[(
+                StatusCode::SERVICE_UNAVAILABLE,
+                r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+            )],
+        )
+        .await;
+        let (fallback, captured) = mock_provider(
+            "/v1/chat/completions",
+            &[(
+                StatusCode::OK,
+                "data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"stop\"}]}\n\n\
+data: [DONE]\n\n",
+            )],
+        )
+        .await;
+        let mut backup = target(
+            2,
+            Box::new(OpenAICompatibleAdapter::new(Provider::OpenAI)),
+            &fallback,
+        );
+        backup.provider_model = "backup-model".to_string();
+        let targets = [target(0, Box::new(AnthropicAdapter), &unavailable), backup];
+
+        let (result, _) = run_targets(&targets).await;
+        match result.unwrap() {
+            AIResponse::Text(text, usage) => {
+                assert_eq!(text, "Hi");
+                let served_by = usage.unwrap().served_by.unwrap();
+                assert_eq!(served_by.backend_index, 2);
+                assert_eq!(served_by.provider_model, "backup-model");
+            }
+            _ => panic!("Expected a text response"),
+        }
+        let (_, body) = captured.lock().unwrap().pop().unwrap();
+        assert_eq!(body["model"], "backup-model");
+    }
+
+    #[tokio::test]
+    async fn test_failover_when_stream_fails_before_first_token() {
+        let (overloaded, _) = mock_provider(
+            "/v1/messages",
+            &[(StatusCode::OK, ANTHROPIC_OVERLOADED_STREAM)],
+        )
+        .await;
+        let (fallback, captured) =
+            mock_provider("/v1/messages", &[(StatusCode::OK, ANTHROPIC_TEXT_STREAM)]).await;
+        let targets = [
+            target(0, Box::new(AnthropicAdapter), &overloaded),
+            target(1, Box::new(AnthropicAdapter), &fallback),
+        ];
+
+        let (result, broadcasts) = run_targets(&targets).await;
+        match result.unwrap() {
+            AIResponse::Text(text, usage) => {
+                assert_eq!(text, "Hello there");
+                assert_eq!(usage.unwrap().served_by.unwrap().backend_index, 1);
+            }
+            _ => panic!("Expected a text response"),
+        }
+        assert!(broadcasts.iter().all(|b| b.error_status.is_none()));
+        assert_eq!(captured.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_failover_after_first_token() {
+        let (interrupted, _) = mock_provider(
+            "/v1/messages",
+            &[(
+                StatusCode::OK,
+                "event: content_block_delta\n\
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hel\"}}\n\n\
+event: error\n\
+data: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+            )],
+        )
+        .await;
+        let (fallback, captured) =
+            mock_provider("/v1/messages", &[(StatusCode::OK, ANTHROPIC_TEXT_STREAM)]).await;
+        let targets = [
+            target(0, Box::new(AnthropicAdapter), &interrupted),
+            target(1, Box::new(AnthropicAdapter), &fallback),
+        ];
+
+        let (result, broadcasts) = run_targets(&targets).await;
+        assert!(result.is_err());
+        assert!(broadcasts.last().unwrap().error_status.is_some());
+        assert!(captured.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_no_failover_on_terminal_error() {
+        let (rejected, _) = mock_provider(
+            "/v1/messages",
+            &[(
+                StatusCode::UNAUTHORIZED,
+                r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#,
+            )],
+        )
+        .await;
+        let (fallback, captured) =
+            mock_provider("/v1/messages", &[(StatusCode::OK, ANTHROPIC_TEXT_STREAM)]).await;
+        let targets = [
+            target(0, Box::new(AnthropicAdapter), &rejected),
+            target(1, Box::new(AnthropicAdapter), &fallback),
+        ];
+
+        let (result, _) = run_targets(&targets).await;
+        assert!(result.is_err());
+        assert!(captured.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_job_does_not_reach_provider() {
+        let (endpoint, captured) =
+            mock_provider("/v1/messages", &[(StatusCode::OK, ANTHROPIC_TEXT_STREAM)]).await;
+        let stream = JobStream::new();
+        stream.cancel();
+
+        let targets = [target(0, Box::new(AnthropicAdapter), &endpoint)];
+        let (result, broadcasts) = run_on_stream(&targets, stream).await;
+        assert!(matches!(result, Ok(AIResponse::Cancelled(text, _)) if text.is_empty()));
+        let last = broadcasts.last().unwrap();
+        assert!(last.is_complete);
+        assert!(last.error_status.is_none());
+        assert!(captured.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,383 @@
+//! Structured output for jobs with a response schema.
+//!
+//! Adapters ask the provider for JSON matching the schema where the API allows it, but
+//! no provider guarantees conformance for arbitrary schemas. The final text is
+//! validated here; a response that does not match is sent back to the provider with
+//! the validation errors, a bounded number of times, before the job fails.
+
+use super::adapter::ChatRequest;
+use super::stream_handler::{handle_stream, send_final};
+use super::tokenizer::TokenCounter;
+use super::{AIResponse, backend::ProviderTarget};
+use crate::{
+    core::error::{NodeError, map_node_error_to_message_status},
+    core::job::{
+        stream::JobStream,
+        types::{StreamedResponse, TokenizedMessage},
+    },
+    core::metrics::Metrics,
+};
+use gpt_types::domain::message::TokenUsage;
+use jsonschema::Validator;
+use serde_json::Value;
+use tracing::{info, instrument, warn};
+
+/// Additional requests made after the first response fails validation.
+const MAX_REPAIR_ATTEMPTS: u32 = 2;
+/// Validation errors reported per attempt.
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// A compiled response schema.
+pub(super) struct SchemaValidator {
+    validator: Validator,
+}
+
+/// Why a response did not match the schema.
+#[derive(Debug)]
+pub(super) struct SchemaViolations {
+    /// Full error messages, which quote the response; only sent back to the provider.
+    for_repair: String,
+    /// Locations and kinds of errors without response values, safe to log and store.
+    summary: String,
+}
+
+impl SchemaValidator {
+    /// Fails with `NodeError::SchemaValidation` when `schema` is not a valid JSON Schema.
+    pub(super) fn new(schema: &Value) -> Result<Self, NodeError> {
+        let validator = jsonschema::validator_for(schema).map_err(|e| {
+            NodeError::SchemaValidation(format!("Invalid response schema: {}", e.masked()))
+        })?;
+        Ok(Self { validator })
+    }
+
+    /// Returns the JSON in `text`, without surrounding code fences, if it matches.
+    pub(super) fn validate<'a>(&self, text: &'a str) -> Result<&'a str, SchemaViolations> {
+        let json = strip_code_fence(text);
+        let instance: Value = serde_json::from_str(json).map_err(|e| SchemaViolations {
+            for_repair: format!("The response is not valid JSON: {}", e),
+            summary: format!(
+                "response is not valid JSON (line {}, column {})",
+                e.line(),
+                e.column()
+            ),
+        })?;
+
+        let errors: Vec<_> = self
+            .validator
+            .iter_errors(&instance)
+            .take(MAX_REPORTED_ERRORS)
+            .collect();
+        if errors.is_empty() {
+            return Ok(json);
+        }
+        let describe = |path: &str, message: String| {
+            let path = if path.is_empty() { "/" } else { path };
+            format!("at {}: {}", path, message)
+        };
+        Err(SchemaViolations {
+            for_repair: errors
+                .iter()
+                .map(|e| describe(e.instance_path().as_str(), e.to_string()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            summary: errors
+                .iter()
+                .map(|e| describe(e.instance_path().as_str(), e.masked().to_string()))
+                .collect::<Vec<_>>()
+                .join("; "),
+        })
+    }
+}
+
+/// Runs `request` like [`handle_stream`], then validates a text response against
+/// `validator`, asking the provider to repair it up to [`MAX_REPAIR_ATTEMPTS`] times.
+/// Sends the final update of the stream. Usage covers every attempt.
+///
+/// Uses skip_all to prevent logging of request content and response text.
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all, fields(stream_key = %stream_key))]
+pub(super) async fn handle_structured_stream(
+    client: &reqwest::Client,
+    targets: &[ProviderTarget],
+    request: ChatRequest,
+    validator: &SchemaValidator,
+    token_counter: &TokenCounter,
+    stream_key: &str,
+    tx: &JobStream,
+    metrics: &Metrics,
+) -> Result<AIResponse, NodeError> {
+    let mut total_usage: Option<TokenUsage> = None;
+    let mut attempt_request = request.clone();
+
+    for attempt in 0..=MAX_REPAIR_ATTEMPTS {
+        // Errors, tool calls and cancellations have already sent their final update.
+        let response = handle_stream(client, targets, attempt_request, stream_key, tx, metrics)
+            .await
+            .map(|response| with_total_usage(response, &mut total_usage))?;
+        let (text, usage) = match response {
+            AIResponse::Text(text, usage) => (text, usage),
+            other => return Ok(other),
+        };
+
+        let violations = match validator.validate(&text) {
+            Ok(json) => {
+                info!(stream_key, attempt, "Response matches the response schema.");
+                let json = json.to_string();
+                send_final(
+                    tx,
+                    StreamedResponse {
+                        text: json.clone(),
+                        is_complete: true,
+                        error_status: None,
+                        usage: usage.clone(),
+                    },
+                    stream_key,
+                );
+                return Ok(AIResponse::Text(json, usage));
+            }
+            Err(violations) => violations,
+        };
+
+        warn!(
+            stream_key,
+            attempt,
+            violations = %violations.summary,
+            "Response does not match the response schema."
+        );
+        if attempt == MAX_REPAIR_ATTEMPTS {
+            let err = NodeError::SchemaValidation(violations.summary);
+            send_final(
+                tx,
+                StreamedResponse {
+                    text: String::new(),
+                    is_complete: true,
+                    error_status: Some(map_node_error_to_message_status(&err)),
+                    usage: None,
+                },
+                stream_key,
+            );
+            return Err(err);
+        }
+        attempt_request = repair_request(&request, text, &violations, token_counter);
+    }
+    unreachable!("The last attempt returns.")
+}
+
+/// `request` followed by the rejected response and a request to correct it.
+fn repair_request(
+    request: &ChatRequest,
+    rejected: String,
+    violations: &SchemaViolations,
+    token_counter: &TokenCounter,
+) -> ChatRequest {
+    let correction = format!(
+        "Your response does not match the required JSON Schema:\n{}\n\
+         Reply with only the corrected JSON value.",
+        violations.for_repair
+    );
+    let mut repair = request.clone();
+    for (role, content) in [("assistant", rejected), ("user", correction)] {
+        repair.messages.push(TokenizedMessage {
+            role: role.to_string(),
+            token_count: token_counter.count_text(&content),
+            content,
+            attachments: None,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+    }
+    repair
+}
+
+/// Adds the usage of `response` to `total` and replaces it with the running total.
+fn with_total_usage(response: AIResponse, total: &mut Option<TokenUsage>) -> AIResponse {
+    let add = |usage: Option<TokenUsage>, total: &mut Option<TokenUsage>| {
+        if let Some(usage) = usage {
+            let sum = total.get_or_insert_with(TokenUsage::default);
+            sum.prompt_tokens += usage.prompt_tokens;
+            sum.completion_tokens += usage.completion_tokens;
+            sum.total_tokens += usage.total_tokens;
+            sum.served_by = usage.served_by;
+        }
+        total.clone()
+    };
+    match response {
+        AIResponse::Text(text, usage) => AIResponse::Text(text, add(usage, total)),
+        AIResponse::ToolCall(calls, usage) => AIResponse::ToolCall(calls, add(usage, total)),
+        AIResponse::Cancelled(text, usage) => AIResponse::Cancelled(text, add(usage, total)),
+        AIResponse::Embedding(embedding, usage) => AIResponse::Embedding(embedding, usage),
+        AIResponse::Embeddings(embeddings, usage) => AIResponse::Embeddings(embeddings, usage),
+    }
+}
+
+/// Models often wrap JSON in a Markdown code fence despite instructions.
+fn strip_code_fence(text: &str) -> &str {
+    let trimmed = text.trim();
+    trimmed
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map(|inner| {
+            // Drop the info string, e.g. `json`.
+            let body = inner.trim_start_matches(|c: char| c.is_ascii_alphanumeric());
+            body.trim()
+        })
+        .unwrap_or(trimmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ai_provider::adapter::test_support::{message, request};
+    use crate::clients::ai_provider::adapter::{OpenAICompatibleAdapter, ResponseFormat};
+    use crate::clients::ai_provider::provider::Provider;
+    use crate::clients::ai_provider::test_support::{
+        Requests, mock_provider, target, with_broadcasts,
+    };
+    use axum::http::StatusCode;
+    use gpt_types::error::MessageErrorStatus;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city"],
+        })
+    }
+
+    /// An OpenAI-compatible stream that sends `text` in one chunk.
+    fn openai_stream(text: &str) -> String {
+        let chunk = json!({
+            "id": "c1", "object": "chat.completion.chunk", "created": 1, "model": "m",
+            "choices": [{ "index": 0, "delta": { "content": text }, "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 4, "completion_tokens": 1, "total_tokens": 5 },
+        });
+        format!("data: {}\n\ndata: [DONE]\n\n", chunk)
+    }
+
+    /// Asks for a place in the schema, answering the n-th request with the n-th of
+    /// `texts` and repeating the last one.
+    async fn run(
+        texts: &[&str],
+    ) -> (
+        Result<AIResponse, NodeError>,
+        Vec<StreamedResponse>,
+        Requests,
+    ) {
+        let bodies: Vec<String> = texts.iter().map(|text| openai_stream(text)).collect();
+        let responses: Vec<(StatusCode, &str)> = bodies
+            .iter()
+            .map(|body| (StatusCode::OK, body.as_str()))
+            .collect();
+        let (endpoint, requests) = mock_provider("/v1/chat/completions", &responses).await;
+        let targets = [target(
+            0,
+            Box::new(OpenAICompatibleAdapter::new(Provider::OpenAI)),
+            &endpoint,
+        )];
+        let mut request = request(vec![message("user", "Where is the Louvre?")], false);
+        request.response_format = Some(ResponseFormat {
+            name: "place".to_string(),
+            schema: schema(),
+        });
+
+        let stream = JobStream::new();
+        let (result, broadcasts) = with_broadcasts(
+            &stream,
+            handle_structured_stream(
+                &reqwest::Client::new(),
+                &targets,
+                request,
+                &SchemaValidator::new(&schema()).unwrap(),
+                &TokenCounter::heuristic(),
+                "test-stream",
+                &stream,
+                &Metrics::new("test-model", "test", 0, 0),
+            ),
+        )
+        .await;
+        (result, broadcasts, requests)
+    }
+
+    #[tokio::test]
+    async fn test_invalid_response_is_repaired() {
+        let (result, broadcasts, requests) = run(&[
+            r#"{"town": "Paris"}"#,
+            "```json\n{\"city\": \"Paris\"}\n```",
+        ])
+        .await;
+
+        match result.unwrap() {
+            AIResponse::Text(text, usage) => {
+                assert_eq!(text, r#"{"city": "Paris"}"#);
+                let usage = usage.unwrap();
+                assert_eq!(usage.prompt_tokens, 8);
+                assert_eq!(usage.total_tokens, 10);
+            }
+            _ => panic!("Expected a text response"),
+        }
+        // Only the validated response is sent as final.
+        let finals: Vec<_> = broadcasts.iter().filter(|b| b.is_complete).collect();
+        assert_eq!(finals.len(), 1);
+        assert_eq!(finals[0].text, r#"{"city": "Paris"}"#);
+
+        let requests: Vec<Value> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, body)| body.clone())
+            .collect();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["response_format"]["type"], "json_schema");
+        assert_eq!(
+            requests[0]["response_format"]["json_schema"]["name"],
+            "place"
+        );
+        let messages = requests[1]["messages"].as_array().unwrap();
+        let rejected = &messages[messages.len() - 2];
+        assert_eq!(rejected["role"], "assistant");
+        assert_eq!(rejected["content"], r#"{"town": "Paris"}"#);
+        let correction = messages.last().unwrap()["content"].as_str().unwrap();
+        assert!(correction.contains("\"city\" is a required property"));
+    }
+
+    #[tokio::test]
+    async fn test_repairs_are_bounded() {
+        let (result, broadcasts, requests) = run(&["The Louvre is in Paris."]).await;
+
+        assert!(matches!(result, Err(NodeError::SchemaValidation(_))));
+        assert_eq!(
+            requests.lock().unwrap().len(),
+            1 + MAX_REPAIR_ATTEMPTS as usize
+        );
+        let last = broadcasts.last().unwrap();
+        assert!(last.is_complete);
+        match &last.error_status {
+            Some(MessageErrorStatus::SchemaValidationFailed(detail)) => {
+                assert!(!detail.contains("Louvre"));
+            }
+            other => panic!("Expected a schema validation status, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_violation_summary_omits_response_values() {
+        let validator = SchemaValidator::new(&schema()).unwrap();
+        assert!(validator.validate(r#"{"city": "Paris"}"#).is_ok());
+
+        let violations = validator.validate(r#"{"city": 75001}"#).unwrap_err();
+        assert!(violations.for_repair.contains("75001"));
+        assert!(violations.summary.contains("/city"));
+        assert!(!violations.summary.contains("75001"));
+    }
+
+    #[test]
+    fn test_invalid_schema_fails_the_job_only() {
+        let err = SchemaValidator::new(&json!({ "type": 12 })).err().unwrap();
+        assert!(matches!(err, NodeError::SchemaValidation(_)));
+        assert_eq!(
+            err.severity(),
+            crate::core::error::ErrorSeverity::JobFailure
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! One-off summarization calls used to compress long conversation histories.
+
+use super::adapter::ChatRequest;
+use super::{AIResponse, stream_handler};
+use crate::{
+    core::error::NodeError,
+    core::job::stream::JobStream,
+    core::job::types::TokenizedMessage,
+    core::state::AppState,
+};
+use std::collections::HashMap;
+use tracing::{info, instrument, warn};
+
+const SUMMARY_SYSTEM_PROMPT: &str = "You compress conversations so they can be continued \
+later. Summarize the transcript you are given. Keep every requirement, constraint, \
+decision, name, number and open question the user or assistant stated, and note which \
+tools were called and what they returned. Write in the language of the conversation, in \
+plain prose without preamble. Do not answer or continue the conversation.";
+
+/// Asks the served model to summarize `transcript` in at most `max_tokens` tokens.
+///
+/// The call is not streamed to any client and does not count towards the request
+/// metrics of the job it serves.
+#[instrument(skip_all, fields(job_id, max_tokens))]
+pub async fn summarize_transcript(
+    state: &AppState,
+    job_id: u64,
+    transcript: String,
+    max_tokens: u32,
+) -> Result<String, NodeError> {
+    let model_details = state.get_model_details().await.map_err(|e| {
+        warn!(error = ?e, "Could not fetch model details from state.");
+        NodeError::Configuration("Could not retrieve model details.".to_string())
+    })?;
+
+    if let Some(limiter) = &state.rate_limiter {
+        limiter.until_ready().await;
+    }
+    let _permit = super::acquire_request_permit(state)
+        .await
+        .map_err(|e| NodeError::Other(format!("Failed to acquire semaphore permit: {}", e)))?;
+
+    let token_count = state.token_counter.count_text(&transcript);
+    let request = ChatRequest {
+        model: state.provider_model.clone(),
+        system_prompt: SUMMARY_SYSTEM_PROMPT.to_string(),
+        messages: vec![TokenizedMessage {
+            role: "user".to_string(),
+            content: transcript,
+            token_count,
+            attachments: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }],
+        tools: Vec::new(),
+        max_completion_tokens: max_tokens,
+        temperature: 0.2,
+        reasoning_effort: None,
+        is_reasoning_model: model_details.is_reasoning,
+        supports_images: false,
+        extra_fields: HashMap::new(),
+        response_format: None,
+    };
+
+    // Nobody listens to this stream; the summary is only needed once it is complete.
+    let stream = JobStream::new();
+    let stream_key = format!("summary-{}", job_id);
+    let response = stream_handler::handle_stream(
+        &state.provider_http_client,
+        &state.provider_targets,
+        request,
+        &stream_key,
+        &stream,
+        &state.metrics,
+    )
+    .await?;
+
+    match response {
+        AIResponse::Text(summary, _) if !summary.trim().is_empty() => {
+            info!(
+                transcript_tokens = token_count,
+                summary_len = summary.len(),
+                "Conversation summarized."
+            );
+            Ok(summary)
+        }
+        _ => Err(NodeError::Other(
+            "Summarization returned no text.".to_string(),
+        )),
+    }
+}
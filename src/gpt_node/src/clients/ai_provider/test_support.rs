@@ -0,0 +1,79 @@
+//! A mock provider server and stream harness for the transport tests.
+
+use super::adapter::ProviderAdapter;
+use super::backend::ProviderTarget;
+use crate::core::job::stream::JobStream;
+use crate::core::job::types::StreamedResponse;
+use crate::core::sensitive::SecretString;
+use axum::{
+    Router,
+    http::{HeaderMap, StatusCode, header::CONTENT_TYPE},
+    response::IntoResponse,
+    routing::post,
+};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+/// The requests a mock provider received, oldest first.
+pub type Requests = Arc<Mutex<Vec<(HeaderMap, Value)>>>;
+
+/// Serves `path`, answering the n-th request with the n-th of `responses` and
+/// repeating the last one. Returns the server's endpoint and the requests it records.
+pub async fn mock_provider(
+    path: &'static str,
+    responses: &[(StatusCode, &str)],
+) -> (String, Requests) {
+    let responses: Vec<(StatusCode, String)> = responses
+        .iter()
+        .map(|(status, body)| (*status, body.to_string()))
+        .collect();
+    let requests: Requests = Arc::default();
+    let sink = requests.clone();
+    let app = Router::new().route(
+        path,
+        post(
+            move |headers: HeaderMap, axum::Json(json): axum::Json<Value>| async move {
+                let mut received = sink.lock().unwrap();
+                received.push((headers, json));
+                let (status, body) =
+                    responses[(received.len() - 1).min(responses.len() - 1)].clone();
+                (status, [(CONTENT_TYPE, "text/event-stream")], body).into_response()
+            },
+        ),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{}/v1", addr), requests)
+}
+
+/// A backend serving `test-model` at `endpoint` through `adapter`.
+pub fn target(
+    backend_index: u32,
+    adapter: Box<dyn ProviderAdapter>,
+    endpoint: &str,
+) -> ProviderTarget {
+    ProviderTarget {
+        backend_index,
+        provider: adapter.name().to_string(),
+        provider_model: "test-model".to_string(),
+        endpoint: endpoint.to_string(),
+        api_key: SecretString::new("sk-test"),
+        adapter,
+    }
+}
+
+/// Runs `job`, which streams to `stream`, and returns its result with the responses
+/// it broadcast.
+pub async fn with_broadcasts<T>(
+    stream: &JobStream,
+    job: impl Future<Output = T>,
+) -> (T, Vec<StreamedResponse>) {
+    let mut rx = stream.subscribe(None).receiver;
+    let result = job.await;
+    let mut broadcasts = Vec::new();
+    while let Ok(frame) = rx.try_recv() {
+        broadcasts.push(frame.response);
+    }
+    (result, broadcasts)
+}
@@ -0,0 +1,331 @@
+//! Token counting for context budgeting.
+//!
+//! Text is counted with the model's own tokenizer when the node has one: OpenAI BPE
+//! encodings are compiled into the binary, and HuggingFace `tokenizer.json` files are
+//! bundled in the enclave image under the tokenizer directory, looked up by model id
+//! and then by maker (e.g. `<dir>/qwen/tokenizer.json`). Anything else falls back to
+//! the chars/4 heuristic. Images are costed from their dimensions using the vision
+//! encoder formula of the model's maker.
+
+use gpt_types::domain::{Model, message::ImageAttachment};
+use std::path::Path;
+use tiktoken_rs::CoreBPE;
+use tokenizers::Tokenizer;
+use tracing::{info, warn};
+
+/// Cost of an image whose dimensions cannot be read.
+const FALLBACK_IMAGE_TOKENS: u32 = 1000;
+
+enum TextTokenizer {
+    Tiktoken(&'static CoreBPE),
+    HuggingFace(Box<Tokenizer>),
+    Heuristic,
+}
+
+/// How a model family turns an image into input tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageTokenFormula {
+    /// 512px tiles after fitting into 2048px and scaling the short side to 768px.
+    OpenAITiles,
+    /// One token per 750 pixels after fitting into 1568px and ~1.15 megapixels.
+    Anthropic,
+    /// 258 tokens for small images, otherwise 258 per crop unit tile.
+    Gemini,
+    /// One token per 16px patch plus a break token per row, after fitting into 1024px.
+    Pixtral,
+}
+
+/// Counts tokens for one model. Built once at startup, since a node serves one model.
+pub struct TokenCounter {
+    text: TextTokenizer,
+    images: ImageTokenFormula,
+}
+
+impl TokenCounter {
+    /// Picks the tokenizer and image formula for `model` from its metadata.
+    pub fn for_model(model: &Model, tokenizer_dir: &Path) -> Self {
+        let text = select_text_tokenizer(model, tokenizer_dir);
+        let images = image_formula(&model.maker, &model.provider);
+        info!(
+            model_id = %model.model_id,
+            tokenizer = text.name(),
+            image_formula = ?images,
+            "Token counter configured."
+        );
+        Self { text, images }
+    }
+
+    /// The chars/4 estimate with OpenAI image costs.
+    #[cfg(test)]
+    pub fn heuristic() -> Self {
+        Self {
+            text: TextTokenizer::Heuristic,
+            images: ImageTokenFormula::OpenAITiles,
+        }
+    }
+
+    pub fn count_text(&self, text: &str) -> u32 {
+        match &self.text {
+            TextTokenizer::Tiktoken(bpe) => bpe.encode_ordinary(text).len() as u32,
+            TextTokenizer::HuggingFace(tokenizer) => match tokenizer.encode(text, false) {
+                Ok(encoding) => encoding.len() as u32,
+                Err(e) => {
+                    warn!(error = %e, "Tokenizer failed to encode text; estimating instead.");
+                    estimate_tokens(text)
+                }
+            },
+            TextTokenizer::Heuristic => estimate_tokens(text),
+        }
+    }
+
+    pub fn count_image(&self, image: &ImageAttachment) -> u32 {
+        match imagesize::blob_size(&image.data) {
+            Ok(size) => image_tokens(self.images, size.width as u32, size.height as u32),
+            Err(_) => FALLBACK_IMAGE_TOKENS,
+        }
+    }
+}
+
+impl TextTokenizer {
+    fn name(&self) -> &'static str {
+        match self {
+            TextTokenizer::Tiktoken(_) => "tiktoken",
+            TextTokenizer::HuggingFace(_) => "huggingface",
+            TextTokenizer::Heuristic => "heuristic",
+        }
+    }
+}
+
+fn estimate_tokens(text: &str) -> u32 {
+    ((text.chars().count() as f64) / 4.0).ceil() as u32
+}
+
+fn select_text_tokenizer(model: &Model, tokenizer_dir: &Path) -> TextTokenizer {
+    if let Some(bpe) = tiktoken_encoding(&model.maker, &model.provider_model) {
+        return TextTokenizer::Tiktoken(bpe);
+    }
+    for name in [&model.model_id, &model.maker.to_lowercase()] {
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            continue;
+        }
+        let path = tokenizer_dir.join(name).join("tokenizer.json");
+        if !path.is_file() {
+            continue;
+        }
+        match Tokenizer::from_file(&path) {
+            Ok(tokenizer) => return TextTokenizer::HuggingFace(Box::new(tokenizer)),
+            Err(e) => warn!(
+                path = %path.display(),
+                error = %e,
+                "Failed to load bundled tokenizer."
+            ),
+        }
+    }
+    TextTokenizer::Heuristic
+}
+
+/// OpenAI encoding for the model, if it is an OpenAI model.
+fn tiktoken_encoding(maker: &str, provider_model: &str) -> Option<&'static CoreBPE> {
+    // Provider models are often namespaced, e.g. `openai/gpt-oss-20b`.
+    let name = provider_model
+        .rsplit('/')
+        .next()
+        .unwrap_or(provider_model)
+        .to_lowercase();
+    let is_cl100k = name.starts_with("gpt-3.5")
+        || (name.starts_with("gpt-4")
+            && !name.starts_with("gpt-4o")
+            && !name.starts_with("gpt-4."));
+    if is_cl100k {
+        return Some(tiktoken_rs::cl100k_base_singleton());
+    }
+    let is_current = ["gpt-", "chatgpt-", "o1", "o3", "o4"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix));
+    (is_current || maker.eq_ignore_ascii_case("openai")).then(tiktoken_rs::o200k_base_singleton)
+}
+
+fn image_formula(maker: &str, provider: &str) -> ImageTokenFormula {
+    // The maker decides the vision encoder; the provider only matters for native APIs
+    // whose models have no maker set.
+    let family = if maker.is_empty() { provider } else { maker };
+    match family.to_lowercase().as_str() {
+        "anthropic" => ImageTokenFormula::Anthropic,
+        "google" | "gemini" => ImageTokenFormula::Gemini,
+        "mistral" | "mistralai" => ImageTokenFormula::Pixtral,
+        _ => ImageTokenFormula::OpenAITiles,
+    }
+}
+
+/// Scales `width`x`height` down to fit within `max_width`x`max_height`, keeping the
+/// aspect ratio.
+fn fit_within(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    let scale = (max_width as f64 / width as f64)
+        .min(max_height as f64 / height as f64)
+        .min(1.0);
+    (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    )
+}
+
+fn image_tokens(formula: ImageTokenFormula, width: u32, height: u32) -> u32 {
+    if width == 0 || height == 0 {
+        return FALLBACK_IMAGE_TOKENS;
+    }
+    match formula {
+        ImageTokenFormula::OpenAITiles => {
+            let (w, h) = fit_within(width, height, 2048, 2048);
+            let short_side = w.min(h);
+            let (w, h) = if short_side > 768 {
+                let scale = 768.0 / short_side as f64;
+                (
+                    (w as f64 * scale).round() as u32,
+                    (h as f64 * scale).round() as u32,
+                )
+            } else {
+                (w, h)
+            };
+            85 + 170 * w.div_ceil(512) * h.div_ceil(512)
+        }
+        ImageTokenFormula::Anthropic => {
+            let (w, h) = fit_within(width, height, 1568, 1568);
+            let pixels = w as f64 * h as f64;
+            let pixels = pixels.min(1_150_000.0);
+            (pixels / 750.0).ceil() as u32
+        }
+        ImageTokenFormula::Gemini => {
+            if width <= 384 && height <= 384 {
+                return 258;
+            }
+            let unit = ((width.min(height) as f64 / 1.5).floor() as u32).clamp(256, 768);
+            258 * width.div_ceil(unit) * height.div_ceil(unit)
+        }
+        ImageTokenFormula::Pixtral => {
+            let (w, h) = fit_within(width, height, 1024, 1024);
+            let rows = h.div_ceil(16);
+            w.div_ceil(16) * rows + rows
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ai_provider::backend::tests::model;
+    use tokenizers::models::wordlevel::WordLevel;
+    use tokenizers::pre_tokenizers::whitespace::Whitespace;
+
+    /// A PNG signature and IHDR chunk, which is all dimension sniffing reads.
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut data = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[8, 6, 0, 0, 0, 0, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn test_image_formulas() {
+        assert_eq!(
+            image_tokens(ImageTokenFormula::OpenAITiles, 1024, 1024),
+            765
+        );
+        assert_eq!(
+            image_tokens(ImageTokenFormula::OpenAITiles, 2048, 4096),
+            1105
+        );
+        assert_eq!(image_tokens(ImageTokenFormula::OpenAITiles, 256, 256), 255);
+        assert_eq!(image_tokens(ImageTokenFormula::Anthropic, 1000, 1000), 1334);
+        assert_eq!(image_tokens(ImageTokenFormula::Anthropic, 4000, 4000), 1534);
+        assert_eq!(image_tokens(ImageTokenFormula::Gemini, 300, 300), 258);
+        assert_eq!(image_tokens(ImageTokenFormula::Gemini, 1024, 1024), 1032);
+        assert_eq!(image_tokens(ImageTokenFormula::Pixtral, 1024, 512), 2080);
+    }
+
+    #[test]
+    fn test_image_cost_uses_dimensions() {
+        let counter = TokenCounter::heuristic();
+        let image = ImageAttachment {
+            mime_type: "image/png".to_string(),
+            data: png_header(512, 512),
+        };
+        assert_eq!(counter.count_image(&image), 255);
+
+        let unreadable = ImageAttachment {
+            mime_type: "image/png".to_string(),
+            data: vec![1, 2, 3],
+        };
+        assert_eq!(counter.count_image(&unreadable), FALLBACK_IMAGE_TOKENS);
+    }
+
+    #[test]
+    fn test_tokenizer_selection() {
+        assert!(tiktoken_encoding("openai", "openai/gpt-oss-20b").is_some());
+        assert!(tiktoken_encoding("", "gpt-4o-mini").is_some());
+        assert!(tiktoken_encoding("qwen", "qwen/qwen3-32b").is_none());
+        assert_eq!(image_formula("", "anthropic"), ImageTokenFormula::Anthropic);
+        assert_eq!(
+            image_formula("meta", "groq"),
+            ImageTokenFormula::OpenAITiles
+        );
+
+        let counter = TokenCounter {
+            text: TextTokenizer::Tiktoken(tiktoken_rs::o200k_base_singleton()),
+            images: ImageTokenFormula::OpenAITiles,
+        };
+        assert_eq!(counter.count_text("hello world"), 2);
+        // CJK text is denser than the heuristic assumes.
+        let cjk = "今日はいい天気ですね。散歩に行きましょう。";
+        assert!(counter.count_text(cjk) > estimate_tokens(cjk));
+    }
+
+    /// Writes a word-level tokenizer to `<dir>/<name>/tokenizer.json`. Without
+    /// `split_words` the whole text is one unknown word.
+    fn write_tokenizer(dir: &Path, name: &str, split_words: bool) {
+        let vocab = [("[UNK]".to_string(), 0), ("hello".to_string(), 1)]
+            .into_iter()
+            .collect();
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        if split_words {
+            tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+        }
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+        tokenizer
+            .save(dir.join(name).join("tokenizer.json"), false)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_bundled_tokenizer_is_found_by_model_id_then_maker() {
+        let dir = std::env::temp_dir().join(format!("gpt_node_tokenizers_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let model = model(Vec::new());
+        let text = "hello unknownword hello";
+
+        let counter = TokenCounter::for_model(&model, &dir);
+        assert_eq!(counter.text.name(), "heuristic");
+
+        write_tokenizer(&dir, &model.maker, true);
+        let counter = TokenCounter::for_model(&model, &dir);
+        assert_eq!(counter.text.name(), "huggingface");
+        assert_eq!(counter.count_text(text), 3);
+
+        // The model's own tokenizer takes precedence over its maker's.
+        write_tokenizer(&dir, &model.model_id, false);
+        assert_eq!(TokenCounter::for_model(&model, &dir).count_text(text), 1);
+
+        // A file that does not load falls through to the next candidate.
+        std::fs::write(dir.join(&model.model_id).join("tokenizer.json"), "{").unwrap();
+        assert_eq!(TokenCounter::for_model(&model, &dir).count_text(text), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -18,4 +18,8 @@ pub enum AIResponse {
     Text(String, Option<TokenUsage>),
     ToolCall(Vec<ChatCompletionMessageToolCall>, Option<TokenUsage>),
     Embedding(Vec<f32>, Option<TokenUsage>),
+    /// One vector per input of an embedding batch, in input order.
+    Embeddings(Vec<Vec<f32>>, Option<TokenUsage>),
+    /// Generation stopped by the user; carries the text produced until then.
+    Cancelled(String, Option<TokenUsage>),
 }
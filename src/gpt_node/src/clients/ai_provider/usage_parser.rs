@@ -38,6 +38,7 @@ pub fn parse_extended_usage_with_reasoning(
         prompt_tokens: u.prompt_tokens,
         completion_tokens: u.completion_tokens,
         total_tokens: u.total_tokens,
+        served_by: None,
     })?;
 
     let extension = match provider {
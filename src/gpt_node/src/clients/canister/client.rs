@@ -1,7 +1,11 @@
-use crate::core::error::NodeError;
+use crate::core::{error::NodeError, metrics, telemetry};
+use async_trait::async_trait;
+use ic_agent::agent::HttpService;
 use ic_agent::identity::Secp256k1Identity;
-use ic_agent::{Agent, export::Principal};
+use ic_agent::{Agent, AgentError, export::Principal};
 use k256::ecdsa::SigningKey;
+use reqwest::{Request, Response, StatusCode};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::{Instrument, debug, error, info, info_span, warn};
 
@@ -9,6 +13,10 @@ const MAX_ROOT_KEY_RETRIES: u32 = 5;
 const ROOT_KEY_RETRY_DELAY: Duration = Duration::from_secs(2);
 const MAX_AGENT_CALL_RETRIES: u32 = 3;
 const AGENT_CALL_RETRY_DELAY: Duration = Duration::from_secs(1);
+// Transport settings matching the agent's built-in HTTP service.
+const AGENT_HTTP_TIMEOUT: Duration = Duration::from_secs(360);
+const MAX_RATE_LIMIT_RETRIES: usize = 6;
+const RATE_LIMIT_RETRY_DELAY: Duration = Duration::from_millis(250);
 
 pub async fn build_ic_agent(
     network_type: &str,
@@ -28,6 +36,7 @@ pub async fn build_ic_agent(
     let agent = Agent::builder()
         .with_url(url)
         .with_identity(ephemeral_identity)
+        .with_arc_http_middleware(Arc::new(TracingHttpService::new()?))
         .build()
         .map_err(|e| NodeError::Other(format!("Failed to build agent: {}", e)))?;
 
@@ -43,6 +52,53 @@ pub async fn build_ic_agent(
     Ok(agent)
 }
 
+/// HTTP transport for the agent that adds the current trace context to every
+/// request, so canister calls join the trace of the job that made them.
+struct TracingHttpService {
+    client: reqwest::Client,
+}
+
+impl TracingHttpService {
+    fn new() -> Result<Self, NodeError> {
+        let client = reqwest::Client::builder()
+            .use_rustls_tls()
+            .timeout(AGENT_HTTP_TIMEOUT)
+            .build()
+            .map_err(|e| NodeError::Other(format!("Failed to build agent HTTP client: {}", e)))?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl HttpService for TracingHttpService {
+    async fn call<'a>(
+        &'a self,
+        req: &'a (dyn Fn() -> Result<Request, AgentError> + Send + Sync),
+        max_tcp_retries: usize,
+    ) -> Result<Response, AgentError> {
+        let traced_request = || {
+            let mut request = req()?;
+            request
+                .headers_mut()
+                .extend(telemetry::trace_context_headers());
+            Ok(request)
+        };
+
+        // Replacing the agent's transport also replaces its retry on rate limiting.
+        let mut retries = 0;
+        loop {
+            let response = self.client.call(&traced_request, max_tcp_retries).await?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS
+                || retries == MAX_RATE_LIMIT_RETRIES
+            {
+                return Ok(response);
+            }
+            retries += 1;
+            tokio::time::sleep(RATE_LIMIT_RETRY_DELAY).await;
+        }
+    }
+}
+
 async fn fetch_root_key_with_retry(agent: &Agent, max_retries: u32) -> Result<(), NodeError> {
     for attempt in 1..=max_retries {
         debug!(
@@ -197,7 +253,11 @@ where
 
         for attempt in 1..=retries {
             let start_time = Instant::now();
-            match operation().await {
+            let outcome = operation().await;
+            if let Some(metrics) = metrics::installed() {
+                metrics.observe_canister_call(method_name, start_time.elapsed());
+            }
+            match outcome {
                 Ok(result) => {
                     let duration_ms = start_time.elapsed().as_millis();
                     tracing::info!(duration_ms, attempt, "Call successful");
@@ -1,11 +1,12 @@
-use crate::{core::error::NodeError, clients::canister::instrumented_canister_call};
+use crate::{clients::canister::instrumented_canister_call, core::error::NodeError};
 use candid::{Decode, Encode};
 use gpt_types::{
     api::{
         ClaimJobRequest, ClaimJobResponse, ClaimJobResult, CompleteJobRequest, CompleteJobResponse,
-        CompleteJobResult, JobCompletionResult,
+        CompleteJobResult, JobCompletionResult, NodeGetJobStatusRequest, NodeGetJobStatusResult,
+        NodeStoreContextCheckpointRequest, NodeStoreContextCheckpointResult,
     },
-    domain::message::TokenUsage,
+    domain::{Citation, GenerationStatus, MessageId, message::TokenUsage},
     error::CanisterResult,
 };
 use ic_agent::{Agent, export::Principal};
@@ -62,11 +63,13 @@ pub async fn complete_job(
     completion_result: JobCompletionResult,
     user_canister: Principal,
     usage: Option<TokenUsage>,
+    citations: Option<Vec<Citation>>,
 ) -> Result<(), NodeError> {
     let request = CompleteJobRequest {
         job_id,
         result: completion_result,
         usage,
+        citations,
     };
 
     // Redact sensitive data from logging
@@ -74,6 +77,8 @@ pub async fn complete_job(
         JobCompletionResult::Success(content) => format!("Success(len:{})", content.len()),
         JobCompletionResult::Failure(e) => format!("Failure({e:?})"),
         JobCompletionResult::ToolCall(calls) => format!("ToolCall(count:{})", calls.len()),
+        JobCompletionResult::Cancelled(content) => format!("Cancelled(len:{})", content.len()),
+        JobCompletionResult::Embeddings(vectors) => format!("Embeddings(count:{})", vectors.len()),
     };
     debug!(
         job_id,
@@ -109,3 +114,84 @@ pub async fn complete_job(
 
     decoded.map(|_| ()).map_err(NodeError::from)
 }
+
+/// Current status of a job assigned to this node, used to notice cancellations.
+pub async fn get_job_status(
+    agent: &Agent,
+    job_id: u64,
+    user_canister: Principal,
+) -> Result<GenerationStatus, NodeError> {
+    let request = NodeGetJobStatusRequest { job_id };
+    let args = Encode!(&request)?;
+    let operation = || async {
+        agent
+            .query(&user_canister, "node_get_job_status")
+            .with_arg(args.clone())
+            .call()
+            .await
+    };
+
+    let response_bytes = instrumented_canister_call(
+        "get_job_status",
+        false,
+        &user_canister,
+        "node_get_job_status",
+        operation,
+        Some(1),
+    )
+    .await?;
+
+    let decoded: NodeGetJobStatusResult = Decode!(&response_bytes, NodeGetJobStatusResult)?;
+
+    decoded
+        .map(|response| response.generation_status)
+        .map_err(NodeError::from)
+}
+
+/// Stores an encrypted summary of the job's history up to `covered_message_id`.
+/// Returns the id of the checkpoint message.
+pub async fn store_context_checkpoint(
+    agent: &Agent,
+    job_id: u64,
+    covered_message_id: MessageId,
+    content: Vec<u8>,
+    user_canister: Principal,
+) -> Result<MessageId, NodeError> {
+    let request = NodeStoreContextCheckpointRequest {
+        job_id,
+        covered_message_id,
+        content,
+    };
+    debug!(
+        job_id,
+        covered_message_id,
+        content_len = request.content.len(),
+        user_canister = %user_canister,
+        "Calling 'node_store_context_checkpoint' on user canister"
+    );
+
+    let args = Encode!(&request)?;
+    let operation = || {
+        agent
+            .update(&user_canister, "node_store_context_checkpoint")
+            .with_arg(args.clone())
+            .call_and_wait()
+    };
+
+    let response_bytes = instrumented_canister_call(
+        "node_store_context_checkpoint",
+        true,
+        &user_canister,
+        "node_store_context_checkpoint",
+        operation,
+        Some(MAX_RETRIES),
+    )
+    .await?;
+
+    let decoded: NodeStoreContextCheckpointResult =
+        Decode!(&response_bytes, NodeStoreContextCheckpointResult)?;
+
+    decoded
+        .map(|response| response.checkpoint_message_id)
+        .map_err(NodeError::from)
+}
@@ -0,0 +1,192 @@
+use crate::clients::canister::instrumented_canister_call;
+use crate::core::error::NodeError;
+use candid::{Decode, Encode};
+use gpt_types::{
+    api::{
+        NodeGetFileContentRequest, NodeGetFileContentResponse, NodeGetFileContentResult,
+        NodeGetRetrievalChunksRequest, NodeGetRetrievalChunksResult, NodeStoreFileChunksRequest,
+        NodeStoreFileChunksResult, RetrievalChunk,
+    },
+    domain::{FileId, TextChunk},
+};
+use ic_agent::{Agent, export::Principal};
+use tracing::{debug, error};
+
+/// Bytes requested per page; the canister caps each read at 2 MB.
+const FILE_READ_PAGE_BYTES: u64 = 2_000_000;
+/// Attempts per page before the read or write is abandoned.
+const FILE_PAGE_RETRIES: u32 = 3;
+
+/// A stored file read by a job.
+pub struct FileContent {
+    pub content: Vec<u8>,
+    pub name: String,
+    pub mime_type: String,
+}
+
+/// Reads a whole file for a job claimed by this node, one range at a time: the file
+/// attached in the job's chain with id `file_id`, or the file of an ingestion job.
+pub async fn fetch_file_content(
+    agent: &Agent,
+    job_id: u64,
+    file_id: Option<FileId>,
+    user_canister: Principal,
+) -> Result<FileContent, NodeError> {
+    let mut content = Vec::new();
+    loop {
+        let page =
+            fetch_file_page(agent, job_id, file_id, content.len() as u64, user_canister).await?;
+        debug!(
+            job_id,
+            offset = page.offset,
+            page_bytes = page.content.len(),
+            total = page.total_size_bytes,
+            "Fetched file content page"
+        );
+        if page.content.is_empty() && page.offset < page.total_size_bytes {
+            return Err(NodeError::Other("File read did not advance".to_string()));
+        }
+        content.extend_from_slice(&page.content);
+        if content.len() as u64 >= page.total_size_bytes {
+            return Ok(FileContent {
+                content,
+                name: page.name,
+                mime_type: page.mime_type,
+            });
+        }
+    }
+}
+
+async fn fetch_file_page(
+    agent: &Agent,
+    job_id: u64,
+    file_id: Option<FileId>,
+    offset: u64,
+    user_canister: Principal,
+) -> Result<NodeGetFileContentResponse, NodeError> {
+    let request = NodeGetFileContentRequest {
+        job_id,
+        file_id,
+        offset,
+        length: FILE_READ_PAGE_BYTES,
+    };
+    let args = Encode!(&request)?;
+    let operation = || async {
+        agent
+            .query(&user_canister, "node_get_file_content")
+            .with_arg(args.clone())
+            .call()
+            .await
+    };
+
+    let response_bytes = instrumented_canister_call(
+        "fetch_file_content",
+        false,
+        &user_canister,
+        "node_get_file_content",
+        operation,
+        Some(FILE_PAGE_RETRIES),
+    )
+    .await?;
+
+    let decoded: NodeGetFileContentResult = Decode!(&response_bytes, NodeGetFileContentResult)?;
+    decoded.map_err(|e| {
+        error!(job_id, ?file_id, offset, error = ?e, "Canister error when reading file content");
+        NodeError::Canister(e)
+    })
+}
+
+/// Stores one page of an ingestion job's chunks. Returns the number of chunks the
+/// canister holds for the job.
+pub async fn store_file_chunks(
+    agent: &Agent,
+    job_id: u64,
+    chunks: Vec<TextChunk>,
+    user_canister: Principal,
+) -> Result<u32, NodeError> {
+    let request = NodeStoreFileChunksRequest { job_id, chunks };
+    debug!(
+        job_id,
+        chunk_count = request.chunks.len(),
+        user_canister = %user_canister,
+        "Calling 'node_store_file_chunks' on user canister"
+    );
+
+    let args = Encode!(&request)?;
+    let operation = || {
+        agent
+            .update(&user_canister, "node_store_file_chunks")
+            .with_arg(args.clone())
+            .call_and_wait()
+    };
+
+    let response_bytes = instrumented_canister_call(
+        "node_store_file_chunks",
+        true,
+        &user_canister,
+        "node_store_file_chunks",
+        operation,
+        Some(FILE_PAGE_RETRIES),
+    )
+    .await?;
+
+    let decoded: NodeStoreFileChunksResult = Decode!(&response_bytes, NodeStoreFileChunksResult)?;
+    decoded
+        .map(|response| response.stored_chunks)
+        .map_err(NodeError::from)
+}
+
+/// Reads every chunk a retrieval job claimed by this node searches, one page at a
+/// time. Returns the chunks with the provider's name for the job's embedding model.
+pub async fn fetch_retrieval_chunks(
+    agent: &Agent,
+    job_id: u64,
+    user_canister: Principal,
+) -> Result<(Vec<RetrievalChunk>, String), NodeError> {
+    let mut chunks = Vec::new();
+    let mut cursor = None;
+    loop {
+        let request = NodeGetRetrievalChunksRequest { job_id, cursor };
+        let args = Encode!(&request)?;
+        let operation = || async {
+            agent
+                .query(&user_canister, "node_get_retrieval_chunks")
+                .with_arg(args.clone())
+                .call()
+                .await
+        };
+
+        let response_bytes = instrumented_canister_call(
+            "fetch_retrieval_chunks",
+            false,
+            &user_canister,
+            "node_get_retrieval_chunks",
+            operation,
+            Some(FILE_PAGE_RETRIES),
+        )
+        .await?;
+
+        let decoded: NodeGetRetrievalChunksResult =
+            Decode!(&response_bytes, NodeGetRetrievalChunksResult)?;
+        let page = decoded.map_err(|e| {
+            error!(job_id, ?cursor, error = ?e, "Canister error when reading retrieval chunks");
+            NodeError::Canister(e)
+        })?;
+        debug!(
+            job_id,
+            ?cursor,
+            page_chunks = page.chunks.len(),
+            "Fetched retrieval chunk page"
+        );
+        chunks.extend(page.chunks);
+        match page.next_cursor {
+            Some(next) if Some(next) != cursor => cursor = Some(next),
+            Some(_) => {
+                return Err(NodeError::Other(
+                    "Retrieval chunk read did not advance".to_string(),
+                ));
+            }
+            None => return Ok((chunks, page.embedding_provider_model)),
+        }
+    }
+}
@@ -2,97 +2,127 @@ use crate::core::error::NodeError;
 use crate::clients::canister::instrumented_canister_call;
 use candid::{Decode, Encode};
 use gpt_types::{
-    api::{NodeGetMessageRequest, NodeGetMessageResponse, NodeGetMessageResult},
+    api::{NodeGetMessageChainRequest, NodeGetMessageChainResponse, NodeGetMessageChainResult},
     domain::Message,
-    error::CanisterResult,
 };
 use ic_agent::{Agent, export::Principal};
 use tracing::{debug, error, warn};
 
-pub async fn fetch_message(
+/// Attempts per page before the history fetch is abandoned.
+const MESSAGE_CHAIN_PAGE_RETRIES: u32 = 3;
+
+/// Fetches the full message chain of a claimed job, oldest message first.
+/// Pages are requested until the canister reports no further cursor; each page is
+/// retried independently, so a transient failure does not restart the whole fetch.
+pub async fn fetch_message_chain(
     agent: &Agent,
-    message_id: u64,
+    job_id: u64,
     chat_id: u64,
     user_canister_principal: Principal,
-) -> Result<Option<Message>, NodeError> {
-    debug!(message_id, chat_id, "Fetching message from user canister");
+) -> Result<Vec<Message>, NodeError> {
+    let mut messages = Vec::new();
+    let mut cursor = None;
+
+    loop {
+        let page = fetch_message_chain_page(agent, job_id, cursor, user_canister_principal).await?;
+        let received = page.messages.len();
+
+        for msg in page.messages {
+            if msg.chat_id != chat_id {
+                warn!(
+                    message_id = msg.message_id,
+                    reported_chat_id = msg.chat_id,
+                    expected_chat_id = chat_id,
+                    "Message belongs to a different chat"
+                );
+                continue;
+            }
+            messages.push(msg);
+        }
 
-    let get_message_args = match Encode!(&NodeGetMessageRequest { message_id }) {
+        debug!(
+            job_id,
+            page_messages = received,
+            total = page.total,
+            next_cursor = ?page.next_cursor,
+            "Fetched message chain page"
+        );
+
+        match page.next_cursor {
+            Some(next) if cursor.is_none_or(|current| next > current) => cursor = Some(next),
+            Some(next) => {
+                error!(job_id, ?cursor, next, "Message chain cursor did not advance");
+                return Err(NodeError::Other(
+                    "Message chain pagination did not advance".to_string(),
+                ));
+            }
+            None => break,
+        }
+    }
+
+    Ok(messages)
+}
+
+async fn fetch_message_chain_page(
+    agent: &Agent,
+    job_id: u64,
+    cursor: Option<u32>,
+    user_canister_principal: Principal,
+) -> Result<NodeGetMessageChainResponse, NodeError> {
+    let args = match Encode!(&NodeGetMessageChainRequest { job_id, cursor }) {
         Ok(args) => args,
         Err(e) => {
-            error!(error = ?e, "Failed to encode NodeGetMessageRequest");
+            error!(error = ?e, "Failed to encode NodeGetMessageChainRequest");
             return Err(NodeError::Candid(e));
         }
     };
 
     let operation = || async {
         agent
-            .query(&user_canister_principal, "node_get_message")
-            .with_arg(get_message_args.clone())
+            .query(&user_canister_principal, "node_get_message_chain")
+            .with_arg(args.clone())
             .call()
             .await
     };
 
     let response_bytes = match instrumented_canister_call(
-        "fetch_message",
+        "fetch_message_chain",
         false,
         &user_canister_principal,
-        "node_get_message",
+        "node_get_message_chain",
         operation,
-        Some(3),
+        Some(MESSAGE_CHAIN_PAGE_RETRIES),
     )
     .await
     {
         Ok(bytes) => bytes,
         Err(e) => {
             error!(
-                message_id,
+                job_id,
+                ?cursor,
                 error = %e,
-                "Failed to fetch message after retries"
+                "Failed to fetch message chain page after retries"
             );
             return Err(e);
         }
     };
 
-    let decoded_result: NodeGetMessageResult = match Decode!(&response_bytes, NodeGetMessageResult)
-    {
-        Ok(res) => res,
-        Err(e) => {
-            error!(error = %e, "Failed to decode NodeGetMessageResponse");
-            return Err(NodeError::Candid(e));
-        }
-    };
-
-    let result: CanisterResult<NodeGetMessageResponse> = decoded_result.into();
-
-    match result {
-        Ok(resp) => {
-            let msg = resp.message;
-            if msg.chat_id != chat_id {
-                warn!(
-                    message_id,
-                    reported_chat_id = msg.chat_id,
-                    expected_chat_id = chat_id,
-                    "Message belongs to a different chat"
-                );
-                return Ok(None);
+    let decoded_result: NodeGetMessageChainResult =
+        match Decode!(&response_bytes, NodeGetMessageChainResult) {
+            Ok(res) => res,
+            Err(e) => {
+                error!(error = %e, "Failed to decode NodeGetMessageChainResponse");
+                return Err(NodeError::Candid(e));
             }
-            // Redacted log: Do not log full message content
-            debug!(
-                message_id = msg.message_id,
-                chat_id = msg.chat_id,
-                role = ?msg.role,
-                "Successfully fetched and validated message"
-            );
-            Ok(Some(msg))
-        }
-        Err(e) => {
-            error!(
-                message_id,
-                error = ?e,
-                "Canister error when fetching message"
-            );
-            Err(NodeError::Canister(e))
-        }
-    }
+        };
+
+    decoded_result.map_err(|e| {
+        error!(
+            job_id,
+            ?cursor,
+            error = ?e,
+            "Canister error when fetching message chain"
+        );
+        NodeError::Canister(e)
+    })
 }
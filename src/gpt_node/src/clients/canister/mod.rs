@@ -1,5 +1,6 @@
 pub mod client;
 pub mod conversation;
+pub mod file;
 pub mod message;
 pub mod requirements;
 pub mod whoami;
@@ -15,6 +15,8 @@ pub enum NodeError {
     Provider(OpenAIError),
     Configuration(String),
     Attestation(String),
+    /// The response did not match the job's response schema, or the schema is unusable.
+    SchemaValidation(String),
     Other(String),
 }
 
@@ -57,6 +59,7 @@ impl NodeError {
             },
             NodeError::Agent(_) => ErrorSeverity::Transient, // Transport issues
             NodeError::Candid(_) => ErrorSeverity::JobFailure, // Bad data format
+            NodeError::SchemaValidation(_) => ErrorSeverity::JobFailure,
             NodeError::Other(_) => ErrorSeverity::JobFailure,
         }
     }
@@ -118,6 +121,7 @@ impl fmt::Display for NodeError {
             NodeError::Provider(e) => write!(f, "Provider API error: {}", e),
             NodeError::Configuration(e) => write!(f, "Configuration error: {}", e),
             NodeError::Attestation(e) => write!(f, "Attestation error: {}", e),
+            NodeError::SchemaValidation(e) => write!(f, "Schema validation error: {}", e),
             NodeError::Other(e) => write!(f, "Other node error: {}", e),
         }
     }
@@ -189,6 +193,7 @@ pub fn map_node_error_to_message_status(err: &NodeError) -> MessageErrorStatus {
         NodeError::Candid(e) => {
             MessageErrorStatus::CanisterCallError(format!("Candid error: {}", e))
         }
+        NodeError::SchemaValidation(msg) => MessageErrorStatus::SchemaValidationFailed(msg.clone()),
         NodeError::Other(msg) => MessageErrorStatus::Unknown(msg.clone()),
     }
 }
@@ -0,0 +1,132 @@
+//! Files attached to the messages of a job's chain. The node reads each file through
+//! the job's read grant and decrypts it with the job's file key; images become image
+//! parts of their message and other files are appended to its text.
+
+use crate::{
+    clients::canister::file::fetch_file_content,
+    core::error::NodeError,
+    core::job::encryption::{decrypt_bytes, decrypt_chat_key},
+    core::job::ingestion::extract::extract_text,
+    core::state::AppState,
+};
+use gpt_types::{
+    domain::{FileId, Job, message::ImageAttachment},
+    error::CanisterError,
+};
+use ic_agent::export::Principal;
+use tracing::{error, info, warn};
+
+/// Formats `extract_text` reads; other attached files are read as UTF-8 text.
+const EXTRACTED_MIME_TYPES: &[&str] = &[
+    "application/pdf",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "text/html",
+    "text/markdown",
+];
+
+/// The decrypted files of one message.
+#[derive(Default)]
+pub struct AttachedFiles {
+    pub images: Vec<ImageAttachment>,
+    /// The text files, formatted to be appended to the message content
+    pub text: String,
+}
+
+/// Decrypts the job's file key for this node.
+pub fn attached_file_key(state: &AppState, job: &Job) -> Result<Vec<u8>, NodeError> {
+    let file_key = job
+        .file_keys
+        .iter()
+        .flatten()
+        .find(|key| key.node_id == state.node_id)
+        .ok_or_else(|| NodeError::Other("The job has no file key for this node".to_string()))?;
+    decrypt_chat_key(&file_key.encrypted_chat_key, &state.node_x25519_identity).map_err(|e| {
+        error!("Failed to decrypt file key: {}", e);
+        NodeError::Other("File key decryption failed".to_string())
+    })
+}
+
+/// Reads and decrypts the files a message attaches. Files deleted since they were
+/// attached, and files without readable text, are left out.
+pub async fn read_attached_files(
+    state: &AppState,
+    job_id: u64,
+    user_canister: Principal,
+    file_ids: &[FileId],
+    file_key: &[u8],
+) -> Result<AttachedFiles, NodeError> {
+    let mut files = AttachedFiles::default();
+    for &file_id in file_ids {
+        let file =
+            match fetch_file_content(&state.agent, job_id, Some(file_id), user_canister).await {
+                Ok(file) => file,
+                Err(NodeError::Canister(CanisterError::FileNotFound)) => {
+                    warn!(file_id, "Attached file no longer exists.");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+        let content = decrypt_bytes(&file.content, file_key).map_err(|e| {
+            error!("Failed to decrypt attached file {}: {}", file_id, e);
+            NodeError::Other("Attached file decryption failed".to_string())
+        })?;
+
+        if file.mime_type.starts_with("image/") {
+            files.images.push(ImageAttachment {
+                mime_type: file.mime_type,
+                data: content,
+            });
+            continue;
+        }
+        let mime_type = file.mime_type.clone();
+        let text = tokio::task::spawn_blocking(move || attached_text(content, &mime_type))
+            .await
+            .map_err(|e| NodeError::Other(format!("Text extraction did not finish: {e}")))?;
+        match text {
+            Ok(text) => files.text.push_str(&format_attached_text(&file.name, &text)),
+            Err(e) => warn!(file_id, error = %e, "Skipped an attached file without readable text."),
+        }
+    }
+    info!(
+        file_count = file_ids.len(),
+        image_count = files.images.len(),
+        text_chars = files.text.chars().count(),
+        "Read the message's attached files."
+    );
+    Ok(files)
+}
+
+fn attached_text(content: Vec<u8>, mime_type: &str) -> Result<String, NodeError> {
+    if EXTRACTED_MIME_TYPES.contains(&mime_type) {
+        return extract_text(&content, mime_type);
+    }
+    String::from_utf8(content)
+        .map_err(|_| NodeError::Other(format!("The {mime_type} file is not UTF-8 text")))
+}
+
+fn format_attached_text(name: &str, text: &str) -> String {
+    format!(
+        "\n\n<attached_file name=\"{}\">\n{}\n</attached_file>",
+        name,
+        text.trim()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attached_text_is_read_by_format() {
+        let json = attached_text(b"{\"a\": 1}".to_vec(), "application/json").unwrap();
+        assert_eq!(json, "{\"a\": 1}");
+        let markdown = attached_text(b"# Title\n\nBody".to_vec(), "text/markdown").unwrap();
+        assert!(markdown.contains("Title") && !markdown.contains('#'));
+        assert!(attached_text(vec![0xff, 0xfe], "text/csv").is_err());
+
+        assert_eq!(
+            format_attached_text("notes.txt", "  Buy milk.\n"),
+            "\n\n<attached_file name=\"notes.txt\">\nBuy milk.\n</attached_file>"
+        );
+    }
+}
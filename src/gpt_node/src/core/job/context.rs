@@ -10,6 +10,8 @@ pub struct JobProcessingContext {
     pub user_canister: Principal,
     pub claim_response: ClaimJobResponse,
     pub conversation_history: Vec<MessageData>,
+    /// Decrypted inputs of an embedding batch job
+    pub embedding_inputs: Option<Vec<String>>,
     pub tools: Option<Vec<Tool>>,
     pub stream_key: String,
     pub chat_key: Vec<u8>,
@@ -0,0 +1,352 @@
+//! Fitting a job's conversation history into its context budget.
+//!
+//! `Truncate` leaves overflow to the request builder, which drops the oldest messages.
+//! `Summarize` replaces the oldest messages with a summary written by the served model
+//! and stores it on the user canister as an encrypted checkpoint. Later jobs in the
+//! chat receive the checkpoint in place of the messages it covers, and only summarize
+//! again, folding in the previous summary, once the remaining history overflows.
+
+use super::{
+    context::JobProcessingContext,
+    encryption::{decrypt_content, encrypt_content},
+    processor::strip_reasoning,
+    types::MessageData,
+};
+use crate::{
+    clients::ai_provider::{
+        context::message_tokens, summarize_transcript, tokenizer::TokenCounter,
+    },
+    clients::canister::conversation::store_context_checkpoint,
+    core::error::NodeError,
+    core::state::AppState,
+};
+use gpt_types::domain::{ContextStrategy, MessageId};
+use std::fmt::Write;
+use tracing::{info, warn};
+
+/// Upper bound on the length of a summary.
+const MAX_SUMMARY_TOKENS: u32 = 1024;
+const SUMMARY_HEADER: &str = "Summary of the earlier conversation:";
+
+/// A summary and the id of the last message it covers.
+struct Summary {
+    covered_message_id: MessageId,
+    text: String,
+}
+
+/// Applies the job's context strategy to `history`, the messages of the claimed chain.
+/// Any checkpoint the canister returned with the claim is put in front of them.
+pub(super) async fn apply_context_strategy(
+    state: &AppState,
+    ctx: &JobProcessingContext,
+    history: Vec<MessageData>,
+) -> Vec<MessageData> {
+    let summary =
+        ctx.claim_response.context_checkpoint.as_ref().and_then(
+            |checkpoint| match decrypt_content(&checkpoint.content, &ctx.chat_key) {
+                Ok(text) => Some(Summary {
+                    covered_message_id: checkpoint.parent_message_id?,
+                    text,
+                }),
+                Err(e) => {
+                    warn!(
+                        checkpoint_id = checkpoint.message_id,
+                        error = %e,
+                        "Failed to decrypt context checkpoint; continuing without it."
+                    );
+                    None
+                }
+            },
+        );
+
+    let (system, others): (Vec<_>, Vec<_>) = history.into_iter().partition(|m| m.role == "system");
+
+    match ctx.claim_response.job.context_strategy.unwrap_or_default() {
+        ContextStrategy::Truncate => assemble(system, summary, others),
+        ContextStrategy::Summarize => summarize_overflow(state, ctx, system, summary, others).await,
+    }
+}
+
+async fn summarize_overflow(
+    state: &AppState,
+    ctx: &JobProcessingContext,
+    system: Vec<MessageData>,
+    summary: Option<Summary>,
+    others: Vec<MessageData>,
+) -> Vec<MessageData> {
+    let counter = &state.token_counter;
+    let max_context = ctx.claim_response.job.max_context;
+    let tokens = |messages: &[MessageData]| -> u32 {
+        messages.iter().map(|m| message_tokens(m, counter)).sum()
+    };
+    let system_tokens = tokens(&system);
+    let summary_tokens = summary
+        .as_ref()
+        .map_or(0, |s| counter.count_text(&summary_content(&s.text)));
+    if system_tokens + summary_tokens + tokens(&others) <= max_context {
+        return assemble(system, summary, others);
+    }
+
+    let summary_budget = MAX_SUMMARY_TOKENS.min(max_context / 4);
+    let Some(kept_budget) = max_context.checked_sub(system_tokens + summary_budget) else {
+        warn!("System messages leave no room for a summary; truncating instead.");
+        return assemble(system, summary, others);
+    };
+    let split = split_point(&others, kept_budget, counter);
+    if split == 0 {
+        return assemble(system, summary, others);
+    }
+
+    let covered_message_id = others[split - 1].message_id;
+    let start = transcript_start(
+        &others[..split],
+        max_context.saturating_sub(summary_tokens),
+        counter,
+    );
+    let transcript = build_transcript(summary.as_ref(), &others[start..split]);
+    info!(
+        summarized_messages = split,
+        dropped_messages = start,
+        covered_message_id,
+        "Summarizing the oldest messages to fit the context budget."
+    );
+
+    match summarize_and_store(state, ctx, transcript, covered_message_id, summary_budget).await {
+        Ok(text) => {
+            let kept = others.into_iter().skip(split).collect();
+            let summary = Summary {
+                covered_message_id,
+                text,
+            };
+            assemble(system, Some(summary), kept)
+        }
+        Err(e) => {
+            warn!(error = %e, "Summarizing the history failed; truncating instead.");
+            assemble(system, summary, others)
+        }
+    }
+}
+
+/// Writes the summary and stores it as a checkpoint. A summary that could not be
+/// stored is still used for this job.
+async fn summarize_and_store(
+    state: &AppState,
+    ctx: &JobProcessingContext,
+    transcript: String,
+    covered_message_id: MessageId,
+    max_tokens: u32,
+) -> Result<String, NodeError> {
+    let summary = summarize_transcript(state, ctx.job_id, transcript, max_tokens).await?;
+    let summary = strip_reasoning(&summary);
+
+    let stored = match encrypt_content(&summary, &ctx.chat_key) {
+        Ok(content) => {
+            store_context_checkpoint(
+                &state.agent,
+                ctx.job_id,
+                covered_message_id,
+                content,
+                ctx.user_canister,
+            )
+            .await
+        }
+        Err(e) => Err(NodeError::Other(format!(
+            "Failed to encrypt summary: {}",
+            e
+        ))),
+    };
+    match stored {
+        Ok(checkpoint_message_id) => info!(checkpoint_message_id, "Context checkpoint stored."),
+        Err(e) => warn!(error = %e, "Failed to store context checkpoint."),
+    }
+    Ok(summary)
+}
+
+/// Number of leading messages to summarize so that the rest fits in `budget`.
+/// The kept part always includes the latest user turn and never starts with a tool
+/// result, which must follow the call it answers.
+fn split_point(messages: &[MessageData], budget: u32, counter: &TokenCounter) -> usize {
+    let mut split = messages.len();
+    let mut kept_tokens = 0u32;
+    for (index, msg) in messages.iter().enumerate().rev() {
+        kept_tokens += message_tokens(msg, counter);
+        if kept_tokens > budget {
+            break;
+        }
+        split = index;
+    }
+
+    if let Some(latest_turn) = messages.iter().rposition(|m| m.role == "user") {
+        split = split.min(latest_turn);
+    }
+    while split < messages.len() && messages[split].role == "tool" {
+        split += 1;
+    }
+    split
+}
+
+/// First message of `prefix` that fits in a summarization request of `limit` tokens;
+/// anything older is dropped as truncation would.
+fn transcript_start(prefix: &[MessageData], limit: u32, counter: &TokenCounter) -> usize {
+    let mut start = prefix.len();
+    let mut tokens = 0u32;
+    for (index, msg) in prefix.iter().enumerate().rev() {
+        tokens += message_tokens(msg, counter);
+        if tokens > limit {
+            break;
+        }
+        start = index;
+    }
+    start
+}
+
+fn build_transcript(previous: Option<&Summary>, messages: &[MessageData]) -> String {
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        let _ = writeln!(transcript, "{}\n{}\n", SUMMARY_HEADER, previous.text);
+    }
+    for msg in messages {
+        let speaker = match msg.role.as_str() {
+            "user" => "User",
+            "assistant" => "Assistant",
+            "tool" => "Tool result",
+            _ => "System",
+        };
+        let _ = write!(transcript, "{}: {}", speaker, msg.content);
+        if let Some(images) = msg.attachments.as_ref().filter(|a| !a.is_empty()) {
+            let _ = write!(transcript, " [{} image(s) attached]", images.len());
+        }
+        for call in msg.tool_calls.iter().flatten() {
+            let _ = write!(
+                transcript,
+                " [Called tool {} with {}]",
+                call.function.name, call.function.arguments
+            );
+        }
+        transcript.push_str("\n\n");
+    }
+    transcript
+}
+
+fn summary_content(text: &str) -> String {
+    format!("{}\n{}", SUMMARY_HEADER, text)
+}
+
+/// System messages first, then the summary, then the remaining conversation.
+fn assemble(
+    mut system: Vec<MessageData>,
+    summary: Option<Summary>,
+    others: Vec<MessageData>,
+) -> Vec<MessageData> {
+    if let Some(summary) = summary {
+        system.push(MessageData {
+            message_id: summary.covered_message_id,
+            role: "system".to_string(),
+            content: summary_content(&summary.text),
+            attachments: None,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+    }
+    system.extend(others);
+    system
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::job::types::{FunctionCall, ToolCall};
+
+    fn message(message_id: MessageId, role: &str, content: &str) -> MessageData {
+        MessageData {
+            message_id,
+            role: role.to_string(),
+            content: content.to_string(),
+            attachments: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    #[test]
+    fn test_split_keeps_newest_messages_that_fit() {
+        let counter = TokenCounter::heuristic();
+        let messages = vec![
+            message(1, "user", "First question about the project setup"), // 10 tokens
+            message(2, "assistant", "A long answer about the setup"),     // 8 tokens
+            message(3, "user", "Follow-up question"),                     // 5 tokens
+            message(4, "assistant", ""),
+        ];
+        assert_eq!(split_point(&messages, 100, &counter), 0);
+        assert_eq!(split_point(&messages, 12, &counter), 2);
+        // The latest user turn is kept even if it alone exceeds the budget.
+        assert_eq!(split_point(&messages, 1, &counter), 2);
+    }
+
+    #[test]
+    fn test_split_does_not_start_with_tool_result() {
+        let counter = TokenCounter::heuristic();
+        let mut call = message(2, "assistant", "");
+        call.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            _type: "function".to_string(),
+            function: FunctionCall {
+                name: "search".to_string(),
+                arguments: "{}".to_string(),
+            },
+        }]);
+        let messages = vec![
+            message(1, "user", "Look this up for me please"),
+            call,
+            message(3, "tool", "A very long tool result that fills the budget"),
+            message(4, "tool", "ok"),
+            message(5, "assistant", ""),
+        ];
+        // The latest user turn bounds the split.
+        assert_eq!(split_point(&messages, 1, &counter), 0);
+
+        // Only the last tool result fits, so it is summarized together with its call.
+        let without_user = &messages[1..];
+        assert_eq!(split_point(without_user, 1, &counter), 3);
+    }
+
+    #[test]
+    fn test_transcript_and_assembly() {
+        let previous = Summary {
+            covered_message_id: 1,
+            text: "The user wants a Rust CLI.".to_string(),
+        };
+        let transcript = build_transcript(
+            Some(&previous),
+            &[
+                message(2, "user", "Use clap"),
+                message(3, "assistant", "Sure"),
+            ],
+        );
+        assert!(transcript.starts_with(SUMMARY_HEADER));
+        assert!(transcript.contains("The user wants a Rust CLI."));
+        assert!(transcript.contains("User: Use clap\n\nAssistant: Sure"));
+
+        let assembled = assemble(
+            vec![message(1, "system", "Be brief")],
+            Some(previous),
+            vec![message(4, "user", "Next")],
+        );
+        let roles: Vec<&str> = assembled.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "system", "user"]);
+        assert_eq!(assembled[1].message_id, 1);
+        assert!(assembled[1].content.starts_with(SUMMARY_HEADER));
+    }
+
+    #[test]
+    fn test_transcript_start_drops_oldest_overflow() {
+        let counter = TokenCounter::heuristic();
+        let prefix = vec![
+            message(1, "user", "Old message 1"),
+            message(2, "user", "Old message 2"),
+            message(3, "user", "Old message 3"),
+        ];
+        assert_eq!(transcript_start(&prefix, 100, &counter), 0);
+        assert_eq!(transcript_start(&prefix, 8, &counter), 1);
+    }
+}
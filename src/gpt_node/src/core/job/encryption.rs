@@ -66,9 +66,19 @@ pub fn encrypt_content(plaintext: &str, key: &[u8]) -> anyhow::Result<Vec<u8>> {
     Ok(final_payload)
 }
 
-/// Decrypts content encrypted with AES-256-GCM.
+/// Decrypts text encrypted with AES-256-GCM.
 /// Expects payload format: [nonce (12 bytes)][ciphertext + auth tag].
 pub(super) fn decrypt_content(payload: &[u8], key: &[u8]) -> anyhow::Result<String> {
+    let plaintext_bytes = decrypt_bytes(payload, key)?;
+
+    let plaintext = String::from_utf8(plaintext_bytes)
+        .map_err(|e| anyhow::anyhow!("Invalid UTF-8 after decryption: {}", e))?;
+
+    Ok(plaintext)
+}
+
+/// Decrypts binary content, such as a stored file, in the format of `decrypt_content`.
+pub(super) fn decrypt_bytes(payload: &[u8], key: &[u8]) -> anyhow::Result<Vec<u8>> {
     if payload.len() < GCM_NONCE_SIZE {
         return Err(anyhow::anyhow!("Payload too short for IV"));
     }
@@ -80,12 +90,7 @@ pub(super) fn decrypt_content(payload: &[u8], key: &[u8]) -> anyhow::Result<Stri
     let ciphertext = &payload[GCM_NONCE_SIZE..];
     let nonce = Nonce::from_slice(nonce_bytes);
 
-    let plaintext_bytes = cipher
+    cipher
         .decrypt(nonce, ciphertext)
-        .map_err(|e| anyhow::anyhow!("Decryption error: {}", e))?;
-
-    let plaintext = String::from_utf8(plaintext_bytes)
-        .map_err(|e| anyhow::anyhow!("Invalid UTF-8 after decryption: {}", e))?;
-
-    Ok(plaintext)
+        .map_err(|e| anyhow::anyhow!("Decryption error: {}", e))
 }
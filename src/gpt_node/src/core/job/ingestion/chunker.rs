@@ -0,0 +1,106 @@
+//! Splits extracted text into overlapping chunks for embedding.
+
+/// A chunk of extracted text and its character range within that text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSpan {
+    pub start_char: u32,
+    pub end_char: u32,
+    pub text: String,
+}
+
+/// Splits `text` into chunks of at most `size` characters, each starting `overlap`
+/// characters before the previous one ended. A chunk ends after the last whitespace
+/// in the final fifth of its window when there is one, so words are rarely cut.
+/// Chunks holding only whitespace are dropped.
+pub fn chunk_text(text: &str, size: usize, overlap: usize) -> Vec<TextSpan> {
+    let chars: Vec<char> = text.chars().collect();
+    let size = size.max(1);
+    let overlap = overlap.min(size - 1);
+
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + size).min(chars.len());
+        if end < chars.len() {
+            let earliest_break = (end - size / 5).max(start + 1);
+            if let Some(space) = (earliest_break..end)
+                .rev()
+                .find(|&i| chars[i].is_whitespace())
+            {
+                end = space + 1;
+            }
+        }
+
+        let chunk: String = chars[start..end].iter().collect();
+        if !chunk.trim().is_empty() {
+            spans.push(TextSpan {
+                start_char: start as u32,
+                end_char: end as u32,
+                text: chunk,
+            });
+        }
+        if end == chars.len() {
+            break;
+        }
+        start = (end - overlap).max(start + 1);
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_text_is_one_chunk() {
+        assert_eq!(
+            chunk_text("Hello there", 100, 10),
+            vec![TextSpan {
+                start_char: 0,
+                end_char: 11,
+                text: "Hello there".to_string(),
+            }]
+        );
+        assert!(chunk_text("", 100, 10).is_empty());
+        assert!(chunk_text(" \n ", 100, 10).is_empty());
+    }
+
+    #[test]
+    fn test_chunks_overlap_and_break_at_whitespace() {
+        let text = "alpha beta gamma delta epsilon zeta eta theta";
+        let spans = chunk_text(text, 20, 6);
+
+        assert_eq!(spans[0].text, "alpha beta gamma ");
+        for pair in spans.windows(2) {
+            assert_eq!(pair[1].start_char, pair[0].end_char - 6);
+        }
+        for span in &spans {
+            assert!(span.text.chars().count() <= 20);
+            let expected: String = text
+                .chars()
+                .skip(span.start_char as usize)
+                .take((span.end_char - span.start_char) as usize)
+                .collect();
+            assert_eq!(span.text, expected);
+        }
+        assert_eq!(
+            spans.last().unwrap().end_char as usize,
+            text.chars().count()
+        );
+    }
+
+    #[test]
+    fn test_offsets_count_characters_not_bytes() {
+        let text = "ééééé ééééé";
+        let spans = chunk_text(text, 6, 0);
+        assert_eq!(spans[0].text, "ééééé ");
+        assert_eq!((spans[1].start_char, spans[1].end_char), (6, 11));
+    }
+
+    #[test]
+    fn test_text_without_whitespace_is_cut_at_the_size() {
+        let spans = chunk_text("abcdefghij", 4, 1);
+        let texts: Vec<&str> = spans.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, vec!["abcd", "defg", "ghij"]);
+    }
+}
@@ -0,0 +1,395 @@
+//! Plain-text extraction for the file formats an ingestion job accepts.
+
+use crate::core::error::NodeError;
+use pulldown_cmark::{Event as MarkdownEvent, Parser, TagEnd};
+use quick_xml::events::Event as XmlEvent;
+use std::io::{Cursor, Read, Write};
+use std::process::{Child, Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+/// First argument that starts the binary as a PDF worker instead of a node.
+pub const PDF_WORKER_FLAG: &str = "--pdf-worker";
+
+const PDF_MIME_TYPE: &str = "application/pdf";
+const DOCX_MIME_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+
+/// Upper bound on the decompressed document part of a DOCX file.
+const MAX_DOCX_XML_BYTES: u64 = 64 * 1024 * 1024;
+/// How long a PDF worker may run before it is killed.
+const PDF_WORKER_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often a running PDF worker is checked for having exited.
+const PDF_WORKER_POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Line width HTML is rendered at; wide enough that paragraphs are not wrapped.
+const HTML_RENDER_WIDTH: usize = 10_000;
+
+/// Extracts the text of a decrypted file. Runs of blank lines are collapsed and
+/// trailing spaces removed, so chunk boundaries fall on content.
+pub fn extract_text(content: &[u8], mime_type: &str) -> Result<String, NodeError> {
+    let text = match mime_type {
+        PDF_MIME_TYPE => extract_pdf(content)?,
+        DOCX_MIME_TYPE => extract_docx(content)?,
+        "text/html" => html2text::config::plain_no_decorate()
+            .string_from_read(content, HTML_RENDER_WIDTH)
+            .map_err(|e| invalid_file("HTML", e))?,
+        "text/markdown" => extract_markdown(as_utf8(content)?),
+        "text/plain" => as_utf8(content)?.to_string(),
+        other => {
+            return Err(NodeError::Other(format!(
+                "Text cannot be extracted from {other} files"
+            )));
+        }
+    };
+    Ok(normalize_whitespace(&text))
+}
+
+/// Parses a PDF in a child process. The PDF parser panics on some malformed
+/// documents, and release builds abort on panic, so a parse in this process would
+/// take the node and its running jobs down with it.
+fn extract_pdf(content: &[u8]) -> Result<String, NodeError> {
+    let mut worker = pdf_worker_command()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| NodeError::Other(format!("Failed to start the PDF worker: {e}")))?;
+    // The worker reads all of its input before it writes, so the pipes cannot
+    // fill up both ways. A write error means the worker died; its status says why.
+    if let Some(mut stdin) = worker.stdin.take() {
+        let _ = stdin.write_all(content);
+    }
+    let output = wait_for_pdf_worker(worker, PDF_WORKER_TIMEOUT)?;
+    if !output.status.success() {
+        return Err(NodeError::Other(format!(
+            "PDF could not be parsed: the worker exited with {}",
+            output.status
+        )));
+    }
+    // The worker's result is the last line of its output.
+    let result = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .last()
+        .and_then(|line| serde_json::from_str::<Result<String, String>>(line).ok())
+        .ok_or_else(|| NodeError::Other("The PDF worker returned no result".to_string()))?;
+    result.map_err(|e| invalid_file("PDF", e))
+}
+
+/// Waits for the worker to exit, killing it once `timeout` has passed. Its output
+/// is read on another thread so a full pipe cannot stall the worker.
+fn wait_for_pdf_worker(mut worker: Child, timeout: Duration) -> Result<Output, NodeError> {
+    let mut stdout = worker.stdout.take();
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(stdout) = stdout.as_mut() {
+            let _ = stdout.read_to_end(&mut output);
+        }
+        output
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match worker.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(PDF_WORKER_POLL_INTERVAL),
+            Ok(None) => {
+                let _ = worker.kill();
+                let _ = worker.wait();
+                return Err(NodeError::Other(format!(
+                    "PDF could not be parsed: the worker did not finish within {timeout:?}"
+                )));
+            }
+            Err(e) => {
+                let _ = worker.kill();
+                return Err(NodeError::Other(format!(
+                    "The PDF worker did not finish: {e}"
+                )));
+            }
+        }
+    };
+    let stdout = reader
+        .join()
+        .map_err(|_| NodeError::Other("Failed to read the PDF worker's output".to_string()))?;
+    Ok(Output {
+        status,
+        stdout,
+        stderr: Vec::new(),
+    })
+}
+
+/// Runs the process as a PDF worker: reads a PDF from stdin, prints its text as a
+/// JSON `Result` line and exits. A panic ends the process without a result.
+pub fn run_pdf_worker() -> ! {
+    let mut content = Vec::new();
+    if let Err(e) = std::io::stdin().read_to_end(&mut content) {
+        eprintln!("Failed to read the PDF: {e}");
+        std::process::exit(1);
+    }
+    let result = pdf_extract::extract_text_from_mem(&content).map_err(|e| e.to_string());
+    let line = serde_json::to_string(&result).expect("a Result<String, String> serializes");
+    let mut stdout = std::io::stdout().lock();
+    if writeln!(stdout, "{line}")
+        .and_then(|_| stdout.flush())
+        .is_err()
+    {
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+#[cfg(not(test))]
+fn pdf_worker_command() -> Command {
+    let mut command = Command::new(std::env::current_exe().unwrap_or_else(|_| "gpt_node".into()));
+    command.arg(PDF_WORKER_FLAG);
+    command
+}
+
+/// In tests the current executable is the test harness, which runs the worker
+/// through the `pdf_worker` test.
+#[cfg(test)]
+fn pdf_worker_command() -> Command {
+    let mut command = Command::new(std::env::current_exe().expect("test executable"));
+    command
+        .args([
+            "core::job::ingestion::extract::tests::pdf_worker",
+            "--exact",
+        ])
+        .args(["--nocapture", "--quiet", "--test-threads=1"])
+        .env(tests::PDF_WORKER_ENV, "1");
+    command
+}
+
+/// Collects the runs of `word/document.xml`, ending each paragraph with a newline.
+fn extract_docx(content: &[u8]) -> Result<String, NodeError> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(content)).map_err(|e| invalid_file("DOCX", e))?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .map_err(|e| invalid_file("DOCX", e))?
+        .take(MAX_DOCX_XML_BYTES)
+        .read_to_string(&mut xml)
+        .map_err(|e| invalid_file("DOCX", e))?;
+
+    let mut reader = quick_xml::Reader::from_str(&xml);
+    let mut text = String::new();
+    let mut in_run_text = false;
+    loop {
+        match reader.read_event().map_err(|e| invalid_file("DOCX", e))? {
+            XmlEvent::Start(tag) if tag.local_name().as_ref() == b"t" => in_run_text = true,
+            XmlEvent::End(tag) => match tag.local_name().as_ref() {
+                b"t" => in_run_text = false,
+                b"p" => text.push('\n'),
+                _ => {}
+            },
+            XmlEvent::Empty(tag) => match tag.local_name().as_ref() {
+                b"tab" => text.push('\t'),
+                b"br" | b"cr" => text.push('\n'),
+                _ => {}
+            },
+            XmlEvent::Text(run) if in_run_text => {
+                text.push_str(&run.unescape().map_err(|e| invalid_file("DOCX", e))?)
+            }
+            XmlEvent::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+/// Keeps the text of a Markdown document and drops its markup.
+fn extract_markdown(markdown: &str) -> String {
+    let mut text = String::new();
+    for event in Parser::new(markdown) {
+        match event {
+            MarkdownEvent::Text(t) | MarkdownEvent::Code(t) => text.push_str(&t),
+            MarkdownEvent::SoftBreak => text.push(' '),
+            MarkdownEvent::HardBreak | MarkdownEvent::End(TagEnd::Item | TagEnd::TableRow) => {
+                text.push('\n')
+            }
+            MarkdownEvent::End(
+                TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::BlockQuote(_),
+            ) => text.push_str("\n\n"),
+            MarkdownEvent::End(TagEnd::TableCell) => text.push('\t'),
+            _ => {}
+        }
+    }
+    text
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    let mut blank_run = 0;
+    for line in text.lines().map(str::trim_end) {
+        if line.is_empty() {
+            blank_run += 1;
+            if blank_run > 1 {
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+        normalized.push_str(line);
+        normalized.push('\n');
+    }
+    normalized.trim().to_string()
+}
+
+fn as_utf8(content: &[u8]) -> Result<&str, NodeError> {
+    std::str::from_utf8(content).map_err(|e| invalid_file("text", e))
+}
+
+fn invalid_file(format: &str, error: impl std::fmt::Display) -> NodeError {
+    NodeError::Other(format!("Invalid {format} file: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Set for the test harness process that `pdf_worker_command` starts.
+    pub const PDF_WORKER_ENV: &str = "GPT_NODE_TEST_PDF_WORKER";
+
+    /// The PDF worker when run by `pdf_worker_command`; a no-op otherwise.
+    #[test]
+    fn pdf_worker() {
+        if std::env::var_os(PDF_WORKER_ENV).is_some() {
+            run_pdf_worker();
+        }
+    }
+
+    #[test]
+    fn test_extracts_markdown_text() {
+        let markdown = "# Title\n\nSome *emphasis* and `code`.\n\n- one\n- two\n";
+        assert_eq!(
+            extract_text(markdown.as_bytes(), "text/markdown").unwrap(),
+            "Title\n\nSome emphasis and code.\n\none\ntwo"
+        );
+    }
+
+    #[test]
+    fn test_extracts_html_text() {
+        let html = "<html><head><style>p { color: red; }</style></head>\
+                    <body><h1>Report</h1><p>First &amp; <b>second</b>.</p></body></html>";
+        let text = extract_text(html.as_bytes(), "text/html").unwrap();
+        assert!(text.contains("Report"));
+        assert!(text.contains("First & second."));
+        assert!(!text.contains("color"));
+        assert!(!text.contains('<'));
+    }
+
+    #[test]
+    fn test_extracts_docx_paragraphs() {
+        let document = r#"<?xml version="1.0" encoding="UTF-8"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+  <w:body>
+    <w:p><w:r><w:t>Hello</w:t></w:r><w:r><w:t xml:space="preserve"> world &amp; more</w:t></w:r></w:p>
+    <w:p><w:r><w:t>Second</w:t><w:tab/><w:t>line</w:t></w:r></w:p>
+  </w:body>
+</w:document>"#;
+        let mut docx = Cursor::new(Vec::new());
+        let mut writer = zip::ZipWriter::new(&mut docx);
+        writer
+            .start_file(
+                "word/document.xml",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(document.as_bytes()).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            extract_text(docx.get_ref(), DOCX_MIME_TYPE).unwrap(),
+            "Hello world & more\nSecond\tline"
+        );
+    }
+
+    /// A one-page PDF showing `text` in a standard font.
+    fn pdf_with_text(text: &str) -> Vec<u8> {
+        let stream = format!("BT /F1 12 Tf 72 720 Td ({text}) Tj ET");
+        pdf_from_objects(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] \
+             /Resources << /Font << /F1 4 0 R >> >> /Contents 5 0 R >>"
+                .to_string(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+            format!(
+                "<< /Length {} >>\nstream\n{stream}\nendstream",
+                stream.len()
+            ),
+        ])
+    }
+
+    /// A PDF of the numbered `objects`, object 1 being the catalog, with a valid
+    /// xref table.
+    fn pdf_from_objects(objects: &[String]) -> Vec<u8> {
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(
+            format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+        );
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+        }
+        pdf.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+                objects.len() + 1
+            )
+            .as_bytes(),
+        );
+        pdf
+    }
+
+    #[test]
+    fn test_extracts_pdf_text() {
+        let pdf = pdf_with_text("Quarterly results");
+        assert_eq!(
+            extract_text(&pdf, PDF_MIME_TYPE).unwrap(),
+            "Quarterly results"
+        );
+    }
+
+    #[test]
+    fn test_malformed_pdf_is_an_error_not_a_crash() {
+        // The parser panics on a page without a media box.
+        let pdf = pdf_from_objects(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R >>".to_string(),
+        ]);
+        assert!(extract_text(&pdf, PDF_MIME_TYPE).is_err());
+        let truncated = pdf_with_text("Quarterly results");
+        assert!(extract_text(&truncated[..truncated.len() / 2], PDF_MIME_TYPE).is_err());
+    }
+
+    #[test]
+    fn test_kills_a_pdf_worker_that_runs_past_its_deadline() {
+        let worker = Command::new("sleep")
+            .arg("30")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        let error = wait_for_pdf_worker(worker, Duration::from_millis(200)).unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(error.to_string().contains("did not finish within 200ms"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_and_unsupported_files() {
+        assert!(extract_text(b"not a zip", DOCX_MIME_TYPE).is_err());
+        assert!(extract_text(b"%PDF-1.4 truncated", PDF_MIME_TYPE).is_err());
+        assert!(extract_text(&[0xff, 0xfe], "text/plain").is_err());
+        assert!(extract_text(b"a,b", "text/csv").is_err());
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        assert_eq!(normalize_whitespace("\n\n a  \n\n\n\nb\t\n\n"), "a\n\nb");
+    }
+}
@@ -0,0 +1,112 @@
+//! File ingestion jobs: the node reads a stored file, extracts and chunks its text,
+//! embeds the chunks with the job's embedding model and writes them back to the
+//! user canister, encrypted with the chat key.
+
+pub mod chunker;
+pub mod extract;
+
+use crate::{
+    clients::canister::file::{fetch_file_content, store_file_chunks},
+    core::error::NodeError,
+    core::job::encryption::{decrypt_bytes, encrypt_content},
+};
+use chunker::{TextSpan, chunk_text};
+use extract::extract_text;
+use gpt_types::domain::{FileIngestion, TextChunk};
+use ic_agent::{Agent, export::Principal};
+use serde_bytes::ByteBuf;
+use tracing::{error, info};
+
+/// Encrypted size budget of one node_store_file_chunks page, below the 2 MiB
+/// ingress limit.
+const MAX_CHUNK_PAGE_BYTES: usize = 1_500_000;
+
+/// Reads and decrypts the file of an ingestion job and splits its text into chunks.
+pub async fn load_file_chunks(
+    agent: &Agent,
+    job_id: u64,
+    user_canister: Principal,
+    ingestion: &FileIngestion,
+    chat_key: &[u8],
+) -> Result<Vec<TextSpan>, NodeError> {
+    let file = fetch_file_content(agent, job_id, None, user_canister).await?;
+    let content = decrypt_bytes(&file.content, chat_key).map_err(|e| {
+        error!("Failed to decrypt file content: {}", e);
+        NodeError::Other("File decryption failed".to_string())
+    })?;
+
+    let mime_type = file.mime_type;
+    let text = tokio::task::spawn_blocking(move || extract_text(&content, &mime_type))
+        .await
+        .map_err(|e| NodeError::Other(format!("Text extraction did not finish: {e}")))??;
+
+    let spans = chunk_text(
+        &text,
+        ingestion.chunk_size_chars as usize,
+        ingestion.chunk_overlap_chars as usize,
+    );
+    info!(
+        text_chars = text.chars().count(),
+        chunk_count = spans.len(),
+        "Extracted and chunked file text."
+    );
+    if spans.is_empty() {
+        return Err(NodeError::Other("The file contains no text".to_string()));
+    }
+    Ok(spans)
+}
+
+/// Encrypts each chunk's text and embedding and stores them on the user canister,
+/// in pages that fit the ingress limit.
+pub async fn store_chunks(
+    agent: &Agent,
+    job_id: u64,
+    user_canister: Principal,
+    spans: &[TextSpan],
+    embeddings: &[Vec<f32>],
+    chat_key: &[u8],
+) -> Result<(), NodeError> {
+    if spans.len() != embeddings.len() {
+        return Err(NodeError::Other(format!(
+            "Expected {} embeddings, got {}",
+            spans.len(),
+            embeddings.len()
+        )));
+    }
+
+    let mut page = Vec::new();
+    let mut page_bytes = 0;
+    for (index, (span, embedding)) in spans.iter().zip(embeddings).enumerate() {
+        let encrypted = serde_json::to_string(embedding)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| {
+                Ok((
+                    encrypt_content(&json, chat_key)?,
+                    encrypt_content(&span.text, chat_key)?,
+                ))
+            });
+        let (embedding, content) = encrypted.map_err(|e| {
+            error!("Failed to encrypt chunk {}: {}", index, e);
+            NodeError::Other("Encryption failed".to_string())
+        })?;
+
+        let chunk_bytes = embedding.len() + content.len();
+        if !page.is_empty() && page_bytes + chunk_bytes > MAX_CHUNK_PAGE_BYTES {
+            store_file_chunks(agent, job_id, std::mem::take(&mut page), user_canister).await?;
+            page_bytes = 0;
+        }
+        page.push(TextChunk {
+            chunk_index: index as u32,
+            start_char: span.start_char,
+            end_char: span.end_char,
+            embedding,
+            content: Some(ByteBuf::from(content)),
+        });
+        page_bytes += chunk_bytes;
+    }
+    if !page.is_empty() {
+        let stored = store_file_chunks(agent, job_id, page, user_canister).await?;
+        info!(stored_chunks = stored, "Stored the file's chunks.");
+    }
+    Ok(())
+}
@@ -1,4 +1,9 @@
+pub mod attachments;
 pub mod context;
+pub mod context_strategy;
 pub mod encryption;
+pub mod ingestion;
 pub mod processor;
+pub mod retrieval;
+pub mod stream;
 pub mod types;
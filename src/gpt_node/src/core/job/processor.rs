@@ -2,26 +2,36 @@ use crate::{
     api::websocket::types::ConversationRequest,
     clients::ai_provider::{AIResponse, process_request},
     clients::canister::{
-        conversation::{claim_job, complete_job},
-        message::fetch_message,
+        conversation::{claim_job, complete_job, get_job_status},
+        message::fetch_message_chain,
     },
     core::error::{ErrorSeverity, NodeError, map_node_error_to_message_status},
     core::job::{
+        attachments::{attached_file_key, read_attached_files},
         context::JobProcessingContext,
+        context_strategy::apply_context_strategy,
         encryption::{decrypt_chat_key, decrypt_content, encrypt_content},
+        ingestion::{load_file_chunks, store_chunks},
+        retrieval::add_retrieved_passages,
+        stream::{JobStream, get_or_create_job_stream, retire_job_stream},
         types::{MessageData, OpenAIRequest},
     },
-    core::state::SharedState,
+    core::state::{AppState, SharedState},
     lifecycle,
 };
 use gpt_types::{
     api::{ClaimJobResponse, JobCompletionResult},
-    domain::{Role, tool::Tool},
+    domain::{GenerationStatus, JobKind, Role, tool::Tool},
 };
 use ic_agent::{Agent, export::Principal};
-use std::time::Duration;
+use serde_bytes::ByteBuf;
+use std::{sync::Arc, time::Duration};
 use tracing::{Instrument, debug, error, info, info_span, instrument, warn};
 
+/// How often a running job's status is checked for a cancellation made through the
+/// user canister.
+const CANCELLATION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
 #[instrument(
     skip_all,
     fields(
@@ -39,12 +49,20 @@ pub async fn handle_conversation_job(
     let prep_result = prepare_for_ai_processing(state, &request).await;
 
     match prep_result {
-        Ok((user_canister, claim_resp, conversation_messages, tools, chat_key)) => {
+        Ok((
+            user_canister,
+            claim_resp,
+            conversation_messages,
+            embedding_inputs,
+            tools,
+            chat_key,
+        )) => {
             let context = JobProcessingContext {
                 job_id: request.job_id,
                 user_canister,
                 claim_response: claim_resp,
                 conversation_history: conversation_messages,
+                embedding_inputs,
                 tools,
                 stream_key,
                 chat_key: chat_key.clone(),
@@ -68,6 +86,7 @@ async fn prepare_for_ai_processing(
         Principal,
         ClaimJobResponse,
         Vec<MessageData>,
+        Option<Vec<String>>,
         Option<Vec<Tool>>,
         Vec<u8>,
     ),
@@ -94,15 +113,18 @@ async fn prepare_for_ai_processing(
             let agent_clone = agent.clone();
             let job_id = request.job_id;
             let user_canister_clone = user_canister;
-            tokio::spawn(async move {
-                mark_job_as_failed_and_log(
-                    &agent_clone,
-                    job_id,
-                    user_canister_clone,
-                    failure_status,
-                )
-                .await;
-            });
+            tokio::spawn(
+                async move {
+                    mark_job_as_failed_and_log(
+                        &agent_clone,
+                        job_id,
+                        user_canister_clone,
+                        failure_status,
+                    )
+                    .await;
+                }
+                .in_current_span(),
+            );
             return Err(e);
         }
     };
@@ -117,15 +139,18 @@ async fn prepare_for_ai_processing(
             let agent_clone = agent.clone();
             let job_id = request.job_id;
             let user_canister_clone = user_canister;
-            tokio::spawn(async move {
-                mark_job_as_failed_and_log(
-                    &agent_clone,
-                    job_id,
-                    user_canister_clone,
-                    failure_status,
-                )
-                .await;
-            });
+            tokio::spawn(
+                async move {
+                    mark_job_as_failed_and_log(
+                        &agent_clone,
+                        job_id,
+                        user_canister_clone,
+                        failure_status,
+                    )
+                    .await;
+                }
+                .in_current_span(),
+            );
             NodeError::Attestation(format!("Chat key decryption error: {}", e))
         })?
     } else {
@@ -136,10 +161,18 @@ async fn prepare_for_ai_processing(
         let agent_clone = agent.clone();
         let job_id = request.job_id;
         let user_canister_clone = user_canister;
-        tokio::spawn(async move {
-            mark_job_as_failed_and_log(&agent_clone, job_id, user_canister_clone, failure_status)
+        tokio::spawn(
+            async move {
+                mark_job_as_failed_and_log(
+                    &agent_clone,
+                    job_id,
+                    user_canister_clone,
+                    failure_status,
+                )
                 .await;
-        });
+            }
+            .in_current_span(),
+        );
         return Err(NodeError::Configuration(
             "Missing encrypted_chat_key".to_string(),
         ));
@@ -149,7 +182,7 @@ async fn prepare_for_ai_processing(
 
     info!("Fetching conversation history...");
     let conversation_messages =
-        match fetch_conversation_history(agent, &claim_resp, user_canister, &chat_key).await {
+        match fetch_conversation_history(state, &claim_resp, user_canister, &chat_key).await {
             Ok(messages) => {
                 info!(
                     message_count = messages.len(),
@@ -172,7 +205,34 @@ async fn prepare_for_ai_processing(
                 let agent_clone = agent.clone();
                 let job_id = request.job_id;
                 let user_canister_clone = user_canister;
-                tokio::spawn(async move {
+                tokio::spawn(
+                    async move {
+                        mark_job_as_failed_and_log(
+                            &agent_clone,
+                            job_id,
+                            user_canister_clone,
+                            failure_status,
+                        )
+                        .await;
+                    }
+                    .in_current_span(),
+                );
+                return Err(e);
+            }
+        };
+
+    let embedding_inputs = match decrypt_embedding_inputs(&claim_resp, &chat_key) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            error!(error = ?e, "Failed to decrypt embedding inputs. Aborting.");
+            let failure_status = gpt_types::error::MessageErrorStatus::ConfigurationError(
+                "Embedding inputs corrupted or decryption failed.".to_string(),
+            );
+            let agent_clone = agent.clone();
+            let job_id = request.job_id;
+            let user_canister_clone = user_canister;
+            tokio::spawn(
+                async move {
                     mark_job_as_failed_and_log(
                         &agent_clone,
                         job_id,
@@ -180,21 +240,24 @@ async fn prepare_for_ai_processing(
                         failure_status,
                     )
                     .await;
-                });
-                return Err(e);
-            }
-        };
+                }
+                .in_current_span(),
+            );
+            return Err(e);
+        }
+    };
 
     Ok((
         user_canister,
         claim_resp,
         conversation_messages,
+        embedding_inputs,
         tools,
         chat_key,
     ))
 }
 
-fn spawn_ai_processing_task(state: SharedState, ctx: JobProcessingContext) {
+fn spawn_ai_processing_task(state: SharedState, mut ctx: JobProcessingContext) {
     let span = info_span!(
         "ai_processing",
         job_id = ctx.job_id,
@@ -203,16 +266,51 @@ fn spawn_ai_processing_task(state: SharedState, ctx: JobProcessingContext) {
 
     tokio::spawn(
         async move {
+            let history = std::mem::take(&mut ctx.conversation_history);
+            // An ingestion job embeds the chunks of its file.
+            let ingestion_chunks = match &ctx.claim_response.job.kind {
+                Some(JobKind::FileIngestion(ingestion)) => load_file_chunks(
+                    &state.agent,
+                    ctx.job_id,
+                    ctx.user_canister,
+                    ingestion,
+                    &ctx.chat_key,
+                )
+                .await
+                .map(Some),
+                _ => Ok(None),
+            };
+            let embedding_inputs = match &ingestion_chunks {
+                Ok(Some(spans)) => Some(spans.iter().map(|span| span.text.clone()).collect()),
+                _ => ctx.embedding_inputs.take(),
+            };
+            let mut messages = apply_context_strategy(&state, &ctx, history).await;
+            // A job grounded on the user's files gets the best matching passages.
+            let citations = match &ctx.claim_response.job.retrieval {
+                Some(retrieval) => add_retrieved_passages(
+                    &state,
+                    ctx.job_id,
+                    ctx.user_canister,
+                    retrieval,
+                    &mut messages,
+                )
+                .await
+                .map(Some),
+                None => Ok(None),
+            };
+
             // Unpack context for use
             let job = &ctx.claim_response.job;
             let openai_req = OpenAIRequest {
-                messages: ctx.conversation_history,
+                messages,
                 max_completion_tokens: job.max_completion_tokens,
                 temperature: job.temperature,
                 max_context: job.max_context,
                 tools: ctx.tools,
                 extra_body_json: job.extra_body_json.clone(),
                 reasoning_effort: job.reasoning_effort.clone(),
+                response_schema: job.response_schema.clone(),
+                embedding_inputs,
             };
             let custom_prompt = job.custom_prompt.clone();
             let stream_key = ctx.stream_key.clone();
@@ -222,11 +320,27 @@ fn spawn_ai_processing_task(state: SharedState, ctx: JobProcessingContext) {
                 temperature = openai_req.temperature,
                 has_extra_json = openai_req.extra_body_json.is_some(),
                 reasoning_effort = ?openai_req.reasoning_effort,
+                has_response_schema = openai_req.response_schema.is_some(),
+                embedding_input_count = openai_req.embedding_inputs.as_ref().map(Vec::len),
                 "Starting AI processing with provider"
             );
 
-            let processing_result =
-                process_request(openai_req, stream_key.clone(), &state, custom_prompt).await;
+            let stream = get_or_create_job_stream(&state, &stream_key).await;
+            let cancellation_watch = tokio::spawn(
+                watch_for_cancellation(state.agent.clone(), ctx.job_id, ctx.user_canister, stream)
+                    .in_current_span(),
+            );
+            let (ingestion_chunks, citations, processing_result) =
+                match (ingestion_chunks, citations) {
+                    (Ok(chunks), Ok(citations)) => (
+                        chunks,
+                        citations,
+                        process_request(openai_req, stream_key.clone(), &state, custom_prompt)
+                            .await,
+                    ),
+                    (Err(e), _) | (_, Err(e)) => (None, None, Err(e)),
+                };
+            cancellation_watch.abort();
 
             let (completion_payload, usage) = match processing_result {
                 Ok(AIResponse::Text(text_response, usage)) => {
@@ -252,6 +366,21 @@ fn spawn_ai_processing_task(state: SharedState, ctx: JobProcessingContext) {
                         }
                     }
                 }
+                Ok(AIResponse::Cancelled(partial_text, usage)) => {
+                    info!(
+                        response_len = partial_text.len(),
+                        "AI processing cancelled by the user."
+                    );
+                    match encrypt_content(&partial_text, &ctx.chat_key) {
+                        Ok(encrypted_bytes) => {
+                            (JobCompletionResult::Cancelled(encrypted_bytes), usage)
+                        }
+                        Err(e) => {
+                            error!("Failed to encrypt partial response text: {}", e);
+                            (JobCompletionResult::Cancelled(Vec::new()), None)
+                        }
+                    }
+                }
                 Ok(AIResponse::ToolCall(api_tool_calls, usage)) => {
                     info!(
                         num_tool_calls = api_tool_calls.len(),
@@ -301,6 +430,60 @@ fn spawn_ai_processing_task(state: SharedState, ctx: JobProcessingContext) {
                         }
                     }
                 }
+                Ok(AIResponse::Embeddings(embeddings, usage)) if ingestion_chunks.is_some() => {
+                    let spans = ingestion_chunks.as_deref().unwrap_or_default();
+                    info!(
+                        chunk_count = spans.len(),
+                        "AI processing finished embedding the file's chunks."
+                    );
+                    match store_chunks(
+                        &state.agent,
+                        ctx.job_id,
+                        ctx.user_canister,
+                        spans,
+                        &embeddings,
+                        &ctx.chat_key,
+                    )
+                    .await
+                    {
+                        Ok(()) => (JobCompletionResult::Success(Vec::new()), usage),
+                        Err(e) => {
+                            error!(error = ?e, "Failed to store the file's chunks.");
+                            (
+                                JobCompletionResult::Failure(map_node_error_to_message_status(&e)),
+                                None,
+                            )
+                        }
+                    }
+                }
+                Ok(AIResponse::Embeddings(embeddings, usage)) => {
+                    info!(
+                        embedding_count = embeddings.len(),
+                        "AI processing finished with a batch of embeddings."
+                    );
+                    // Each vector is encrypted on its own so it can be stored as a file chunk
+                    let encrypted: anyhow::Result<Vec<ByteBuf>> = embeddings
+                        .iter()
+                        .map(|embedding| {
+                            let json = serde_json::to_string(embedding)?;
+                            encrypt_content(&json, &ctx.chat_key).map(ByteBuf::from)
+                        })
+                        .collect();
+                    match encrypted {
+                        Ok(encrypted) => (JobCompletionResult::Embeddings(encrypted), usage),
+                        Err(e) => {
+                            error!("Failed to encrypt embeddings: {}", e);
+                            (
+                                JobCompletionResult::Failure(
+                                    gpt_types::error::MessageErrorStatus::Unknown(
+                                        "Encryption failed".to_string(),
+                                    ),
+                                ),
+                                None,
+                            )
+                        }
+                    }
+                }
                 Err(ref node_err) => {
                     let severity = node_err.severity();
                     error!(error = ?node_err, ?severity, "AI processing failed.");
@@ -331,6 +514,7 @@ fn spawn_ai_processing_task(state: SharedState, ctx: JobProcessingContext) {
                 completion_payload,
                 ctx.user_canister,
                 usage,
+                citations,
             )
             .await
             {
@@ -342,90 +526,166 @@ fn spawn_ai_processing_task(state: SharedState, ctx: JobProcessingContext) {
                 info!("Successfully called complete_job.");
             }
 
-            state.job_streams.lock().await.remove(&stream_key);
-            info!("Cleaned up job stream broadcast sender.");
+            retire_job_stream(state.clone(), stream_key).await;
+            info!("Retired job stream; its replay buffer is kept for the grace period.");
         }
         .instrument(span),
     );
 }
 
+/// Cancels the job's stream once the user cancels the job through the user canister,
+/// which is how clients that are not connected to this node stop a generation.
+async fn watch_for_cancellation(
+    agent: Agent,
+    job_id: u64,
+    user_canister: Principal,
+    stream: Arc<JobStream>,
+) {
+    while !stream.is_cancelled() {
+        tokio::time::sleep(CANCELLATION_POLL_INTERVAL).await;
+        match get_job_status(&agent, job_id, user_canister).await {
+            Ok(GenerationStatus::Cancelled) => {
+                info!("Job was cancelled on the user canister.");
+                stream.cancel();
+            }
+            Ok(_) => {}
+            Err(e) => debug!(error = %e, "Could not check job status for cancellation."),
+        }
+    }
+}
+
 async fn fetch_conversation_history(
-    agent: &Agent,
+    state: &AppState,
     claim_resp: &ClaimJobResponse,
     user_canister: Principal,
     chat_key: &[u8],
 ) -> Result<Vec<MessageData>, NodeError> {
-    let mut messages = Vec::new();
-    for msg_id in &claim_resp.message_chain_ids {
-        match fetch_message(agent, *msg_id, claim_resp.chat.chat_id, user_canister).await {
-            Ok(Some(message)) => {
-                if message.error_status.is_some() {
-                    warn!(
-                        message_id = msg_id,
-                        error = ?message.error_status,
-                        "Skipping message in history due to previous error."
-                    );
-                    continue;
-                }
+    let chain = fetch_message_chain(
+        &state.agent,
+        claim_resp.job.job_id,
+        claim_resp.chat.chat_id,
+        user_canister,
+    )
+    .await?;
+    if chain.len() != claim_resp.message_chain_ids.len() {
+        warn!(
+            expected = claim_resp.message_chain_ids.len(),
+            received = chain.len(),
+            "Message chain differs from the claimed chain."
+        );
+    }
 
-                // Decrypt content if it exists
-                let decrypted_content = if !message.content.is_empty() {
-                    decrypt_content(&message.content, chat_key).map_err(|e| {
-                        error!("Failed to decrypt history message {}: {}", msg_id, e);
-                        NodeError::Attestation("History decryption failed".to_string())
-                    })?
-                } else {
-                    String::new()
-                };
+    // Only a chain that attaches files comes with a file key.
+    let attaches_files = chain
+        .iter()
+        .any(|m| m.attached_file_ids.as_ref().is_some_and(|ids| !ids.is_empty()));
+    let file_key = if attaches_files {
+        Some(attached_file_key(state, &claim_resp.job)?)
+    } else {
+        None
+    };
 
-                let final_content = match message.role {
-                    Role::Assistant => strip_reasoning(&decrypted_content),
-                    _ => decrypted_content,
-                };
+    let mut messages = Vec::new();
+    for message in chain {
+        let msg_id = message.message_id;
+        if message.error_status.is_some() {
+            warn!(
+                message_id = msg_id,
+                error = ?message.error_status,
+                "Skipping message in history due to previous error."
+            );
+            continue;
+        }
 
-                let msg_data = MessageData {
-                    role: match message.role {
-                        Role::System => "system".to_string(),
-                        Role::User => "user".to_string(),
-                        Role::Assistant => "assistant".to_string(),
-                        Role::Tool => "tool".to_string(),
-                    },
-                    content: final_content,
-                    attachments: message.attachments,
-                    tool_calls: message.tool_calls.map(|tcs| {
-                        tcs.into_iter()
-                            .map(|tc| crate::core::job::types::ToolCall {
-                                id: tc.id,
-                                _type: tc.r#type,
-                                function: crate::core::job::types::FunctionCall {
-                                    name: tc.function.name,
-                                    arguments: tc.function.arguments,
-                                },
-                            })
-                            .collect()
-                    }),
-                    tool_call_id: message.tool_call_id,
-                };
+        // Decrypt content if it exists
+        let decrypted_content = if !message.content.is_empty() {
+            decrypt_content(&message.content, chat_key).map_err(|e| {
+                error!("Failed to decrypt history message {}: {}", msg_id, e);
+                NodeError::Attestation("History decryption failed".to_string())
+            })?
+        } else {
+            String::new()
+        };
 
-                debug!(
-                    role = %msg_data.role,
-                    content_len = msg_data.content.len(),
-                    num_attachments = msg_data.attachments.as_ref().map_or(0, |a| a.len()),
-                    num_tool_calls = msg_data.tool_calls.as_ref().map_or(0, |t| t.len()),
-                    "Adding message to conversation history."
-                );
-                messages.push(msg_data);
+        let mut final_content = match message.role {
+            Role::Assistant => strip_reasoning(&decrypted_content),
+            _ => decrypted_content,
+        };
+
+        // Attached files join the message: text after its content, images as parts.
+        let mut attachments = message.attachments;
+        if let (Some(file_ids), Some(file_key)) = (&message.attached_file_ids, &file_key) {
+            let files = read_attached_files(
+                state,
+                claim_resp.job.job_id,
+                user_canister,
+                file_ids,
+                file_key,
+            )
+            .await?;
+            final_content.push_str(&files.text);
+            if !files.images.is_empty() {
+                attachments.get_or_insert_with(Vec::new).extend(files.images);
             }
-            Ok(None) => warn!(
-                message_id = msg_id,
-                "Message not found during history fetch. Skipping."
-            ),
-            Err(e) => return Err(e),
         }
+
+        let msg_data = MessageData {
+            message_id: msg_id,
+            role: match message.role {
+                Role::System => "system".to_string(),
+                Role::User => "user".to_string(),
+                Role::Assistant => "assistant".to_string(),
+                Role::Tool => "tool".to_string(),
+            },
+            content: final_content,
+            attachments,
+            tool_calls: message.tool_calls.map(|tcs| {
+                tcs.into_iter()
+                    .map(|tc| crate::core::job::types::ToolCall {
+                        id: tc.id,
+                        _type: tc.r#type,
+                        function: crate::core::job::types::FunctionCall {
+                            name: tc.function.name,
+                            arguments: tc.function.arguments,
+                        },
+                    })
+                    .collect()
+            }),
+            tool_call_id: message.tool_call_id,
+        };
+
+        debug!(
+            role = %msg_data.role,
+            content_len = msg_data.content.len(),
+            num_attachments = msg_data.attachments.as_ref().map_or(0, |a| a.len()),
+            num_tool_calls = msg_data.tool_calls.as_ref().map_or(0, |t| t.len()),
+            "Adding message to conversation history."
+        );
+        messages.push(msg_data);
     }
     Ok(messages)
 }
 
+/// Decrypts the inputs of an embedding batch job; `None` for other jobs.
+fn decrypt_embedding_inputs(
+    claim_resp: &ClaimJobResponse,
+    chat_key: &[u8],
+) -> Result<Option<Vec<String>>, NodeError> {
+    let Some(inputs) = &claim_resp.embedding_inputs else {
+        return Ok(None);
+    };
+    inputs
+        .iter()
+        .map(|input| {
+            decrypt_content(&input.content, chat_key).map_err(|e| {
+                error!("Failed to decrypt embedding input: {}", e);
+                NodeError::Attestation("Embedding input decryption failed".to_string())
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
 async fn mark_job_as_failed_and_log(
     agent: &Agent,
     job_id: u64,
@@ -434,7 +694,7 @@ async fn mark_job_as_failed_and_log(
 ) {
     info!(?failure_status, "Marking job as failed on canister.");
     let payload = JobCompletionResult::Failure(failure_status);
-    if let Err(e) = complete_job(agent, job_id, payload, user_canister, None).await {
+    if let Err(e) = complete_job(agent, job_id, payload, user_canister, None, None).await {
         error!(
             error = ?e,
             "Further error trying to mark job as failed."
@@ -451,7 +711,7 @@ fn find_case_insensitive(haystack: &str, needle: &str) -> Option<usize> {
         .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
 }
 
-fn strip_reasoning(content: &str) -> String {
+pub(super) fn strip_reasoning(content: &str) -> String {
     let start_tag = "<think>";
     let end_tag = "</think>";
     let mut processed_content = content.to_string();
@@ -0,0 +1,185 @@
+//! Retrieval-augmented generation: the node ranks the ingested chunks of the files a
+//! job names against the user's latest message and adds the best passages to the
+//! prompt, as a system message placed before that message.
+
+pub mod ranking;
+
+use crate::{
+    clients::ai_provider::embed_query,
+    clients::canister::file::fetch_retrieval_chunks,
+    core::error::NodeError,
+    core::job::encryption::{decrypt_chat_key, decrypt_content},
+    core::job::types::MessageData,
+    core::state::AppState,
+};
+use gpt_types::domain::{CharSpan, Citation, CitationSource, FileId, Retrieval};
+use ic_agent::export::Principal;
+use ranking::{Selection, select_passages};
+use tracing::{error, info, warn};
+
+const PASSAGES_PREAMBLE: &str = "Passages from the user's files that may help answer \
+their next message. Use them where they are relevant and say which passage, by its \
+number, a statement relies on.";
+
+/// A decrypted chunk that can be added to the prompt.
+struct Passage {
+    file_id: FileId,
+    chunk_index: u32,
+    span: CharSpan,
+    text: String,
+    embedding: Vec<f32>,
+}
+
+/// Adds the passages that best match the latest user message to `messages` and
+/// returns citations of the chunks they came from, in prompt order. Nothing is added when there
+/// is no user message or none of the files has chunks the node can read.
+pub async fn add_retrieved_passages(
+    state: &AppState,
+    job_id: u64,
+    user_canister: Principal,
+    retrieval: &Retrieval,
+    messages: &mut Vec<MessageData>,
+) -> Result<Vec<Citation>, NodeError> {
+    let Some(query_index) = messages.iter().rposition(|m| m.role == "user") else {
+        info!("No user message to retrieve passages for.");
+        return Ok(Vec::new());
+    };
+
+    let file_key = retrieval
+        .file_keys
+        .iter()
+        .find(|key| key.node_id == state.node_id)
+        .ok_or_else(|| NodeError::Other("The job has no file key for this node".to_string()))?;
+    let file_key = decrypt_chat_key(&file_key.encrypted_chat_key, &state.node_x25519_identity)
+        .map_err(|e| {
+            error!("Failed to decrypt file key: {}", e);
+            NodeError::Other("File key decryption failed".to_string())
+        })?;
+
+    let (chunks, embedding_provider_model) =
+        fetch_retrieval_chunks(&state.agent, job_id, user_canister).await?;
+    let chunk_count = chunks.len();
+    let passages: Vec<Passage> = chunks
+        .into_iter()
+        .filter_map(|retrieved| {
+            let chunk = retrieved.chunk;
+            let text = decrypt_content(chunk.content.as_deref()?, &file_key).ok()?;
+            let embedding_json = decrypt_content(&chunk.embedding, &file_key).ok()?;
+            let embedding = serde_json::from_str(&embedding_json).ok()?;
+            Some(Passage {
+                file_id: retrieved.file_id,
+                chunk_index: chunk.chunk_index,
+                span: CharSpan {
+                    start_char: chunk.start_char,
+                    end_char: chunk.end_char,
+                },
+                text,
+                embedding,
+            })
+        })
+        .collect();
+    if passages.len() < chunk_count {
+        // Files ingested under another key cannot be read with this one.
+        warn!(
+            skipped = chunk_count - passages.len(),
+            "Skipped chunks that could not be decrypted."
+        );
+    }
+    if passages.is_empty() {
+        info!(chunk_count, "No readable chunks to retrieve passages from.");
+        return Ok(Vec::new());
+    }
+
+    let query = messages[query_index].content.clone();
+    let query_embedding = embed_query(state, &embedding_provider_model, query).await?;
+
+    let embeddings: Vec<Vec<f32>> = passages.iter().map(|p| p.embedding.clone()).collect();
+    let token_counts: Vec<u32> = passages
+        .iter()
+        .map(|p| state.token_counter.count_text(&p.text))
+        .collect();
+    let picked = select_passages(
+        &query_embedding,
+        &embeddings,
+        &token_counts,
+        Selection {
+            top_k: retrieval.top_k as usize,
+            max_tokens: retrieval.max_passage_tokens,
+            mmr_lambda: retrieval.mmr_lambda,
+        },
+    );
+    info!(
+        candidate_count = passages.len(),
+        picked_count = picked.len(),
+        "Ranked the files' chunks."
+    );
+    if picked.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let picked: Vec<&Passage> = picked.into_iter().map(|index| &passages[index]).collect();
+    messages.insert(
+        query_index,
+        MessageData {
+            message_id: messages[query_index].message_id,
+            role: "system".to_string(),
+            content: format_passages(&picked),
+            attachments: None,
+            tool_calls: None,
+            tool_call_id: None,
+        },
+    );
+    Ok(picked
+        .into_iter()
+        .map(|p| Citation {
+            source: CitationSource::FileChunk {
+                file_id: p.file_id,
+                chunk_index: p.chunk_index,
+            },
+            span: Some(p.span),
+        })
+        .collect())
+}
+
+fn format_passages(passages: &[&Passage]) -> String {
+    let mut content = PASSAGES_PREAMBLE.to_string();
+    for (number, passage) in passages.iter().enumerate() {
+        content.push_str(&format!(
+            "\n\n[{}] (file {}, characters {}-{})\n{}",
+            number + 1,
+            passage.file_id,
+            passage.span.start_char,
+            passage.span.end_char,
+            passage.text.trim()
+        ));
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passages_are_numbered_in_prompt_order() {
+        let passage = |file_id, start_char, text: &str| Passage {
+            file_id,
+            chunk_index: 0,
+            span: CharSpan {
+                start_char,
+                end_char: start_char + text.len() as u32,
+            },
+            text: format!("{text}\n"),
+            embedding: Vec::new(),
+        };
+        let first = passage(4, 0, "Revenue grew.");
+        let second = passage(9, 120, "Costs fell.");
+
+        let content = format_passages(&[&first, &second]);
+        assert!(content.starts_with(PASSAGES_PREAMBLE));
+        assert!(content.ends_with(
+            "\n\n[1] (file 4, characters 0-13)\nRevenue grew.\
+             \n\n[2] (file 9, characters 120-131)\nCosts fell."
+        ));
+    }
+}
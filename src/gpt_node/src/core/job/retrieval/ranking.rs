@@ -0,0 +1,155 @@
+//! Picks the passages a retrieval job adds to its prompt.
+
+/// How many passages to pick, their token budget and the MMR weight of relevance
+/// against diversity, if any.
+#[derive(Debug, Clone, Copy)]
+pub struct Selection {
+    pub top_k: usize,
+    pub max_tokens: u32,
+    pub mmr_lambda: Option<f32>,
+}
+
+/// Cosine similarity of two vectors of the same length; 0 when either is zero.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+/// Returns the indices of the picked candidates, best first.
+///
+/// Each step picks, among the candidates that still fit the token budget, the one
+/// with the highest score. The score is the candidate's similarity to the query or,
+/// with an MMR weight λ, `λ · similarity − (1 − λ) · redundancy`, where redundancy
+/// is its highest similarity to an already picked candidate. Candidates embedded
+/// with a different dimension than the query are never picked.
+pub fn select_passages(
+    query: &[f32],
+    embeddings: &[Vec<f32>],
+    token_counts: &[u32],
+    selection: Selection,
+) -> Vec<usize> {
+    let relevance: Vec<Option<f32>> = embeddings
+        .iter()
+        .map(|embedding| {
+            (embedding.len() == query.len()).then(|| cosine_similarity(query, embedding))
+        })
+        .collect();
+    let mut redundancy: Vec<Option<f32>> = vec![None; embeddings.len()];
+    let mut picked: Vec<usize> = Vec::new();
+    let mut tokens = 0u32;
+
+    while picked.len() < selection.top_k {
+        let score = |index: usize, similarity: f32| match selection.mmr_lambda {
+            Some(lambda) => lambda * similarity - (1.0 - lambda) * redundancy[index].unwrap_or(0.0),
+            None => similarity,
+        };
+        let best = relevance
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| {
+                !picked.contains(index)
+                    && tokens.saturating_add(token_counts[*index]) <= selection.max_tokens
+            })
+            .filter_map(|(index, similarity)| similarity.map(|s| (index, score(index, s))))
+            .fold(None, |best: Option<(usize, f32)>, candidate| match best {
+                Some(best) if best.1 >= candidate.1 => Some(best),
+                _ => Some(candidate),
+            });
+        let Some((index, _)) = best else {
+            break;
+        };
+        tokens += token_counts[index];
+        picked.push(index);
+
+        if selection.mmr_lambda.is_some() {
+            for (other, similarity) in relevance.iter().enumerate() {
+                if similarity.is_some() && !picked.contains(&other) {
+                    let overlap = cosine_similarity(&embeddings[index], &embeddings[other]);
+                    redundancy[other] = Some(redundancy[other].map_or(overlap, |r| r.max(overlap)));
+                }
+            }
+        }
+    }
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selection(top_k: usize, max_tokens: u32, mmr_lambda: Option<f32>) -> Selection {
+        Selection {
+            top_k,
+            max_tokens,
+            mmr_lambda,
+        }
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn test_ranks_by_similarity_and_skips_other_dimensions() {
+        let query = [1.0, 0.0];
+        let embeddings = vec![
+            vec![0.0, 1.0],
+            vec![1.0, 0.1],
+            vec![1.0, 0.0, 0.0],
+            vec![1.0, 0.5],
+        ];
+        assert_eq!(
+            select_passages(&query, &embeddings, &[1; 4], selection(10, 100, None)),
+            vec![1, 3, 0]
+        );
+        assert_eq!(
+            select_passages(&query, &embeddings, &[1; 4], selection(2, 100, None)),
+            vec![1, 3]
+        );
+    }
+
+    #[test]
+    fn test_token_budget_skips_passages_that_do_not_fit() {
+        let query = [1.0, 0.0];
+        let embeddings = vec![vec![1.0, 0.0], vec![1.0, 0.2], vec![1.0, 0.4]];
+        // The second best passage is too long once the best is in; the third still fits.
+        assert_eq!(
+            select_passages(&query, &embeddings, &[50, 60, 40], selection(3, 100, None)),
+            vec![0, 2]
+        );
+        assert!(
+            select_passages(&query, &embeddings, &[200; 3], selection(3, 100, None)).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_mmr_prefers_diverse_passages() {
+        let query = [1.0, 1.0];
+        let embeddings = vec![vec![1.0, 0.9], vec![1.0, 0.89], vec![0.6, 1.0]];
+        assert_eq!(
+            select_passages(&query, &embeddings, &[1; 3], selection(2, 100, None)),
+            vec![0, 1]
+        );
+        assert_eq!(
+            select_passages(&query, &embeddings, &[1; 3], selection(2, 100, Some(0.5))),
+            vec![0, 2]
+        );
+        // A weight of 1 ignores redundancy.
+        assert_eq!(
+            select_passages(&query, &embeddings, &[1; 3], selection(2, 100, Some(1.0))),
+            vec![0, 1]
+        );
+    }
+}
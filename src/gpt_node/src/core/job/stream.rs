@@ -0,0 +1,383 @@
+//! Per-job response streams that clients can resume after reconnecting.
+//!
+//! Every update sent on a stream gets the next sequence number and is kept in a
+//! bounded replay buffer, encrypted with a key that only lives as long as the stream.
+//! A client that reconnects with the last sequence number it saw is sent the buffered
+//! updates it missed before following the live stream. Updates carry the full response
+//! text so far, so evicting the oldest ones never loses text for a resuming client.
+//!
+//! The stream also carries cancellation: a cancelled stream asks its producer to stop
+//! generating and finish with the text produced so far.
+//!
+//! Once the job completes the stream is retired: live subscribers see it close and it
+//! stays resumable from the buffer for `STREAM_REPLAY_GRACE`.
+
+use super::types::StreamedResponse;
+use crate::{clients::ai_provider::BROADCAST_CHANNEL_CAPACITY, core::state::AppState};
+use aes_gcm::{
+    Aes256Gcm, Nonce,
+    aead::{Aead, KeyInit},
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+use tokio::sync::{
+    broadcast::{self, error::SendError},
+    watch,
+};
+use tracing::{debug, info, warn};
+use zeroize::Zeroizing;
+
+/// How long a completed stream can still be resumed.
+pub const STREAM_REPLAY_GRACE: Duration = Duration::from_secs(60);
+const MAX_REPLAY_FRAMES: usize = 256;
+const MAX_REPLAY_BYTES: usize = 4 * 1024 * 1024;
+const GCM_NONCE_SIZE: usize = 12;
+
+/// A streamed update with its position in the stream, as sent to the client.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamFrame {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub response: StreamedResponse,
+}
+
+impl StreamFrame {
+    pub fn is_final(&self) -> bool {
+        self.response.is_complete || self.response.error_status.is_some()
+    }
+}
+
+/// Buffered frame, serialized and encrypted with the stream's buffer key.
+struct BufferedFrame {
+    seq: u64,
+    is_final: bool,
+    sealed: Vec<u8>,
+}
+
+struct StreamInner {
+    /// `None` once the stream is retired.
+    tx: Option<broadcast::Sender<StreamFrame>>,
+    next_seq: u64,
+    frames: VecDeque<BufferedFrame>,
+    buffered_bytes: usize,
+}
+
+pub struct JobStream {
+    inner: Mutex<StreamInner>,
+    buffer_cipher: Aes256Gcm,
+    /// Chat key of the job, set once the job is claimed. Resumed connections encrypt
+    /// with it since they do not claim the job again.
+    chat_key: OnceLock<Zeroizing<Vec<u8>>>,
+    cancel_tx: watch::Sender<bool>,
+}
+
+/// What a subscriber receives: missed frames to send first, then the live stream.
+pub struct Subscription {
+    pub replay: Vec<StreamFrame>,
+    pub receiver: broadcast::Receiver<StreamFrame>,
+    /// Whether the final frame had already been sent when subscribing.
+    pub finished: bool,
+}
+
+impl JobStream {
+    pub fn new() -> Self {
+        let mut key = Zeroizing::new([0u8; 32]);
+        rand::thread_rng().fill_bytes(key.as_mut());
+        let (tx, _) = broadcast::channel(BROADCAST_CHANNEL_CAPACITY);
+        Self {
+            inner: Mutex::new(StreamInner {
+                tx: Some(tx),
+                next_seq: 1,
+                frames: VecDeque::new(),
+                buffered_bytes: 0,
+            }),
+            buffer_cipher: Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(key.as_ref())),
+            chat_key: OnceLock::new(),
+            cancel_tx: watch::channel(false).0,
+        }
+    }
+
+    /// Numbers `response`, buffers it and broadcasts it to live subscribers.
+    /// Returns the number of subscribers that received it, or the sequence number of
+    /// the frame if there were none.
+    pub fn send(&self, response: StreamedResponse) -> Result<usize, SendError<u64>> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let frame = StreamFrame {
+            seq: inner.next_seq,
+            response,
+        };
+        inner.next_seq += 1;
+
+        match self.seal(&frame) {
+            Ok(sealed) => inner.push(BufferedFrame {
+                seq: frame.seq,
+                is_final: frame.is_final(),
+                sealed,
+            }),
+            Err(e) => warn!(seq = frame.seq, error = %e, "Failed to buffer stream frame."),
+        }
+
+        let seq = frame.seq;
+        match &inner.tx {
+            Some(tx) => tx.send(frame).map_err(|_| SendError(seq)),
+            None => Err(SendError(seq)),
+        }
+    }
+
+    /// Subscribes to the stream. With `after`, buffered frames newer than that
+    /// sequence number are returned for replay; without it only live frames follow.
+    pub fn subscribe(&self, after: Option<u64>) -> Subscription {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let receiver = match &inner.tx {
+            Some(tx) => tx.subscribe(),
+            // A retired stream has nothing live left; the receiver reports it closed.
+            None => broadcast::channel(1).1,
+        };
+        let replay = match after {
+            Some(after) => inner
+                .frames
+                .iter()
+                .filter(|frame| frame.seq > after)
+                .filter_map(|frame| match self.open(&frame.sealed) {
+                    Ok(frame) => Some(frame),
+                    Err(e) => {
+                        warn!(error = %e, "Failed to read buffered stream frame.");
+                        None
+                    }
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        Subscription {
+            replay,
+            receiver,
+            finished: inner.frames.back().is_some_and(|frame| frame.is_final),
+        }
+    }
+
+    pub fn set_chat_key(&self, chat_key: Vec<u8>) {
+        let _ = self.chat_key.set(Zeroizing::new(chat_key));
+    }
+
+    pub fn chat_key(&self) -> Option<&[u8]> {
+        self.chat_key.get().map(|key| key.as_slice())
+    }
+
+    /// Asks the producer to stop generating.
+    pub fn cancel(&self) {
+        self.cancel_tx.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancel_tx.borrow()
+    }
+
+    /// Resolves once the stream has been cancelled.
+    pub async fn cancelled(&self) {
+        let mut rx = self.cancel_tx.subscribe();
+        // The sender lives as long as `self`, so this only returns once cancelled.
+        let _ = rx.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// Closes the live stream. Subscribers are told it closed; the buffer stays.
+    fn close(&self) {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).tx = None;
+    }
+
+    fn seal(&self, frame: &StreamFrame) -> anyhow::Result<Vec<u8>> {
+        let json = Zeroizing::new(serde_json::to_vec(frame)?);
+        let mut nonce_bytes = [0u8; GCM_NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        let ciphertext = self
+            .buffer_cipher
+            .encrypt(Nonce::from_slice(&nonce_bytes), json.as_slice())
+            .map_err(|e| anyhow::anyhow!("Encryption error: {}", e))?;
+
+        let mut sealed = Vec::with_capacity(GCM_NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(&nonce_bytes);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    fn open(&self, sealed: &[u8]) -> anyhow::Result<StreamFrame> {
+        if sealed.len() < GCM_NONCE_SIZE {
+            return Err(anyhow::anyhow!("Buffered frame too short for IV"));
+        }
+        let (nonce_bytes, ciphertext) = sealed.split_at(GCM_NONCE_SIZE);
+        let json = Zeroizing::new(
+            self.buffer_cipher
+                .decrypt(Nonce::from_slice(nonce_bytes), ciphertext)
+                .map_err(|e| anyhow::anyhow!("Decryption error: {}", e))?,
+        );
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+impl Default for JobStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamInner {
+    /// Appends a frame, evicting the oldest ones past the buffer limits. The newest
+    /// frame is always kept.
+    fn push(&mut self, frame: BufferedFrame) {
+        self.buffered_bytes += frame.sealed.len();
+        self.frames.push_back(frame);
+        while self.frames.len() > 1
+            && (self.frames.len() > MAX_REPLAY_FRAMES || self.buffered_bytes > MAX_REPLAY_BYTES)
+        {
+            if let Some(evicted) = self.frames.pop_front() {
+                self.buffered_bytes -= evicted.sealed.len();
+            }
+        }
+    }
+}
+
+/// The active stream for `stream_key`, created if there is none yet.
+pub async fn get_or_create_job_stream(state: &AppState, stream_key: &str) -> Arc<JobStream> {
+    state
+        .job_streams
+        .lock()
+        .await
+        .entry(stream_key.to_string())
+        .or_insert_with(|| {
+            info!(stream_key, "Creating job stream.");
+            Arc::new(JobStream::new())
+        })
+        .clone()
+}
+
+/// The active or recently retired stream for `stream_key`.
+pub async fn find_job_stream(state: &AppState, stream_key: &str) -> Option<Arc<JobStream>> {
+    if let Some(stream) = state.job_streams.lock().await.get(stream_key) {
+        return Some(stream.clone());
+    }
+    state.retired_streams.lock().await.get(stream_key).cloned()
+}
+
+/// Moves the stream out of the active streams once its job is done, keeping it
+/// resumable for `STREAM_REPLAY_GRACE`.
+pub async fn retire_job_stream(state: Arc<AppState>, stream_key: String) {
+    let Some(stream) = state.job_streams.lock().await.remove(&stream_key) else {
+        return;
+    };
+    stream.close();
+    state
+        .retired_streams
+        .lock()
+        .await
+        .insert(stream_key.clone(), stream.clone());
+
+    tokio::spawn(async move {
+        tokio::time::sleep(STREAM_REPLAY_GRACE).await;
+        let mut retired = state.retired_streams.lock().await;
+        // A newer stream may have been retired under the same key in the meantime.
+        if retired
+            .get(&stream_key)
+            .is_some_and(|current| Arc::ptr_eq(current, &stream))
+        {
+            retired.remove(&stream_key);
+            debug!(stream_key, "Freed replay buffer of retired stream.");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(text: &str, is_complete: bool) -> StreamedResponse {
+        StreamedResponse {
+            text: text.to_string(),
+            is_complete,
+            error_status: None,
+            usage: None,
+        }
+    }
+
+    #[test]
+    fn test_resume_replays_missed_frames() {
+        let stream = JobStream::new();
+        let mut live = stream.subscribe(None).receiver;
+        stream.send(update("He", false)).unwrap();
+        stream.send(update("Hello", false)).unwrap();
+        assert_eq!(live.try_recv().unwrap().seq, 1);
+
+        let resumed = stream.subscribe(Some(1));
+        let replayed: Vec<(u64, &str)> = resumed
+            .replay
+            .iter()
+            .map(|f| (f.seq, f.response.text.as_str()))
+            .collect();
+        assert_eq!(replayed, [(2, "Hello")]);
+
+        let mut receiver = resumed.receiver;
+        stream.send(update("Hello!", true)).unwrap();
+        let last = receiver.try_recv().unwrap();
+        assert_eq!(last.seq, 3);
+        assert!(last.is_final());
+        assert!(stream.subscribe(Some(3)).finished);
+    }
+
+    #[test]
+    fn test_buffer_is_bounded_and_keeps_newest() {
+        let stream = JobStream::new();
+        for i in 0..(MAX_REPLAY_FRAMES + 10) {
+            let _ = stream.send(update(&i.to_string(), false));
+        }
+        let replay = stream.subscribe(Some(0)).replay;
+        assert_eq!(replay.len(), MAX_REPLAY_FRAMES);
+        assert_eq!(replay.first().unwrap().seq, 11);
+        assert_eq!(replay.last().unwrap().seq, (MAX_REPLAY_FRAMES + 10) as u64);
+
+        // A single frame over the byte limit is still kept.
+        let _ = stream.send(update(&"x".repeat(MAX_REPLAY_BYTES), true));
+        let replay = stream.subscribe(Some(0)).replay;
+        assert_eq!(replay.len(), 1);
+        assert!(replay[0].is_final());
+    }
+
+    #[test]
+    fn test_closed_stream_still_replays() {
+        let stream = JobStream::new();
+        let mut live = stream.subscribe(None).receiver;
+        stream.send(update("Done", true)).unwrap();
+        stream.close();
+
+        assert_eq!(live.try_recv().unwrap().seq, 1);
+        assert!(matches!(
+            live.try_recv(),
+            Err(broadcast::error::TryRecvError::Closed)
+        ));
+
+        let resumed = stream.subscribe(Some(0));
+        assert_eq!(resumed.replay.len(), 1);
+        assert!(stream.send(update("late", false)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_wakes_waiters() {
+        let stream = Arc::new(JobStream::new());
+        assert!(!stream.is_cancelled());
+        let waiter = tokio::spawn({
+            let stream = stream.clone();
+            async move { stream.cancelled().await }
+        });
+        stream.cancel();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(stream.is_cancelled());
+        // Already cancelled streams resolve immediately.
+        stream.cancelled().await;
+    }
+}
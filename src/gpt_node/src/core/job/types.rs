@@ -1,5 +1,6 @@
 use gpt_types::{
     domain::{
+        MessageId, ResponseSchema,
         message::{ImageAttachment, TokenUsage},
         tool::Tool,
     },
@@ -34,6 +35,9 @@ pub struct OpenAIRequest {
     pub tools: Option<Vec<Tool>>,
     pub extra_body_json: Option<String>,
     pub reasoning_effort: Option<String>,
+    pub response_schema: Option<ResponseSchema>,
+    /// Decrypted inputs of an embedding batch job, in order
+    pub embedding_inputs: Option<Vec<String>>,
 }
 
 /// Streamed response sent back to the client via WebSocket.
@@ -52,6 +56,8 @@ pub struct StreamedResponse {
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct MessageData {
+    /// Id of the stored message; summaries stand in for messages up to this id
+    pub message_id: MessageId,
     pub role: String,
     pub content: String,
     pub attachments: Option<Vec<ImageAttachment>>,
@@ -60,7 +66,7 @@ pub struct MessageData {
 }
 
 /// Message with token count information (used for context management).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TokenizedMessage {
     pub role: String,
     pub content: String,
@@ -1,4 +1,29 @@
-use std::sync::atomic::AtomicU64;
+//! Node metrics: the counters reported on `/status` and the Prometheus histograms
+//! served on `/metrics`.
+//!
+//! Every Prometheus series is labeled with the node's model and the provider that
+//! handled the work. Labels are taken from node configuration and fixed variant
+//! names only, never from request or response content.
+
+use gpt_types::error::{MessageErrorStatus, ProviderErrorType};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock, atomic::AtomicU64},
+    time::Duration,
+};
+
+const TIME_TO_FIRST_TOKEN_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0];
+const REQUEST_DURATION_BUCKETS: &[f64] =
+    &[0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 40.0, 80.0, 160.0, 320.0];
+const TOKENS_PER_SECOND_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 20.0, 40.0, 80.0, 160.0, 320.0];
+const CANISTER_CALL_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+const SEMAPHORE_WAIT_BUCKETS: &[f64] = &[0.001, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0];
+
+/// Metrics used by code that has no access to the node state, such as canister calls.
+static INSTALLED: OnceLock<Arc<Metrics>> = OnceLock::new();
 
 /// Metrics tracking for node performance and request statistics.
 pub struct Metrics {
@@ -12,4 +37,225 @@ pub struct Metrics {
     pub peak_concurrent_requests: AtomicU64,
     pub rpm_limit: AtomicU64,
     pub concurrency_limit: AtomicU64,
+    /// Provider of the model's primary backend, for work not tied to one backend.
+    primary_provider: String,
+    registry: Registry,
+    time_to_first_token: HistogramVec,
+    request_duration: HistogramVec,
+    tokens_per_second: HistogramVec,
+    canister_call_duration: HistogramVec,
+    provider_errors: IntCounterVec,
+    semaphore_wait: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new(
+        model_id: &str,
+        primary_provider: &str,
+        rpm_limit: u64,
+        concurrency_limit: u64,
+    ) -> Self {
+        let const_labels = HashMap::from([("model".to_string(), model_id.to_string())]);
+        let registry = Registry::new_custom(Some("gpt_node".to_string()), Some(const_labels))
+            .expect("metric labels are valid");
+
+        let time_to_first_token = register_histogram(
+            &registry,
+            "time_to_first_token_seconds",
+            "Time from sending a chat request until the first streamed token.",
+            TIME_TO_FIRST_TOKEN_BUCKETS,
+            &["provider"],
+        );
+        let request_duration = register_histogram(
+            &registry,
+            "request_duration_seconds",
+            "Total time spent on a provider request, including failover.",
+            REQUEST_DURATION_BUCKETS,
+            &["provider"],
+        );
+        let tokens_per_second = register_histogram(
+            &registry,
+            "tokens_per_second",
+            "Completion tokens generated per second after the first token.",
+            TOKENS_PER_SECOND_BUCKETS,
+            &["provider"],
+        );
+        let canister_call_duration = register_histogram(
+            &registry,
+            "canister_call_duration_seconds",
+            "Latency of a single canister call attempt.",
+            CANISTER_CALL_BUCKETS,
+            &["provider", "method"],
+        );
+        let semaphore_wait = register_histogram(
+            &registry,
+            "semaphore_wait_seconds",
+            "Time spent waiting for a concurrency permit.",
+            SEMAPHORE_WAIT_BUCKETS,
+            &["provider"],
+        );
+        let provider_errors = IntCounterVec::new(
+            Opts::new("provider_errors_total", "Provider errors by error type."),
+            &["provider", "error_type"],
+        )
+        .expect("metric definition is valid");
+        registry
+            .register(Box::new(provider_errors.clone()))
+            .expect("metric is registered once");
+
+        Self {
+            requests_total: AtomicU64::new(0),
+            requests_succeeded: AtomicU64::new(0),
+            requests_failed: AtomicU64::new(0),
+            tokens_processed: AtomicU64::new(0),
+            current_active_requests: AtomicU64::new(0),
+            avg_response_time_ms: AtomicU64::new(0),
+            total_response_time_ms: AtomicU64::new(0),
+            peak_concurrent_requests: AtomicU64::new(0),
+            rpm_limit: AtomicU64::new(rpm_limit),
+            concurrency_limit: AtomicU64::new(concurrency_limit),
+            primary_provider: primary_provider.to_string(),
+            registry,
+            time_to_first_token,
+            request_duration,
+            tokens_per_second,
+            canister_call_duration,
+            provider_errors,
+            semaphore_wait,
+        }
+    }
+
+    /// Makes `metrics` available through [`installed`].
+    pub fn install(metrics: Arc<Metrics>) {
+        let _ = INSTALLED.set(metrics);
+    }
+
+    pub fn observe_time_to_first_token(&self, provider: &str, elapsed: Duration) {
+        self.time_to_first_token
+            .with_label_values(&[provider])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_request_duration(&self, provider: &str, elapsed: Duration) {
+        self.request_duration
+            .with_label_values(&[provider])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_tokens_per_second(
+        &self,
+        provider: &str,
+        completion_tokens: u32,
+        elapsed: Duration,
+    ) {
+        let seconds = elapsed.as_secs_f64();
+        if completion_tokens > 0 && seconds > 0.0 {
+            self.tokens_per_second
+                .with_label_values(&[provider])
+                .observe(f64::from(completion_tokens) / seconds);
+        }
+    }
+
+    pub fn observe_canister_call(&self, method: &str, elapsed: Duration) {
+        self.canister_call_duration
+            .with_label_values(&[self.primary_provider.as_str(), method])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_semaphore_wait(&self, elapsed: Duration) {
+        self.semaphore_wait
+            .with_label_values(&[self.primary_provider.as_str()])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Counts `status` if it is a provider error.
+    pub fn record_provider_error(&self, provider: &str, status: &MessageErrorStatus) {
+        if let MessageErrorStatus::ProviderError(error_type) = status {
+            self.provider_errors
+                .with_label_values(&[provider, provider_error_label(error_type)])
+                .inc();
+        }
+    }
+
+    pub fn primary_provider(&self) -> &str {
+        &self.primary_provider
+    }
+
+    /// All series in the Prometheus text exposition format.
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+    }
+}
+
+/// The metrics installed at startup, if any.
+pub fn installed() -> Option<&'static Metrics> {
+    INSTALLED.get().map(Arc::as_ref)
+}
+
+fn register_histogram(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    buckets: &[f64],
+    labels: &[&str],
+) -> HistogramVec {
+    let histogram = HistogramVec::new(
+        HistogramOpts::new(name, help).buckets(buckets.to_vec()),
+        labels,
+    )
+    .expect("metric definition is valid");
+    registry
+        .register(Box::new(histogram.clone()))
+        .expect("metric is registered once");
+    histogram
+}
+
+/// Label for an error type. Variants carrying provider messages are reduced to their
+/// name, since the message may echo request content.
+fn provider_error_label(error_type: &ProviderErrorType) -> &'static str {
+    match error_type {
+        ProviderErrorType::RateLimited => "rate_limited",
+        ProviderErrorType::AuthenticationError => "authentication_error",
+        ProviderErrorType::ServerError => "server_error",
+        ProviderErrorType::ServiceUnavailable => "service_unavailable",
+        ProviderErrorType::BadRequest => "bad_request",
+        ProviderErrorType::ContextLengthExceeded => "context_length_exceeded",
+        ProviderErrorType::ContentPolicyViolation => "content_policy_violation",
+        ProviderErrorType::NetworkError => "network_error",
+        ProviderErrorType::Timeout => "timeout",
+        ProviderErrorType::InvalidImage(_) => "invalid_image",
+        ProviderErrorType::Unknown(_) => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_labels_by_model_and_provider() {
+        let metrics = Metrics::new("gpt-test", "openai", 0, 0);
+        metrics.observe_time_to_first_token("anthropic", Duration::from_millis(300));
+        metrics.observe_canister_call("claim_job", Duration::from_millis(80));
+        metrics.record_provider_error(
+            "anthropic",
+            &MessageErrorStatus::ProviderError(ProviderErrorType::Unknown(
+                "prompt echoed in error".to_string(),
+            )),
+        );
+
+        let text = metrics.encode().unwrap();
+        assert!(text.contains(
+            "gpt_node_time_to_first_token_seconds_count{provider=\"anthropic\",model=\"gpt-test\"} 1"
+        ));
+        assert!(text.contains(
+            "gpt_node_canister_call_duration_seconds_count{method=\"claim_job\",provider=\"openai\",model=\"gpt-test\"} 1"
+        ));
+        assert!(text.contains(
+            "gpt_node_provider_errors_total{error_type=\"unknown\",provider=\"anthropic\",model=\"gpt-test\"} 1"
+        ));
+        assert!(!text.contains("prompt echoed"));
+    }
 }
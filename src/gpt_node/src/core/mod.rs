@@ -3,3 +3,4 @@ pub mod job;
 pub mod metrics;
 pub mod sensitive;
 pub mod state;
+pub mod telemetry;
@@ -105,6 +105,7 @@ const SENSITIVE_FIELD_PATTERNS: &[&str] = &[
 /// This layer intercepts log events and redacts field values that match
 /// sensitive patterns, preventing accidental exposure of user data,
 /// message content, or credentials in logs.
+#[derive(Debug, Clone)]
 pub struct SensitiveDataFilter {
     /// Maximum length for any single field value before truncation
     max_field_length: usize,
@@ -129,6 +130,12 @@ impl SensitiveDataFilter {
         self
     }
 
+    /// The value to record for field `name`: `[REDACTED]` for sensitive fields,
+    /// otherwise `value` truncated to the maximum field length.
+    pub fn redact_field(&self, name: &str, value: &str) -> String {
+        redact_field_value(name, value, self.max_field_length)
+    }
+
     /// Check if a field name indicates sensitive content.
     fn is_sensitive_field(name: &str) -> bool {
         let name_lower = name.to_lowercase();
@@ -155,22 +162,26 @@ impl RedactingVisitor {
 
     fn redact_if_sensitive(&mut self, field: &Field, value: String) {
         let field_name = field.name();
+        let final_value = redact_field_value(field_name, &value, self.max_length);
+        self.fields.push((field_name.to_string(), final_value));
+    }
+}
 
-        // Always redact fields with sensitive names
-        if SensitiveDataFilter::is_sensitive_field(field_name) {
-            self.fields
-                .push((field_name.to_string(), "[REDACTED]".to_string()));
-            return;
-        }
-
-        // Truncate long values (might contain embedded sensitive data)
-        let final_value = if value.len() > self.max_length {
-            format!("{}...[truncated {} chars]", &value[..self.max_length], value.len() - self.max_length)
-        } else {
-            value
-        };
+fn redact_field_value(name: &str, value: &str, max_length: usize) -> String {
+    // Always redact fields with sensitive names
+    if SensitiveDataFilter::is_sensitive_field(name) {
+        return "[REDACTED]".to_string();
+    }
 
-        self.fields.push((field_name.to_string(), final_value));
+    // Truncate long values (might contain embedded sensitive data)
+    if value.len() > max_length {
+        let mut end = max_length;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...[truncated {} chars]", &value[..end], value.len() - end)
+    } else {
+        value.to_string()
     }
 }
 
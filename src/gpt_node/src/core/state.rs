@@ -1,8 +1,9 @@
 use crate::{
     core::error::NodeError,
     core::metrics::Metrics,
+    clients::ai_provider::{backend::ProviderTarget, tokenizer::TokenCounter},
     clients::canister::instrumented_canister_call,
-    core::job::types::StreamedResponse,
+    core::job::stream::JobStream,
 };
 use age::x25519::Identity as X25519Identity;
 use async_openai::Client;
@@ -18,7 +19,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 use std::time::SystemTime;
-use tokio::sync::{Mutex, Semaphore, broadcast};
+use tokio::sync::{Mutex, Semaphore};
 
 #[allow(dead_code)]
 pub struct AppState {
@@ -33,10 +34,18 @@ pub struct AppState {
     pub node_x25519_identity: X25519Identity,
     pub node_public_key: String,
     pub openai_client: Client<async_openai::config::OpenAIConfig>,
+    /// Chat requests go through provider adapters rather than `openai_client`.
+    pub provider_http_client: reqwest::Client,
+    /// The model's provider backends in failover order, primary first.
+    pub provider_targets: Vec<ProviderTarget>,
+    /// Context budgeting tokenizer for the served model.
+    pub token_counter: TokenCounter,
     pub agent: Agent,
     pub request_semaphore: Arc<Semaphore>,
     pub rate_limiter: Option<Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>>,
-    pub job_streams: Arc<Mutex<HashMap<String, broadcast::Sender<StreamedResponse>>>>,
+    pub job_streams: Arc<Mutex<HashMap<String, Arc<JobStream>>>>,
+    /// Completed job streams kept resumable until their grace period ends.
+    pub retired_streams: Arc<Mutex<HashMap<String, Arc<JobStream>>>>,
     pub metrics: Arc<Metrics>,
     pub shutdown: Arc<AtomicBool>,
     pub is_draining: Arc<AtomicBool>,
@@ -0,0 +1,227 @@
+//! Optional OpenTelemetry trace export.
+//!
+//! When an OTLP endpoint is configured, `tracing` spans are exported over OTLP/HTTP.
+//! Spans only leave the enclave after passing through [`RedactingSpanProcessor`], which
+//! applies the same field redaction as the log output (see [`SensitiveDataFilter`]).
+//! The context of the current span is propagated to canister calls as a W3C
+//! `traceparent` header via [`trace_context_headers`].
+
+use crate::core::sensitive::{SensitiveDataFilter, redact_sensitive};
+use opentelemetry::{
+    Context, KeyValue, Value,
+    propagation::{Injector, TextMapPropagator},
+    trace::{Status, TracerProvider as _},
+};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource,
+    error::OTelSdkResult,
+    propagation::TraceContextPropagator,
+    trace::{BatchSpanProcessor, Sampler, SdkTracerProvider, Span, SpanData, SpanProcessor},
+};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::time::Duration;
+use tracing::Subscriber;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+const SERVICE_NAME: &str = "gpt_node";
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where and how much to export.
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    /// Full OTLP/HTTP traces URL, e.g. `http://collector:4318/v1/traces`.
+    pub endpoint: String,
+    /// Fraction of traces exported, between 0 and 1.
+    pub sample_ratio: f64,
+}
+
+/// Builds a tracer provider that exports redacted spans to the configured endpoint.
+/// Spans are exported from a background thread in batches.
+pub fn init_tracer_provider(config: &OtlpConfig) -> anyhow::Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(config.endpoint.clone())
+        .with_timeout(EXPORT_TIMEOUT)
+        .build()?;
+    Ok(tracer_provider(
+        BatchSpanProcessor::builder(exporter).build(),
+        config.sample_ratio,
+    ))
+}
+
+fn tracer_provider<P: SpanProcessor + 'static>(
+    processor: P,
+    sample_ratio: f64,
+) -> SdkTracerProvider {
+    SdkTracerProvider::builder()
+        .with_span_processor(RedactingSpanProcessor::new(processor))
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            sample_ratio,
+        ))))
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build()
+}
+
+/// A `tracing` layer that records spans with `provider`.
+pub fn layer<S>(
+    provider: &SdkTracerProvider,
+) -> OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+}
+
+/// W3C trace context headers for the current span. Empty when the span is not
+/// exported.
+pub fn trace_context_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context: Context = tracing::Span::current().context();
+    TraceContextPropagator::new().inject_context(&context, &mut HeaderInjector(&mut headers));
+    headers
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// Redacts finished spans before handing them to the exporting processor.
+///
+/// Attributes of spans and span events go through the log redaction rules. Event
+/// names (the log message of a `tracing` event) and error descriptions are free text,
+/// so they are checked with [`redact_sensitive`].
+#[derive(Debug)]
+pub struct RedactingSpanProcessor<P> {
+    inner: P,
+    filter: SensitiveDataFilter,
+}
+
+impl<P: SpanProcessor> RedactingSpanProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            filter: SensitiveDataFilter::new(),
+        }
+    }
+
+    fn redact_attributes(&self, attributes: &mut [KeyValue]) {
+        for attribute in attributes {
+            attribute.value = match &attribute.value {
+                Value::Bool(_) | Value::I64(_) | Value::F64(_) => continue,
+                value => Value::from(
+                    self.filter
+                        .redact_field(attribute.key.as_str(), &value.as_str()),
+                ),
+            };
+        }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for RedactingSpanProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        span.name = redact_sensitive(&span.name).into();
+        self.redact_attributes(&mut span.attributes);
+        for event in span.events.events.iter_mut() {
+            event.name = redact_sensitive(&event.name).into();
+            self.redact_attributes(&mut event.attributes);
+        }
+        for link in span.links.links.iter_mut() {
+            self.redact_attributes(&mut link.attributes);
+        }
+        if let Status::Error { description } = &span.status {
+            span.status = Status::error(redact_sensitive(description));
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Bytes, routing::post};
+    use std::sync::{Arc, Mutex};
+    use tracing::{error, info, info_span};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const SECRET: &str = "enclave-only-secret";
+
+    /// Accepts OTLP/HTTP exports and keeps the raw request bodies.
+    async fn collector() -> (String, Arc<Mutex<Vec<Bytes>>>) {
+        let received: Arc<Mutex<Vec<Bytes>>> = Arc::default();
+        let sink = received.clone();
+        let app = Router::new().route(
+            "/v1/traces",
+            post(move |body: Bytes| async move {
+                sink.lock().unwrap().push(body);
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/v1/traces", addr), received)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_exported_spans_carry_no_message_content() {
+        let (endpoint, received) = collector().await;
+        let provider = init_tracer_provider(&OtlpConfig {
+            endpoint,
+            sample_ratio: 1.0,
+        })
+        .unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = info_span!("ai_processing", job_id = 7, message_content = SECRET);
+            let _guard = span.enter();
+            info!(response_text = SECRET, "Stream processing finished.");
+            info!("{}", serde_json::json!({ "content": SECRET }));
+            error!(payload = %SECRET, "Provider call failed.");
+            assert!(trace_context_headers().contains_key("traceparent"));
+        });
+
+        tokio::task::spawn_blocking(move || provider.force_flush().unwrap())
+            .await
+            .unwrap();
+
+        let bodies = received.lock().unwrap();
+        let exported: Vec<u8> = bodies.iter().flat_map(|body| body.to_vec()).collect();
+        let contains = |needle: &[u8]| exported.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"ai_processing"));
+        assert!(contains(b"Stream processing finished."));
+        assert!(!contains(SECRET.as_bytes()));
+    }
+
+    #[test]
+    fn test_no_trace_headers_without_exporter() {
+        let _span = info_span!("canister_call").entered();
+        assert!(trace_context_headers().is_empty());
+    }
+}
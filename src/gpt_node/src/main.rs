@@ -24,7 +24,9 @@ use tracing_subscriber::{
     EnvFilter, fmt, fmt::time::ChronoUtc, layer::SubscriberExt, util::SubscriberInitExt,
 };
 
+use crate::core::job::ingestion::extract::{PDF_WORKER_FLAG, run_pdf_worker};
 use crate::core::sensitive::SensitiveDataFilter;
+use crate::core::telemetry::{self, OtlpConfig};
 
 use crate::api::create_router;
 use crate::bootstrap::perform_startup;
@@ -57,10 +59,33 @@ pub struct Args {
     /// Optional: Maximum number of concurrent AI provider requests.
     #[arg(long)]
     pub concurrency: Option<u32>,
+
+    /// Directory of bundled HuggingFace tokenizers, one `<name>/tokenizer.json` per
+    /// model id or maker.
+    #[arg(long, default_value = "/usr/local/share/gpt_node/tokenizers")]
+    pub tokenizer_dir: String,
+
+    /// Optional: OTLP/HTTP traces URL to export redacted spans to, e.g.
+    /// `http://collector:4318/v1/traces`. Traces are not exported without it.
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+
+    /// Fraction of traces to export when an OTLP endpoint is set.
+    #[arg(long, default_value_t = 1.0)]
+    pub otlp_sample_ratio: f64,
 }
 
 #[tokio::main]
 async fn main() -> AnyhowResult<()> {
+    // PDFs are parsed in a child process running this binary; see `extract_pdf`.
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == PDF_WORKER_FLAG)
+    {
+        run_pdf_worker();
+    }
+    let args = Args::parse();
+
     // Initialize logging (JSON or compact format).
     let log_format = std::env::var("LOG_FORMAT").unwrap_or_else(|_| "compact".to_string());
     let (non_blocking_writer, _guard) = tracing_appender::non_blocking(std::io::stdout());
@@ -73,6 +98,18 @@ async fn main() -> AnyhowResult<()> {
     // Add sensitive data filter to prevent logging of message content, tokens, etc.
     let sensitive_filter = SensitiveDataFilter::new().with_max_field_length(200);
 
+    // Optional trace export; spans are redacted the same way before they leave the node.
+    let tracer_provider = match &args.otlp_endpoint {
+        Some(endpoint) => Some(telemetry::init_tracer_provider(&OtlpConfig {
+            endpoint: endpoint.clone(),
+            sample_ratio: args.otlp_sample_ratio,
+        })?),
+        None => None,
+    };
+    let base_subscriber = base_subscriber
+        .with(sensitive_filter)
+        .with(tracer_provider.as_ref().map(telemetry::layer));
+
     match log_format.as_str() {
         "json" => {
             let json_layer = tracing_bunyan_formatter::JsonStorageLayer;
@@ -80,11 +117,7 @@ async fn main() -> AnyhowResult<()> {
                 "gpt_node".into(),
                 non_blocking_writer,
             );
-            base_subscriber
-                .with(sensitive_filter)
-                .with(json_layer)
-                .with(bunyan_layer)
-                .init();
+            base_subscriber.with(json_layer).with(bunyan_layer).init();
         }
         _ => {
             let fmt_layer = fmt::layer()
@@ -96,7 +129,7 @@ async fn main() -> AnyhowResult<()> {
                 .with_ansi(true)
                 .with_timer(ChronoUtc::new("%T%.3f".to_string()))
                 .compact();
-            base_subscriber.with(sensitive_filter).with(fmt_layer).init();
+            base_subscriber.with(fmt_layer).init();
         }
     }
 
@@ -116,7 +149,6 @@ async fn main() -> AnyhowResult<()> {
         error!(target: "panic", location = %location, error = %payload, "CRITICAL: Process Panic");
     }));
 
-    let args = Args::parse();
     info!(
         version = env!("CARGO_PKG_VERSION"),
         log_format, "Starting gpt_node instance"
@@ -127,6 +159,7 @@ async fn main() -> AnyhowResult<()> {
         replica_url = if args.network_type == "local" { Some(&args.replica_url) } else { None },
         rpm_limit = ?args.rpm,
         concurrency_limit = ?args.concurrency,
+        otlp_endpoint = ?args.otlp_endpoint,
         "Loaded configuration"
     );
 
@@ -202,6 +235,12 @@ async fn main() -> AnyhowResult<()> {
         );
     }
 
+    if let Some(provider) = tracer_provider
+        && let Err(e) = provider.shutdown()
+    {
+        warn!(error = %e, "Failed to flush pending trace exports.");
+    }
+
     info!("Graceful shutdown complete. Exiting process.");
     Ok(())
 }
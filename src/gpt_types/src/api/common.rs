@@ -14,10 +14,27 @@ pub enum CanisterPoolState {
     },
 }
 
+/// Progress of a pool canister through a gpt_user upgrade rollout
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType, PartialEq, Eq)]
+pub enum CanisterUpgradeState {
+    /// Queued for the running rollout
+    Pending,
+    /// Running the rollout's target module
+    Upgraded { at: u64 },
+    /// The upgrade was rejected; the canister still runs its previous module
+    Failed { at: u64, error: String },
+    /// Reinstalled with the previous module during a rollback
+    RolledBack { at: u64 },
+}
+
 /// Entry in the canister pool (replaces legacy CanisterStats)
 #[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
 pub struct CanisterPoolEntry {
     pub canister_id: Principal,
     pub time_created: u64,
     pub state: CanisterPoolState,
+    /// SHA-256 of the gpt_user module last installed by the index (None = empty or unknown)
+    pub module_hash: Option<Vec<u8>>,
+    /// None = never part of a rollout
+    pub upgrade_state: Option<CanisterUpgradeState>,
 }
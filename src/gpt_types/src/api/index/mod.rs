@@ -4,6 +4,7 @@ pub mod model;
 pub mod node;
 pub mod user;
 pub mod user_canister;
+pub mod user_canister_upgrade;
 
 pub use attestation::*;
 pub use governance::*;
@@ -11,3 +12,4 @@ pub use model::*;
 pub use node::*;
 pub use user::*;
 pub use user_canister::*;
+pub use user_canister_upgrade::*;
@@ -24,6 +24,7 @@ pub struct CreateIndexNodeRequest {
     pub hostname: String,
     pub model_id: String,
     pub encrypted_api_key: String,
+    pub encrypted_fallback_api_keys: Option<Vec<String>>,
     pub expected_chip_id: String,
 }
 
@@ -62,6 +63,7 @@ pub struct GetNodeConfigResponse {
     pub hostname: String,
     pub model_id: String,
     pub encrypted_api_key: String,
+    pub encrypted_fallback_api_keys: Option<Vec<String>>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
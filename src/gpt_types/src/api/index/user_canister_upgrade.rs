@@ -0,0 +1,111 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Lifecycle of a gpt_user rollout across the assigned pool canisters
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType, PartialEq, Eq)]
+pub enum UpgradeRolloutStatus {
+    /// Upgrading batches towards the target module
+    Running,
+    /// Stopped after a failed batch or by a manager; remaining canisters are untouched
+    Halted { reason: String },
+    /// Every assigned canister runs the target module, which is now the install default
+    Completed,
+    /// Reinstalling the previous module on canisters already running the target
+    RollingBack,
+    /// Every canister upgraded by the rollout is back on the previous module
+    RolledBack,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct UpgradeRollout {
+    pub target_module_hash: Vec<u8>,
+    pub previous_module_hash: Vec<u8>,
+    pub batch_size: u32,
+    pub batch_interval_secs: u64,
+    pub status: UpgradeRolloutStatus,
+    pub batches_run: u32,
+    /// Storage schema the previous module supports, as reported by a canister running it
+    pub previous_schema_version: Option<u32>,
+    /// Highest storage schema version reported by a canister after its upgrade
+    pub target_schema_version: Option<u32>,
+    pub started_at: u64,
+    pub updated_at: u64,
+}
+
+/// Starts a chunked upload of a gpt_user module (manager-only).
+/// Any previous unfinished upload is discarded.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct BeginUserWasmUploadRequest {
+    pub total_size: u64,
+    /// Expected SHA-256 of the complete module, checked on commit
+    pub module_hash: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct BeginUserWasmUploadResponse;
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct UploadUserWasmChunkRequest {
+    #[serde(with = "serde_bytes")]
+    pub chunk: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct UploadUserWasmChunkResponse {
+    pub received_bytes: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct CommitUserWasmUploadRequest {}
+
+/// The committed module is staged as the target of the next rollout
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct CommitUserWasmUploadResponse {
+    pub module_hash: Vec<u8>,
+    pub size: u64,
+}
+
+/// Starts (or resumes a halted) rollout of the staged module (manager-only).
+/// Unset fields fall back to the index defaults.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct StartUserCanisterUpgradeRequest {
+    pub batch_size: Option<u32>,
+    pub batch_interval_secs: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct StartUserCanisterUpgradeResponse {
+    pub rollout: UpgradeRollout,
+    pub canisters_queued: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct HaltUserCanisterUpgradeRequest {}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct HaltUserCanisterUpgradeResponse;
+
+/// Rolls back a halted or completed rollout (manager-only). Refused when the upgraded
+/// canisters store a schema version the previous module cannot read.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct RollbackUserCanisterUpgradeRequest {}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct RollbackUserCanisterUpgradeResponse {
+    pub canisters_queued: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct GetUserCanisterUpgradeStatusRequest {}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct GetUserCanisterUpgradeStatusResponse {
+    pub rollout: Option<UpgradeRollout>,
+    /// Module installed on newly assigned canisters
+    pub current_module_hash: Vec<u8>,
+    pub staged_module_hash: Option<Vec<u8>>,
+    pub pending: u32,
+    pub upgraded: u32,
+    pub failed: u32,
+    pub rolled_back: u32,
+}
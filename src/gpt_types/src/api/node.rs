@@ -1,3 +1,4 @@
+use crate::domain::JobId;
 use crate::domain::message::Message;
 use candid::CandidType;
 use serde::{Deserialize, Serialize};
@@ -11,3 +12,20 @@ pub struct NodeGetMessageRequest {
 pub struct NodeGetMessageResponse {
     pub message: Message,
 }
+
+/// Fetches the message chain of a job claimed by the calling node, oldest message first.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeGetMessageChainRequest {
+    pub job_id: JobId,
+    /// Chain index to start from; `None` for the first page
+    pub cursor: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeGetMessageChainResponse {
+    pub messages: Vec<Message>,
+    /// Cursor of the next page; `None` once the chain is exhausted
+    pub next_cursor: Option<u32>,
+    /// Number of messages in the whole chain
+    pub total: u32,
+}
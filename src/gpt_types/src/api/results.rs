@@ -6,7 +6,9 @@ use crate::error::CanisterError;
 pub type AddManagerResult = Result<AddManagerResponse, CanisterError>;
 pub type AddMeasurementResult = Result<AddMeasurementResponse, CanisterError>;
 pub type AddModelResult = Result<AddModelResponse, CanisterError>;
+pub type BeginUserWasmUploadResult = Result<BeginUserWasmUploadResponse, CanisterError>;
 pub type ClaimManagerRoleResult = Result<ClaimManagerRoleResponse, CanisterError>;
+pub type CommitUserWasmUploadResult = Result<CommitUserWasmUploadResponse, CanisterError>;
 pub type ConfirmRegistrationResult = Result<ConfirmRegistrationResponse, CanisterError>;
 pub type CreateIndexNodeResult = Result<CreateIndexNodeResponse, CanisterError>;
 pub type CreateUserCanisterResult = Result<CreateUserCanisterResponse, CanisterError>;
@@ -15,6 +17,9 @@ pub type GetAttestationRequirementsResult =
 pub type GetNodeConfigResult = Result<GetNodeConfigResponse, CanisterError>;
 pub type GetProvisioningInfoResult = Result<GetProvisioningInfoResponse, CanisterError>;
 pub type GetUserAssignmentResult = Result<GetUserAssignmentResponse, CanisterError>;
+pub type GetUserCanisterUpgradeStatusResult =
+    Result<GetUserCanisterUpgradeStatusResponse, CanisterError>;
+pub type HaltUserCanisterUpgradeResult = Result<HaltUserCanisterUpgradeResponse, CanisterError>;
 pub type HeartbeatResult = Result<HeartbeatResponse, CanisterError>;
 pub type IsManagerResult = Result<IsManagerResponse, CanisterError>;
 pub type ListActiveNodesResult = Result<ListActiveNodesResponse, CanisterError>;
@@ -27,42 +32,76 @@ pub type RegisterNodeResult = Result<RegisterNodeResponse, CanisterError>;
 pub type RegisterUserResult = Result<RegisterUserResponse, CanisterError>;
 pub type RemoveManagerResult = Result<RemoveManagerResponse, CanisterError>;
 pub type RemoveMeasurementResult = Result<RemoveMeasurementResponse, CanisterError>;
+pub type RollbackUserCanisterUpgradeResult =
+    Result<RollbackUserCanisterUpgradeResponse, CanisterError>;
+pub type StartUserCanisterUpgradeResult = Result<StartUserCanisterUpgradeResponse, CanisterError>;
 pub type UnregisterNodeResult = Result<UnregisterNodeResponse, CanisterError>;
 pub type UpdateAttestationPoliciesResult = Result<UpdateAttestationPoliciesResponse, CanisterError>;
 pub type UpdateMeasurementStatusResult = Result<UpdateMeasurementStatusResponse, CanisterError>;
 pub type UpdateModelResult = Result<UpdateModelResponse, CanisterError>;
+pub type UploadUserWasmChunkResult = Result<UploadUserWasmChunkResponse, CanisterError>;
 
 // --- User Canister Results ---
 
+pub type AbortFileUploadResult = Result<AbortFileUploadResponse, CanisterError>;
+pub type AbortVaultImportResult = Result<AbortVaultImportResponse, CanisterError>;
 pub type AddMessageResult = Result<AddMessageResponse, CanisterError>;
 pub type GptUserAddUserResult = Result<GptUserAddUserResponse, CanisterError>;
 pub type ArchiveChatResult = Result<ArchiveChatResponse, CanisterError>;
+pub type BeginFileUploadResult = Result<BeginFileUploadResponse, CanisterError>;
+pub type BeginVaultImportResult = Result<BeginVaultImportResponse, CanisterError>;
+pub type CancelJobResult = Result<CancelJobResponse, CanisterError>;
 pub type ClaimJobResult = Result<ClaimJobResponse, CanisterError>;
+pub type CommitFileUploadResult = Result<CommitFileUploadResponse, CanisterError>;
+pub type CommitVaultImportResult = Result<CommitVaultImportResponse, CanisterError>;
 pub type CompleteJobResult = Result<CompleteJobResponse, CanisterError>;
 pub type ContinueFromToolResponseResult = Result<ContinueFromToolResponseResponse, CanisterError>;
 pub type CreateChatResult = Result<CreateChatResponse, CanisterError>;
+pub type CreateEmbeddingBatchResult = Result<CreateEmbeddingBatchResponse, CanisterError>;
+pub type CreateFileIngestionResult = Result<CreateFileIngestionResponse, CanisterError>;
 pub type CreateFolderResult = Result<CreateFolderResponse, CanisterError>;
 pub type DeleteChatResult = Result<DeleteChatResponse, CanisterError>;
 pub type DeleteItemResult = Result<DeleteItemResponse, CanisterError>;
 pub type EditUserMessageResult = Result<EditUserMessageResponse, CanisterError>;
+pub type ExportVaultPageResult = Result<ExportVaultPageResponse, CanisterError>;
 pub type FinalizeRegistrationResult = Result<FinalizeRegistrationResponse, CanisterError>;
 pub type GetChatResult = Result<GetChatResponse, CanisterError>;
+pub type GetChatTreeResult = Result<GetChatTreeResponse, CanisterError>;
 pub type GetChatJobsResult = Result<GetChatJobsResponse, CanisterError>;
+pub type GetEmbeddingBatchResult = Result<GetEmbeddingBatchResponse, CanisterError>;
 pub type GetFileContentResult = Result<GetFileContentResponse, CanisterError>;
 pub type GetFolderContentResult = Result<GetFolderContentResponse, CanisterError>;
 pub type GetItemByPathResult = Result<GetItemByPathResponse, CanisterError>;
 pub type GetMessageResult = Result<GetMessageResponse, CanisterError>;
+pub type GetRetentionPolicyResult = Result<GetRetentionPolicyResponse, CanisterError>;
+pub type GetScheduledChatDeletionsResult =
+    Result<GetScheduledChatDeletionsResponse, CanisterError>;
+pub type GetStorageSchemaVersionResult = Result<GetStorageSchemaVersionResponse, CanisterError>;
 pub type GetUserStorageUsageResult = Result<GetUserStorageUsageResponse, CanisterError>;
+pub type GetVaultImportStatusResult = Result<GetVaultImportStatusResponse, CanisterError>;
+pub type GetVaultManifestResult = Result<GetVaultManifestResponse, CanisterError>;
 pub type GptUserGetNodesResult = Result<GptUserGetNodesResponse, CanisterError>;
 pub type GptUserListRegisteredUsersResult =
     Result<GptUserListRegisteredUsersResponse, CanisterError>;
+pub type ImportVaultPageResult = Result<ImportVaultPageResponse, CanisterError>;
 pub type IsUserFinalizedResult = Result<IsUserFinalizedResponse, CanisterError>;
 pub type ListChatsResult = Result<ListChatsResponse, CanisterError>;
+pub type NodeGetFileContentResult = Result<NodeGetFileContentResponse, CanisterError>;
+pub type NodeGetJobStatusResult = Result<NodeGetJobStatusResponse, CanisterError>;
+pub type NodeGetMessageChainResult = Result<NodeGetMessageChainResponse, CanisterError>;
 pub type NodeGetMessageResult = Result<NodeGetMessageResponse, CanisterError>;
+pub type NodeGetRetrievalChunksResult = Result<NodeGetRetrievalChunksResponse, CanisterError>;
+pub type NodeStoreContextCheckpointResult =
+    Result<NodeStoreContextCheckpointResponse, CanisterError>;
+pub type NodeStoreFileChunksResult = Result<NodeStoreFileChunksResponse, CanisterError>;
 pub type RenameChatResult = Result<RenameChatResponse, CanisterError>;
 pub type RenameItemResult = Result<RenameItemResponse, CanisterError>;
 pub type RetryAiMessageResult = Result<RetryAiMessageResponse, CanisterError>;
+pub type SetChatActiveLeafResult = Result<SetChatActiveLeafResponse, CanisterError>;
+pub type SetChatPinnedResult = Result<SetChatPinnedResponse, CanisterError>;
+pub type SetRetentionPolicyResult = Result<SetRetentionPolicyResponse, CanisterError>;
 pub type StoreToolResultsResult = Result<StoreToolResultsResponse, CanisterError>;
 pub type UnarchiveChatResult = Result<UnarchiveChatResponse, CanisterError>;
 pub type UpdateMessageAttachmentsResult = Result<UpdateMessageAttachmentsResponse, CanisterError>;
+pub type UploadFileChunkResult = Result<UploadFileChunkResponse, CanisterError>;
 pub type UploadFileResult = Result<UploadFileResponse, CanisterError>;
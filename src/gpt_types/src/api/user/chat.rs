@@ -1,19 +1,37 @@
+use crate::api::user::listing::{ListSortField, ListSortKey, SortDirection};
+use crate::api::user::retrieval::RetrievalOptions;
 use crate::domain::chat::Chat;
-use crate::domain::common::{ChatId, JobId, MessageId, ModelId};
-use crate::domain::job::Job;
-use crate::domain::message::ImageAttachment;
+use crate::domain::common::{ChatId, JobId, MessageId, ModelId, Role};
+use crate::domain::job::{Job, NodeChatKey, ResponseSchema};
+use crate::domain::file_system::FileId;
+use crate::error::MessageErrorStatus;
 use crate::domain::tool::Tool;
 use candid::CandidType;
 use serde::{Deserialize, Serialize};
 
+/// Lists chats a page at a time. Defaults to the most recently updated first;
+/// title sorts default to ascending.
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
 pub struct ListChatsRequest {
     pub include_archived: bool,
+    pub sort_by: Option<ListSortField>,
+    pub direction: Option<SortDirection>,
+    /// `next_cursor` of the previous page, for the same sort
+    pub cursor: Option<ChatListCursor>,
+    pub limit: Option<u32>,
+}
+
+/// Position of the last chat of a page.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct ChatListCursor {
+    pub key: ListSortKey,
+    pub chat_id: ChatId,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
 pub struct ListChatsResponse {
     pub chats: Vec<Chat>,
+    pub next_cursor: Option<ChatListCursor>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -28,11 +46,17 @@ pub struct CreateChatRequest {
     pub node_id: u64,
     pub custom_prompt: Option<String>,
     pub temporary: bool,
-    pub attachments: Option<Vec<ImageAttachment>>,
+    pub attached_file_ids: Option<Vec<FileId>>,
     pub tools: Option<Vec<Tool>>,
     #[serde(with = "serde_bytes")]
     pub encryption_salt: Vec<u8>,
     pub encrypted_chat_key: Option<String>,
+    pub failover_chat_keys: Option<Vec<NodeChatKey>>,
+    pub response_schema: Option<ResponseSchema>,
+    pub retrieval: Option<RetrievalOptions>,
+    /// The key of the files attached in the chain, wrapped for the node and each
+    /// failover node; required when any message of the chain attaches files
+    pub file_keys: Option<Vec<NodeChatKey>>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -71,6 +95,59 @@ pub struct UnarchiveChatResponse {
     pub chat: Chat,
 }
 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct SetChatPinnedRequest {
+    pub chat_id: u64,
+    pub pinned: bool,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct SetChatPinnedResponse {
+    pub chat: Chat,
+}
+
+/// Returns the message tree of a chat without message content, in pages of nodes
+/// ordered by message id.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct GetChatTreeRequest {
+    pub chat_id: ChatId,
+    /// Return nodes with ids greater than this one
+    pub cursor: Option<MessageId>,
+    pub limit: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct MessageTreeNode {
+    pub message_id: MessageId,
+    /// None for roots, including messages whose parent was replaced by an edit
+    pub parent_message_id: Option<MessageId>,
+    pub children: Vec<MessageId>,
+    pub role: Role,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub error_status: Option<MessageErrorStatus>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct GetChatTreeResponse {
+    pub nodes: Vec<MessageTreeNode>,
+    pub next_cursor: Option<MessageId>,
+    pub active_leaf_message_id: Option<MessageId>,
+    /// Message ids from the root to the active leaf
+    pub active_path: Vec<MessageId>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct SetChatActiveLeafRequest {
+    pub chat_id: ChatId,
+    pub message_id: MessageId,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct SetChatActiveLeafResponse {
+    pub active_path: Vec<MessageId>,
+}
+
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
 pub struct RenameChatRequest {
     pub chat_id: u64,
@@ -0,0 +1,105 @@
+use crate::domain::common::{ChatId, GenerationStatus, JobId, ModelId, NodeId};
+use crate::domain::file_system::FileId;
+use crate::domain::job::{EmbeddingInput, NodeChatKey};
+use crate::domain::message::TokenUsage;
+use crate::domain::text_chunk::TextChunk;
+use crate::error::MessageErrorStatus;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Embeds many inputs in one job. The job runs in a new temporary chat whose key
+/// encrypts the inputs and the resulting embeddings.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CreateEmbeddingBatchRequest {
+    pub model_id: ModelId,
+    pub node_id: NodeId,
+    pub inputs: Vec<EmbeddingInput>,
+    /// Stores the embeddings as the chunks of this file instead of on the batch
+    pub target_file_id: Option<FileId>,
+    #[serde(with = "serde_bytes")]
+    pub encryption_salt: Vec<u8>,
+    pub encrypted_chat_key: String,
+    pub failover_chat_keys: Option<Vec<NodeChatKey>>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CreateEmbeddingBatchResponse {
+    pub chat_id: ChatId,
+    pub job_id: JobId,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct GetEmbeddingBatchRequest {
+    pub job_id: JobId,
+}
+
+/// Progress and result of an embedding batch. `embeddings` stays empty for batches
+/// written to a file.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct GetEmbeddingBatchResponse {
+    pub generation_status: GenerationStatus,
+    /// Encrypted embeddings in input order
+    pub embeddings: Vec<serde_bytes::ByteBuf>,
+    pub target_file_id: Option<FileId>,
+    pub usage: Option<TokenUsage>,
+    pub error_status: Option<MessageErrorStatus>,
+}
+
+/// Extracts, chunks and embeds the text of a stored file on the node. The file's
+/// content must be encrypted with the key of the new chat, and the chunks that
+/// replace the file's current ones are encrypted with it as well.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CreateFileIngestionRequest {
+    pub file_id: FileId,
+    pub model_id: ModelId,
+    pub node_id: NodeId,
+    /// Characters per chunk; the canister default when `None`
+    pub chunk_size_chars: Option<u32>,
+    /// Characters shared by consecutive chunks; the canister default when `None`
+    pub chunk_overlap_chars: Option<u32>,
+    #[serde(with = "serde_bytes")]
+    pub encryption_salt: Vec<u8>,
+    pub encrypted_chat_key: String,
+    pub failover_chat_keys: Option<Vec<NodeChatKey>>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CreateFileIngestionResponse {
+    pub chat_id: ChatId,
+    pub job_id: JobId,
+}
+
+/// Reads a file for a job claimed by the calling node.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeGetFileContentRequest {
+    pub job_id: JobId,
+    /// A file attached in the job's chain; `None` reads the file an ingestion job
+    /// ingests
+    pub file_id: Option<FileId>,
+    pub offset: u64,
+    pub length: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeGetFileContentResponse {
+    #[serde(with = "serde_bytes")]
+    pub content: Vec<u8>,
+    pub name: String,
+    pub mime_type: String,
+    pub offset: u64,
+    pub total_size_bytes: u64,
+}
+
+/// Stores chunks produced by an ingestion job. They replace the file's chunks once
+/// the job completes and are dropped if it fails.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeStoreFileChunksRequest {
+    pub job_id: JobId,
+    pub chunks: Vec<TextChunk>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeStoreFileChunksResponse {
+    /// Chunks stored for the job so far
+    pub stored_chunks: u32,
+}
@@ -1,3 +1,4 @@
+use crate::api::user::listing::{ListSortField, ListSortKey, SortDirection};
 use crate::domain::text_chunk::TextChunk;
 use crate::domain::{FileId, file_system::FolderId};
 use candid::CandidType;
@@ -23,9 +24,24 @@ pub struct FileInfo {
     pub chunks: Vec<TextChunk>,
 }
 
+/// Lists a folder a page at a time, folders before files. Defaults to sorting by
+/// name ascending.
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
 pub struct GetFolderContentRequest {
     pub folder_id: Option<FolderId>,
+    pub sort_by: Option<ListSortField>,
+    pub direction: Option<SortDirection>,
+    /// `next_cursor` of the previous page, for the same sort
+    pub cursor: Option<FolderContentCursor>,
+    pub limit: Option<u32>,
+}
+
+/// Position of the last item of a page.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct FolderContentCursor {
+    pub key: ListSortKey,
+    pub item_type: FsItemType,
+    pub id: u64,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -35,11 +51,18 @@ pub struct GetFolderContentResponse {
     pub folder_id: FolderId,
     pub folder_name: String,
     pub parent_folder_id: Option<FolderId>,
+    pub next_cursor: Option<FolderContentCursor>,
 }
 
+/// Reads file content. Without a range the whole file is returned, which fails for
+/// files larger than a single reply can carry; read those in ranges.
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
 pub struct GetFileContentRequest {
     pub file_id: FileId,
+    /// Byte offset to start reading at (default 0)
+    pub offset: Option<u64>,
+    /// Maximum number of bytes to return (default: up to the end of the file)
+    pub length: Option<u64>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -47,6 +70,8 @@ pub struct GetFileContentResponse {
     #[serde(with = "serde_bytes")]
     pub content: Vec<u8>,
     pub mime_type: String,
+    pub offset: u64,
+    pub total_size_bytes: u64,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -75,7 +100,57 @@ pub struct UploadFileResponse {
     pub file: FileInfo,
 }
 
+/// Opens a chunked upload session. The file becomes visible only once committed.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct BeginFileUploadRequest {
+    pub name: String,
+    pub parent_folder_id: FolderId,
+    pub mime_type: String,
+    pub total_size_bytes: u64,
+}
+
+/// Every chunk except the last must be exactly `chunk_size_bytes` long.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct BeginFileUploadResponse {
+    pub upload_id: FileId,
+    pub chunk_size_bytes: u64,
+    pub chunk_count: u32,
+}
+
+/// Stores one chunk of an open upload. Re-sending a chunk overwrites it.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct UploadFileChunkRequest {
+    pub upload_id: FileId,
+    pub index: u32,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct UploadFileChunkResponse {
+    pub received_chunks: u32,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CommitFileUploadRequest {
+    pub upload_id: FileId,
+    pub chunks: Option<Vec<TextChunk>>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CommitFileUploadResponse {
+    pub file: FileInfo,
+}
+
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct AbortFileUploadRequest {
+    pub upload_id: FileId,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct AbortFileUploadResponse;
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
 pub enum FsItemType {
     File,
     Folder,
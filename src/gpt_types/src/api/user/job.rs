@@ -1,7 +1,7 @@
 use crate::domain::chat::Chat;
-use crate::domain::common::{JobId, MessageId};
-use crate::domain::job::Job;
-use crate::domain::message::TokenUsage;
+use crate::domain::common::{GenerationStatus, JobId, MessageId};
+use crate::domain::job::{EmbeddingInput, Job};
+use crate::domain::message::{Citation, Message, TokenUsage};
 use crate::domain::tool::{Tool, ToolCall};
 use crate::error::MessageErrorStatus;
 use candid::CandidType;
@@ -12,6 +12,10 @@ pub enum JobCompletionResult {
     Success(#[serde(with = "serde_bytes")] Vec<u8>),
     Failure(MessageErrorStatus),
     ToolCall(Vec<ToolCall>),
+    /// The user stopped the generation; carries the encrypted text produced so far.
+    Cancelled(#[serde(with = "serde_bytes")] Vec<u8>),
+    /// One encrypted embedding per input of an embedding batch job, in input order.
+    Embeddings(Vec<serde_bytes::ByteBuf>),
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -25,6 +29,11 @@ pub struct ClaimJobResponse {
     pub message_chain_ids: Vec<MessageId>,
     pub job: Job,
     pub tools: Option<Vec<Tool>>,
+    /// Summary of the chain up to its parent message. When present, the messages it
+    /// covers are left out of `message_chain_ids`, except for system messages.
+    pub context_checkpoint: Option<Message>,
+    /// The inputs of an embedding batch job, in order.
+    pub embedding_inputs: Option<Vec<EmbeddingInput>>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -32,7 +41,42 @@ pub struct CompleteJobRequest {
     pub job_id: JobId,
     pub result: JobCompletionResult,
     pub usage: Option<TokenUsage>,
+    /// Sources the response drew on, stored on the job's assistant message
+    pub citations: Option<Vec<Citation>>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
 pub struct CompleteJobResponse;
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CancelJobRequest {
+    pub job_id: JobId,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CancelJobResponse;
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeGetJobStatusRequest {
+    pub job_id: JobId,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeGetJobStatusResponse {
+    pub generation_status: GenerationStatus,
+}
+
+/// Stores a summary of the job's message chain up to and including
+/// `covered_message_id`, encrypted with the chat key.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeStoreContextCheckpointRequest {
+    pub job_id: JobId,
+    pub covered_message_id: MessageId,
+    #[serde(with = "serde_bytes")]
+    pub content: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeStoreContextCheckpointResponse {
+    pub checkpoint_message_id: MessageId,
+}
@@ -0,0 +1,24 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Field a paginated listing is ordered by.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum ListSortField {
+    UpdatedAt,
+    CreatedAt,
+    Title,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+/// Sort value of the last item of a page, carried in listing cursors.
+/// Titles are compared case-insensitively and stored lowercased.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ListSortKey {
+    Timestamp(u64),
+    Title(String),
+}
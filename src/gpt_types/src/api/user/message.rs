@@ -1,7 +1,8 @@
+use crate::api::user::retrieval::RetrievalOptions;
 use crate::domain::common::JobId;
 use crate::domain::common::{MessageId, Role};
-use crate::domain::job::Job;
-use crate::domain::message::ImageAttachment;
+use crate::domain::file_system::FileId;
+use crate::domain::job::{ContextStrategy, Job, NodeChatKey, ResponseSchema};
 use crate::domain::message::Message;
 use crate::domain::tool::{Tool, ToolResult};
 use candid::CandidType;
@@ -30,10 +31,17 @@ pub struct AddMessageRequest {
     pub max_completion_tokens: u32,
     pub max_context: u32,
     pub custom_prompt: Option<String>,
-    pub attachments: Option<Vec<ImageAttachment>>,
+    pub attached_file_ids: Option<Vec<FileId>>,
     pub tools: Option<Vec<Tool>>,
     pub reasoning_effort: Option<String>,
     pub encrypted_chat_key: Option<String>,
+    pub failover_chat_keys: Option<Vec<NodeChatKey>>,
+    pub context_strategy: Option<ContextStrategy>,
+    pub response_schema: Option<ResponseSchema>,
+    pub retrieval: Option<RetrievalOptions>,
+    /// The key of the files attached in the chain, wrapped for the node and each
+    /// failover node; required when any message of the chain attaches files
+    pub file_keys: Option<Vec<NodeChatKey>>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -43,10 +51,12 @@ pub struct AddMessageResponse {
     pub job: Job,
 }
 
+/// Replaces the files attached to a user message. Inline images stored with the
+/// message are dropped.
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
 pub struct UpdateMessageAttachmentsRequest {
     pub message_id: MessageId,
-    pub attachments: Option<Vec<ImageAttachment>>,
+    pub attached_file_ids: Option<Vec<FileId>>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -72,10 +82,16 @@ pub struct EditUserMessageRequest {
     pub max_completion_tokens: u32,
     pub max_context: u32,
     pub custom_prompt: Option<String>,
-    pub attachments: Option<Vec<ImageAttachment>>,
+    pub attached_file_ids: Option<Vec<FileId>>,
     pub tools: Option<Vec<Tool>>,
     pub reasoning_effort: Option<String>,
     pub encrypted_chat_key: Option<String>,
+    pub failover_chat_keys: Option<Vec<NodeChatKey>>,
+    pub context_strategy: Option<ContextStrategy>,
+    pub retrieval: Option<RetrievalOptions>,
+    /// The key of the files attached in the chain, wrapped for the node and each
+    /// failover node; required when any message of the chain attaches files
+    pub file_keys: Option<Vec<NodeChatKey>>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -98,6 +114,12 @@ pub struct RetryAiMessageRequest {
     pub tools: Option<Vec<Tool>>,
     pub reasoning_effort: Option<String>,
     pub encrypted_chat_key: Option<String>,
+    pub failover_chat_keys: Option<Vec<NodeChatKey>>,
+    pub context_strategy: Option<ContextStrategy>,
+    pub retrieval: Option<RetrievalOptions>,
+    /// The key of the files attached in the chain, wrapped for the node and each
+    /// failover node; required when any message of the chain attaches files
+    pub file_keys: Option<Vec<NodeChatKey>>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
@@ -127,6 +149,12 @@ pub struct ContinueFromToolResponseRequest {
     pub tools: Option<Vec<Tool>>,
     pub reasoning_effort: Option<String>,
     pub encrypted_chat_key: Option<String>,
+    pub failover_chat_keys: Option<Vec<NodeChatKey>>,
+    pub context_strategy: Option<ContextStrategy>,
+    pub retrieval: Option<RetrievalOptions>,
+    /// The key of the files attached in the chain, wrapped for the node and each
+    /// failover node; required when any message of the chain attaches files
+    pub file_keys: Option<Vec<NodeChatKey>>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
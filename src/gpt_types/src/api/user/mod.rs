@@ -1,13 +1,23 @@
 pub mod chat;
+pub mod embedding;
 pub mod fs;
 pub mod job;
+pub mod listing;
 pub mod message;
 pub mod registration;
+pub mod retention;
+pub mod retrieval;
 pub mod storage_usage;
+pub mod vault;
 
 pub use chat::*;
+pub use embedding::*;
 pub use fs::*;
 pub use job::*;
+pub use listing::*;
 pub use message::*;
 pub use registration::*;
+pub use retention::*;
+pub use retrieval::*;
 pub use storage_usage::*;
+pub use vault::*;
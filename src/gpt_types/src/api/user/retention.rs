@@ -0,0 +1,42 @@
+use crate::domain::common::ChatId;
+use crate::domain::retention::RetentionPolicy;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct GetRetentionPolicyResponse {
+    pub policy: RetentionPolicy,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct SetRetentionPolicyRequest {
+    pub policy: RetentionPolicy,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct SetRetentionPolicyResponse {
+    pub policy: RetentionPolicy,
+}
+
+/// Lists chats the cleanup task will delete, soonest first.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct GetScheduledChatDeletionsRequest {
+    /// Only include deletions due within this many seconds (default: all)
+    pub within_secs: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct ScheduledChatDeletion {
+    pub chat_id: ChatId,
+    pub title: String,
+    pub archived: bool,
+    pub temporary: bool,
+    /// Time after which the next cleanup run deletes the chat
+    pub delete_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct GetScheduledChatDeletionsResponse {
+    pub deletions: Vec<ScheduledChatDeletion>,
+}
@@ -0,0 +1,43 @@
+use crate::domain::common::{JobId, ModelId};
+use crate::domain::file_system::{FileId, FolderId};
+use crate::domain::job::NodeChatKey;
+use crate::domain::text_chunk::TextChunk;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Grounds a generation on the ingested chunks of the user's files. Limits left
+/// unset take the canister's defaults.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct RetrievalOptions {
+    pub file_ids: Vec<FileId>,
+    pub folder_ids: Vec<FolderId>,
+    pub embedding_model_id: ModelId,
+    pub top_k: Option<u32>,
+    pub max_passage_tokens: Option<u32>,
+    pub mmr_lambda: Option<f32>,
+    /// Must hold an entry for the job's node
+    pub file_keys: Vec<NodeChatKey>,
+}
+
+/// Returns the ingested chunks searched by a retrieval job, in pages. Only chunks
+/// that carry their text are returned.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeGetRetrievalChunksRequest {
+    pub job_id: JobId,
+    /// Position of the first chunk to return; `None` starts from the beginning
+    pub cursor: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct RetrievalChunk {
+    pub file_id: FileId,
+    pub chunk: TextChunk,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct NodeGetRetrievalChunksResponse {
+    pub chunks: Vec<RetrievalChunk>,
+    /// The provider's name for the job's embedding model
+    pub embedding_provider_model: String,
+    pub next_cursor: Option<u32>,
+}
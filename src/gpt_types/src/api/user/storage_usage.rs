@@ -6,3 +6,13 @@ pub struct GetUserStorageUsageResponse {
     pub usage_bytes: u64,
     pub limit_bytes: u64,
 }
+
+/// Storage schema versions of a user canister, read by the index before it rolls
+/// the canister back to an older module.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct GetStorageSchemaVersionResponse {
+    /// Version the canister's stable storage has been migrated to
+    pub stored_version: u32,
+    /// Newest version the running module can read and write
+    pub supported_version: u32,
+}
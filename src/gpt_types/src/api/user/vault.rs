@@ -0,0 +1,166 @@
+use crate::domain::{Chat, FileId, FileMetadata, Folder, FolderId, Job, Message, RetentionPolicy};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Archive format written by `get_vault_manifest` and `export_vault_page`.
+/// Imports reject manifests of any other version.
+pub const VAULT_ARCHIVE_FORMAT_VERSION: u32 = 2;
+
+/// A group of records exported and imported independently, in pages.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum VaultSection {
+    Chats,
+    Messages,
+    Jobs,
+    Folders,
+    Files,
+    FileContents,
+}
+
+impl VaultSection {
+    pub const ALL: [VaultSection; 6] = [
+        VaultSection::Chats,
+        VaultSection::Messages,
+        VaultSection::Jobs,
+        VaultSection::Folders,
+        VaultSection::Files,
+        VaultSection::FileContents,
+    ];
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct VaultSectionCount {
+    pub section: VaultSection,
+    pub record_count: u64,
+}
+
+/// Describes a whole archive. The import checks every section against these counts
+/// before it commits, so a partial import cannot be mistaken for a complete one.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct VaultManifest {
+    pub format_version: u32,
+    pub exported_at: u64,
+    pub root_folder_id: Option<FolderId>,
+    #[serde(with = "serde_bytes")]
+    pub enc_salt: Vec<u8>,
+    pub enc_validator: String,
+    pub sections: Vec<VaultSectionCount>,
+    /// The owner's retention policy; `None` means the default one
+    pub retention_policy: Option<RetentionPolicy>,
+}
+
+/// Stored file content. `chunk_index` is set for files uploaded in chunks.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct VaultFileContent {
+    pub file_id: FileId,
+    pub chunk_index: Option<u32>,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// One exported record, exactly as stored; encrypted fields stay ciphertext.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub enum VaultRecord {
+    Chat(Chat),
+    Message(Message),
+    Job(Job),
+    Folder(Folder),
+    File(FileMetadata),
+    FileContent(VaultFileContent),
+}
+
+/// Records `offset..offset + records.len()` of a section.
+/// `sha256` covers the Candid encoding of `records`.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct VaultPage {
+    pub section: VaultSection,
+    pub offset: u64,
+    pub records: Vec<VaultRecord>,
+    #[serde(with = "serde_bytes")]
+    pub sha256: Vec<u8>,
+}
+
+/// The storage key of an exported record. `chunk_index` is set for the content of
+/// files uploaded in chunks.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct VaultRecordKey {
+    pub id: u64,
+    pub chunk_index: Option<u32>,
+}
+
+/// Where the next page of a section starts: after the record stored at `after`,
+/// which is record `offset - 1` of the section.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct VaultPageCursor {
+    pub offset: u64,
+    pub after: VaultRecordKey,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct GetVaultManifestResponse {
+    pub manifest: VaultManifest,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct ExportVaultPageRequest {
+    pub section: VaultSection,
+    /// The `next_cursor` of the previous page; `None` for the first page
+    pub cursor: Option<VaultPageCursor>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct ExportVaultPageResponse {
+    pub page: VaultPage,
+    pub next_cursor: Option<VaultPageCursor>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct VaultSectionProgress {
+    pub section: VaultSection,
+    /// Records imported so far; also the offset of the next page to send
+    pub imported: u64,
+    pub expected: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct VaultImportStatus {
+    pub exported_at: u64,
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub sections: Vec<VaultSectionProgress>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct BeginVaultImportRequest {
+    pub manifest: VaultManifest,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct BeginVaultImportResponse {
+    pub status: VaultImportStatus,
+}
+
+/// Pages may be resent: records that were already imported are skipped.
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct ImportVaultPageRequest {
+    pub page: VaultPage,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct ImportVaultPageResponse {
+    pub status: VaultImportStatus,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct GetVaultImportStatusResponse {
+    /// None when no import is in progress
+    pub status: Option<VaultImportStatus>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CommitVaultImportResponse {
+    pub status: VaultImportStatus,
+}
+
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct AbortVaultImportResponse;
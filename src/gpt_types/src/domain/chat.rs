@@ -14,6 +14,10 @@ pub struct Chat {
     pub updated_at: u64,
     pub archived: bool,
     pub temporary: bool,
+    /// Pinned chats are exempt from the retention policy
+    pub pinned: bool,
+    /// Leaf of the branch the client shows; maintained on add, edit and retry
+    pub active_leaf_message_id: Option<MessageId>,
     #[serde(with = "serde_bytes")]
     pub encryption_salt: Vec<u8>,
 }
@@ -23,4 +23,5 @@ pub enum GenerationStatus {
     InProgress,
     Completed,
     Failed,
+    Cancelled,
 }
@@ -1,5 +1,6 @@
 use crate::domain::common::GenerationStatus;
 use crate::domain::common::{ChatId, JobId, MessageId, ModelId, NodeId};
+use crate::domain::file_system::{FileId, FolderId};
 use crate::domain::tool::Tool;
 use candid::CandidType;
 use serde::{Deserialize, Serialize};
@@ -22,4 +23,110 @@ pub struct Job {
     pub extra_body_json: Option<String>,
     pub reasoning_effort: Option<String>,
     pub encrypted_chat_key: Option<String>,
+    /// Number of times the job was reassigned to another node
+    pub retry_count: u32,
+    /// Nodes that held the job before the current one, oldest first
+    pub node_history: Vec<NodeId>,
+    /// The chat key wrapped for other nodes serving the same model, used on failover
+    pub failover_chat_keys: Vec<NodeChatKey>,
+    /// How the node fits history longer than `max_context`; `None` means truncation
+    pub context_strategy: Option<ContextStrategy>,
+    /// JSON Schema the final response must match; `None` means free-form text
+    pub response_schema: Option<ResponseSchema>,
+    /// What the job produces; `None` means a chat completion
+    pub kind: Option<JobKind>,
+    /// Files whose ingested chunks ground the response; `None` means no retrieval
+    pub retrieval: Option<Retrieval>,
+    /// The key of the files attached in the job's chain, wrapped for the job's node
+    /// and for each failover node
+    pub file_keys: Option<Vec<NodeChatKey>>,
+}
+
+/// The kind of work a job asks of its node.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize, PartialEq, Eq, Default)]
+pub enum JobKind {
+    /// A response to the job's message chain.
+    #[default]
+    Chat,
+    /// One embedding per input, computed by an embedding model. The inputs are held
+    /// by the user canister and handed to the node when it claims the job.
+    EmbeddingBatch(EmbeddingBatch),
+    /// Text extracted from a stored file, split into chunks and embedded. The node
+    /// reads the file and writes the chunks back to the user canister itself.
+    FileIngestion(FileIngestion),
+}
+
+/// Size and destination of an embedding batch.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct EmbeddingBatch {
+    pub input_count: u32,
+    /// File whose chunks are replaced by the embeddings when the job completes
+    pub target_file_id: Option<FileId>,
+}
+
+/// The file an ingestion job reads and how its text is split, in characters.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct FileIngestion {
+    pub file_id: FileId,
+    pub chunk_size_chars: u32,
+    pub chunk_overlap_chars: u32,
+}
+
+/// The files a job grounds its response on, and how passages are picked from their
+/// ingested chunks.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize)]
+pub struct Retrieval {
+    pub file_ids: Vec<FileId>,
+    /// Folders whose files, including those of subfolders, are searched as well
+    pub folder_ids: Vec<FolderId>,
+    /// The model the chunks were embedded with; the node embeds the query with it
+    pub embedding_model_id: ModelId,
+    /// Most passages added to the prompt
+    pub top_k: u32,
+    /// Token budget shared by the added passages
+    pub max_passage_tokens: u32,
+    /// Weight of relevance against diversity, from 0 to 1, in a maximal marginal
+    /// relevance pass; `None` ranks by relevance alone
+    pub mmr_lambda: Option<f32>,
+    /// The key the chunks were encrypted with, wrapped for the job's node and for
+    /// each failover node
+    pub file_keys: Vec<NodeChatKey>,
+}
+
+/// Text to embed, encrypted with the chat key.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize, PartialEq)]
+pub struct EmbeddingInput {
+    #[serde(with = "serde_bytes")]
+    pub content: Vec<u8>,
+    /// Character range of the input within the target file; ignored without one
+    pub start_char: u32,
+    pub end_char: u32,
+}
+
+/// A JSON Schema for structured output. The node asks the provider for a conforming
+/// response and validates the final text against the schema.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize, PartialEq)]
+pub struct ResponseSchema {
+    /// Schema name, as required by OpenAI-style `json_schema` response formats.
+    pub name: String,
+    /// The schema document, serialized as JSON.
+    pub schema_json: String,
+}
+
+/// How a node shrinks a conversation that does not fit the model's context window.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Serialize, PartialEq, Eq, Default)]
+pub enum ContextStrategy {
+    /// Drop the oldest non-system messages.
+    #[default]
+    Truncate,
+    /// Replace the oldest messages with a model-written summary, stored as a
+    /// checkpoint so later jobs in the chat reuse it.
+    Summarize,
+}
+
+/// A chat key wrapped for one specific node.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize)]
+pub struct NodeChatKey {
+    pub node_id: NodeId,
+    pub encrypted_chat_key: String,
 }
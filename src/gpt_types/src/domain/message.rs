@@ -1,4 +1,5 @@
 use crate::domain::common::{ChatId, MessageId, Role};
+use crate::domain::file_system::FileId;
 use crate::domain::tool::{ToolCall, ToolResult};
 use crate::error::MessageErrorStatus;
 use candid::CandidType;
@@ -17,6 +18,17 @@ pub struct TokenUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// The provider backend that produced the response
+    pub served_by: Option<ServedBy>,
+}
+
+/// Which of a model's provider backends served a request.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct ServedBy {
+    /// 0 for the primary provider, 1 and up for the model's fallback providers
+    pub backend_index: u32,
+    pub provider: String,
+    pub provider_model: String,
 }
 
 #[derive(CandidType, Deserialize, Clone, Debug, Serialize)]
@@ -30,10 +42,41 @@ pub struct Message {
     pub created_at: u64,
     pub updated_at: u64,
     pub error_status: Option<MessageErrorStatus>,
+    /// Inline images of messages stored before files could be attached
     pub attachments: Option<Vec<ImageAttachment>>,
+    /// Files of the owner's file system attached to the message. The node reads them
+    /// when it builds the prompt: images as image parts, other files as text
+    pub attached_file_ids: Option<Vec<FileId>>,
     pub tool_calls: Option<Vec<ToolCall>>,
     pub tool_results: Option<Vec<ToolResult>>,
     pub tool_call_id: Option<String>,
     pub requires_client_action: bool,
     pub usage: Option<TokenUsage>,
+    /// Sources an assistant message drew on, as reported by its node
+    pub citations: Vec<Citation>,
+}
+
+/// A source cited by an assistant message.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct Citation {
+    pub source: CitationSource,
+    /// The cited characters of the source; `None` cites all of it
+    pub span: Option<CharSpan>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize, PartialEq, Eq)]
+pub enum CitationSource {
+    /// A chunk of an ingested file, such as a passage added by retrieval. Its span
+    /// is counted in characters of the file's extracted text.
+    FileChunk { file_id: FileId, chunk_index: u32 },
+    /// The result of a tool call in the chat. Its span is counted in characters of
+    /// the result.
+    ToolResult { tool_call_id: String },
+}
+
+/// A range of characters, end exclusive.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub struct CharSpan {
+    pub start_char: u32,
+    pub end_char: u32,
 }
@@ -5,6 +5,7 @@ pub mod job;
 pub mod message;
 pub mod model;
 pub mod node;
+pub mod retention;
 pub mod text_chunk;
 pub mod tool;
 pub mod user;
@@ -16,6 +17,7 @@ pub use job::*;
 pub use message::*;
 pub use model::*;
 pub use node::*;
+pub use retention::*;
 pub use text_chunk::*;
 pub use tool::*;
 pub use user::*;
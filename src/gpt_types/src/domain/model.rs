@@ -30,4 +30,15 @@ pub struct Model {
     pub is_reasoning: bool,
     pub is_embedding: bool,
     pub is_featured: bool,
+    /// Backends tried in order when the primary provider fails before streaming.
+    /// Each node holds its own API key for every backend it serves.
+    pub fallback_providers: Option<Vec<ProviderBackend>>,
+}
+
+/// A provider that serves a model, with the model's name at that provider.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct ProviderBackend {
+    pub provider: String,
+    pub provider_model: String,
+    pub provider_endpoint: String,
 }
@@ -73,6 +73,9 @@ pub struct Node {
     pub hostname: String,
     pub model_id: ModelId,
     pub encrypted_api_key: String,
+    /// API keys for the model's fallback providers, in the same order. An empty key
+    /// skips that backend on this node.
+    pub encrypted_fallback_api_keys: Option<Vec<String>>,
 
     pub lifecycle_status: NodeLifecycleStatus,
 
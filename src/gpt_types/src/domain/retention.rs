@@ -0,0 +1,22 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// How long chats are kept after their last update before the cleanup task
+/// deletes them. `None` keeps chats of that kind forever; pinned chats are never
+/// deleted.
+#[derive(CandidType, Deserialize, Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub chat_ttl_secs: Option<u64>,
+    pub archived_chat_ttl_secs: Option<u64>,
+    pub temporary_chat_ttl_secs: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            chat_ttl_secs: Some(7 * 24 * 60 * 60),
+            archived_chat_ttl_secs: Some(14 * 24 * 60 * 60),
+            temporary_chat_ttl_secs: 10 * 60,
+        }
+    }
+}
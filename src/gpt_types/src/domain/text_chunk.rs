@@ -8,4 +8,7 @@ pub struct TextChunk {
     pub end_char: u32,
     #[serde(with = "serde_bytes")]
     pub embedding: Vec<u8>,
+    /// Text of the chunk, encrypted with the key of the ingestion job that produced
+    /// it; `None` when the client embedded the file itself
+    pub content: Option<serde_bytes::ByteBuf>,
 }
@@ -56,6 +56,8 @@ pub enum MessageErrorStatus {
     InvalidState(String),
     ConfigurationError(String),
     Unknown(String),
+    /// The response still did not match the job's response schema after repair attempts.
+    SchemaValidationFailed(String),
 }
 
 impl fmt::Display for CanisterError {
@@ -135,12 +137,34 @@ impl fmt::Display for MessageErrorStatus {
                 write!(f, "Node configuration error: {}", msg)
             }
             MessageErrorStatus::Unknown(msg) => write!(f, "Unknown generation error: {}", msg),
+            MessageErrorStatus::SchemaValidationFailed(msg) => {
+                write!(f, "Response did not match the requested schema: {}", msg)
+            }
         }
     }
 }
 
 pub type CanisterResult<T> = Result<T, CanisterError>;
 
+impl MessageErrorStatus {
+    /// Whether the failure is tied to the node or a transient provider condition,
+    /// so the job may succeed on another node serving the same model.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            MessageErrorStatus::Timeout | MessageErrorStatus::NodeOffline => true,
+            MessageErrorStatus::ProviderError(pe) => matches!(
+                pe,
+                ProviderErrorType::RateLimited
+                    | ProviderErrorType::ServerError
+                    | ProviderErrorType::ServiceUnavailable
+                    | ProviderErrorType::NetworkError
+                    | ProviderErrorType::Timeout
+            ),
+            _ => false,
+        }
+    }
+}
+
 impl From<ProviderErrorType> for MessageErrorStatus {
     fn from(pe: ProviderErrorType) -> Self {
         MessageErrorStatus::ProviderError(pe)
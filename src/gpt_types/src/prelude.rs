@@ -2,50 +2,77 @@ pub use crate::domain::chat::Chat;
 pub use crate::domain::common::{ChatId, JobId, MessageId, ModelId, NodeId, SecretKey, UserId};
 pub use crate::domain::common::{GenerationStatus, Role};
 pub use crate::domain::file_system::{FileId, FileMetadata, Folder, FolderId};
-pub use crate::domain::job::Job;
-pub use crate::domain::message::{ImageAttachment, Message};
-pub use crate::domain::model::Model;
+pub use crate::domain::job::{
+    ContextStrategy, EmbeddingBatch, EmbeddingInput, FileIngestion, Job, JobKind, NodeChatKey,
+    ResponseSchema, Retrieval,
+};
+pub use crate::domain::message::{CharSpan, Citation, CitationSource, ImageAttachment, Message};
+pub use crate::domain::model::{Model, ProviderBackend};
 pub use crate::domain::node::{AttestationRequirements, Node, PublicNodeInfo};
+pub use crate::domain::retention::RetentionPolicy;
 pub use crate::domain::text_chunk::TextChunk;
 pub use crate::domain::user::User;
 pub use crate::error::{CanisterError, CanisterResult};
-pub use crate::api::common::{CanisterPoolEntry, CanisterPoolState};
+pub use crate::api::common::{CanisterPoolEntry, CanisterPoolState, CanisterUpgradeState};
 
 // Export all API structs (Requests/Responses)
 pub use crate::api::{
-    AddManagerRequest, AddManagerResponse, AddMeasurementRequest, AddMeasurementResponse,
-    AddMessageRequest, AddMessageResponse, AddModelRequest, AddModelResponse, ArchiveChatRequest,
-    ArchiveChatResponse, ClaimJobRequest, ClaimJobResponse, ClaimManagerRoleResponse,
+    AbortFileUploadRequest, AbortFileUploadResponse, AbortVaultImportResponse, AddManagerRequest,
+    AddManagerResponse, AddMeasurementRequest, AddMeasurementResponse, AddMessageRequest,
+    AddMessageResponse, AddModelRequest, AddModelResponse, ArchiveChatRequest, ArchiveChatResponse,
+    BeginFileUploadRequest, BeginFileUploadResponse, BeginUserWasmUploadRequest,
+    BeginUserWasmUploadResponse, BeginVaultImportRequest, BeginVaultImportResponse,
+    CancelJobRequest, CancelJobResponse, ChatListCursor, ClaimJobRequest, ClaimJobResponse,
+    ClaimManagerRoleResponse, CommitFileUploadRequest, CommitFileUploadResponse,
+    CommitUserWasmUploadRequest, CommitUserWasmUploadResponse, CommitVaultImportResponse,
     CompleteJobRequest, CompleteJobResponse, ConfirmRegistrationRequest,
     ConfirmRegistrationResponse, ContinueFromToolResponseRequest, ContinueFromToolResponseResponse,
-    CreateChatRequest, CreateChatResponse, CreateFolderRequest, CreateFolderResponse,
-    CreateIndexNodeRequest, CreateIndexNodeResponse, CreateUserCanisterResponse, DeleteChatRequest,
-    DeleteChatResponse, DeleteItemRequest, DeleteItemResponse, EditUserMessageRequest,
-    EditUserMessageResponse, FinalizeRegistrationRequest, FinalizeRegistrationResponse,
-    FileInfo, FolderInfo, FsItemInfo, FsItemType, GetAttestationRequirementsRequest,
+    CreateChatRequest, CreateChatResponse, CreateEmbeddingBatchRequest,
+    CreateEmbeddingBatchResponse, CreateFileIngestionRequest, CreateFileIngestionResponse,
+    CreateFolderRequest, CreateFolderResponse, CreateIndexNodeRequest, CreateIndexNodeResponse,
+    CreateUserCanisterResponse, DeleteChatRequest, DeleteChatResponse, DeleteItemRequest,
+    DeleteItemResponse, EditUserMessageRequest, EditUserMessageResponse, ExportVaultPageRequest,
+    ExportVaultPageResponse, FinalizeRegistrationRequest, FinalizeRegistrationResponse, FileInfo,
+    FolderContentCursor, FolderInfo, FsItemInfo, FsItemType, GetAttestationRequirementsRequest,
     GetAttestationRequirementsResponse, GetChatJobsRequest, GetChatJobsResponse, GetChatRequest,
-    GetChatResponse, GetFileContentRequest, GetFileContentResponse, GetFolderContentRequest,
-    GetFolderContentResponse, GetItemByPathRequest, GetItemByPathResponse, GetMessageRequest,
-    GetMessageResponse, GetModelsRequest, GetModelsResponse, GetNodeConfigRequest,
-    GetNodeConfigResponse, GetProvisioningInfoRequest, GetProvisioningInfoResponse,
-    GetUserAssignmentRequest, GetUserAssignmentResponse, GptUserAddUserRequest,
-    GptUserAddUserResponse, GptUserListRegisteredUsersResponse, HeartbeatRequest,
-    HeartbeatResponse, IsUserFinalizedRequest, IsUserFinalizedResponse, ListActiveNodesRequest,
-    ListActiveNodesResponse, ListCanisterPoolResponse, ListChatsRequest, ListChatsResponse,
-    ListMyNodesRequest, ListMyNodesResponse, ListUserCanistersResponse, NodeGetMessageRequest,
-    NodeGetMessageResponse,
-    NodeHeartbeatCommand, ProvisionCanistersRequest, ProvisionCanistersResponse, RawWhoAmIRequest,
-    RawWhoAmIResponse, RegisterNodeRequest, RegisterNodeResponse, RegisterUserRequest,
-    RegisterUserResponse, RemoveManagerRequest,
+    GetChatResponse, GetChatTreeRequest, GetChatTreeResponse, GetEmbeddingBatchRequest,
+    GetEmbeddingBatchResponse, GetFileContentRequest, GetFileContentResponse,
+    GetFolderContentRequest, GetFolderContentResponse, GetItemByPathRequest, GetItemByPathResponse,
+    GetMessageRequest, GetMessageResponse, GetModelsRequest, GetModelsResponse,
+    GetNodeConfigRequest, GetNodeConfigResponse, GetProvisioningInfoRequest,
+    GetProvisioningInfoResponse, GetRetentionPolicyResponse, GetScheduledChatDeletionsRequest,
+    GetScheduledChatDeletionsResponse, GetUserAssignmentRequest, GetUserAssignmentResponse,
+    GetUserCanisterUpgradeStatusRequest, GetUserCanisterUpgradeStatusResponse,
+    GetVaultImportStatusResponse, GetVaultManifestResponse, GptUserAddUserRequest,
+    GptUserAddUserResponse, GptUserListRegisteredUsersResponse, HaltUserCanisterUpgradeRequest,
+    HaltUserCanisterUpgradeResponse, HeartbeatRequest, HeartbeatResponse, ImportVaultPageRequest,
+    ImportVaultPageResponse, IsUserFinalizedRequest, IsUserFinalizedResponse,
+    ListActiveNodesRequest, ListActiveNodesResponse, ListCanisterPoolResponse, ListChatsRequest,
+    ListChatsResponse, ListMyNodesRequest, ListMyNodesResponse, ListSortField, ListSortKey,
+    ListUserCanistersResponse, MessageTreeNode, NodeGetFileContentRequest,
+    NodeGetFileContentResponse, NodeGetJobStatusRequest, NodeGetJobStatusResponse,
+    NodeGetMessageChainRequest, NodeGetMessageChainResponse, NodeGetMessageRequest,
+    NodeGetMessageResponse, NodeGetRetrievalChunksRequest, NodeGetRetrievalChunksResponse,
+    NodeHeartbeatCommand, NodeStoreContextCheckpointRequest, NodeStoreContextCheckpointResponse,
+    NodeStoreFileChunksRequest, NodeStoreFileChunksResponse, ProvisionCanistersRequest,
+    ProvisionCanistersResponse, RawWhoAmIRequest, RawWhoAmIResponse, RegisterNodeRequest,
+    RegisterNodeResponse, RegisterUserRequest, RegisterUserResponse, RemoveManagerRequest,
     RemoveManagerResponse, RemoveMeasurementRequest, RemoveMeasurementResponse, RenameChatRequest,
-    RenameChatResponse, RenameItemRequest, RenameItemResponse, RetryAiMessageRequest,
-    RetryAiMessageResponse, StoreToolResultsRequest, StoreToolResultsResponse,
-    UnarchiveChatRequest, UnarchiveChatResponse, UnregisterNodeRequest, UnregisterNodeResponse,
-    UpdateAttestationPoliciesRequest, UpdateAttestationPoliciesResponse,
+    RenameChatResponse, RenameItemRequest, RenameItemResponse, RetrievalChunk, RetrievalOptions,
+    RetryAiMessageRequest, RetryAiMessageResponse, RollbackUserCanisterUpgradeRequest,
+    RollbackUserCanisterUpgradeResponse, ScheduledChatDeletion, SetChatActiveLeafRequest,
+    SetChatActiveLeafResponse, SetChatPinnedRequest, SetChatPinnedResponse,
+    SetRetentionPolicyRequest, SetRetentionPolicyResponse, SortDirection,
+    StartUserCanisterUpgradeRequest, StartUserCanisterUpgradeResponse, StoreToolResultsRequest,
+    StoreToolResultsResponse, UnarchiveChatRequest, UnarchiveChatResponse, UnregisterNodeRequest,
+    UnregisterNodeResponse, UpdateAttestationPoliciesRequest, UpdateAttestationPoliciesResponse,
     UpdateMeasurementStatusRequest, UpdateMeasurementStatusResponse,
     UpdateMessageAttachmentsRequest, UpdateMessageAttachmentsResponse, UpdateModelRequest,
-    UpdateModelResponse, UploadFileRequest, UploadFileResponse, UserDetails, WhoAmIRequest,
-    WhoAmIResponse, WhoAmIUserResponse,
+    UpdateModelResponse, UploadFileChunkRequest, UploadFileChunkResponse, UploadFileRequest,
+    UploadFileResponse, UploadUserWasmChunkRequest, UploadUserWasmChunkResponse, UpgradeRollout,
+    UpgradeRolloutStatus, UserDetails, VaultFileContent, VaultImportStatus, VaultManifest,
+    VaultPage, VaultPageCursor, VaultRecord, VaultRecordKey, VaultSection, VaultSectionCount,
+    VaultSectionProgress, WhoAmIRequest, WhoAmIResponse, WhoAmIUserResponse,
 };
 
 // Export all specific Result types (aliases)
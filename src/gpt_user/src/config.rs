@@ -1,12 +1,71 @@
-pub const MAX_ATTACHMENT_SIZE_BYTES: usize = 1_500_000;
+// Files one message may attach, and the largest attachable file
+pub const MAX_ATTACHED_FILES: usize = 10;
+pub const MAX_ATTACHED_FILE_BYTES: u64 = 20 * 1_048_576;
 pub const MAX_FILES_PER_USER: usize = 200;
 pub const MAX_FOLDERS_PER_USER: usize = 50;
 pub const MAX_FS_DEPTH: u32 = 5;
 pub const MAX_ITEMS_PER_FOLDER: usize = 50;
 pub const MAX_FILENAME_LENGTH: usize = 255;
 pub const MAX_FILE_UPLOAD_SIZE_BYTES: usize = 1_900_000;
+// Chunked uploads: fixed chunk size, total size cap, open session cap and idle lifetime
+pub const FILE_CHUNK_SIZE_BYTES: u64 = 1_048_576;
+pub const MAX_CHUNKED_FILE_SIZE_BYTES: u64 = 100 * 1_048_576;
+pub const MAX_OPEN_UPLOAD_SESSIONS: usize = 5;
+pub const UPLOAD_SESSION_TTL_NS: u64 = 30 * 60 * 1_000_000_000;
+// Largest range a single get_file_content call returns
+pub const MAX_FILE_READ_BYTES: u64 = 2_000_000;
+// Encoded size budget of one node_get_message_chain page, below the 3 MiB reply limit
+pub const MAX_MESSAGE_CHAIN_PAGE_BYTES: usize = 2_000_000;
+// Nodes returned by one get_chat_tree page
+pub const MAX_CHAT_TREE_PAGE_SIZE: u32 = 500;
+// Messages added to the chat tree per timer call while it is built after an upgrade
+pub const MESSAGE_TREE_BACKFILL_BATCH_SIZE: usize = 200;
+// Items returned by one list_chats or get_folder_content page, also the default
+pub const MAX_LIST_PAGE_SIZE: u32 = 200;
+// Encoded size budget of one vault page; pages are sent back as import arguments,
+// so they must stay below the 2 MiB ingress limit
+pub const MAX_VAULT_PAGE_BYTES: usize = 1_800_000;
+// Largest encrypted summary a node may store as a context checkpoint
+pub const MAX_CONTEXT_CHECKPOINT_BYTES: usize = 256 * 1024;
+// A claimed job that has not completed within this window is timed out
+pub const JOB_INPROGRESS_TIMEOUT_NS: u64 = 5 * 60 * 1_000_000_000;
+// Failover limits: reassignments per job and fallback keys accepted per request
+pub const MAX_JOB_RETRIES: u32 = 2;
+pub const MAX_FAILOVER_CHAT_KEYS: usize = 8;
+// Bounds on owner-configured retention TTLs and the scheduled deletions report
+pub const MIN_RETENTION_TTL_SECS: u64 = 60;
+pub const MAX_RETENTION_TTL_SECS: u64 = 10 * 365 * 24 * 60 * 60;
+pub const MAX_TEMPORARY_CHAT_TTL_SECS: u64 = 24 * 60 * 60;
+pub const MAX_SCHEDULED_DELETIONS: u32 = 100;
 // Security constant for input validation
 pub const MAX_CUSTOM_PROMPT_CHARS: usize = 32_000;
+// Structured output: schema name length and serialized schema size
+pub const MAX_RESPONSE_SCHEMA_NAME_CHARS: usize = 64;
+pub const MAX_RESPONSE_SCHEMA_BYTES: usize = 16 * 1024;
+// Embedding batches: inputs per job and their total encrypted size. The inputs come
+// back in the claim_job reply, so they must leave room below the 3 MiB reply limit
+pub const MAX_EMBEDDING_BATCH_INPUTS: usize = 512;
+pub const MAX_EMBEDDING_BATCH_BYTES: usize = 1_800_000;
+// File ingestion: largest file a node extracts, default and allowed chunk sizes in
+// characters, and the chunks one job may write back
+pub const MAX_INGESTION_FILE_BYTES: u64 = 20 * 1_048_576;
+pub const DEFAULT_INGESTION_CHUNK_CHARS: u32 = 2_000;
+pub const DEFAULT_INGESTION_CHUNK_OVERLAP_CHARS: u32 = 200;
+pub const MIN_INGESTION_CHUNK_CHARS: u32 = 100;
+pub const MAX_INGESTION_CHUNK_CHARS: u32 = 8_000;
+pub const MAX_INGESTED_CHUNKS: u32 = 1_000;
+// Retrieval: files and folders one job may search, default and largest number of
+// passages and their token budget, and the size of a node_get_retrieval_chunks page
+pub const MAX_RETRIEVAL_SOURCES: usize = 32;
+pub const DEFAULT_RETRIEVAL_TOP_K: u32 = 5;
+pub const MAX_RETRIEVAL_TOP_K: u32 = 20;
+pub const DEFAULT_RETRIEVAL_PASSAGE_TOKENS: u32 = 2_000;
+pub const MAX_RETRIEVAL_PASSAGE_TOKENS: u32 = 16_000;
+pub const MAX_RETRIEVAL_PAGE_BYTES: usize = 2_000_000;
+// Citations a node may attach to one assistant message, and the length of a cited
+// tool call id
+pub const MAX_MESSAGE_CITATIONS: usize = 64;
+pub const MAX_CITATION_TOOL_CALL_ID_CHARS: usize = 256;
 
 pub const ALLOWED_MIME_TYPES: &[&str] = &[
     "image/jpeg",
@@ -35,6 +94,16 @@ pub const ALLOWED_MIME_TYPES: &[&str] = &[
     "text/x-java-source",
     "application/x-shellscript",
     "application/x-sh",
+    "application/pdf",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+];
+// Formats a node can extract text from for ingestion
+pub const INGESTIBLE_MIME_TYPES: &[&str] = &[
+    "application/pdf",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "text/html",
+    "text/markdown",
+    "text/plain",
 ];
 pub const ALLOWED_IMAGE_MIME_TYPES: &[&str] =
     &["image/jpeg", "image/jpg", "image/png", "image/webp"];
@@ -1,5 +1,5 @@
 use crate::helpers::user_helpers::verify_owner;
-use crate::storage::{CandidWrapper, CHATS};
+use crate::storage::{save_chat, CHATS};
 use gpt_types::api::{ArchiveChatRequest, ArchiveChatResponse, ArchiveChatResult};
 use gpt_types::error::CanisterError;
 use ic_cdk::api;
@@ -13,7 +13,7 @@ pub fn archive_chat(req: ArchiveChatRequest) -> ArchiveChatResult {
     verify_owner(caller)?;
 
     let updated_chat = CHATS.with(|c| {
-        let mut chats = c.borrow_mut();
+        let chats = c.borrow();
 
         // Get the chat
         let chat_opt = chats.get(&req.chat_id);
@@ -33,11 +33,9 @@ pub fn archive_chat(req: ArchiveChatRequest) -> ArchiveChatResult {
 
         chat.archived = true;
         chat.updated_at = api::time();
-
-        // Insert the updated chat back
-        chats.insert(req.chat_id, CandidWrapper(chat.clone()));
         Ok(chat)
     })?;
+    save_chat(updated_chat.clone());
 
     Ok(ArchiveChatResponse { chat: updated_chat })
 }
@@ -0,0 +1,60 @@
+use crate::helpers::node_helpers::revoke_node_reads;
+use crate::helpers::user_helpers::verify_owner;
+use crate::storage::{save_chat, save_message, CandidWrapper, CHATS, CHAT_JOBS, MESSAGES};
+use gpt_types::api::{CancelJobRequest, CancelJobResponse, CancelJobResult};
+use gpt_types::domain::GenerationStatus;
+use gpt_types::error::CanisterError;
+use ic_cdk::api;
+use ic_cdk_macros::update;
+
+/// Stops a pending or in-progress generation and unblocks its chat. The node serving
+/// the job notices on its next status check, stops the provider stream and stores the
+/// text written so far through `complete_job`.
+#[update]
+pub fn cancel_job(req: CancelJobRequest) -> CancelJobResult {
+    ic_cdk::println!("cancel_job called with request: {:?}", req);
+
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+    let timestamp = api::time();
+
+    let mut job = CHAT_JOBS
+        .with(|cj| cj.borrow().get(&req.job_id).map(|w| w.0.clone()))
+        .ok_or_else(|| CanisterError::Other(format!("Job {} not found", req.job_id)))?;
+    let mut chat = CHATS
+        .with(|c| c.borrow().get(&job.chat_id).map(|w| w.0.clone()))
+        .ok_or(CanisterError::ChatNotFound)?;
+    if chat.owner != caller {
+        return Err(CanisterError::Unauthorized);
+    }
+    if !matches!(
+        job.generation_status,
+        GenerationStatus::Pending | GenerationStatus::InProgress
+    ) {
+        return Err(CanisterError::InvalidInput(format!(
+            "Job {} is not running (current status: {:?}).",
+            req.job_id, job.generation_status
+        )));
+    }
+
+    let placeholder_id = job.placeholder_message_id;
+    job.generation_status = GenerationStatus::Cancelled;
+    job.updated_at = timestamp;
+    CHAT_JOBS.with(|cj| cj.borrow_mut().insert(req.job_id, CandidWrapper(job)));
+    revoke_node_reads(req.job_id);
+
+    if let Some(mut msg) = MESSAGES.with(|m| m.borrow().get(&placeholder_id).map(|w| w.0)) {
+        msg.requires_client_action = false;
+        msg.updated_at = timestamp;
+        save_message(msg);
+    }
+
+    if chat.active_job_id == Some(req.job_id) {
+        chat.active_job_id = None;
+        chat.updated_at = timestamp;
+        save_chat(chat);
+    }
+
+    ic_cdk::println!("[JOB] Cancelled job {}", req.job_id);
+    Ok(CancelJobResponse)
+}
@@ -1,11 +1,13 @@
 use crate::helpers::user_helpers::verify_owner;
 use crate::helpers::generation_helpers::{
     GenerationParams, create_generation_entities, validate_generation_request,
+    validate_response_schema,
 };
-use crate::helpers::message_helpers::validate_attachments;
+use crate::helpers::attachment_helpers::{resolve_file_keys, validate_attached_files};
+use crate::helpers::retrieval_helpers::resolve_retrieval;
 use crate::storage::{
-    get_next_chat_id, get_next_message_id, CandidWrapper, StorableString,
-    CHAT_JOBS, CHATS, MESSAGES, MODELS,
+    get_next_chat_id, get_next_message_id, save_chat, save_message, CandidWrapper, StorableString,
+    CHAT_JOBS, MODELS,
 };
 use gpt_types::api::{CreateChatRequest, CreateChatResponse, CreateChatResult};
 use gpt_types::domain::{Chat, Message, ModelStatus, Role};
@@ -32,7 +34,7 @@ pub fn create_chat(req: CreateChatRequest) -> CreateChatResult {
         Ok(())
     })?;
 
-    if req.initial_message.is_empty() && req.attachments.is_none() {
+    if req.initial_message.is_empty() && req.attached_file_ids.is_none() {
         return Err(CanisterError::InvalidInput(
             "Initial message cannot be empty without attachments".to_string(),
         ));
@@ -44,14 +46,18 @@ pub fn create_chat(req: CreateChatRequest) -> CreateChatResult {
         ));
     }
 
-    validate_attachments(&req.attachments, &req.model_id)?;
+    validate_attached_files(&req.attached_file_ids, caller, &req.model_id)?;
     validate_generation_request(
         0,
         req.node_id,
         &req.model_id,
         &req.tools,
         req.custom_prompt.as_ref(),
+        &req.failover_chat_keys,
     )?;
+    let retrieval = resolve_retrieval(req.retrieval, caller, &req.model_id, req.node_id)?;
+    let file_keys = resolve_file_keys(req.file_keys, req.node_id, None, &req.attached_file_ids)?;
+    validate_response_schema(&req.response_schema, &req.model_id)?;
 
     let timestamp = api::time();
     let chat_id = get_next_chat_id();
@@ -66,12 +72,14 @@ pub fn create_chat(req: CreateChatRequest) -> CreateChatResult {
         created_at: timestamp,
         updated_at: timestamp,
         error_status: None,
-        attachments: req.attachments.clone(),
+        attachments: None,
+        attached_file_ids: req.attached_file_ids.clone(),
         tool_calls: None,
         tool_results: None,
         tool_call_id: None,
         requires_client_action: false,
         usage: None,
+        citations: Vec::new(),
     };
 
     let gen_params = GenerationParams {
@@ -86,6 +94,12 @@ pub fn create_chat(req: CreateChatRequest) -> CreateChatResult {
         tools: req.tools.clone(),
         reasoning_effort: None,
         encrypted_chat_key: req.encrypted_chat_key,
+        failover_chat_keys: req.failover_chat_keys.unwrap_or_default(),
+        context_strategy: None,
+        response_schema: req.response_schema,
+        kind: None,
+        retrieval,
+        file_keys,
     };
     let (ai_message, job) = create_generation_entities(gen_params, timestamp);
     let ai_message_id = ai_message.message_id;
@@ -106,24 +120,19 @@ pub fn create_chat(req: CreateChatRequest) -> CreateChatResult {
         updated_at: timestamp,
         archived: false,
         temporary: req.temporary,
+        pinned: false,
+        active_leaf_message_id: Some(ai_message_id),
         encryption_salt: req.encryption_salt,
     };
 
-    MESSAGES.with(|m| {
-        let mut msgs = m.borrow_mut();
-        msgs.insert(user_message_id, CandidWrapper(user_message));
-        msgs.insert(ai_message_id, CandidWrapper(ai_message));
-    });
+    save_message(user_message);
+    save_message(ai_message);
 
     CHAT_JOBS.with(|cj| {
         cj.borrow_mut().insert(job_id, CandidWrapper(job));
     });
 
-    CHATS.with(|c| {
-        c.borrow_mut().insert(chat_id, CandidWrapper(chat));
-    });
-
-    // No CHATS_INDEX needed - single user canister
+    save_chat(chat);
 
     Ok(CreateChatResponse {
         chat_id,
@@ -1,6 +1,8 @@
-use crate::helpers::message_helpers::is_chat_in_generation;
+use crate::helpers::embedding_helpers::remove_embedding_batches;
+use crate::helpers::ingestion_helpers::remove_ingested_chunks;
+use crate::helpers::message_helpers::{is_chat_in_generation, remove_context_checkpoints};
 use crate::helpers::user_helpers::verify_owner;
-use crate::storage::{CHAT_JOBS, CHATS, MESSAGES};
+use crate::storage::{remove_chat, remove_message, CHAT_JOBS, CHATS};
 use candid::Principal;
 use gpt_types::api::{DeleteChatRequest, DeleteChatResponse, DeleteChatResult};
 use gpt_types::error::{CanisterError, CanisterResult};
@@ -38,17 +40,13 @@ pub fn delete_chat_internal(user: Principal, chat_id: u64) -> CanisterResult<()>
     };
 
     // Remove the chat
-    CHATS.with(|c| {
-        c.borrow_mut().remove(&chat_id);
-    });
+    remove_chat(chat_id);
 
     // Remove associated messages
-    MESSAGES.with(|m| {
-        let mut msgs = m.borrow_mut();
-        for msg_id in &chat.message_ids {
-            msgs.remove(msg_id);
-        }
-    });
+    for msg_id in &chat.message_ids {
+        remove_message(*msg_id);
+    }
+    remove_context_checkpoints(&chat.message_ids);
 
     // Remove associated jobs
     CHAT_JOBS.with(|cj| {
@@ -57,8 +55,8 @@ pub fn delete_chat_internal(user: Principal, chat_id: u64) -> CanisterResult<()>
             jobs.remove(job_id);
         }
     });
-
-    // No CHATS_INDEX to update - single user canister
+    remove_embedding_batches(&chat.job_ids);
+    remove_ingested_chunks(&chat.job_ids);
 
     Ok(())
 }
@@ -1,9 +1,18 @@
+use crate::helpers::listing_helpers::{
+    list_page_limit, list_sort_key, paginate, resolve_list_order, validate_cursor_key,
+};
 use crate::helpers::user_helpers::verify_owner;
-use crate::storage::CHATS;
-use gpt_types::api::{ListChatsRequest, ListChatsResponse, ListChatsResult};
+use crate::storage::{CHATS, CHATS_BY_UPDATED, ChatUpdatedKey};
+use gpt_types::api::{
+    ChatListCursor, ListChatsRequest, ListChatsResponse, ListChatsResult, ListSortField,
+    ListSortKey,
+};
 use gpt_types::domain::Chat;
 use ic_cdk_macros::query;
+use std::ops::Bound;
 
+/// Lists chats one page at a time, most recently updated first by default.
+/// Pass the returned `next_cursor` with the same sort to fetch the following page.
 #[query]
 pub fn list_chats(req: ListChatsRequest) -> ListChatsResult {
     ic_cdk::println!("list_chats called with request: {:?}", req);
@@ -13,19 +22,76 @@ pub fn list_chats(req: ListChatsRequest) -> ListChatsResult {
         ic_cdk::trap(format!("User not authorized: {:?}", e));
     }
 
-    // Single user canister - all chats belong to the owner
-    let mut chats: Vec<Chat> = CHATS.with(|c| {
-        let chats_map = c.borrow();
-        let mut result = Vec::new();
-        for entry in chats_map.iter() {
-            let chat = entry.value().0.clone();
-            if req.include_archived || !chat.archived {
-                result.push(chat);
+    let order = resolve_list_order(req.sort_by, req.direction, ListSortField::UpdatedAt);
+    let limit = list_page_limit(req.limit);
+    if let Some(cursor) = &req.cursor {
+        validate_cursor_key(order.field, &cursor.key)?;
+    }
+
+    let (chats, has_more) = if order.field == ListSortField::UpdatedAt {
+        let after = req.cursor.as_ref().map(|c| match c.key {
+            ListSortKey::Timestamp(updated_at) => (updated_at, c.chat_id),
+            ListSortKey::Title(_) => unreachable!("cursor key validated above"),
+        });
+        list_by_recency(req.include_archived, order.descending, after, limit)
+    } else {
+        // Single user canister - all chats belong to the owner
+        let chats: Vec<((ListSortKey, u64), Chat)> = CHATS.with(|c| {
+            c.borrow()
+                .iter()
+                .map(|entry| entry.value().0.clone())
+                .filter(|chat| req.include_archived || !chat.archived)
+                .map(|chat| {
+                    let key =
+                        list_sort_key(order.field, chat.created_at, chat.updated_at, &chat.title);
+                    ((key, chat.chat_id), chat)
+                })
+                .collect()
+        });
+        let after = req.cursor.map(|c| (c.key, c.chat_id));
+        let (page, has_more) = paginate(chats, order.descending, after.as_ref(), limit);
+        (page.into_iter().map(|(_, chat)| chat).collect(), has_more)
+    };
+
+    let next_cursor = chats
+        .last()
+        .filter(|_| has_more)
+        .map(|chat| ChatListCursor {
+            key: list_sort_key(order.field, chat.created_at, chat.updated_at, &chat.title),
+            chat_id: chat.chat_id,
+        });
+
+    Ok(ListChatsResponse { chats, next_cursor })
+}
+
+/// Walks `CHATS_BY_UPDATED` so only the returned page is loaded.
+fn list_by_recency(
+    include_archived: bool,
+    descending: bool,
+    after: Option<ChatUpdatedKey>,
+    limit: usize,
+) -> (Vec<Chat>, bool) {
+    let mut chats: Vec<Chat> = CHATS_BY_UPDATED.with(|idx| {
+        let index = idx.borrow();
+        let keys: Box<dyn Iterator<Item = ChatUpdatedKey>> = match (descending, after) {
+            (true, Some(after)) => Box::new(index.keys_range(..after).rev()),
+            (true, None) => Box::new(index.keys().rev()),
+            (false, Some(after)) => {
+                Box::new(index.keys_range((Bound::Excluded(after), Bound::Unbounded)))
             }
-        }
-        result
+            (false, None) => Box::new(index.keys()),
+        };
+
+        CHATS.with(|c| {
+            let all_chats = c.borrow();
+            keys.filter_map(|(_, chat_id)| all_chats.get(&chat_id).map(|w| w.0))
+                .filter(|chat| include_archived || !chat.archived)
+                .take(limit + 1)
+                .collect()
+        })
     });
 
-    chats.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
-    Ok(ListChatsResponse { chats })
+    let has_more = chats.len() > limit;
+    chats.truncate(limit);
+    (chats, has_more)
 }
@@ -1,8 +1,11 @@
 pub mod archive;
+pub mod cancel_job;
 pub mod create;
 pub mod delete;
 pub mod get;
 pub mod get_jobs;
 pub mod list;
+pub mod pin;
 pub mod rename;
+pub mod tree;
 pub mod unarchive;
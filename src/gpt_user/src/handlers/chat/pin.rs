@@ -0,0 +1,40 @@
+use crate::helpers::user_helpers::verify_owner;
+use crate::storage::{save_chat, CHATS};
+use gpt_types::api::{SetChatPinnedRequest, SetChatPinnedResponse, SetChatPinnedResult};
+use gpt_types::error::CanisterError;
+use ic_cdk::api;
+use ic_cdk_macros::update;
+
+/// Pins or unpins a chat. Pinned chats are never deleted by the retention cleanup.
+#[update]
+pub fn set_chat_pinned(req: SetChatPinnedRequest) -> SetChatPinnedResult {
+    ic_cdk::println!("set_chat_pinned called with request: {:?}", req);
+
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    let updated_chat = CHATS.with(|c| {
+        let chats = c.borrow();
+
+        let mut chat = match chats.get(&req.chat_id) {
+            Some(w) => w.0.clone(),
+            None => return Err(CanisterError::ChatNotFound),
+        };
+
+        if chat.owner != caller {
+            return Err(CanisterError::Unauthorized);
+        }
+        if chat.temporary && req.pinned {
+            return Err(CanisterError::InvalidInput(
+                "Temporary chats cannot be pinned.".to_string(),
+            ));
+        }
+
+        chat.pinned = req.pinned;
+        chat.updated_at = api::time();
+        Ok(chat)
+    })?;
+    save_chat(updated_chat.clone());
+
+    Ok(SetChatPinnedResponse { chat: updated_chat })
+}
@@ -0,0 +1,179 @@
+use crate::config::MAX_CHAT_TREE_PAGE_SIZE;
+use crate::helpers::message_helpers::{active_leaf, build_message_chain};
+use crate::helpers::user_helpers::verify_owner;
+use crate::storage::{
+    CHATS, MESSAGE_CHILDREN, MESSAGE_TREE, MESSAGES, is_message_tree_complete, save_chat,
+};
+use gpt_types::api::{
+    GetChatTreeRequest, GetChatTreeResponse, GetChatTreeResult, MessageTreeNode,
+    SetChatActiveLeafRequest, SetChatActiveLeafResponse, SetChatActiveLeafResult,
+};
+use gpt_types::domain::{Chat, ChatId, MessageId};
+use gpt_types::error::{CanisterError, CanisterResult};
+use ic_cdk::api::msg_caller;
+use ic_cdk_macros::{query, update};
+use std::ops::Bound::{Excluded, Included};
+
+/// Returns the branch structure of a chat: every message with its parent and
+/// children, but without content. Pages read only the tree records they return.
+#[query]
+pub fn get_chat_tree(req: GetChatTreeRequest) -> GetChatTreeResult {
+    ic_cdk::println!("get_chat_tree called with request: {:?}", req);
+
+    let caller = msg_caller();
+    verify_owner(caller)?;
+    let chat = get_owned_chat(req.chat_id, caller)?;
+    if !is_message_tree_complete() {
+        return Err(CanisterError::Other(
+            "Chat trees are still being built after an upgrade; try again shortly.".to_string(),
+        ));
+    }
+
+    let limit = req
+        .limit
+        .unwrap_or(MAX_CHAT_TREE_PAGE_SIZE)
+        .clamp(1, MAX_CHAT_TREE_PAGE_SIZE) as usize;
+    let (nodes, next_cursor) = chat_tree_page(chat.chat_id, req.cursor, limit);
+
+    let active_leaf_message_id = active_leaf(&chat);
+    Ok(GetChatTreeResponse {
+        nodes,
+        next_cursor,
+        active_leaf_message_id,
+        active_path: active_leaf_message_id
+            .map(build_message_chain)
+            .unwrap_or_default(),
+    })
+}
+
+/// The tree nodes of a chat's messages after `cursor`, at most `limit` of them, and
+/// the cursor of the next page. Parents removed by an edit make their children roots.
+fn chat_tree_page(
+    chat_id: ChatId,
+    cursor: Option<MessageId>,
+    limit: usize,
+) -> (Vec<MessageTreeNode>, Option<MessageId>) {
+    MESSAGE_TREE.with(|t| {
+        MESSAGE_CHILDREN.with(|c| {
+            let (tree, children) = (t.borrow(), c.borrow());
+            let start = match cursor {
+                Some(cursor) => Excluded((chat_id, cursor)),
+                None => Included((chat_id, 0)),
+            };
+            let mut entries = tree.range((start, Included((chat_id, MessageId::MAX))));
+            let nodes: Vec<MessageTreeNode> = entries
+                .by_ref()
+                .take(limit)
+                .map(|entry| {
+                    let message_id = entry.key().1;
+                    let record = entry.value().0;
+                    MessageTreeNode {
+                        message_id,
+                        parent_message_id: record.parent_message_id.filter(|parent| {
+                            *parent != message_id && tree.contains_key(&(chat_id, *parent))
+                        }),
+                        children: children
+                            .keys_range(
+                                (chat_id, message_id, 0)..=(chat_id, message_id, MessageId::MAX),
+                            )
+                            .map(|(_, _, child)| child)
+                            .filter(|child| *child != message_id)
+                            .collect(),
+                        role: record.role,
+                        created_at: record.created_at,
+                        updated_at: record.updated_at,
+                        error_status: record.error_status,
+                    }
+                })
+                .collect();
+            let next_cursor = match entries.next() {
+                Some(_) => nodes.last().map(|node| node.message_id),
+                None => None,
+            };
+            (nodes, next_cursor)
+        })
+    })
+}
+
+/// Switches the branch a chat shows. The message is normally a leaf, but any message
+/// of the chat is accepted.
+#[update]
+pub fn set_chat_active_leaf(req: SetChatActiveLeafRequest) -> SetChatActiveLeafResult {
+    ic_cdk::println!("set_chat_active_leaf called with request: {:?}", req);
+
+    let caller = msg_caller();
+    verify_owner(caller)?;
+    let mut chat = get_owned_chat(req.chat_id, caller)?;
+
+    let belongs_to_chat = MESSAGES.with(|m| {
+        m.borrow()
+            .get(&req.message_id)
+            .is_some_and(|w| w.0.chat_id == req.chat_id)
+    });
+    if !belongs_to_chat {
+        return Err(CanisterError::MessageNotFound);
+    }
+
+    chat.active_leaf_message_id = Some(req.message_id);
+    save_chat(chat);
+
+    Ok(SetChatActiveLeafResponse {
+        active_path: build_message_chain(req.message_id),
+    })
+}
+
+fn get_owned_chat(chat_id: ChatId, caller: candid::Principal) -> CanisterResult<Chat> {
+    let chat = CHATS
+        .with(|c| c.borrow().get(&chat_id).map(|w| w.0.clone()))
+        .ok_or(CanisterError::ChatNotFound)?;
+    if chat.owner != caller {
+        return Err(CanisterError::Unauthorized);
+    }
+    Ok(chat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{remove_message, save_message, test_support};
+    use gpt_types::domain::Message;
+
+    fn ids(nodes: &[MessageTreeNode]) -> Vec<MessageId> {
+        nodes.iter().map(|node| node.message_id).collect()
+    }
+
+    #[test]
+    fn test_tree_pages_follow_saved_and_removed_messages() {
+        // 1 <- 2 <- 3, with 4 a second reply to 2.
+        for id in 1..=3 {
+            save_message(test_support::message(id));
+        }
+        save_message(Message {
+            parent_message_id: Some(2),
+            ..test_support::message(4)
+        });
+        save_message(Message {
+            chat_id: 2,
+            ..test_support::message(5)
+        });
+
+        let (first, cursor) = chat_tree_page(1, None, 3);
+        assert_eq!(ids(&first), vec![1, 2, 3]);
+        assert_eq!(first[1].children, vec![3, 4]);
+        assert_eq!(cursor, Some(3));
+        let (rest, cursor) = chat_tree_page(1, Some(3), 3);
+        assert_eq!(ids(&rest), vec![4]);
+        assert_eq!(cursor, None);
+
+        // An edit removes the message it replaces; its replies become roots.
+        remove_message(2);
+        let (nodes, _) = chat_tree_page(1, None, 10);
+        assert_eq!(ids(&nodes), vec![1, 3, 4]);
+        assert!(nodes[0].children.is_empty());
+        assert!(
+            nodes[1..]
+                .iter()
+                .all(|node| node.parent_message_id.is_none())
+        );
+    }
+}
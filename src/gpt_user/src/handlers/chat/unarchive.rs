@@ -1,5 +1,5 @@
 use crate::helpers::user_helpers::verify_owner;
-use crate::storage::{CandidWrapper, CHATS};
+use crate::storage::{save_chat, CHATS};
 use gpt_types::api::{UnarchiveChatRequest, UnarchiveChatResponse, UnarchiveChatResult};
 use gpt_types::error::CanisterError;
 use ic_cdk::api;
@@ -13,7 +13,7 @@ pub fn unarchive_chat(req: UnarchiveChatRequest) -> UnarchiveChatResult {
     verify_owner(caller)?;
 
     let updated_chat = CHATS.with(|c| {
-        let mut chats = c.borrow_mut();
+        let chats = c.borrow();
 
         // Get the chat
         let chat_opt = chats.get(&req.chat_id);
@@ -28,11 +28,9 @@ pub fn unarchive_chat(req: UnarchiveChatRequest) -> UnarchiveChatResult {
 
         chat.archived = false;
         chat.updated_at = api::time();
-
-        // Insert the updated chat back
-        chats.insert(req.chat_id, CandidWrapper(chat.clone()));
         Ok(chat)
     })?;
+    save_chat(updated_chat.clone());
 
     Ok(UnarchiveChatResponse { chat: updated_chat })
 }
@@ -0,0 +1,49 @@
+use crate::helpers::embedding_helpers::{
+    create_embedding_job, save_embedding_inputs, validate_embedding_inputs,
+};
+use crate::helpers::user_helpers::verify_owner;
+use crate::storage::FILES_METADATA;
+use gpt_types::api::{
+    CreateEmbeddingBatchRequest, CreateEmbeddingBatchResponse, CreateEmbeddingBatchResult,
+};
+use gpt_types::domain::{EmbeddingBatch, JobKind};
+use gpt_types::error::CanisterError;
+use ic_cdk_macros::update;
+
+/// Creates an embedding batch job in a new temporary chat.
+#[update]
+pub fn create_embedding_batch(req: CreateEmbeddingBatchRequest) -> CreateEmbeddingBatchResult {
+    ic_cdk::println!(
+        "create_embedding_batch called with {} inputs",
+        req.inputs.len()
+    );
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    validate_embedding_inputs(&req.inputs, req.target_file_id)?;
+    if let Some(file_id) = req.target_file_id {
+        let owner = FILES_METADATA.with(|f| f.borrow().get(&file_id).map(|w| w.0.owner));
+        match owner {
+            Some(owner) if owner == caller => {}
+            Some(_) => return Err(CanisterError::Unauthorized),
+            None => return Err(CanisterError::FileNotFound),
+        }
+    }
+
+    let kind = JobKind::EmbeddingBatch(EmbeddingBatch {
+        input_count: req.inputs.len() as u32,
+        target_file_id: req.target_file_id,
+    });
+    let (chat_id, job_id) = create_embedding_job(
+        caller,
+        &req.model_id,
+        req.node_id,
+        kind,
+        req.encryption_salt,
+        req.encrypted_chat_key,
+        req.failover_chat_keys,
+    )?;
+    save_embedding_inputs(job_id, req.inputs);
+
+    Ok(CreateEmbeddingBatchResponse { chat_id, job_id })
+}
@@ -0,0 +1,46 @@
+use crate::helpers::embedding_helpers::create_embedding_job;
+use crate::helpers::ingestion_helpers::{resolve_chunking, validate_ingestible_file};
+use crate::helpers::user_helpers::verify_owner;
+use crate::storage::FILES_METADATA;
+use gpt_types::api::{
+    CreateFileIngestionRequest, CreateFileIngestionResponse, CreateFileIngestionResult,
+};
+use gpt_types::domain::{FileIngestion, JobKind};
+use gpt_types::error::CanisterError;
+use ic_cdk_macros::update;
+
+/// Creates a job in a new temporary chat that has the node extract, chunk and
+/// embed a stored file. The chunks replace the file's when the job completes.
+#[update]
+pub fn create_file_ingestion(req: CreateFileIngestionRequest) -> CreateFileIngestionResult {
+    ic_cdk::println!("create_file_ingestion called for file {}", req.file_id);
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    let file = FILES_METADATA
+        .with(|f| f.borrow().get(&req.file_id).map(|w| w.0))
+        .ok_or(CanisterError::FileNotFound)?;
+    if file.owner != caller {
+        return Err(CanisterError::Unauthorized);
+    }
+    validate_ingestible_file(&file)?;
+    let (chunk_size_chars, chunk_overlap_chars) =
+        resolve_chunking(req.chunk_size_chars, req.chunk_overlap_chars)?;
+
+    let kind = JobKind::FileIngestion(FileIngestion {
+        file_id: req.file_id,
+        chunk_size_chars,
+        chunk_overlap_chars,
+    });
+    let (chat_id, job_id) = create_embedding_job(
+        caller,
+        &req.model_id,
+        req.node_id,
+        kind,
+        req.encryption_salt,
+        req.encrypted_chat_key,
+        req.failover_chat_keys,
+    )?;
+
+    Ok(CreateFileIngestionResponse { chat_id, job_id })
+}
@@ -0,0 +1,42 @@
+use crate::helpers::embedding_helpers::{embedding_batch, stored_embeddings};
+use crate::helpers::user_helpers::verify_owner;
+use crate::storage::{CHAT_JOBS, CHATS, MESSAGES};
+use gpt_types::api::{
+    GetEmbeddingBatchRequest, GetEmbeddingBatchResponse, GetEmbeddingBatchResult,
+};
+use gpt_types::error::CanisterError;
+use ic_cdk_macros::query;
+
+#[query]
+pub fn get_embedding_batch(req: GetEmbeddingBatchRequest) -> GetEmbeddingBatchResult {
+    ic_cdk::println!("get_embedding_batch called with request: {:?}", req);
+
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    let job = CHAT_JOBS
+        .with(|cj| cj.borrow().get(&req.job_id).map(|w| w.0.clone()))
+        .ok_or(CanisterError::Other("Job not found".to_string()))?;
+    let batch = embedding_batch(&job).ok_or_else(|| {
+        CanisterError::InvalidInput(format!("Job {} is not an embedding batch.", req.job_id))
+    })?;
+
+    let chat_owner = CHATS.with(|c| c.borrow().get(&job.chat_id).map(|w| w.0.owner));
+    if chat_owner != Some(caller) {
+        return Err(CanisterError::Unauthorized);
+    }
+
+    let placeholder = MESSAGES.with(|m| {
+        m.borrow()
+            .get(&job.placeholder_message_id)
+            .map(|w| w.0.clone())
+    });
+
+    Ok(GetEmbeddingBatchResponse {
+        generation_status: job.generation_status.clone(),
+        embeddings: stored_embeddings(req.job_id),
+        target_file_id: batch.target_file_id,
+        usage: placeholder.as_ref().and_then(|m| m.usage.clone()),
+        error_status: placeholder.and_then(|m| m.error_status),
+    })
+}
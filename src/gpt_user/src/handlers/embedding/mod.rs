@@ -0,0 +1,3 @@
+pub mod create_batch;
+pub mod create_ingestion;
+pub mod get_batch;
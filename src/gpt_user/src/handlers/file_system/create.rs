@@ -1,10 +1,10 @@
 use super::utils::{validate_item_name_and_collision, validate_parent_folder_and_capacity};
 use crate::config::{
-    ALLOWED_MIME_TYPES, MAX_FILE_UPLOAD_SIZE_BYTES, MAX_FILES_PER_USER, MAX_FOLDERS_PER_USER,
+    MAX_FILE_UPLOAD_SIZE_BYTES, MAX_FILES_PER_USER, MAX_FOLDERS_PER_USER,
     MAX_FS_DEPTH,
 };
 use crate::handlers::file_system::utils::get_folder_depth;
-use crate::handlers::file_system::utils::infer_mime_type_from_name;
+use crate::handlers::file_system::utils::resolve_mime_type;
 use crate::helpers::user_helpers::verify_owner;
 use crate::storage::{
     get_next_file_id, get_next_folder_id, CandidWrapper, FolderContents, FILES_CONTENT,
@@ -102,19 +102,9 @@ pub fn upload_file(req: UploadFileRequest) -> UploadFileResult {
         )));
     }
 
-    let mime_type = if req.mime_type.is_empty() {
-        infer_mime_type_from_name(&req.name)
-            .unwrap_or_default()
-            .to_string()
-    } else {
-        req.mime_type.clone()
-    };
+    let mime_type = resolve_mime_type(&req.name, &req.mime_type)?;
     ic_cdk::println!("[UploadFile] Inferred MIME type: '{}'", mime_type);
 
-    if !ALLOWED_MIME_TYPES.contains(&mime_type.as_str()) {
-        return Err(CanisterError::UnsupportedMimeType(req.mime_type));
-    }
-
     // Count files owned by the user (single user = all files)
     if FILES_METADATA.with(|f| f.borrow().len()) as usize >= MAX_FILES_PER_USER {
         return Err(CanisterError::FileSystemLimitExceeded(
@@ -1,3 +1,4 @@
+use super::utils::remove_content_chunks;
 use crate::helpers::user_helpers::verify_owner;
 use crate::storage::{CandidWrapper, FILES_CONTENT, FILES_METADATA, FOLDER_CONTENTS_INDEX, FOLDERS};
 use gpt_types::{
@@ -27,8 +28,9 @@ pub fn delete_item(req: DeleteItemRequest) -> DeleteItemResult {
             // Remove file metadata
             FILES_METADATA.with(|f| f.borrow_mut().remove(&req.item_id));
 
-            // Remove file content
+            // Remove file content, stored whole or in chunks
             FILES_CONTENT.with(|c| c.borrow_mut().remove(&req.item_id));
+            remove_content_chunks(req.item_id);
 
             // Update parent folder's contents index
             FOLDER_CONTENTS_INDEX.with(|idx| {
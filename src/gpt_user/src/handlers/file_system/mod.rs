@@ -2,4 +2,5 @@ pub mod create;
 pub mod delete;
 pub mod read;
 pub mod update;
+pub mod upload;
 pub mod utils;
@@ -1,22 +1,35 @@
-use super::utils::{get_or_create_root_folder_id, resolve_path};
+use super::utils::{get_or_create_root_folder_id, read_file_range, resolve_path};
+use crate::config::MAX_FILE_READ_BYTES;
+use crate::helpers::listing_helpers::{
+    list_page_limit, list_sort_key, paginate, resolve_list_order, validate_cursor_key,
+};
 use crate::helpers::user_helpers::verify_owner;
-use crate::storage::{FILES_CONTENT, FILES_METADATA, FOLDER_CONTENTS_INDEX, FOLDERS};
+use crate::storage::{FILES_METADATA, FOLDER_CONTENTS_INDEX, FOLDERS};
 use gpt_types::{
     api::{
-        FileInfo, FolderInfo, GetFileContentRequest, GetFileContentResponse, GetFileContentResult,
-        GetFolderContentRequest, GetFolderContentResponse, GetFolderContentResult,
-        GetItemByPathRequest, GetItemByPathResponse, GetItemByPathResult,
+        FileInfo, FolderContentCursor, FolderInfo, FsItemType, GetFileContentRequest,
+        GetFileContentResponse, GetFileContentResult, GetFolderContentRequest,
+        GetFolderContentResponse, GetFolderContentResult, GetItemByPathRequest,
+        GetItemByPathResponse, GetItemByPathResult, ListSortField, ListSortKey,
     },
     domain::ROOT_FOLDER_ID,
     error::CanisterError,
 };
 use ic_cdk_macros::query;
 
+/// Lists a folder one page at a time, folders before files, by name by default.
+/// Pass the returned `next_cursor` with the same sort to fetch the following page.
 #[query]
 pub fn get_folder_content(req: GetFolderContentRequest) -> GetFolderContentResult {
     let caller = ic_cdk::api::msg_caller();
     verify_owner(caller)?;
 
+    let order = resolve_list_order(req.sort_by, req.direction, ListSortField::Title);
+    let limit = list_page_limit(req.limit);
+    if let Some(cursor) = &req.cursor {
+        validate_cursor_key(order.field, &cursor.key)?;
+    }
+
     let target_folder_id = match req.folder_id {
         Some(id) => id,
         None => get_or_create_root_folder_id()?,
@@ -38,44 +51,89 @@ pub fn get_folder_content(req: GetFolderContentRequest) -> GetFolderContentResul
         .unwrap_or_default();
 
     // Get child folders
-    let folders = FOLDERS.with(|f_map| {
+    let folders: Vec<((ListSortKey, u64), FolderInfo)> = FOLDERS.with(|f_map| {
         let all_folders = f_map.borrow();
         contents
             .child_folder_ids
             .iter()
             .filter_map(|id| all_folders.get(id).map(|w| w.0.clone()))
-            .map(|f| FolderInfo {
-                id: f.id,
-                name: f.name,
-                created_at: f.created_at,
-                updated_at: f.updated_at,
+            .map(|f| {
+                let key = list_sort_key(order.field, f.created_at, f.updated_at, &f.name);
+                let info = FolderInfo {
+                    id: f.id,
+                    name: f.name,
+                    created_at: f.created_at,
+                    updated_at: f.updated_at,
+                };
+                ((key, info.id), info)
             })
             .collect()
     });
 
     // Get child files
-    let files = FILES_METADATA.with(|f_map| {
+    let files: Vec<((ListSortKey, u64), FileInfo)> = FILES_METADATA.with(|f_map| {
         let all_files = f_map.borrow();
         contents
             .child_file_ids
             .iter()
             .filter_map(|id| all_files.get(id).map(|w| w.0.clone()))
-            .map(|f| FileInfo {
-                id: f.id,
-                name: f.name,
-                mime_type: f.mime_type,
-                content_size_bytes: f.content_size_bytes,
-                created_at: f.created_at,
-                updated_at: f.updated_at,
-                parent_folder_id: f.parent_folder_id,
-                chunks: f.chunks,
+            .map(|f| {
+                let key = list_sort_key(order.field, f.created_at, f.updated_at, &f.name);
+                let info = FileInfo {
+                    id: f.id,
+                    name: f.name,
+                    mime_type: f.mime_type,
+                    content_size_bytes: f.content_size_bytes,
+                    created_at: f.created_at,
+                    updated_at: f.updated_at,
+                    parent_folder_id: f.parent_folder_id,
+                    chunks: f.chunks,
+                };
+                ((key, info.id), info)
             })
             .collect()
     });
 
+    // Folders are listed before files; a file cursor means the folders are exhausted.
+    let (folders, has_more_folders) = match &req.cursor {
+        Some(cursor) if cursor.item_type == FsItemType::File => (Vec::new(), false),
+        cursor => {
+            let after = cursor.as_ref().map(|c| (c.key.clone(), c.id));
+            paginate(folders, order.descending, after.as_ref(), limit)
+        }
+    };
+    let (files, has_more) = if has_more_folders {
+        (Vec::new(), true)
+    } else {
+        let after = req
+            .cursor
+            .filter(|c| c.item_type == FsItemType::File)
+            .map(|c| (c.key, c.id));
+        paginate(
+            files,
+            order.descending,
+            after.as_ref(),
+            limit - folders.len(),
+        )
+    };
+
+    let last_item = match (files.last(), folders.last()) {
+        (Some(((key, id), _)), _) => Some((key, FsItemType::File, *id)),
+        (None, Some(((key, id), _))) => Some((key, FsItemType::Folder, *id)),
+        (None, None) => None,
+    };
+    let next_cursor =
+        last_item
+            .filter(|_| has_more)
+            .map(|(key, item_type, id)| FolderContentCursor {
+                key: key.clone(),
+                item_type,
+                id,
+            });
+
     Ok(GetFolderContentResponse {
-        folders,
-        files,
+        folders: folders.into_iter().map(|(_, info)| info).collect(),
+        files: files.into_iter().map(|(_, info)| info).collect(),
         folder_id: folder.id,
         folder_name: folder.name,
         parent_folder_id: if folder.parent_folder_id == ROOT_FOLDER_ID {
@@ -83,9 +141,12 @@ pub fn get_folder_content(req: GetFolderContentRequest) -> GetFolderContentResul
         } else {
             Some(folder.parent_folder_id)
         },
+        next_cursor,
     })
 }
 
+/// Returns file content, optionally limited to a byte range.
+/// Ranges are capped at `MAX_FILE_READ_BYTES`; larger files must be read in ranges.
 #[query]
 pub fn get_file_content(req: GetFileContentRequest) -> GetFileContentResult {
     let caller = ic_cdk::api::msg_caller();
@@ -101,14 +162,34 @@ pub fn get_file_content(req: GetFileContentRequest) -> GetFileContentResult {
         return Err(CanisterError::Unauthorized);
     }
 
-    // Get file content
-    let file_content = FILES_CONTENT
-        .with(|fc| fc.borrow().get(&req.file_id).map(|w| w.0.clone()))
-        .ok_or(CanisterError::FileNotFound)?;
+    // Clamp the requested range to the file
+    let total_size_bytes = file_metadata.content_size_bytes;
+    let offset = req.offset.unwrap_or(0);
+    if offset > total_size_bytes {
+        return Err(CanisterError::InvalidInput(format!(
+            "Offset {} is beyond the end of the file ({} bytes).",
+            offset, total_size_bytes
+        )));
+    }
+    let remaining = total_size_bytes - offset;
+    let length = match req.length {
+        Some(length) => length.min(remaining).min(MAX_FILE_READ_BYTES),
+        None if remaining > MAX_FILE_READ_BYTES => {
+            return Err(CanisterError::InvalidInput(format!(
+                "File is larger than {} bytes; read it in ranges.",
+                MAX_FILE_READ_BYTES
+            )));
+        }
+        None => remaining,
+    };
+
+    let content = read_file_range(req.file_id, offset, length)?;
 
     Ok(GetFileContentResponse {
-        content: file_content,
+        content,
         mime_type: file_metadata.mime_type,
+        offset,
+        total_size_bytes,
     })
 }
 
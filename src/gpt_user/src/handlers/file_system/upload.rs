@@ -0,0 +1,243 @@
+use super::utils::{
+    remove_content_chunks, resolve_mime_type, validate_item_name_and_collision,
+    validate_parent_folder_and_capacity,
+};
+use crate::config::{
+    FILE_CHUNK_SIZE_BYTES, MAX_CHUNKED_FILE_SIZE_BYTES, MAX_FILES_PER_USER,
+    MAX_OPEN_UPLOAD_SESSIONS, UPLOAD_SESSION_TTL_NS,
+};
+use crate::helpers::user_helpers::verify_owner;
+use crate::storage::{
+    get_next_file_id, CandidWrapper, UploadSession, FILE_CONTENT_CHUNKS, FILES_METADATA,
+    FOLDER_CONTENTS_INDEX, UPLOAD_SESSIONS,
+};
+use gpt_types::{
+    api::{
+        AbortFileUploadRequest, AbortFileUploadResponse, AbortFileUploadResult,
+        BeginFileUploadRequest, BeginFileUploadResponse, BeginFileUploadResult,
+        CommitFileUploadRequest, CommitFileUploadResponse, CommitFileUploadResult, FileInfo,
+        FsItemType, UploadFileChunkRequest, UploadFileChunkResponse, UploadFileChunkResult,
+    },
+    domain::{FileId, FileMetadata},
+    error::{CanisterError, CanisterResult},
+};
+use ic_cdk::api;
+use ic_cdk_macros::update;
+
+/// Opens an upload session for a file too large for `upload_file`.
+/// Content is sent with `upload_file_chunk` and published with `commit_file_upload`.
+#[update]
+pub fn begin_file_upload(req: BeginFileUploadRequest) -> BeginFileUploadResult {
+    ic_cdk::println!(
+        "[BeginFileUpload] '{}' ({} bytes)",
+        req.name,
+        req.total_size_bytes
+    );
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    validate_parent_folder_and_capacity(req.parent_folder_id)?;
+    validate_item_name_and_collision(&req.name, req.parent_folder_id, &FsItemType::File)?;
+    let mime_type = resolve_mime_type(&req.name, &req.mime_type)?;
+
+    if req.total_size_bytes == 0 || req.total_size_bytes > MAX_CHUNKED_FILE_SIZE_BYTES {
+        return Err(CanisterError::InvalidInput(format!(
+            "File size must be between 1 and {} bytes.",
+            MAX_CHUNKED_FILE_SIZE_BYTES
+        )));
+    }
+
+    let open_sessions = UPLOAD_SESSIONS.with(|s| s.borrow().len()) as usize;
+    if open_sessions >= MAX_OPEN_UPLOAD_SESSIONS {
+        return Err(CanisterError::FileSystemLimitExceeded(
+            "Too many uploads in progress.".to_string(),
+        ));
+    }
+
+    // Open sessions count towards the file limit so commits cannot overshoot it.
+    if FILES_METADATA.with(|f| f.borrow().len()) as usize + open_sessions >= MAX_FILES_PER_USER {
+        return Err(CanisterError::FileSystemLimitExceeded(
+            "Maximum number of files reached.".to_string(),
+        ));
+    }
+
+    let timestamp = api::time();
+    let upload_id = get_next_file_id();
+    let chunk_count = req.total_size_bytes.div_ceil(FILE_CHUNK_SIZE_BYTES) as u32;
+
+    let session = UploadSession {
+        name: req.name.trim().to_string(),
+        parent_folder_id: req.parent_folder_id,
+        mime_type,
+        total_size_bytes: req.total_size_bytes,
+        chunk_count,
+        received_chunks: 0,
+        created_at: timestamp,
+        updated_at: timestamp,
+    };
+    UPLOAD_SESSIONS.with(|s| s.borrow_mut().insert(upload_id, CandidWrapper(session)));
+
+    Ok(BeginFileUploadResponse {
+        upload_id,
+        chunk_size_bytes: FILE_CHUNK_SIZE_BYTES,
+        chunk_count,
+    })
+}
+
+#[update]
+pub fn upload_file_chunk(req: UploadFileChunkRequest) -> UploadFileChunkResult {
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    let mut session = get_session(req.upload_id)?;
+    if req.index >= session.chunk_count {
+        return Err(CanisterError::InvalidInput(format!(
+            "Chunk index {} is out of range (upload has {} chunks).",
+            req.index, session.chunk_count
+        )));
+    }
+
+    let expected_len = expected_chunk_len(&session, req.index);
+    if req.data.len() as u64 != expected_len {
+        return Err(CanisterError::InvalidInput(format!(
+            "Chunk {} must be {} bytes, got {}.",
+            req.index,
+            expected_len,
+            req.data.len()
+        )));
+    }
+
+    let key = (req.upload_id, req.index);
+    let is_new = FILE_CONTENT_CHUNKS.with(|c| {
+        let mut chunks = c.borrow_mut();
+        let is_new = !chunks.contains_key(&key);
+        chunks.insert(key, CandidWrapper(req.data));
+        is_new
+    });
+
+    if is_new {
+        session.received_chunks += 1;
+    }
+    session.updated_at = api::time();
+    let received_chunks = session.received_chunks;
+    UPLOAD_SESSIONS.with(|s| s.borrow_mut().insert(req.upload_id, CandidWrapper(session)));
+
+    Ok(UploadFileChunkResponse { received_chunks })
+}
+
+/// Publishes a fully received upload as a file in its target folder.
+#[update]
+pub fn commit_file_upload(req: CommitFileUploadRequest) -> CommitFileUploadResult {
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    let session = get_session(req.upload_id)?;
+    if session.received_chunks != session.chunk_count {
+        return Err(CanisterError::InvalidInput(format!(
+            "Upload is incomplete: {} of {} chunks received.",
+            session.received_chunks, session.chunk_count
+        )));
+    }
+
+    // The folder may have changed while chunks were uploading.
+    validate_parent_folder_and_capacity(session.parent_folder_id)?;
+    validate_item_name_and_collision(&session.name, session.parent_folder_id, &FsItemType::File)?;
+
+    let timestamp = api::time();
+    let file_id = req.upload_id;
+    let metadata = FileMetadata {
+        id: file_id,
+        owner: caller,
+        name: session.name,
+        parent_folder_id: session.parent_folder_id,
+        mime_type: session.mime_type,
+        content_size_bytes: session.total_size_bytes,
+        chunks: req.chunks.unwrap_or_default(),
+        created_at: timestamp,
+        updated_at: timestamp,
+    };
+
+    FILES_METADATA.with(|f| {
+        f.borrow_mut().insert(file_id, CandidWrapper(metadata.clone()));
+    });
+
+    FOLDER_CONTENTS_INDEX.with(|idx| {
+        let mut index_mut = idx.borrow_mut();
+        let mut parent_contents = index_mut
+            .get(&metadata.parent_folder_id)
+            .map(|w| w.0.clone())
+            .unwrap_or_default();
+        parent_contents.child_file_ids.push(file_id);
+        index_mut.insert(metadata.parent_folder_id, CandidWrapper(parent_contents));
+    });
+
+    UPLOAD_SESSIONS.with(|s| s.borrow_mut().remove(&req.upload_id));
+
+    ic_cdk::println!(
+        "[CommitFileUpload] Committed file_id {} ({} bytes).",
+        file_id,
+        metadata.content_size_bytes
+    );
+
+    Ok(CommitFileUploadResponse {
+        file: FileInfo {
+            id: metadata.id,
+            name: metadata.name,
+            mime_type: metadata.mime_type,
+            content_size_bytes: metadata.content_size_bytes,
+            created_at: metadata.created_at,
+            updated_at: metadata.updated_at,
+            parent_folder_id: metadata.parent_folder_id,
+            chunks: metadata.chunks,
+        },
+    })
+}
+
+#[update]
+pub fn abort_file_upload(req: AbortFileUploadRequest) -> AbortFileUploadResult {
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    get_session(req.upload_id)?;
+    discard_upload(req.upload_id);
+
+    Ok(AbortFileUploadResponse)
+}
+
+/// Drops an upload session together with the chunks it received.
+pub fn discard_upload(upload_id: FileId) {
+    UPLOAD_SESSIONS.with(|s| s.borrow_mut().remove(&upload_id));
+    remove_content_chunks(upload_id);
+}
+
+/// Discards sessions that received nothing for `UPLOAD_SESSION_TTL_NS`.
+pub fn discard_abandoned_uploads(now: u64) -> usize {
+    let abandoned: Vec<FileId> = UPLOAD_SESSIONS.with(|s| {
+        s.borrow()
+            .iter()
+            .filter(|entry| now.saturating_sub(entry.value().updated_at) > UPLOAD_SESSION_TTL_NS)
+            .map(|entry| *entry.key())
+            .collect()
+    });
+    for upload_id in &abandoned {
+        discard_upload(*upload_id);
+    }
+    abandoned.len()
+}
+
+fn get_session(upload_id: FileId) -> CanisterResult<UploadSession> {
+    UPLOAD_SESSIONS
+        .with(|s| s.borrow().get(&upload_id).map(|w| w.0.clone()))
+        .ok_or(CanisterError::InvalidInput(format!(
+            "Upload {} not found or expired.",
+            upload_id
+        )))
+}
+
+fn expected_chunk_len(session: &UploadSession, index: u32) -> u64 {
+    if index + 1 == session.chunk_count {
+        session.total_size_bytes - FILE_CHUNK_SIZE_BYTES * (session.chunk_count as u64 - 1)
+    } else {
+        FILE_CHUNK_SIZE_BYTES
+    }
+}
@@ -1,11 +1,15 @@
-use crate::config::{MAX_FILENAME_LENGTH, MAX_FS_DEPTH, MAX_ITEMS_PER_FOLDER};
+use crate::config::{
+    ALLOWED_MIME_TYPES, FILE_CHUNK_SIZE_BYTES, MAX_FILENAME_LENGTH, MAX_FS_DEPTH,
+    MAX_ITEMS_PER_FOLDER,
+};
 use crate::storage::{
-    get_next_folder_id, get_root_folder_id, set_root_folder_id, CandidWrapper, FolderContents,
-    FILES_METADATA, FOLDER_CONTENTS_INDEX, FOLDERS,
+    get_next_folder_id, get_root_folder_id, set_root_folder_id, CandidWrapper, FileChunkKey,
+    FolderContents, FILE_CONTENT_CHUNKS, FILES_CONTENT, FILES_METADATA, FOLDER_CONTENTS_INDEX,
+    FOLDERS,
 };
 use gpt_types::{
     api::{FileInfo, FolderInfo, FsItemInfo, FsItemType},
-    domain::{Folder, FolderId, ROOT_FOLDER_ID},
+    domain::{FileId, Folder, FolderId, ROOT_FOLDER_ID},
     error::{CanisterError, CanisterResult},
 };
 use ic_cdk::api;
@@ -180,10 +184,31 @@ pub fn infer_mime_type_from_name(name: &str) -> Option<&'static str> {
         "sh" => Some("application/x-sh"),
         "toml" => Some("application/toml"),
         "yaml" | "yml" => Some("application/yaml"),
+        "pdf" => Some("application/pdf"),
+        "docx" => {
+            Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document")
+        }
         _ => None,
     }
 }
 
+/// Uses the declared MIME type, or infers one from the file name when none is given,
+/// and rejects types outside `ALLOWED_MIME_TYPES`.
+pub fn resolve_mime_type(name: &str, declared: &str) -> CanisterResult<String> {
+    let mime_type = if declared.is_empty() {
+        infer_mime_type_from_name(name)
+            .unwrap_or_default()
+            .to_string()
+    } else {
+        declared.to_string()
+    };
+
+    if !ALLOWED_MIME_TYPES.contains(&mime_type.as_str()) {
+        return Err(CanisterError::UnsupportedMimeType(declared.to_string()));
+    }
+    Ok(mime_type)
+}
+
 pub fn resolve_path(path: &str) -> CanisterResult<FsItemInfo> {
     let root_id = get_or_create_root_folder_id()?;
     let trimmed_path = path.trim();
@@ -272,6 +297,54 @@ pub fn resolve_path(path: &str) -> CanisterResult<FsItemInfo> {
     Err(CanisterError::PathNotFound)
 }
 
+/// Reads `length` bytes at `offset` from a file's content, whether it was uploaded
+/// in one call (`FILES_CONTENT`) or in chunks (`FILE_CONTENT_CHUNKS`).
+/// The range must already be clamped to the file size.
+pub fn read_file_range(file_id: FileId, offset: u64, length: u64) -> CanisterResult<Vec<u8>> {
+    let whole = FILES_CONTENT.with(|fc| fc.borrow().get(&file_id).map(|w| w.0));
+    if let Some(content) = whole {
+        let start = offset as usize;
+        let end = (offset + length) as usize;
+        return content
+            .get(start..end)
+            .map(|range| range.to_vec())
+            .ok_or(CanisterError::FileNotFound);
+    }
+
+    let mut result = Vec::with_capacity(length as usize);
+    let end = offset + length;
+    let mut position = offset;
+    while position < end {
+        let index = (position / FILE_CHUNK_SIZE_BYTES) as u32;
+        let chunk = FILE_CONTENT_CHUNKS
+            .with(|c| c.borrow().get(&(file_id, index)).map(|w| w.0))
+            .ok_or(CanisterError::FileNotFound)?;
+
+        let chunk_start = index as u64 * FILE_CHUNK_SIZE_BYTES;
+        let from = (position - chunk_start) as usize;
+        let to = ((end - chunk_start) as usize).min(chunk.len());
+        if from >= to {
+            return Err(CanisterError::FileNotFound);
+        }
+        result.extend_from_slice(&chunk[from..to]);
+        position = chunk_start + to as u64;
+    }
+    Ok(result)
+}
+
+/// Removes every content chunk stored for a file or upload session.
+pub fn remove_content_chunks(file_id: FileId) {
+    FILE_CONTENT_CHUNKS.with(|c| {
+        let mut chunks = c.borrow_mut();
+        let keys: Vec<FileChunkKey> = chunks
+            .keys_range((file_id, 0)..=(file_id, u32::MAX))
+            .collect();
+        for key in keys {
+            chunks.remove(&key);
+        }
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -313,5 +386,6 @@ mod tests {
         assert_eq!(infer_mime_type_from_name(".config"), None);
         assert_eq!(infer_mime_type_from_name(".zshrc"), None);
         assert_eq!(infer_mime_type_from_name("main.rs"), Some("text/x-rust"));
+        assert_eq!(infer_mime_type_from_name("paper.PDF"), Some("application/pdf"));
     }
 }
@@ -1,10 +1,12 @@
 use crate::helpers::user_helpers::verify_owner;
 use crate::helpers::generation_helpers::{
     GenerationParams, create_generation_entities, validate_generation_request,
+    validate_response_schema,
 };
-use crate::helpers::message_helpers::validate_attachments;
+use crate::helpers::attachment_helpers::{resolve_file_keys, validate_attached_files};
+use crate::helpers::retrieval_helpers::resolve_retrieval;
 use crate::storage::{
-    get_next_message_id, CandidWrapper, StorableString,
+    get_next_message_id, save_chat, save_message, CandidWrapper, StorableString,
     CHAT_JOBS, CHATS, MESSAGES, MODELS,
 };
 use gpt_types::api::{AddMessageRequest, AddMessageResponse, AddMessageResult};
@@ -32,7 +34,7 @@ pub fn add_message(req: AddMessageRequest) -> AddMessageResult {
         }
         // Embedding models have specific constraints
         if model.0.is_embedding {
-            if req.attachments.is_some() {
+            if req.attached_file_ids.is_some() {
                 return Err(CanisterError::InvalidInput(
                     "Embedding models do not support attachments.".to_string(),
                 ));
@@ -47,13 +49,13 @@ pub fn add_message(req: AddMessageRequest) -> AddMessageResult {
     })?;
 
     // Validation
-    if req.content.is_empty() && req.attachments.is_none() {
+    if req.content.is_empty() && req.attached_file_ids.is_none() {
         return Err(CanisterError::InvalidInput(
             "Message content cannot be empty without attachments".to_string(),
         ));
     }
-    validate_attachments(&req.attachments, &req.model_id)?;
-    if req.attachments.is_some() && req.role != Role::User {
+    validate_attached_files(&req.attached_file_ids, caller, &req.model_id)?;
+    if req.attached_file_ids.is_some() && req.role != Role::User {
         return Err(CanisterError::InvalidInput(
             "Attachments are only allowed for User roles.".to_string(),
         ));
@@ -91,7 +93,16 @@ pub fn add_message(req: AddMessageRequest) -> AddMessageResult {
         &req.model_id,
         &req.tools,
         req.custom_prompt.as_ref(),
+        &req.failover_chat_keys,
     )?;
+    let retrieval = resolve_retrieval(req.retrieval, caller, &req.model_id, req.node_id)?;
+    let file_keys = resolve_file_keys(
+        req.file_keys,
+        req.node_id,
+        req.parent_message_id,
+        &req.attached_file_ids,
+    )?;
+    validate_response_schema(&req.response_schema, &req.model_id)?;
 
     let timestamp = api::time();
 
@@ -106,12 +117,14 @@ pub fn add_message(req: AddMessageRequest) -> AddMessageResult {
         created_at: timestamp,
         updated_at: timestamp,
         error_status: None,
-        attachments: req.attachments.clone(),
+        attachments: None,
+        attached_file_ids: req.attached_file_ids.clone(),
         tool_calls: None,
         tool_results: None,
         tool_call_id: None,
         requires_client_action: false,
         usage: None,
+        citations: Vec::new(),
     };
 
     // Create AI Message (Placeholder) and Job
@@ -127,33 +140,33 @@ pub fn add_message(req: AddMessageRequest) -> AddMessageResult {
         tools: req.tools.clone(),
         reasoning_effort: req.reasoning_effort,
         encrypted_chat_key: req.encrypted_chat_key,
+        failover_chat_keys: req.failover_chat_keys.unwrap_or_default(),
+        context_strategy: req.context_strategy,
+        response_schema: req.response_schema,
+        kind: None,
+        retrieval,
+        file_keys,
     };
     let (ai_msg, job) = create_generation_entities(gen_params, timestamp);
 
     // Store the new message, placeholder, and job
-    MESSAGES.with(|m| {
-        let mut msgs = m.borrow_mut();
-        msgs.insert(user_message_id, CandidWrapper(user_msg.clone()));
-        msgs.insert(ai_msg.message_id, CandidWrapper(ai_msg.clone()));
-    });
+    save_message(user_msg.clone());
+    save_message(ai_msg.clone());
 
     CHAT_JOBS.with(|cj| {
         cj.borrow_mut().insert(job.job_id, CandidWrapper(job.clone()));
     });
 
     // Update the parent chat to link the new messages and job
-    CHATS.with(|c| {
-        let mut chats = c.borrow_mut();
-        if let Some(chat_wrapper) = chats.get(&req.chat_id) {
-            let mut chat = chat_wrapper.0.clone();
-            chat.message_ids.push(user_message_id);
-            chat.message_ids.push(ai_msg.message_id);
-            chat.job_ids.push(job.job_id);
-            chat.active_job_id = Some(job.job_id);
-            chat.updated_at = timestamp;
-            chats.insert(req.chat_id, CandidWrapper(chat));
-        }
-    });
+    if let Some(mut chat) = CHATS.with(|c| c.borrow().get(&req.chat_id).map(|w| w.0.clone())) {
+        chat.message_ids.push(user_message_id);
+        chat.message_ids.push(ai_msg.message_id);
+        chat.job_ids.push(job.job_id);
+        chat.active_job_id = Some(job.job_id);
+        chat.active_leaf_message_id = Some(ai_msg.message_id);
+        chat.updated_at = timestamp;
+        save_chat(chat);
+    }
 
     Ok(AddMessageResponse {
         message: user_msg,
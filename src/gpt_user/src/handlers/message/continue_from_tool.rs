@@ -1,8 +1,10 @@
+use crate::helpers::attachment_helpers::resolve_file_keys;
 use crate::helpers::generation_helpers::validate_generation_request;
+use crate::helpers::retrieval_helpers::resolve_retrieval;
 use crate::helpers::user_helpers::verify_owner;
 use crate::storage::{
     CHAT_JOBS, CHATS, CandidWrapper, MESSAGES, MODELS, StorableString, get_next_job_id,
-    get_next_message_id,
+    get_next_message_id, save_chat, save_message,
 };
 use gpt_types::{
     api::{
@@ -72,6 +74,14 @@ pub fn continue_from_tool_response(
         &req.model_id,
         &req.tools,
         req.custom_prompt.as_ref(),
+        &req.failover_chat_keys,
+    )?;
+    let retrieval = resolve_retrieval(req.retrieval, caller, &req.model_id, req.node_id)?;
+    let file_keys = resolve_file_keys(
+        req.file_keys,
+        req.node_id,
+        Some(req.assistant_message_id),
+        &None,
     )?;
 
     let timestamp = api::time();
@@ -92,11 +102,24 @@ pub fn continue_from_tool_response(
             updated_at: timestamp,
             error_status: None,
             attachments: None,
+            attached_file_ids: None,
             usage: None,
+            citations: Vec::new(),
         };
         prepared_tool_messages.push(tool_message);
     }
 
+    // A structured answer requested alongside tools is still owed after the tool round.
+    let response_schema = CHAT_JOBS.with(|cj| {
+        let jobs = cj.borrow();
+        chat.job_ids
+            .iter()
+            .rev()
+            .filter_map(|job_id| jobs.get(job_id))
+            .find(|job| job.0.placeholder_message_id == req.assistant_message_id)
+            .and_then(|job| job.0.response_schema.clone())
+    });
+
     // Get extra_body_json from model
     let extra_body_json = MODELS.with(|m| {
         m.borrow()
@@ -112,10 +135,7 @@ pub fn continue_from_tool_response(
         let new_tool_msg_id = get_next_message_id();
         tool_msg.message_id = new_tool_msg_id;
         tool_msg.parent_message_id = Some(last_message_id);
-        MESSAGES.with(|m| {
-            m.borrow_mut()
-                .insert(new_tool_msg_id, CandidWrapper(tool_msg));
-        });
+        save_message(tool_msg);
         all_new_message_ids.push(new_tool_msg_id);
         last_message_id = new_tool_msg_id;
     }
@@ -132,16 +152,15 @@ pub fn continue_from_tool_response(
         updated_at: timestamp,
         error_status: None,
         attachments: None,
+        attached_file_ids: None,
         tool_calls: None,
         tool_results: None,
         tool_call_id: None,
         requires_client_action: false,
         usage: None,
+        citations: Vec::new(),
     };
-    MESSAGES.with(|m| {
-        m.borrow_mut()
-            .insert(final_ai_message_id, CandidWrapper(ai_msg));
-    });
+    save_message(ai_msg);
     all_new_message_ids.push(final_ai_message_id);
 
     // Create job
@@ -163,27 +182,32 @@ pub fn continue_from_tool_response(
         extra_body_json,
         reasoning_effort: req.reasoning_effort,
         encrypted_chat_key: req.encrypted_chat_key,
+        retry_count: 0,
+        node_history: Vec::new(),
+        failover_chat_keys: req.failover_chat_keys.unwrap_or_default(),
+        context_strategy: req.context_strategy,
+        response_schema,
+        kind: None,
+        retrieval,
+        file_keys,
     };
     CHAT_JOBS.with(|cj| {
         cj.borrow_mut().insert(final_job_id, CandidWrapper(job));
     });
 
     // Update chat
-    CHATS.with(|c| {
-        let mut chats = c.borrow_mut();
-        if let Some(chat_wrapper) = chats.get(&req.chat_id) {
-            let mut chat = chat_wrapper.0.clone();
-            for msg_id in all_new_message_ids {
-                if !chat.message_ids.contains(&msg_id) {
-                    chat.message_ids.push(msg_id);
-                }
+    if let Some(mut chat) = CHATS.with(|c| c.borrow().get(&req.chat_id).map(|w| w.0.clone())) {
+        for msg_id in all_new_message_ids {
+            if !chat.message_ids.contains(&msg_id) {
+                chat.message_ids.push(msg_id);
             }
-            chat.job_ids.push(final_job_id);
-            chat.active_job_id = Some(final_job_id);
-            chat.updated_at = timestamp;
-            chats.insert(req.chat_id, CandidWrapper(chat));
         }
-    });
+        chat.job_ids.push(final_job_id);
+        chat.active_job_id = Some(final_job_id);
+        chat.active_leaf_message_id = Some(final_ai_message_id);
+        chat.updated_at = timestamp;
+        save_chat(chat);
+    }
 
     Ok(ContinueFromToolResponseResponse {
         new_ai_message_id: final_ai_message_id,
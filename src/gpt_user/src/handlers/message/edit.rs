@@ -2,9 +2,12 @@ use crate::helpers::user_helpers::verify_owner;
 use crate::helpers::generation_helpers::{
     GenerationParams, create_generation_entities, validate_generation_request,
 };
-use crate::helpers::message_helpers::validate_attachments;
+use crate::helpers::attachment_helpers::{resolve_file_keys, validate_attached_files};
+use crate::helpers::message_helpers::remove_context_checkpoints;
+use crate::helpers::retrieval_helpers::resolve_retrieval;
 use crate::storage::{
-    get_next_message_id, CandidWrapper, CHAT_JOBS, CHATS, MESSAGES,
+    get_next_message_id, remove_message, save_chat, save_message, CandidWrapper, CHAT_JOBS, CHATS,
+    MESSAGES,
 };
 use gpt_types::{
     api::{EditUserMessageRequest, EditUserMessageResponse, EditUserMessageResult},
@@ -20,12 +23,12 @@ pub fn edit_user_message(req: EditUserMessageRequest) -> EditUserMessageResult {
     let caller = ic_cdk::api::msg_caller();
     verify_owner(caller)?;
 
-    if req.new_content.is_empty() && req.attachments.is_none() {
+    if req.new_content.is_empty() && req.attached_file_ids.is_none() {
         return Err(CanisterError::InvalidInput(
             "Cannot edit message to have no content and no attachments.".to_string(),
         ));
     }
-    validate_attachments(&req.attachments, &req.model_id)?;
+    validate_attached_files(&req.attached_file_ids, caller, &req.model_id)?;
 
     // Verify chat ownership
     let chat_opt = CHATS.with(|c| c.borrow().get(&req.chat_id).map(|w| w.0.clone()));
@@ -57,6 +60,14 @@ pub fn edit_user_message(req: EditUserMessageRequest) -> EditUserMessageResult {
         &req.model_id,
         &req.tools,
         req.custom_prompt.as_ref(),
+        &req.failover_chat_keys,
+    )?;
+    let retrieval = resolve_retrieval(req.retrieval, caller, &req.model_id, req.node_id)?;
+    let file_keys = resolve_file_keys(
+        req.file_keys,
+        req.node_id,
+        old_user_msg.parent_message_id,
+        &req.attached_file_ids,
     )?;
 
     let timestamp = api::time();
@@ -70,12 +81,14 @@ pub fn edit_user_message(req: EditUserMessageRequest) -> EditUserMessageResult {
         created_at: timestamp,
         updated_at: timestamp,
         error_status: None,
-        attachments: req.attachments,
+        attachments: None,
+        attached_file_ids: req.attached_file_ids,
         tool_calls: None,
         tool_results: None,
         tool_call_id: None,
         requires_client_action: false,
         usage: None,
+        citations: Vec::new(),
     };
 
     let gen_params = GenerationParams {
@@ -90,39 +103,40 @@ pub fn edit_user_message(req: EditUserMessageRequest) -> EditUserMessageResult {
         tools: req.tools.clone(),
         reasoning_effort: req.reasoning_effort,
         encrypted_chat_key: req.encrypted_chat_key,
+        failover_chat_keys: req.failover_chat_keys.unwrap_or_default(),
+        context_strategy: req.context_strategy,
+        response_schema: None,
+        kind: None,
+        retrieval,
+        file_keys,
     };
     let (ai_msg, job) = create_generation_entities(gen_params, timestamp);
 
-    MESSAGES.with(|m| {
-        let mut msgs = m.borrow_mut();
-        msgs.remove(&req.old_user_message_id);
-        msgs.insert(new_user_id, CandidWrapper(new_user_msg.clone()));
-        msgs.insert(ai_msg.message_id, CandidWrapper(ai_msg.clone()));
-    });
+    remove_message(req.old_user_message_id);
+    save_message(new_user_msg.clone());
+    save_message(ai_msg.clone());
+    remove_context_checkpoints(&[req.old_user_message_id]);
 
     CHAT_JOBS.with(|cj| {
         cj.borrow_mut().insert(job.job_id, CandidWrapper(job.clone()));
     });
 
-    CHATS.with(|c| {
-        let mut chats = c.borrow_mut();
-        if let Some(chat_wrapper) = chats.get(&req.chat_id) {
-            let mut chat = chat_wrapper.0.clone();
-            if let Some(pos) = chat
-                .message_ids
-                .iter()
-                .position(|&id| id == req.old_user_message_id)
-            {
-                chat.message_ids.remove(pos);
-            }
-            chat.message_ids.push(new_user_id);
-            chat.message_ids.push(ai_msg.message_id);
-            chat.job_ids.push(job.job_id);
-            chat.active_job_id = Some(job.job_id);
-            chat.updated_at = timestamp;
-            chats.insert(req.chat_id, CandidWrapper(chat));
+    if let Some(mut chat) = CHATS.with(|c| c.borrow().get(&req.chat_id).map(|w| w.0.clone())) {
+        if let Some(pos) = chat
+            .message_ids
+            .iter()
+            .position(|&id| id == req.old_user_message_id)
+        {
+            chat.message_ids.remove(pos);
         }
-    });
+        chat.message_ids.push(new_user_id);
+        chat.message_ids.push(ai_msg.message_id);
+        chat.job_ids.push(job.job_id);
+        chat.active_job_id = Some(job.job_id);
+        chat.active_leaf_message_id = Some(ai_msg.message_id);
+        chat.updated_at = timestamp;
+        save_chat(chat);
+    }
 
     Ok(EditUserMessageResponse {
         new_user_message: new_user_msg,
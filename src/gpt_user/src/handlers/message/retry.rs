@@ -1,8 +1,10 @@
 use crate::helpers::user_helpers::verify_owner;
+use crate::helpers::attachment_helpers::resolve_file_keys;
 use crate::helpers::generation_helpers::{
     GenerationParams, create_generation_entities, validate_generation_request,
 };
-use crate::storage::{CandidWrapper, CHAT_JOBS, CHATS, MESSAGES};
+use crate::helpers::retrieval_helpers::resolve_retrieval;
+use crate::storage::{save_chat, save_message, CandidWrapper, CHAT_JOBS, CHATS, MESSAGES};
 use gpt_types::{
     api::{RetryAiMessageRequest, RetryAiMessageResponse, RetryAiMessageResult},
     domain::Role,
@@ -47,7 +49,28 @@ pub fn retry_ai_message(req: RetryAiMessageRequest) -> RetryAiMessageResult {
         &req.model_id,
         &req.tools,
         req.custom_prompt.as_ref(),
+        &req.failover_chat_keys,
     )?;
+    let retrieval = resolve_retrieval(req.retrieval, caller, &req.model_id, req.node_id)?;
+    let file_keys =
+        resolve_file_keys(req.file_keys, req.node_id, Some(req.user_message_id), &None)?;
+
+    // Regenerating a structured answer keeps the schema of the answer it replaces.
+    let response_schema = CHAT_JOBS.with(|cj| {
+        let jobs = cj.borrow();
+        chat.job_ids
+            .iter()
+            .rev()
+            .filter_map(|job_id| jobs.get(job_id))
+            .find(|job| {
+                MESSAGES.with(|m| {
+                    m.borrow()
+                        .get(&job.0.placeholder_message_id)
+                        .is_some_and(|msg| msg.0.parent_message_id == Some(req.user_message_id))
+                })
+            })
+            .and_then(|job| job.0.response_schema.clone())
+    });
 
     let timestamp = api::time();
     let gen_params = GenerationParams {
@@ -62,28 +85,29 @@ pub fn retry_ai_message(req: RetryAiMessageRequest) -> RetryAiMessageResult {
         tools: req.tools.clone(),
         reasoning_effort: req.reasoning_effort,
         encrypted_chat_key: req.encrypted_chat_key,
+        failover_chat_keys: req.failover_chat_keys.unwrap_or_default(),
+        context_strategy: req.context_strategy,
+        response_schema,
+        kind: None,
+        retrieval,
+        file_keys,
     };
     let (ai_msg, job) = create_generation_entities(gen_params, timestamp);
 
-    MESSAGES.with(|m| {
-        m.borrow_mut().insert(ai_msg.message_id, CandidWrapper(ai_msg.clone()));
-    });
+    save_message(ai_msg.clone());
 
     CHAT_JOBS.with(|cj| {
         cj.borrow_mut().insert(job.job_id, CandidWrapper(job.clone()));
     });
 
-    CHATS.with(|c| {
-        let mut chats = c.borrow_mut();
-        if let Some(chat_wrapper) = chats.get(&req.chat_id) {
-            let mut chat = chat_wrapper.0.clone();
-            chat.message_ids.push(ai_msg.message_id);
-            chat.job_ids.push(job.job_id);
-            chat.active_job_id = Some(job.job_id);
-            chat.updated_at = timestamp;
-            chats.insert(req.chat_id, CandidWrapper(chat));
-        }
-    });
+    if let Some(mut chat) = CHATS.with(|c| c.borrow().get(&req.chat_id).map(|w| w.0.clone())) {
+        chat.message_ids.push(ai_msg.message_id);
+        chat.job_ids.push(job.job_id);
+        chat.active_job_id = Some(job.job_id);
+        chat.active_leaf_message_id = Some(ai_msg.message_id);
+        chat.updated_at = timestamp;
+        save_chat(chat);
+    }
 
     Ok(RetryAiMessageResponse {
         new_ai_message: ai_msg,
@@ -1,9 +1,9 @@
 use crate::helpers::user_helpers::verify_owner;
-use crate::storage::{CandidWrapper, CHATS, MESSAGES};
+use crate::storage::{save_message, CHATS, MESSAGES};
 use gpt_types::{
     api::{StoreToolResultsRequest, StoreToolResultsResponse, StoreToolResultsResult},
     domain::Role,
-    error::{CanisterError, CanisterResult},
+    error::CanisterError,
 };
 use ic_cdk::api;
 use ic_cdk_macros::update;
@@ -22,32 +22,25 @@ pub fn store_tool_results(req: StoreToolResultsRequest) -> StoreToolResultsResul
     }
 
     // Get and update the message
-    let update_res: CanisterResult<()> = MESSAGES.with(|m_ref| {
-        let mut messages = m_ref.borrow_mut();
-        let msg_wrapper = messages
-            .get(&req.assistant_message_id)
-            .ok_or(CanisterError::MessageNotFound)?;
-        let mut msg = msg_wrapper.0.clone();
-
-        if msg.role != Role::Assistant {
-            return Err(CanisterError::InvalidInput(
-                "Can only store results on an assistant message.".into(),
-            ));
-        }
-        if msg.chat_id != req.chat_id {
-            return Err(CanisterError::InvalidInput(
-                "Message not in specified chat.".into(),
-            ));
-        }
-
-        msg.tool_results = Some(req.results);
-        msg.requires_client_action = false;
-        msg.updated_at = api::time();
+    let mut msg = MESSAGES
+        .with(|m| m.borrow().get(&req.assistant_message_id).map(|w| w.0))
+        .ok_or(CanisterError::MessageNotFound)?;
+
+    if msg.role != Role::Assistant {
+        return Err(CanisterError::InvalidInput(
+            "Can only store results on an assistant message.".into(),
+        ));
+    }
+    if msg.chat_id != req.chat_id {
+        return Err(CanisterError::InvalidInput(
+            "Message not in specified chat.".into(),
+        ));
+    }
 
-        messages.insert(req.assistant_message_id, CandidWrapper(msg));
-        Ok(())
-    });
+    msg.tool_results = Some(req.results);
+    msg.requires_client_action = false;
+    msg.updated_at = api::time();
+    save_message(msg);
 
-    update_res?;
     Ok(StoreToolResultsResponse)
 }
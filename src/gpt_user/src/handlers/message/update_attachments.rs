@@ -1,8 +1,7 @@
 use crate::helpers::user_helpers::verify_owner;
-use crate::helpers::message_helpers::{
-    find_model_id_for_message, is_chat_in_generation, validate_attachments,
-};
-use crate::storage::{CandidWrapper, CHATS, MESSAGES};
+use crate::helpers::attachment_helpers::validate_attached_files;
+use crate::helpers::message_helpers::{find_model_id_for_message, is_chat_in_generation};
+use crate::storage::{save_chat, save_message, CHATS, MESSAGES};
 use gpt_types::{
     api::{
         UpdateMessageAttachmentsRequest, UpdateMessageAttachmentsResponse,
@@ -46,7 +45,7 @@ pub fn update_message_attachments(
         Ok((msg.chat_id, model_id))
     })?;
 
-    validate_attachments(&req.attachments, &model_id)?;
+    validate_attached_files(&req.attached_file_ids, caller, &model_id)?;
 
     if let Ok(in_gen) = is_chat_in_generation(chat_id) {
         if in_gen {
@@ -57,25 +56,18 @@ pub fn update_message_attachments(
     let timestamp = api::time();
 
     // Update message
-    MESSAGES.with(|m| {
-        let mut msgs = m.borrow_mut();
-        if let Some(msg_wrapper) = msgs.get(&req.message_id) {
-            let mut msg = msg_wrapper.0.clone();
-            msg.attachments = req.attachments;
-            msg.updated_at = timestamp;
-            msgs.insert(req.message_id, CandidWrapper(msg));
-        }
-    });
+    if let Some(mut msg) = MESSAGES.with(|m| m.borrow().get(&req.message_id).map(|w| w.0)) {
+        msg.attachments = None;
+        msg.attached_file_ids = req.attached_file_ids;
+        msg.updated_at = timestamp;
+        save_message(msg);
+    }
 
     // Update chat timestamp
-    CHATS.with(|c| {
-        let mut chats = c.borrow_mut();
-        if let Some(chat_wrapper) = chats.get(&chat_id) {
-            let mut chat = chat_wrapper.0.clone();
-            chat.updated_at = timestamp;
-            chats.insert(chat_id, CandidWrapper(chat));
-        }
-    });
+    if let Some(mut chat) = CHATS.with(|c| c.borrow().get(&chat_id).map(|w| w.0.clone())) {
+        chat.updated_at = timestamp;
+        save_chat(chat);
+    }
 
     Ok(UpdateMessageAttachmentsResponse)
 }
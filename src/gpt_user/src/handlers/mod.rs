@@ -1,6 +1,8 @@
 pub mod chat;
+pub mod embedding;
 pub mod file_system;
 pub mod message;
 pub mod node;
 pub mod upgrade;
 pub mod user;
+pub mod vault;
@@ -1,5 +1,9 @@
+use crate::helpers::attachment_helpers::chain_attached_file_ids;
+use crate::helpers::embedding_helpers::{embedding_batch, embedding_inputs};
+use crate::helpers::message_helpers::{apply_context_checkpoint, build_message_chain};
+use crate::helpers::node_helpers::grant_node_reads;
 use crate::helpers::user_helpers::verify_node_by_caller;
-use crate::storage::{CandidWrapper, CHAT_JOBS, CHATS, MESSAGES};
+use crate::storage::{CandidWrapper, CHAT_JOBS, CHATS};
 use gpt_types::api::{ClaimJobRequest, ClaimJobResponse, ClaimJobResult};
 use gpt_types::domain::GenerationStatus;
 use gpt_types::error::CanisterError;
@@ -41,37 +45,31 @@ pub fn claim_job(req: ClaimJobRequest) -> ClaimJobResult {
         return Err(CanisterError::Unauthorized);
     }
 
+    let timestamp = api::time();
+
     // Update job status (get -> modify -> insert pattern for StableBTreeMap)
     CHAT_JOBS.with(|cj| {
         let mut jobs = cj.borrow_mut();
         if let Some(job_wrapper) = jobs.get(&req.job_id) {
             let mut job = job_wrapper.0.clone();
             job.generation_status = GenerationStatus::InProgress;
-            job.updated_at = api::time();
+            job.updated_at = timestamp;
             jobs.insert(req.job_id, CandidWrapper(job));
         }
     });
 
-    // Build message chain
-    let message_chain_ids = {
-        let mut chain = Vec::new();
-        let mut current_message_id = job.placeholder_message_id;
-        loop {
-            let msg_opt = MESSAGES.with(|m| m.borrow().get(&current_message_id).map(|w| w.0.clone()));
-            if let Some(msg) = msg_opt {
-                chain.push(msg.message_id);
-                if let Some(parent_id) = msg.parent_message_id {
-                    current_message_id = parent_id;
-                } else {
-                    break;
-                }
-            } else {
-                break;
-            }
-        }
-        chain.reverse();
-        chain
-    };
+    // Build message chain and allow the node to read exactly that chain and the files
+    // it attaches. A stored summary stands in for the messages it covers.
+    let (message_chain_ids, context_checkpoint) =
+        apply_context_checkpoint(build_message_chain(job.placeholder_message_id));
+    let file_ids = chain_attached_file_ids(&message_chain_ids);
+    grant_node_reads(
+        req.job_id,
+        caller_node_id,
+        message_chain_ids.clone(),
+        file_ids,
+        timestamp,
+    );
 
     // Get updated job
     let updated_job = CHAT_JOBS
@@ -79,11 +77,14 @@ pub fn claim_job(req: ClaimJobRequest) -> ClaimJobResult {
         .unwrap();
 
     let tools = updated_job.tools.clone();
+    let embedding_inputs = embedding_batch(&updated_job).and_then(|_| embedding_inputs(req.job_id));
 
     Ok(ClaimJobResponse {
         chat,
         message_chain_ids,
         job: updated_job,
         tools,
+        context_checkpoint,
+        embedding_inputs,
     })
 }
@@ -1,5 +1,14 @@
+use crate::helpers::embedding_helpers::{
+    embedding_batch, remove_embedding_batches, store_embeddings,
+};
+use crate::helpers::generation_helpers::reassign_job;
+use crate::helpers::ingestion_helpers::{
+    commit_ingested_chunks, file_ingestion, remove_ingested_chunks,
+};
+use crate::helpers::message_helpers::validate_citations;
+use crate::helpers::node_helpers::revoke_node_reads;
 use crate::helpers::user_helpers::verify_node_by_caller;
-use crate::storage::{CandidWrapper, CHAT_JOBS, CHATS, MESSAGES};
+use crate::storage::{save_chat, save_message, CandidWrapper, CHAT_JOBS, CHATS, MESSAGES};
 use gpt_types::{
     api::{CompleteJobRequest, CompleteJobResponse, CompleteJobResult, JobCompletionResult},
     domain::GenerationStatus,
@@ -32,6 +41,34 @@ pub fn complete_job(req: CompleteJobRequest) -> CompleteJobResult {
         return Err(CanisterError::Unauthorized);
     }
 
+    if let Some(citations) = &req.citations {
+        validate_citations(citations)?;
+    }
+
+    // A job the user cancelled still takes the text generated before the node stopped.
+    if job.generation_status == GenerationStatus::Cancelled {
+        remove_embedding_batches(&[req.job_id]);
+        remove_ingested_chunks(&[req.job_id]);
+        if let JobCompletionResult::Success(content) | JobCompletionResult::Cancelled(content) =
+            &req.result
+        {
+            let placeholder =
+                MESSAGES.with(|m| m.borrow().get(&job.placeholder_message_id).map(|w| w.0));
+            if let Some(mut msg) = placeholder {
+                msg.content = content.clone();
+                if let Some(u) = req.usage {
+                    msg.usage = Some(u);
+                }
+                if let Some(citations) = req.citations {
+                    msg.citations = citations;
+                }
+                msg.updated_at = timestamp;
+                save_message(msg);
+            }
+        }
+        return Ok(CompleteJobResponse);
+    }
+
     // A job can only be completed if it's currently in progress.
     if job.generation_status != GenerationStatus::InProgress {
         let msg = format!(
@@ -41,11 +78,51 @@ pub fn complete_job(req: CompleteJobRequest) -> CompleteJobResult {
         return Err(CanisterError::InvalidInput(msg));
     }
 
+    // A retryable failure hands the job to another node instead of ending it.
+    if let JobCompletionResult::Failure(error_status) = &req.result
+        && error_status.is_retryable()
+        && let Some(next_node_id) = reassign_job(req.job_id, timestamp)?
+    {
+        ic_cdk::println!(
+            "complete_job: job {} failed on node {} ({}), reassigned to node {}",
+            req.job_id, caller_node_id, error_status, next_node_id
+        );
+        return Ok(CompleteJobResponse);
+    }
+
+    // Embeddings are only accepted for embedding batches, one per input; any other
+    // end of a batch discards its inputs.
+    match (&req.result, embedding_batch(&job)) {
+        (JobCompletionResult::Embeddings(embeddings), Some(batch)) => {
+            store_embeddings(req.job_id, batch, embeddings.clone(), timestamp)?;
+        }
+        (JobCompletionResult::Embeddings(_), None) => {
+            return Err(CanisterError::InvalidInput(format!(
+                "Job {} is not an embedding batch.",
+                req.job_id
+            )));
+        }
+        (_, Some(_)) => remove_embedding_batches(&[req.job_id]),
+        (_, None) => {}
+    }
+
+    // A successful ingestion replaces the file's chunks with the ones the node stored.
+    if let Some(ingestion) = file_ingestion(&job) {
+        match &req.result {
+            JobCompletionResult::Success(_) => {
+                commit_ingested_chunks(req.job_id, ingestion.file_id, timestamp)?
+            }
+            _ => remove_ingested_chunks(&[req.job_id]),
+        }
+    }
+
     // Determine the final status based on the result.
     let final_status = match &req.result {
         JobCompletionResult::Success(_) => GenerationStatus::Completed,
         JobCompletionResult::Failure(_) => GenerationStatus::Failed,
         JobCompletionResult::ToolCall(_) => GenerationStatus::Completed,
+        JobCompletionResult::Cancelled(_) => GenerationStatus::Cancelled,
+        JobCompletionResult::Embeddings(_) => GenerationStatus::Completed,
     };
 
     // State Updates
@@ -59,52 +136,53 @@ pub fn complete_job(req: CompleteJobRequest) -> CompleteJobResult {
             jobs.insert(req.job_id, CandidWrapper(job));
         }
     });
+    revoke_node_reads(req.job_id);
 
     // 2. Update the placeholder AI message with the final content, error, or tool calls.
-    MESSAGES.with(|m| {
-        let mut messages = m.borrow_mut();
-        if let Some(msg_wrapper) = messages.get(&job.placeholder_message_id) {
-            let mut msg = msg_wrapper.0.clone();
-            msg.updated_at = timestamp;
-
-            // Save usage data if present
-            if let Some(u) = req.usage {
-                msg.usage = Some(u);
-            }
-
-            match &req.result {
-                JobCompletionResult::Success(content) => {
-                    msg.content = content.clone();
-                    msg.error_status = None;
-                    msg.requires_client_action = false;
-                }
-                JobCompletionResult::Failure(error_status) => {
-                    msg.error_status = Some(error_status.clone());
-                    msg.requires_client_action = false;
-                }
-                JobCompletionResult::ToolCall(tool_calls) => {
-                    msg.tool_calls = Some(tool_calls.clone());
-                    msg.requires_client_action = true; // Signals to the UI that user input is needed.
-                    msg.error_status = None;
-                }
-            }
+    let placeholder = MESSAGES.with(|m| m.borrow().get(&job.placeholder_message_id).map(|w| w.0));
+    if let Some(mut msg) = placeholder {
+        msg.updated_at = timestamp;
 
-            messages.insert(job.placeholder_message_id, CandidWrapper(msg));
+        // Save usage data if present
+        if let Some(u) = req.usage {
+            msg.usage = Some(u);
+        }
+        if let Some(citations) = req.citations {
+            msg.citations = citations;
         }
-    });
 
-    // 3. Update the parent chat to remove the active job ID, unblocking the chat for new messages.
-    CHATS.with(|c| {
-        let mut chats = c.borrow_mut();
-        if let Some(chat_wrapper) = chats.get(&job.chat_id) {
-            let mut chat = chat_wrapper.0.clone();
-            if chat.active_job_id == Some(req.job_id) {
-                chat.active_job_id = None;
-                chat.updated_at = timestamp;
-                chats.insert(job.chat_id, CandidWrapper(chat));
+        match &req.result {
+            JobCompletionResult::Success(content) | JobCompletionResult::Cancelled(content) => {
+                msg.content = content.clone();
+                msg.error_status = None;
+                msg.requires_client_action = false;
+            }
+            JobCompletionResult::Failure(error_status) => {
+                msg.error_status = Some(error_status.clone());
+                msg.requires_client_action = false;
+            }
+            JobCompletionResult::ToolCall(tool_calls) => {
+                msg.tool_calls = Some(tool_calls.clone());
+                msg.requires_client_action = true; // Signals to the UI that user input is needed.
+                msg.error_status = None;
+            }
+            JobCompletionResult::Embeddings(_) => {
+                msg.error_status = None;
+                msg.requires_client_action = false;
             }
         }
-    });
+
+        save_message(msg);
+    }
+
+    // 3. Update the parent chat to remove the active job ID, unblocking the chat for new messages.
+    if let Some(mut chat) = CHATS.with(|c| c.borrow().get(&job.chat_id).map(|w| w.0.clone()))
+        && chat.active_job_id == Some(req.job_id)
+    {
+        chat.active_job_id = None;
+        chat.updated_at = timestamp;
+        save_chat(chat);
+    }
 
     Ok(CompleteJobResponse)
 }
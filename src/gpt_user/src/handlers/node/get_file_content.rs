@@ -0,0 +1,53 @@
+use crate::config::MAX_FILE_READ_BYTES;
+use crate::handlers::file_system::utils::read_file_range;
+use crate::helpers::ingestion_helpers::node_ingestion_job;
+use crate::helpers::node_helpers::get_node_read_grant;
+use crate::helpers::user_helpers::verify_node_by_caller;
+use crate::storage::FILES_METADATA;
+use gpt_types::api::{
+    NodeGetFileContentRequest, NodeGetFileContentResponse, NodeGetFileContentResult,
+};
+use gpt_types::error::CanisterError;
+use ic_cdk_macros::query;
+
+/// Returns a range of a file read by an in-progress job: the file an ingestion job
+/// ingests, or a file attached in the job's chain as recorded in its read grant.
+/// Ranges are capped at `MAX_FILE_READ_BYTES`.
+#[query]
+pub fn node_get_file_content(req: NodeGetFileContentRequest) -> NodeGetFileContentResult {
+    let node = verify_node_by_caller()?;
+    let file_id = match req.file_id {
+        Some(file_id) => {
+            let grant = get_node_read_grant(req.job_id, node.node_id, ic_cdk::api::time())?;
+            if !grant.file_ids.contains(&file_id) {
+                return Err(CanisterError::Unauthorized);
+            }
+            file_id
+        }
+        None => node_ingestion_job(req.job_id, node.node_id)?.1.file_id,
+    };
+
+    let file = FILES_METADATA
+        .with(|f| f.borrow().get(&file_id).map(|w| w.0))
+        .ok_or(CanisterError::FileNotFound)?;
+    let total_size_bytes = file.content_size_bytes;
+    if req.offset > total_size_bytes {
+        return Err(CanisterError::InvalidInput(format!(
+            "Offset {} is beyond the end of the file ({} bytes).",
+            req.offset, total_size_bytes
+        )));
+    }
+    let length = req
+        .length
+        .min(total_size_bytes - req.offset)
+        .min(MAX_FILE_READ_BYTES);
+    let content = read_file_range(file_id, req.offset, length)?;
+
+    Ok(NodeGetFileContentResponse {
+        content,
+        name: file.name,
+        mime_type: file.mime_type,
+        offset: req.offset,
+        total_size_bytes,
+    })
+}
@@ -0,0 +1,22 @@
+use crate::helpers::user_helpers::verify_node_by_caller;
+use crate::storage::CHAT_JOBS;
+use gpt_types::api::{NodeGetJobStatusRequest, NodeGetJobStatusResponse, NodeGetJobStatusResult};
+use gpt_types::error::CanisterError;
+use ic_cdk_macros::query;
+
+/// Returns the status of a job assigned to the calling node. Nodes poll it while
+/// generating to learn that the user cancelled the job.
+#[query]
+pub fn node_get_job_status(req: NodeGetJobStatusRequest) -> NodeGetJobStatusResult {
+    let node = verify_node_by_caller()?;
+    let job = CHAT_JOBS
+        .with(|cj| cj.borrow().get(&req.job_id).map(|w| w.0.clone()))
+        .ok_or_else(|| CanisterError::Other(format!("Job {} not found", req.job_id)))?;
+    if job.node_id != node.node_id {
+        return Err(CanisterError::Unauthorized);
+    }
+
+    Ok(NodeGetJobStatusResponse {
+        generation_status: job.generation_status,
+    })
+}
@@ -1,27 +1,22 @@
-use crate::helpers::node_helpers::ensure_no_conflict_incomplete;
+use crate::helpers::node_helpers::ensure_node_can_read;
 use crate::helpers::user_helpers::verify_node_by_caller;
-use crate::storage::{CHATS, MESSAGES};
+use crate::storage::MESSAGES;
 use gpt_types::api::{NodeGetMessageRequest, NodeGetMessageResponse, NodeGetMessageResult};
 use gpt_types::error::CanisterError;
 use ic_cdk_macros::query;
 
+/// Returns a single message to a node. Only messages in the chain of a job the
+/// calling node has claimed, and not yet completed, are readable.
 #[query(name = "node_get_message")]
 pub fn node_get_message(req: NodeGetMessageRequest) -> NodeGetMessageResult {
     ic_cdk::println!("node_get_message called with request: {:?}", req);
     let node = verify_node_by_caller()?;
-    let node_id = node.node_id;
+    ensure_node_can_read(node.node_id, req.message_id, ic_cdk::api::time())?;
 
     // Get message from stable storage
     let msg = MESSAGES
         .with(|m| m.borrow().get(&req.message_id).map(|w| w.0.clone()))
         .ok_or(CanisterError::MessageNotFound)?;
 
-    // Get chat from stable storage
-    let chat = CHATS
-        .with(|c| c.borrow().get(&msg.chat_id).map(|w| w.0.clone()))
-        .ok_or(CanisterError::ChatNotFound)?;
-
-    ensure_no_conflict_incomplete(&chat, node_id)?;
-
     Ok(NodeGetMessageResponse { message: msg })
 }
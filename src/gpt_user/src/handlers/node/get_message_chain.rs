@@ -0,0 +1,55 @@
+use crate::config::MAX_MESSAGE_CHAIN_PAGE_BYTES;
+use crate::helpers::node_helpers::get_node_read_grant;
+use crate::helpers::user_helpers::verify_node_by_caller;
+use crate::storage::MESSAGES;
+use gpt_types::api::{
+    NodeGetMessageChainRequest, NodeGetMessageChainResponse, NodeGetMessageChainResult,
+};
+use gpt_types::error::CanisterError;
+use ic_cdk_macros::query;
+
+/// Returns the conversation history of a job claimed by the calling node, as recorded
+/// in the job's read grant, in pages whose encoded size stays within
+/// `MAX_MESSAGE_CHAIN_PAGE_BYTES`.
+/// A page always carries at least one message so the cursor keeps advancing.
+#[query(name = "node_get_message_chain")]
+pub fn node_get_message_chain(req: NodeGetMessageChainRequest) -> NodeGetMessageChainResult {
+    ic_cdk::println!("node_get_message_chain called with request: {:?}", req);
+    let node = verify_node_by_caller()?;
+
+    let chain = get_node_read_grant(req.job_id, node.node_id, ic_cdk::api::time())?.message_ids;
+    let total = chain.len() as u32;
+    let start = req.cursor.unwrap_or(0);
+    if start > total {
+        return Err(CanisterError::InvalidInput(format!(
+            "Cursor {} is past the end of the chain ({} messages).",
+            start, total
+        )));
+    }
+
+    let mut messages = Vec::new();
+    let mut page_bytes = 0usize;
+    for message_id in &chain[start as usize..] {
+        let msg = MESSAGES
+            .with(|m| m.borrow().get(message_id).map(|w| w.0.clone()))
+            .ok_or(CanisterError::MessageNotFound)?;
+
+        let msg_bytes = candid::encode_one(&msg)
+            .map_err(|e| CanisterError::Other(format!("Failed to encode message: {}", e)))?
+            .len();
+        if !messages.is_empty() && page_bytes + msg_bytes > MAX_MESSAGE_CHAIN_PAGE_BYTES {
+            break;
+        }
+        page_bytes += msg_bytes;
+        messages.push(msg);
+    }
+
+    let end = start + messages.len() as u32;
+    let next_cursor = (end < total).then_some(end);
+
+    Ok(NodeGetMessageChainResponse {
+        messages,
+        next_cursor,
+        total,
+    })
+}
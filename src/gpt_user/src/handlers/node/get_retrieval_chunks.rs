@@ -0,0 +1,42 @@
+use crate::config::MAX_RETRIEVAL_PAGE_BYTES;
+use crate::helpers::retrieval_helpers::{
+    node_retrieval_job, retrieval_chunk_page, retrieval_file_ids,
+};
+use crate::helpers::user_helpers::verify_node_by_caller;
+use crate::storage::{CHATS, MODELS, StorableString};
+use gpt_types::api::{
+    NodeGetRetrievalChunksRequest, NodeGetRetrievalChunksResponse, NodeGetRetrievalChunksResult,
+};
+use gpt_types::error::CanisterError;
+use ic_cdk_macros::query;
+
+/// Returns the ingested chunks searched by an in-progress retrieval job, in pages
+/// whose encoded size stays within `MAX_RETRIEVAL_PAGE_BYTES`.
+#[query]
+pub fn node_get_retrieval_chunks(
+    req: NodeGetRetrievalChunksRequest,
+) -> NodeGetRetrievalChunksResult {
+    let node = verify_node_by_caller()?;
+    let (job, retrieval) = node_retrieval_job(req.job_id, node.node_id)?;
+
+    let owner = CHATS
+        .with(|c| c.borrow().get(&job.chat_id).map(|w| w.0.owner))
+        .ok_or(CanisterError::ChatNotFound)?;
+    let embedding_provider_model = MODELS
+        .with(|m| {
+            m.borrow()
+                .get(&StorableString(retrieval.embedding_model_id.clone()))
+                .map(|w| w.0.provider_model)
+        })
+        .ok_or(CanisterError::ModelNotFound)?;
+
+    let file_ids = retrieval_file_ids(&retrieval, owner);
+    let (chunks, next_cursor) =
+        retrieval_chunk_page(&file_ids, req.cursor.unwrap_or(0), MAX_RETRIEVAL_PAGE_BYTES)?;
+
+    Ok(NodeGetRetrievalChunksResponse {
+        chunks,
+        embedding_provider_model,
+        next_cursor,
+    })
+}
@@ -1,4 +1,10 @@
 pub mod claim_job;
 pub mod complete_job;
+pub mod get_file_content;
+pub mod get_job_status;
 pub mod get_message;
+pub mod get_message_chain;
 pub mod get_nodes;
+pub mod get_retrieval_chunks;
+pub mod store_context_checkpoint;
+pub mod store_file_chunks;
@@ -0,0 +1,83 @@
+use crate::config::MAX_CONTEXT_CHECKPOINT_BYTES;
+use crate::helpers::node_helpers::get_node_read_grant;
+use crate::helpers::user_helpers::verify_node_by_caller;
+use crate::storage::{CHAT_JOBS, CONTEXT_CHECKPOINTS, CandidWrapper, get_next_message_id};
+use gpt_types::api::{
+    NodeStoreContextCheckpointRequest, NodeStoreContextCheckpointResponse,
+    NodeStoreContextCheckpointResult,
+};
+use gpt_types::domain::{GenerationStatus, Message, Role};
+use gpt_types::error::CanisterError;
+use ic_cdk::api;
+use ic_cdk_macros::update;
+
+/// Stores a summary of a claimed job's history so later jobs in the chat can start
+/// from it. The summary replaces any earlier checkpoint covering the same message.
+#[update]
+pub fn node_store_context_checkpoint(
+    req: NodeStoreContextCheckpointRequest,
+) -> NodeStoreContextCheckpointResult {
+    ic_cdk::println!(
+        "node_store_context_checkpoint called for job {} covering message {}",
+        req.job_id,
+        req.covered_message_id
+    );
+    let node = verify_node_by_caller()?;
+    let timestamp = api::time();
+
+    let grant = get_node_read_grant(req.job_id, node.node_id, timestamp)?;
+    let job = CHAT_JOBS
+        .with(|cj| cj.borrow().get(&req.job_id).map(|w| w.0.clone()))
+        .ok_or(CanisterError::Other("Job not found".to_string()))?;
+
+    if job.generation_status != GenerationStatus::InProgress {
+        return Err(CanisterError::InvalidInput(format!(
+            "Job {} is not in progress.",
+            req.job_id
+        )));
+    }
+    // Only history the node was given can be summarized, and never the reply it is
+    // still writing.
+    if req.covered_message_id == job.placeholder_message_id
+        || !grant.message_ids.contains(&req.covered_message_id)
+    {
+        return Err(CanisterError::InvalidInput(format!(
+            "Message {} is not part of the job's history.",
+            req.covered_message_id
+        )));
+    }
+    if req.content.is_empty() || req.content.len() > MAX_CONTEXT_CHECKPOINT_BYTES {
+        return Err(CanisterError::InvalidInput(format!(
+            "Checkpoint content must be between 1 and {} bytes.",
+            MAX_CONTEXT_CHECKPOINT_BYTES
+        )));
+    }
+
+    let checkpoint = Message {
+        message_id: get_next_message_id(),
+        chat_id: job.chat_id,
+        parent_message_id: Some(req.covered_message_id),
+        role: Role::System,
+        content: req.content,
+        created_at: timestamp,
+        updated_at: timestamp,
+        error_status: None,
+        attachments: None,
+        attached_file_ids: None,
+        tool_calls: None,
+        tool_results: None,
+        tool_call_id: None,
+        requires_client_action: false,
+        usage: None,
+        citations: Vec::new(),
+    };
+    let checkpoint_message_id = checkpoint.message_id;
+    CONTEXT_CHECKPOINTS.with(|c| {
+        c.borrow_mut()
+            .insert(req.covered_message_id, CandidWrapper(checkpoint))
+    });
+
+    Ok(NodeStoreContextCheckpointResponse {
+        checkpoint_message_id,
+    })
+}
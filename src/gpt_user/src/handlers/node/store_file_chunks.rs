@@ -0,0 +1,22 @@
+use crate::helpers::ingestion_helpers::{node_ingestion_job, stage_ingested_chunks};
+use crate::helpers::user_helpers::verify_node_by_caller;
+use gpt_types::api::{
+    NodeStoreFileChunksRequest, NodeStoreFileChunksResponse, NodeStoreFileChunksResult,
+};
+use ic_cdk_macros::update;
+
+/// Stores chunks produced by an in-progress ingestion job. Nodes send them in pages
+/// and complete the job once every chunk is stored.
+#[update]
+pub fn node_store_file_chunks(req: NodeStoreFileChunksRequest) -> NodeStoreFileChunksResult {
+    ic_cdk::println!(
+        "node_store_file_chunks called for job {} with {} chunks",
+        req.job_id,
+        req.chunks.len()
+    );
+    let node = verify_node_by_caller()?;
+    node_ingestion_job(req.job_id, node.node_id)?;
+
+    let stored_chunks = stage_ingested_chunks(req.job_id, req.chunks)?;
+    Ok(NodeStoreFileChunksResponse { stored_chunks })
+}
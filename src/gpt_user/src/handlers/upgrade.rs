@@ -9,9 +9,13 @@ fn pre_upgrade_handler() {
 }
 
 /// Post-upgrade handler.
-/// Reinitialize timers after upgrade.
+/// Migrates stable storage to the current schema and reinitializes timers.
 #[post_upgrade]
 fn post_upgrade_handler() {
+    crate::migrations::run_storage_migrations();
+    if !crate::storage::is_message_tree_complete() {
+        crate::timers::manager::schedule_message_tree_backfill();
+    }
     ic_cdk::println!("gpt_user post_upgrade: Reinitializing timers.");
     crate::timers::manager::setup_periodic_tasks_timer();
     // Trigger immediate sync to refresh models/nodes after upgrade.
@@ -1,5 +1,7 @@
 pub mod finalize;
 pub mod is_finalized;
 pub mod list;
+pub mod retention;
+pub mod schema_version;
 pub mod storage_usage;
 pub mod whoami;
@@ -0,0 +1,78 @@
+use crate::config::MAX_SCHEDULED_DELETIONS;
+use crate::helpers::retention_helpers::{
+    chat_delete_at, current_retention_policy, validate_retention_policy,
+};
+use crate::helpers::user_helpers::verify_owner;
+use crate::storage::{update_retention_policy, CHATS};
+use gpt_types::api::{
+    GetRetentionPolicyResponse, GetRetentionPolicyResult, GetScheduledChatDeletionsRequest,
+    GetScheduledChatDeletionsResponse, GetScheduledChatDeletionsResult, ScheduledChatDeletion,
+    SetRetentionPolicyRequest, SetRetentionPolicyResponse, SetRetentionPolicyResult,
+};
+use ic_cdk_macros::{query, update};
+
+#[query]
+pub fn get_retention_policy() -> GetRetentionPolicyResult {
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    Ok(GetRetentionPolicyResponse {
+        policy: current_retention_policy(),
+    })
+}
+
+#[update]
+pub fn set_retention_policy(req: SetRetentionPolicyRequest) -> SetRetentionPolicyResult {
+    ic_cdk::println!("set_retention_policy called with request: {:?}", req);
+
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    validate_retention_policy(&req.policy)?;
+    update_retention_policy(req.policy.clone());
+
+    Ok(SetRetentionPolicyResponse { policy: req.policy })
+}
+
+/// Lists the chats the cleanup task will delete under the current policy, soonest first.
+#[query]
+pub fn get_scheduled_chat_deletions(
+    req: GetScheduledChatDeletionsRequest,
+) -> GetScheduledChatDeletionsResult {
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    let policy = current_retention_policy();
+    let horizon = req
+        .within_secs
+        .map(|secs| ic_cdk::api::time().saturating_add(secs.saturating_mul(1_000_000_000)));
+    let limit = req
+        .limit
+        .unwrap_or(MAX_SCHEDULED_DELETIONS)
+        .min(MAX_SCHEDULED_DELETIONS) as usize;
+
+    let mut deletions: Vec<ScheduledChatDeletion> = CHATS.with(|c| {
+        c.borrow()
+            .iter()
+            .filter_map(|entry| {
+                let chat = &entry.value().0;
+                let delete_at = chat_delete_at(chat, &policy)?;
+                if horizon.is_some_and(|horizon| delete_at > horizon) {
+                    return None;
+                }
+                Some(ScheduledChatDeletion {
+                    chat_id: chat.chat_id,
+                    title: chat.title.clone(),
+                    archived: chat.archived,
+                    temporary: chat.temporary,
+                    delete_at,
+                })
+            })
+            .collect()
+    });
+
+    deletions.sort_by_key(|d| (d.delete_at, d.chat_id));
+    deletions.truncate(limit);
+
+    Ok(GetScheduledChatDeletionsResponse { deletions })
+}
@@ -0,0 +1,14 @@
+use crate::migrations::STORAGE_SCHEMA_VERSION;
+use crate::storage::CONFIG;
+use gpt_types::api::{GetStorageSchemaVersionResponse, GetStorageSchemaVersionResult};
+use ic_cdk_macros::query;
+
+/// Reports how far storage has been migrated and how far this module can go, so the
+/// index never rolls a canister back to a module that cannot read its storage.
+#[query]
+pub fn get_storage_schema_version() -> GetStorageSchemaVersionResult {
+    Ok(GetStorageSchemaVersionResponse {
+        stored_version: CONFIG.with(|c| c.borrow().get().schema_version),
+        supported_version: STORAGE_SCHEMA_VERSION,
+    })
+}
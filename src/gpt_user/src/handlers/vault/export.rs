@@ -0,0 +1,312 @@
+use crate::config::MAX_VAULT_PAGE_BYTES;
+use crate::helpers::user_helpers::verify_owner;
+use crate::helpers::vault_helpers::page_digest;
+use crate::storage::{
+    CHAT_JOBS, CHATS, FILE_CONTENT_CHUNKS, FILES_CONTENT, FILES_METADATA, FOLDERS, MESSAGES,
+    UPLOAD_SESSIONS, get_config,
+};
+use gpt_types::{
+    api::{
+        ExportVaultPageRequest, ExportVaultPageResponse, ExportVaultPageResult,
+        GetVaultManifestResponse, GetVaultManifestResult, VAULT_ARCHIVE_FORMAT_VERSION,
+        VaultFileContent, VaultManifest, VaultPage, VaultPageCursor, VaultRecord, VaultRecordKey,
+        VaultSection, VaultSectionCount,
+    },
+    domain::FileId,
+    error::{CanisterError, CanisterResult},
+};
+use ic_cdk_macros::query;
+use std::collections::HashSet;
+use std::ops::Bound::{Excluded, Unbounded};
+
+/// Describes the archive that `export_vault_page` produces. Export while no chat is
+/// generating: records written in between change the counts and the import will not
+/// commit.
+#[query]
+pub fn get_vault_manifest() -> GetVaultManifestResult {
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    let config = get_config();
+    let (Some(enc_salt), Some(enc_validator)) = (config.enc_salt, config.enc_validator) else {
+        return Err(CanisterError::InvalidInput(
+            "Vault is not set up.".to_string(),
+        ));
+    };
+
+    let sections = VaultSection::ALL
+        .iter()
+        .map(|section| VaultSectionCount {
+            section: *section,
+            record_count: section_record_count(*section),
+        })
+        .collect();
+
+    Ok(GetVaultManifestResponse {
+        manifest: VaultManifest {
+            format_version: VAULT_ARCHIVE_FORMAT_VERSION,
+            exported_at: ic_cdk::api::time(),
+            root_folder_id: config.root_folder_id,
+            enc_salt,
+            enc_validator,
+            sections,
+            retention_policy: config.retention_policy,
+        },
+    })
+}
+
+/// Returns the next page of a section, resuming after `cursor`, in pages whose encoded
+/// size stays within `MAX_VAULT_PAGE_BYTES`. A page always carries at least one record.
+#[query]
+pub fn export_vault_page(req: ExportVaultPageRequest) -> ExportVaultPageResult {
+    ic_cdk::println!("export_vault_page called with request: {:?}", req);
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    let total = section_record_count(req.section);
+    let offset = req.cursor.map_or(0, |cursor| cursor.offset);
+    if offset > total {
+        return Err(CanisterError::InvalidInput(format!(
+            "Offset {} is past the end of the {:?} section ({} records).",
+            offset, req.section, total
+        )));
+    }
+
+    let after = req.cursor.map(|cursor| cursor.after);
+    let (records, last_key) = with_section_records(req.section, after, collect_page)?;
+    let end = offset + records.len() as u64;
+    let next_cursor = last_key
+        .filter(|_| end < total)
+        .map(|after| VaultPageCursor { offset: end, after });
+
+    Ok(ExportVaultPageResponse {
+        page: VaultPage {
+            section: req.section,
+            offset,
+            sha256: page_digest(&records)?,
+            records,
+        },
+        next_cursor,
+    })
+}
+
+/// Collects one page of `records` and returns it with the key of its last record.
+fn collect_page(
+    records: &mut dyn Iterator<Item = (VaultRecordKey, VaultRecord)>,
+) -> CanisterResult<(Vec<VaultRecord>, Option<VaultRecordKey>)> {
+    let mut page = Vec::new();
+    let mut page_bytes = 0usize;
+    let mut last_key = None;
+    for (key, record) in records {
+        let record_bytes = candid::encode_one(&record)
+            .map_err(|e| CanisterError::Other(format!("Failed to encode record: {}", e)))?
+            .len();
+        if !page.is_empty() && page_bytes + record_bytes > MAX_VAULT_PAGE_BYTES {
+            break;
+        }
+        page_bytes += record_bytes;
+        page.push(record);
+        last_key = Some(key);
+    }
+    Ok((page, last_key))
+}
+
+/// Counts the records of a section from the keys alone; no record is decoded.
+fn section_record_count(section: VaultSection) -> u64 {
+    match section {
+        VaultSection::Chats => CHATS.with(|c| c.borrow().len()),
+        VaultSection::Messages => MESSAGES.with(|m| m.borrow().len()),
+        VaultSection::Jobs => CHAT_JOBS.with(|j| j.borrow().len()),
+        VaultSection::Folders => FOLDERS.with(|f| f.borrow().len()),
+        VaultSection::Files => FILES_METADATA.with(|f| f.borrow().len()),
+        VaultSection::FileContents => {
+            let uploading = uploading_file_ids();
+            let whole = FILES_CONTENT.with(|fc| fc.borrow().len());
+            let chunked = FILE_CONTENT_CHUNKS.with(|c| {
+                c.borrow()
+                    .keys()
+                    .filter(|(file_id, _)| !uploading.contains(file_id))
+                    .count() as u64
+            });
+            whole + chunked
+        }
+    }
+}
+
+/// Files whose upload was never committed; their chunks are not part of the archive.
+fn uploading_file_ids() -> HashSet<FileId> {
+    UPLOAD_SESSIONS.with(|s| s.borrow().keys().collect())
+}
+
+/// Runs `visit` over the records of a section stored after `after`, in key order, with
+/// their keys. Only the records `visit` consumes are read. File contents list whole
+/// files first, then the chunks of chunked uploads.
+fn with_section_records<R>(
+    section: VaultSection,
+    after: Option<VaultRecordKey>,
+    visit: impl FnOnce(&mut dyn Iterator<Item = (VaultRecordKey, VaultRecord)>) -> CanisterResult<R>,
+) -> CanisterResult<R> {
+    let start = after.map_or(Unbounded, |key| Excluded(key.id));
+    let key = |id| VaultRecordKey {
+        id,
+        chunk_index: None,
+    };
+    match section {
+        VaultSection::Chats => CHATS.with(|c| {
+            let chats = c.borrow();
+            visit(
+                &mut chats
+                    .range((start, Unbounded))
+                    .map(|entry| (key(*entry.key()), VaultRecord::Chat(entry.value().0))),
+            )
+        }),
+        VaultSection::Messages => MESSAGES.with(|m| {
+            let messages = m.borrow();
+            visit(
+                &mut messages
+                    .range((start, Unbounded))
+                    .map(|entry| (key(*entry.key()), VaultRecord::Message(entry.value().0))),
+            )
+        }),
+        VaultSection::Jobs => CHAT_JOBS.with(|j| {
+            let jobs = j.borrow();
+            visit(
+                &mut jobs
+                    .range((start, Unbounded))
+                    .map(|entry| (key(*entry.key()), VaultRecord::Job(entry.value().0))),
+            )
+        }),
+        VaultSection::Folders => FOLDERS.with(|f| {
+            let folders = f.borrow();
+            visit(
+                &mut folders
+                    .range((start, Unbounded))
+                    .map(|entry| (key(*entry.key()), VaultRecord::Folder(entry.value().0))),
+            )
+        }),
+        VaultSection::Files => FILES_METADATA.with(|f| {
+            let files = f.borrow();
+            visit(
+                &mut files
+                    .range((start, Unbounded))
+                    .map(|entry| (key(*entry.key()), VaultRecord::File(entry.value().0))),
+            )
+        }),
+        VaultSection::FileContents => FILES_CONTENT.with(|fc| {
+            FILE_CONTENT_CHUNKS.with(|c| {
+                let uploading = uploading_file_ids();
+                let (contents, chunks) = (fc.borrow(), c.borrow());
+                // A cursor on a chunk means every whole file has been exported.
+                let (whole_start, chunk_start) = match after {
+                    None => (Unbounded, Unbounded),
+                    Some(VaultRecordKey {
+                        id,
+                        chunk_index: None,
+                    }) => (Excluded(id), Unbounded),
+                    Some(VaultRecordKey {
+                        id,
+                        chunk_index: Some(index),
+                    }) => (Excluded(FileId::MAX), Excluded((id, index))),
+                };
+                let whole =
+                    contents
+                        .range((whole_start, Unbounded))
+                        .map(|entry| VaultFileContent {
+                            file_id: *entry.key(),
+                            chunk_index: None,
+                            data: entry.value().0,
+                        });
+                let chunked = chunks
+                    .range((chunk_start, Unbounded))
+                    .filter(|entry| !uploading.contains(&entry.key().0))
+                    .map(|entry| VaultFileContent {
+                        file_id: entry.key().0,
+                        chunk_index: Some(entry.key().1),
+                        data: entry.value().0,
+                    });
+                visit(&mut whole.chain(chunked).map(|content| {
+                    let key = VaultRecordKey {
+                        id: content.file_id,
+                        chunk_index: content.chunk_index,
+                    };
+                    (key, VaultRecord::FileContent(content))
+                }))
+            })
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{CandidWrapper, UploadSession, test_support};
+
+    /// Walks a section `page_size` records at a time, resuming each page after the
+    /// last key of the one before.
+    fn walk_section(section: VaultSection, page_size: usize) -> Vec<VaultRecordKey> {
+        let mut keys = Vec::new();
+        let mut after = None;
+        loop {
+            let page: Vec<VaultRecordKey> = with_section_records(section, after, |records| {
+                Ok(records.take(page_size).map(|(key, _)| key).collect())
+            })
+            .unwrap();
+            let Some(last) = page.last() else {
+                return keys;
+            };
+            after = Some(*last);
+            keys.extend(page);
+        }
+    }
+
+    #[test]
+    fn test_pages_resume_after_the_cursor_key() {
+        for id in [9, 2, 5] {
+            FILES_METADATA.with(|f| {
+                f.borrow_mut()
+                    .insert(id, CandidWrapper(test_support::file(id)))
+            });
+        }
+        let ids: Vec<u64> = walk_section(VaultSection::Files, 2)
+            .iter()
+            .map(|key| key.id)
+            .collect();
+        assert_eq!(ids, vec![2, 5, 9]);
+    }
+
+    #[test]
+    fn test_file_contents_list_whole_files_then_committed_chunks() {
+        FILES_CONTENT.with(|fc| {
+            let mut contents = fc.borrow_mut();
+            contents.insert(5, CandidWrapper(vec![5]));
+            contents.insert(2, CandidWrapper(vec![2]));
+        });
+        FILE_CONTENT_CHUNKS.with(|c| {
+            let mut chunks = c.borrow_mut();
+            for key in [(3, 1), (3, 0), (4, 0)] {
+                chunks.insert(key, CandidWrapper(vec![0]));
+            }
+        });
+        // File 4 is still uploading.
+        UPLOAD_SESSIONS.with(|s| {
+            let session = UploadSession {
+                name: "upload.bin".to_string(),
+                parent_folder_id: 0,
+                mime_type: "application/octet-stream".to_string(),
+                total_size_bytes: 2,
+                chunk_count: 2,
+                received_chunks: 1,
+                created_at: 0,
+                updated_at: 0,
+            };
+            s.borrow_mut().insert(4, CandidWrapper(session))
+        });
+
+        assert_eq!(section_record_count(VaultSection::FileContents), 4);
+        let keys: Vec<(u64, Option<u32>)> = walk_section(VaultSection::FileContents, 1)
+            .iter()
+            .map(|key| (key.id, key.chunk_index))
+            .collect();
+        assert_eq!(keys, vec![(2, None), (5, None), (3, Some(0)), (3, Some(1))]);
+    }
+}
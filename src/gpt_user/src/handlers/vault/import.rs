@@ -0,0 +1,849 @@
+use crate::handlers::file_system::utils::{get_or_create_root_folder_id, remove_content_chunks};
+use crate::helpers::retention_helpers::validate_retention_policy;
+use crate::helpers::user_helpers::verify_owner;
+use crate::helpers::vault_helpers::{is_vault_import_in_progress, verify_page};
+use crate::storage::{
+    CHAT_JOBS, CHATS, CandidWrapper, FILE_CONTENT_CHUNKS, FILES_CONTENT, FILES_METADATA,
+    FOLDER_CONTENTS_INDEX, FOLDERS, FolderContents, UPLOAD_SESSIONS, VAULT_IMPORT,
+    VAULT_IMPORT_IDS, VaultImportSession, get_next_chat_id, get_next_file_id, get_next_folder_id,
+    get_next_job_id, get_next_message_id, remove_chat, remove_message, save_chat, save_message,
+    set_vault_credentials, update_retention_policy,
+};
+use candid::Principal;
+use gpt_types::{
+    api::{
+        AbortVaultImportResponse, AbortVaultImportResult, BeginVaultImportRequest,
+        BeginVaultImportResponse, BeginVaultImportResult, CommitVaultImportResponse,
+        CommitVaultImportResult, GetVaultImportStatusResponse, GetVaultImportStatusResult,
+        ImportVaultPageRequest, ImportVaultPageResponse, ImportVaultPageResult,
+        VAULT_ARCHIVE_FORMAT_VERSION, VaultImportStatus, VaultManifest, VaultPage, VaultRecord,
+        VaultSection, VaultSectionProgress,
+    },
+    domain::{CitationSource, FolderId, GenerationStatus, JobKind},
+    error::{CanisterError, CanisterResult},
+};
+use ic_cdk::api;
+use ic_cdk_macros::{query, update};
+use std::collections::HashSet;
+
+/// Kinds of ids remapped by an import, as stored in `VAULT_IMPORT_IDS` keys.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum IdKind {
+    Chat = 0,
+    Message = 1,
+    Job = 2,
+    Folder = 3,
+    File = 4,
+}
+
+impl IdKind {
+    fn from_key(kind: u8) -> Option<IdKind> {
+        [
+            IdKind::Chat,
+            IdKind::Message,
+            IdKind::Job,
+            IdKind::Folder,
+            IdKind::File,
+        ]
+        .into_iter()
+        .find(|k| *k as u8 == kind)
+    }
+}
+
+/// Starts importing an archive produced by `get_vault_manifest` and `export_vault_page`.
+/// The canister must hold no chats or files yet; pages of each section are then sent
+/// in order with `import_vault_page` and the import is finished with `commit_vault_import`.
+/// The cleanup task leaves chats alone until the import ends.
+#[update]
+pub fn begin_vault_import(req: BeginVaultImportRequest) -> BeginVaultImportResult {
+    ic_cdk::println!(
+        "[VaultImport] Begin import of archive exported at {}",
+        req.manifest.exported_at
+    );
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    begin_import(req.manifest, api::time())
+}
+
+fn begin_import(manifest: VaultManifest, timestamp: u64) -> BeginVaultImportResult {
+    if manifest.format_version != VAULT_ARCHIVE_FORMAT_VERSION {
+        return Err(CanisterError::InvalidInput(format!(
+            "Unsupported archive format version {} (expected {}).",
+            manifest.format_version, VAULT_ARCHIVE_FORMAT_VERSION
+        )));
+    }
+    if manifest.enc_salt.len() < 16 || manifest.enc_validator.trim().is_empty() {
+        return Err(CanisterError::InvalidInput(
+            "Archive carries no valid vault salt and validator.".to_string(),
+        ));
+    }
+    if let Some(policy) = &manifest.retention_policy {
+        validate_retention_policy(policy)?;
+    }
+    if is_vault_import_in_progress() {
+        return Err(CanisterError::InvalidInput(
+            "A vault import is already in progress.".to_string(),
+        ));
+    }
+    ensure_canister_is_empty()?;
+
+    let mut sections = Vec::with_capacity(VaultSection::ALL.len());
+    for section in VaultSection::ALL {
+        let mut counts = manifest.sections.iter().filter(|c| c.section == section);
+        let expected = counts.next().map_or(0, |c| c.record_count);
+        if counts.next().is_some() {
+            return Err(CanisterError::InvalidInput(format!(
+                "Manifest lists the {:?} section more than once.",
+                section
+            )));
+        }
+        sections.push(VaultSectionProgress {
+            section,
+            imported: 0,
+            expected,
+        });
+    }
+
+    // The archive's home folder becomes this canister's home folder.
+    let root_folder_id = get_or_create_root_folder_id()?;
+    VAULT_IMPORT_IDS.with(|ids| {
+        let mut ids = ids.borrow_mut();
+        ids.clear_new();
+        if let Some(archive_root) = manifest.root_folder_id {
+            ids.insert((IdKind::Folder as u8, archive_root), root_folder_id);
+        }
+    });
+
+    let session = VaultImportSession {
+        manifest,
+        sections,
+        started_at: timestamp,
+        updated_at: timestamp,
+    };
+    let status = import_status(&session);
+    save_session(Some(session));
+
+    Ok(BeginVaultImportResponse { status })
+}
+
+/// Imports one exported page. Records before the section's import progress are
+/// skipped, so a page can be resent after an interrupted call; a page that starts past
+/// the progress is rejected because it would leave a gap.
+#[update]
+pub fn import_vault_page(req: ImportVaultPageRequest) -> ImportVaultPageResult {
+    let page = req.page;
+    ic_cdk::println!(
+        "[VaultImport] {:?} page at offset {} ({} records)",
+        page.section,
+        page.offset,
+        page.records.len()
+    );
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    import_page(page, caller, api::time())
+}
+
+fn import_page(page: VaultPage, owner: Principal, timestamp: u64) -> ImportVaultPageResult {
+    let mut session = get_session()?;
+    verify_page(&page)?;
+
+    let progress = session
+        .sections
+        .iter_mut()
+        .find(|p| p.section == page.section)
+        .ok_or_else(|| {
+            CanisterError::Other(format!("Import has no {:?} section.", page.section))
+        })?;
+
+    if page.offset > progress.imported {
+        return Err(CanisterError::InvalidInput(format!(
+            "{:?} page at offset {} leaves a gap; next expected offset is {}.",
+            page.section, page.offset, progress.imported
+        )));
+    }
+    let skip = (progress.imported - page.offset) as usize;
+    let new_records = page.records.len().saturating_sub(skip) as u64;
+    if progress.imported + new_records > progress.expected {
+        return Err(CanisterError::InvalidInput(format!(
+            "{:?} section holds more records than the manifest lists ({}).",
+            page.section, progress.expected
+        )));
+    }
+
+    // Everything that can fail is checked above; applying records cannot leave a page
+    // half imported.
+    let archive_root = session.manifest.root_folder_id;
+    for record in page.records.into_iter().skip(skip) {
+        import_record(record, owner, archive_root);
+    }
+
+    progress.imported += new_records;
+    session.updated_at = timestamp;
+    let status = import_status(&session);
+    save_session(Some(session));
+
+    Ok(ImportVaultPageResponse { status })
+}
+
+#[query]
+pub fn get_vault_import_status() -> GetVaultImportStatusResult {
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    let session = VAULT_IMPORT.with(|v| v.borrow().get().0.clone());
+    Ok(GetVaultImportStatusResponse {
+        status: session.as_ref().map(import_status),
+    })
+}
+
+/// Finishes an import once every section holds as many records as the manifest lists,
+/// and adopts the archive's vault salt and validator so its ciphertext can be decrypted,
+/// along with its retention policy.
+#[update]
+pub fn commit_vault_import() -> CommitVaultImportResult {
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    commit_import()
+}
+
+fn commit_import() -> CommitVaultImportResult {
+    let session = get_session()?;
+    let incomplete: Vec<String> = session
+        .sections
+        .iter()
+        .filter(|p| p.imported != p.expected)
+        .map(|p| format!("{:?} {}/{}", p.section, p.imported, p.expected))
+        .collect();
+    if !incomplete.is_empty() {
+        return Err(CanisterError::InvalidInput(format!(
+            "Import is incomplete: {}.",
+            incomplete.join(", ")
+        )));
+    }
+
+    let status = import_status(&session);
+    if let Some(policy) = session.manifest.retention_policy {
+        update_retention_policy(policy);
+    }
+    set_vault_credentials(session.manifest.enc_salt, session.manifest.enc_validator);
+    VAULT_IMPORT_IDS.with(|ids| ids.borrow_mut().clear_new());
+    save_session(None);
+
+    ic_cdk::println!(
+        "[VaultImport] Committed import started at {}.",
+        session.started_at
+    );
+    Ok(CommitVaultImportResponse { status })
+}
+
+/// Cancels an import and deletes every record it created.
+#[update]
+pub fn abort_vault_import() -> AbortVaultImportResult {
+    let caller = ic_cdk::api::msg_caller();
+    verify_owner(caller)?;
+
+    abort_import()
+}
+
+fn abort_import() -> AbortVaultImportResult {
+    get_session()?;
+    let root_folder_id = get_or_create_root_folder_id()?;
+
+    let created: Vec<(IdKind, u64)> = VAULT_IMPORT_IDS.with(|ids| {
+        ids.borrow()
+            .iter()
+            .filter_map(|entry| Some((IdKind::from_key(entry.key().0)?, entry.value())))
+            .filter(|(kind, id)| !(*kind == IdKind::Folder && *id == root_folder_id))
+            .collect()
+    });
+
+    let mut removed_folders = HashSet::new();
+    let mut removed_files = HashSet::new();
+    for (kind, id) in created {
+        match kind {
+            IdKind::Chat => {
+                remove_chat(id);
+            }
+            IdKind::Message => {
+                remove_message(id);
+            }
+            IdKind::Job => {
+                CHAT_JOBS.with(|j| j.borrow_mut().remove(&id));
+            }
+            IdKind::Folder => {
+                FOLDERS.with(|f| f.borrow_mut().remove(&id));
+                FOLDER_CONTENTS_INDEX.with(|idx| idx.borrow_mut().remove(&id));
+                removed_folders.insert(id);
+            }
+            IdKind::File => {
+                FILES_METADATA.with(|f| f.borrow_mut().remove(&id));
+                FILES_CONTENT.with(|fc| fc.borrow_mut().remove(&id));
+                remove_content_chunks(id);
+                removed_files.insert(id);
+            }
+        }
+    }
+
+    FOLDER_CONTENTS_INDEX.with(|idx| {
+        let mut index_mut = idx.borrow_mut();
+        if let Some(mut root_contents) = index_mut.get(&root_folder_id).map(|w| w.0) {
+            root_contents
+                .child_folder_ids
+                .retain(|id| !removed_folders.contains(id));
+            root_contents
+                .child_file_ids
+                .retain(|id| !removed_files.contains(id));
+            index_mut.insert(root_folder_id, CandidWrapper(root_contents));
+        }
+    });
+
+    VAULT_IMPORT_IDS.with(|ids| ids.borrow_mut().clear_new());
+    save_session(None);
+
+    ic_cdk::println!("[VaultImport] Aborted import.");
+    Ok(AbortVaultImportResponse)
+}
+
+fn ensure_canister_is_empty() -> CanisterResult<()> {
+    let has_chats = CHATS.with(|c| !c.borrow().is_empty());
+    let has_files = FILES_METADATA.with(|f| !f.borrow().is_empty())
+        || UPLOAD_SESSIONS.with(|s| !s.borrow().is_empty());
+    // The home folder may already exist.
+    let has_folders = FOLDERS.with(|f| f.borrow().len()) > 1;
+    if has_chats || has_files || has_folders {
+        return Err(CanisterError::InvalidInput(
+            "Vault imports need a canister without chats, files or folders.".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn get_session() -> CanisterResult<VaultImportSession> {
+    VAULT_IMPORT
+        .with(|v| v.borrow().get().0.clone())
+        .ok_or(CanisterError::InvalidInput(
+            "No vault import in progress.".to_string(),
+        ))
+}
+
+fn save_session(session: Option<VaultImportSession>) {
+    VAULT_IMPORT.with(|v| {
+        let _ = v.borrow_mut().set(CandidWrapper(session));
+    });
+}
+
+fn import_status(session: &VaultImportSession) -> VaultImportStatus {
+    VaultImportStatus {
+        exported_at: session.manifest.exported_at,
+        started_at: session.started_at,
+        updated_at: session.updated_at,
+        sections: session.sections.clone(),
+    }
+}
+
+/// Returns the id assigned to an archive id, allocating one on first sight.
+/// Records may reference ids of sections that are imported later, so ids are handed
+/// out wherever they first appear.
+fn remap(kind: IdKind, archive_id: u64) -> u64 {
+    let key = (kind as u8, archive_id);
+    if let Some(id) = VAULT_IMPORT_IDS.with(|ids| ids.borrow().get(&key)) {
+        return id;
+    }
+    let id = match kind {
+        IdKind::Chat => get_next_chat_id(),
+        IdKind::Message => get_next_message_id(),
+        IdKind::Job => get_next_job_id(),
+        IdKind::Folder => get_next_folder_id(),
+        IdKind::File => get_next_file_id(),
+    };
+    VAULT_IMPORT_IDS.with(|ids| ids.borrow_mut().insert(key, id));
+    id
+}
+
+fn import_record(record: VaultRecord, owner: Principal, archive_root: Option<FolderId>) {
+    match record {
+        VaultRecord::Chat(mut chat) => {
+            chat.chat_id = remap(IdKind::Chat, chat.chat_id);
+            chat.owner = owner;
+            chat.message_ids = chat
+                .message_ids
+                .iter()
+                .map(|id| remap(IdKind::Message, *id))
+                .collect();
+            chat.job_ids = chat
+                .job_ids
+                .iter()
+                .map(|id| remap(IdKind::Job, *id))
+                .collect();
+            // Generations in flight at export time are not resumed.
+            chat.active_job_id = None;
+            chat.active_leaf_message_id = chat
+                .active_leaf_message_id
+                .map(|id| remap(IdKind::Message, id));
+            save_chat(chat);
+        }
+        VaultRecord::Message(mut message) => {
+            message.message_id = remap(IdKind::Message, message.message_id);
+            message.chat_id = remap(IdKind::Chat, message.chat_id);
+            message.parent_message_id = message
+                .parent_message_id
+                .map(|id| remap(IdKind::Message, id));
+            message.attached_file_ids = message
+                .attached_file_ids
+                .map(|ids| ids.into_iter().map(|id| remap(IdKind::File, id)).collect());
+            for citation in &mut message.citations {
+                if let CitationSource::FileChunk { file_id, .. } = &mut citation.source {
+                    *file_id = remap(IdKind::File, *file_id);
+                }
+            }
+            save_message(message);
+        }
+        VaultRecord::Job(mut job) => {
+            job.job_id = remap(IdKind::Job, job.job_id);
+            job.chat_id = remap(IdKind::Chat, job.chat_id);
+            job.placeholder_message_id = remap(IdKind::Message, job.placeholder_message_id);
+            if matches!(
+                job.generation_status,
+                GenerationStatus::Pending | GenerationStatus::InProgress
+            ) {
+                job.generation_status = GenerationStatus::Failed;
+            }
+            // Keys wrapped for the exporting canister's nodes are of no use here.
+            job.failover_chat_keys.clear();
+            job.file_keys = None;
+            if let Some(retrieval) = &mut job.retrieval {
+                retrieval.file_ids = retrieval
+                    .file_ids
+                    .iter()
+                    .map(|id| remap(IdKind::File, *id))
+                    .collect();
+                retrieval.folder_ids = retrieval
+                    .folder_ids
+                    .iter()
+                    .map(|id| remap(IdKind::Folder, *id))
+                    .collect();
+                retrieval.file_keys.clear();
+            }
+            match &mut job.kind {
+                Some(JobKind::EmbeddingBatch(batch)) => {
+                    batch.target_file_id = batch.target_file_id.map(|id| remap(IdKind::File, id));
+                }
+                Some(JobKind::FileIngestion(ingestion)) => {
+                    ingestion.file_id = remap(IdKind::File, ingestion.file_id);
+                }
+                Some(JobKind::Chat) | None => {}
+            }
+            CHAT_JOBS.with(|j| j.borrow_mut().insert(job.job_id, CandidWrapper(job)));
+        }
+        VaultRecord::Folder(mut folder) => {
+            if Some(folder.id) == archive_root {
+                return;
+            }
+            folder.id = remap(IdKind::Folder, folder.id);
+            folder.parent_folder_id = remap(IdKind::Folder, folder.parent_folder_id);
+            folder.owner = owner;
+            FOLDER_CONTENTS_INDEX.with(|idx| {
+                let mut index_mut = idx.borrow_mut();
+                let mut parent_contents = index_mut
+                    .get(&folder.parent_folder_id)
+                    .map(|w| w.0.clone())
+                    .unwrap_or_default();
+                parent_contents.child_folder_ids.push(folder.id);
+                index_mut.insert(folder.parent_folder_id, CandidWrapper(parent_contents));
+
+                // Files of this folder may have been imported before it.
+                if !index_mut.contains_key(&folder.id) {
+                    index_mut.insert(folder.id, CandidWrapper(FolderContents::default()));
+                }
+            });
+            FOLDERS.with(|f| f.borrow_mut().insert(folder.id, CandidWrapper(folder)));
+        }
+        VaultRecord::File(mut file) => {
+            file.id = remap(IdKind::File, file.id);
+            file.parent_folder_id = remap(IdKind::Folder, file.parent_folder_id);
+            file.owner = owner;
+            FOLDER_CONTENTS_INDEX.with(|idx| {
+                let mut index_mut = idx.borrow_mut();
+                let mut parent_contents = index_mut
+                    .get(&file.parent_folder_id)
+                    .map(|w| w.0.clone())
+                    .unwrap_or_default();
+                parent_contents.child_file_ids.push(file.id);
+                index_mut.insert(file.parent_folder_id, CandidWrapper(parent_contents));
+            });
+            FILES_METADATA.with(|f| f.borrow_mut().insert(file.id, CandidWrapper(file)));
+        }
+        VaultRecord::FileContent(content) => {
+            let file_id = remap(IdKind::File, content.file_id);
+            match content.chunk_index {
+                Some(index) => FILE_CONTENT_CHUNKS.with(|c| {
+                    c.borrow_mut()
+                        .insert((file_id, index), CandidWrapper(content.data))
+                }),
+                None => FILES_CONTENT
+                    .with(|fc| fc.borrow_mut().insert(file_id, CandidWrapper(content.data))),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::vault_helpers::page_digest;
+    use crate::storage::{MESSAGES, get_config, set_root_folder_id, test_support};
+    use gpt_types::api::VaultSectionCount;
+    use gpt_types::domain::{
+        Citation, EmbeddingBatch, FileIngestion, FileMetadata, Job, Message, NodeChatKey,
+        RetentionPolicy, Retrieval, Role,
+    };
+
+    const ARCHIVE_FILE_ID: u64 = 40;
+    const ARCHIVE_FOLDER_ID: u64 = 1;
+
+    fn archive_file() -> FileMetadata {
+        FileMetadata {
+            name: "report.pdf".to_string(),
+            parent_folder_id: ARCHIVE_FOLDER_ID,
+            mime_type: "application/pdf".to_string(),
+            content_size_bytes: 2048,
+            ..test_support::file(ARCHIVE_FILE_ID)
+        }
+    }
+
+    fn archive_message() -> Message {
+        Message {
+            chat_id: 60,
+            parent_message_id: None,
+            role: Role::Assistant,
+            attached_file_ids: Some(vec![ARCHIVE_FILE_ID]),
+            citations: vec![
+                Citation {
+                    source: CitationSource::FileChunk {
+                        file_id: ARCHIVE_FILE_ID,
+                        chunk_index: 2,
+                    },
+                    span: None,
+                },
+                Citation {
+                    source: CitationSource::ToolResult {
+                        tool_call_id: "call_1".to_string(),
+                    },
+                    span: None,
+                },
+            ],
+            ..test_support::message(50)
+        }
+    }
+
+    fn archive_job(job_id: u64, kind: JobKind) -> Job {
+        Job {
+            job_id,
+            chat_id: 60,
+            generation_status: GenerationStatus::InProgress,
+            temperature: 0.0,
+            max_completion_tokens: 0,
+            max_context: 0,
+            model_id: "embedder".to_string(),
+            node_id: 1,
+            placeholder_message_id: 0,
+            custom_prompt: None,
+            created_at: 0,
+            updated_at: 0,
+            tools: None,
+            extra_body_json: None,
+            reasoning_effort: None,
+            encrypted_chat_key: None,
+            retry_count: 0,
+            node_history: Vec::new(),
+            failover_chat_keys: Vec::new(),
+            context_strategy: None,
+            response_schema: None,
+            kind: Some(kind),
+            retrieval: None,
+            file_keys: None,
+        }
+    }
+
+    fn archive_policy() -> RetentionPolicy {
+        RetentionPolicy {
+            chat_ttl_secs: None,
+            archived_chat_ttl_secs: None,
+            temporary_chat_ttl_secs: 60 * 60,
+        }
+    }
+
+    /// Creates the home folder and starts an import of an archive with these sections.
+    fn begin(sections: &[(VaultSection, u64)]) -> FolderId {
+        let root_folder_id = get_next_folder_id();
+        set_root_folder_id(root_folder_id);
+        FOLDER_CONTENTS_INDEX.with(|idx| {
+            idx.borrow_mut()
+                .insert(root_folder_id, CandidWrapper(FolderContents::default()))
+        });
+        let manifest = VaultManifest {
+            format_version: VAULT_ARCHIVE_FORMAT_VERSION,
+            exported_at: 1,
+            root_folder_id: Some(ARCHIVE_FOLDER_ID),
+            enc_salt: vec![7; 16],
+            enc_validator: "archive validator".to_string(),
+            sections: sections
+                .iter()
+                .map(|(section, record_count)| VaultSectionCount {
+                    section: *section,
+                    record_count: *record_count,
+                })
+                .collect(),
+            retention_policy: Some(archive_policy()),
+        };
+        begin_import(manifest, 2).unwrap();
+        root_folder_id
+    }
+
+    fn send(
+        section: VaultSection,
+        offset: u64,
+        records: Vec<VaultRecord>,
+    ) -> ImportVaultPageResult {
+        let page = VaultPage {
+            section,
+            offset,
+            sha256: page_digest(&records).unwrap(),
+            records,
+        };
+        import_page(page, Principal::anonymous(), 3)
+    }
+
+    fn imported(section: VaultSection) -> u64 {
+        let session = get_session().unwrap();
+        let progress = session.sections.iter().find(|p| p.section == section);
+        progress.unwrap().imported
+    }
+
+    fn imported_job(archive_job_id: u64) -> Job {
+        let job_id = remap(IdKind::Job, archive_job_id);
+        CHAT_JOBS.with(|j| j.borrow().get(&job_id).unwrap().0)
+    }
+
+    fn imported_message() -> Message {
+        import_record(
+            VaultRecord::Message(archive_message()),
+            Principal::anonymous(),
+            None,
+        );
+        let message_id = remap(IdKind::Message, 50);
+        MESSAGES.with(|m| m.borrow().get(&message_id).unwrap().0)
+    }
+
+    fn import_job(job: Job) {
+        import_record(VaultRecord::Job(job), Principal::anonymous(), None);
+    }
+
+    #[test]
+    fn test_imported_file_gets_a_new_id() {
+        import_record(
+            VaultRecord::File(archive_file()),
+            Principal::anonymous(),
+            None,
+        );
+
+        let file_id = remap(IdKind::File, ARCHIVE_FILE_ID);
+        let file = FILES_METADATA.with(|f| f.borrow().get(&file_id).unwrap().0);
+        assert_eq!(file.id, file_id);
+        assert_eq!(file.name, "report.pdf");
+    }
+
+    #[test]
+    fn test_imported_message_attaches_imported_files() {
+        let message = imported_message();
+        assert_eq!(
+            message.attached_file_ids,
+            Some(vec![remap(IdKind::File, ARCHIVE_FILE_ID)])
+        );
+    }
+
+    #[test]
+    fn test_imported_file_chunk_citation_cites_the_imported_file() {
+        let message = imported_message();
+        assert_eq!(
+            message.citations[0].source,
+            CitationSource::FileChunk {
+                file_id: remap(IdKind::File, ARCHIVE_FILE_ID),
+                chunk_index: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_imported_tool_result_citation_is_unchanged() {
+        let message = imported_message();
+        assert_eq!(
+            message.citations[1].source,
+            archive_message().citations[1].source
+        );
+    }
+
+    #[test]
+    fn test_imported_retrieval_searches_imported_files_and_folders() {
+        let mut job = archive_job(72, JobKind::Chat);
+        job.retrieval = Some(Retrieval {
+            file_ids: vec![ARCHIVE_FILE_ID],
+            folder_ids: vec![ARCHIVE_FOLDER_ID, 8],
+            embedding_model_id: "embedder".to_string(),
+            top_k: 4,
+            max_passage_tokens: 1000,
+            mmr_lambda: None,
+            file_keys: Vec::new(),
+        });
+        import_job(job);
+
+        let retrieval = imported_job(72).retrieval.unwrap();
+        assert_eq!(
+            retrieval.file_ids,
+            vec![remap(IdKind::File, ARCHIVE_FILE_ID)]
+        );
+        assert_eq!(
+            retrieval.folder_ids,
+            vec![
+                remap(IdKind::Folder, ARCHIVE_FOLDER_ID),
+                remap(IdKind::Folder, 8)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_imported_job_drops_file_keys_wrapped_for_other_nodes() {
+        let key = NodeChatKey {
+            node_id: 1,
+            encrypted_chat_key: "key".to_string(),
+        };
+        let mut job = archive_job(72, JobKind::Chat);
+        job.retrieval = Some(Retrieval {
+            file_ids: vec![ARCHIVE_FILE_ID],
+            folder_ids: Vec::new(),
+            embedding_model_id: "embedder".to_string(),
+            top_k: 4,
+            max_passage_tokens: 1000,
+            mmr_lambda: None,
+            file_keys: vec![key.clone()],
+        });
+        job.file_keys = Some(vec![key]);
+        import_job(job);
+
+        let job = imported_job(72);
+        assert!(job.retrieval.unwrap().file_keys.is_empty());
+        assert!(job.file_keys.is_none());
+    }
+
+    #[test]
+    fn test_imported_ingestion_job_ingests_the_imported_file() {
+        let ingestion = FileIngestion {
+            file_id: ARCHIVE_FILE_ID,
+            chunk_size_chars: 1000,
+            chunk_overlap_chars: 100,
+        };
+        import_job(archive_job(70, JobKind::FileIngestion(ingestion)));
+
+        let file_id = remap(IdKind::File, ARCHIVE_FILE_ID);
+        assert!(matches!(
+            imported_job(70).kind,
+            Some(JobKind::FileIngestion(FileIngestion { file_id: id, .. })) if id == file_id
+        ));
+    }
+
+    #[test]
+    fn test_imported_embedding_batch_targets_the_imported_file() {
+        let batch = EmbeddingBatch {
+            input_count: 3,
+            target_file_id: Some(ARCHIVE_FILE_ID),
+        };
+        import_job(archive_job(71, JobKind::EmbeddingBatch(batch)));
+
+        let file_id = remap(IdKind::File, ARCHIVE_FILE_ID);
+        assert!(matches!(
+            imported_job(71).kind,
+            Some(JobKind::EmbeddingBatch(EmbeddingBatch { target_file_id: Some(id), .. }))
+                if id == file_id
+        ));
+    }
+
+    #[test]
+    fn test_job_in_flight_at_export_is_imported_as_failed() {
+        import_job(archive_job(72, JobKind::Chat));
+        assert_eq!(imported_job(72).generation_status, GenerationStatus::Failed);
+    }
+
+    #[test]
+    fn test_commit_adopts_the_archive_vault_and_retention_policy() {
+        let root_folder_id = begin(&[(VaultSection::Files, 1)]);
+        assert!(get_config().retention_policy.is_none());
+
+        let files = vec![VaultRecord::File(archive_file())];
+        send(VaultSection::Files, 0, files).unwrap();
+        let file_id = remap(IdKind::File, ARCHIVE_FILE_ID);
+        commit_import().unwrap();
+
+        let config = get_config();
+        assert_eq!(config.retention_policy, Some(archive_policy()));
+        assert_eq!(config.enc_salt, Some(vec![7; 16]));
+        assert!(!is_vault_import_in_progress());
+        let root_contents =
+            FOLDER_CONTENTS_INDEX.with(|idx| idx.borrow().get(&root_folder_id).unwrap().0);
+        assert_eq!(root_contents.child_file_ids, vec![file_id]);
+    }
+
+    #[test]
+    fn test_commit_refuses_an_incomplete_import() {
+        begin(&[(VaultSection::Files, 2)]);
+        let files = vec![VaultRecord::File(archive_file())];
+        send(VaultSection::Files, 0, files).unwrap();
+
+        assert!(commit_import().is_err());
+        assert!(is_vault_import_in_progress());
+        assert!(get_config().retention_policy.is_none());
+    }
+
+    #[test]
+    fn test_abort_removes_imported_records_and_keeps_the_retention_policy() {
+        let own_policy = RetentionPolicy::default();
+        update_retention_policy(own_policy.clone());
+        let root_folder_id = begin(&[(VaultSection::Files, 1)]);
+        let files = vec![VaultRecord::File(archive_file())];
+        send(VaultSection::Files, 0, files).unwrap();
+
+        abort_import().unwrap();
+
+        assert!(!is_vault_import_in_progress());
+        assert!(FILES_METADATA.with(|f| f.borrow().is_empty()));
+        let root_contents =
+            FOLDER_CONTENTS_INDEX.with(|idx| idx.borrow().get(&root_folder_id).unwrap().0);
+        assert!(root_contents.child_file_ids.is_empty());
+        assert_eq!(get_config().retention_policy, Some(own_policy));
+    }
+
+    #[test]
+    fn test_page_past_the_import_progress_is_rejected() {
+        begin(&[(VaultSection::Messages, 2)]);
+        let page = vec![VaultRecord::Message(test_support::message(2))];
+
+        assert!(send(VaultSection::Messages, 1, page).is_err());
+        assert_eq!(imported(VaultSection::Messages), 0);
+        assert!(MESSAGES.with(|m| m.borrow().is_empty()));
+    }
+
+    #[test]
+    fn test_resent_page_skips_records_already_imported() {
+        begin(&[(VaultSection::Messages, 2)]);
+        let first = VaultRecord::Message(test_support::message(1));
+        let second = VaultRecord::Message(test_support::message(2));
+        send(VaultSection::Messages, 0, vec![first.clone()]).unwrap();
+
+        send(VaultSection::Messages, 0, vec![first, second]).unwrap();
+
+        assert_eq!(imported(VaultSection::Messages), 2);
+        assert_eq!(MESSAGES.with(|m| m.borrow().len()), 2);
+    }
+}
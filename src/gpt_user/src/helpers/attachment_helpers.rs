@@ -0,0 +1,179 @@
+use std::collections::BTreeSet;
+
+use candid::Principal;
+use gpt_types::{
+    domain::{FileId, MessageId, ModelId, NodeChatKey, NodeId},
+    error::{CanisterError, CanisterResult},
+};
+
+use crate::config::{
+    ALLOWED_IMAGE_MIME_TYPES, MAX_ATTACHED_FILE_BYTES, MAX_ATTACHED_FILES, MAX_FAILOVER_CHAT_KEYS,
+};
+use crate::helpers::message_helpers::build_message_chain;
+use crate::storage::{FILES_METADATA, MESSAGES, MODELS, StorableString};
+
+/// Checks the files a message attaches: they must belong to `owner`, fit the size
+/// limit, and include no more images than `model_id` accepts.
+pub fn validate_attached_files(
+    file_ids: &Option<Vec<FileId>>,
+    owner: Principal,
+    model_id: &ModelId,
+) -> CanisterResult<()> {
+    let Some(file_ids) = file_ids else {
+        return Ok(());
+    };
+    if file_ids.len() > MAX_ATTACHED_FILES {
+        return Err(CanisterError::InvalidInput(format!(
+            "A message can attach at most {} files.",
+            MAX_ATTACHED_FILES
+        )));
+    }
+    if file_ids.iter().collect::<BTreeSet<_>>().len() != file_ids.len() {
+        return Err(CanisterError::InvalidInput(
+            "A file is attached more than once.".to_string(),
+        ));
+    }
+
+    let mut image_count = 0;
+    for file_id in file_ids {
+        let file = FILES_METADATA
+            .with(|f| f.borrow().get(file_id).map(|w| w.0))
+            .ok_or(CanisterError::FileNotFound)?;
+        if file.owner != owner {
+            return Err(CanisterError::Unauthorized);
+        }
+        if file.content_size_bytes > MAX_ATTACHED_FILE_BYTES {
+            return Err(CanisterError::FileSystemLimitExceeded(format!(
+                "File {} is larger than the attachment limit of {} bytes.",
+                file_id, MAX_ATTACHED_FILE_BYTES
+            )));
+        }
+        if ALLOWED_IMAGE_MIME_TYPES.contains(&file.mime_type.as_str()) {
+            image_count += 1;
+        }
+    }
+
+    if image_count > 0 {
+        let model = MODELS
+            .with(|m| {
+                m.borrow()
+                    .get(&StorableString(model_id.clone()))
+                    .map(|w| w.0.clone())
+            })
+            .ok_or(CanisterError::ModelNotFound)?;
+        if image_count > model.max_image_attachments {
+            return Err(CanisterError::InvalidInput(format!(
+                "Number of attached images ({}) exceeds the model's limit of {}.",
+                image_count, model.max_image_attachments
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The files attached by the messages of `chain`, without duplicates.
+pub fn chain_attached_file_ids(chain: &[MessageId]) -> Vec<FileId> {
+    MESSAGES.with(|m| {
+        let messages = m.borrow();
+        chain
+            .iter()
+            .filter_map(|id| messages.get(id))
+            .flat_map(|w| w.0.attached_file_ids.unwrap_or_default())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    })
+}
+
+/// Checks the file keys of a generation on `node_id` whose chain ends with
+/// `parent_message_id` followed by a message attaching `attached_file_ids`. A key
+/// wrapped for the node is required once any message of the chain attaches files.
+pub fn resolve_file_keys(
+    file_keys: Option<Vec<NodeChatKey>>,
+    node_id: NodeId,
+    parent_message_id: Option<MessageId>,
+    attached_file_ids: &Option<Vec<FileId>>,
+) -> CanisterResult<Option<Vec<NodeChatKey>>> {
+    let attaches_files = attached_file_ids
+        .as_ref()
+        .is_some_and(|ids| !ids.is_empty())
+        || parent_message_id
+            .is_some_and(|id| !chain_attached_file_ids(&build_message_chain(id)).is_empty());
+
+    match file_keys {
+        Some(keys) => {
+            validate_file_keys(&keys, node_id)?;
+            Ok(Some(keys))
+        }
+        None if attaches_files => Err(CanisterError::InvalidInput(
+            "Attached files need a file key wrapped for the node.".to_string(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Checks that `file_keys` holds a key for `node_id` and no more keys than a job
+/// can fail over to.
+pub fn validate_file_keys(file_keys: &[NodeChatKey], node_id: NodeId) -> CanisterResult<()> {
+    if file_keys.len() > MAX_FAILOVER_CHAT_KEYS + 1 {
+        return Err(CanisterError::InvalidInput(format!(
+            "At most {} file keys are accepted.",
+            MAX_FAILOVER_CHAT_KEYS + 1
+        )));
+    }
+    if !file_keys.iter().any(|key| key.node_id == node_id) {
+        return Err(CanisterError::InvalidInput(format!(
+            "No file key is wrapped for node {}.",
+            node_id
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{CandidWrapper, test_support};
+    use gpt_types::domain::Message;
+
+    fn message(message_id: MessageId, attached_file_ids: Option<Vec<FileId>>) -> Message {
+        Message {
+            attached_file_ids,
+            ..test_support::message(message_id)
+        }
+    }
+
+    fn key(node_id: NodeId) -> NodeChatKey {
+        NodeChatKey {
+            node_id,
+            encrypted_chat_key: "key".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_file_keys_are_required_once_the_chain_attaches_files() {
+        MESSAGES.with(|m| {
+            let mut messages = m.borrow_mut();
+            messages.insert(1, CandidWrapper(message(1, None)));
+            messages.insert(2, CandidWrapper(message(2, Some(vec![7, 3]))));
+            messages.insert(3, CandidWrapper(message(3, Some(vec![3]))));
+        });
+        assert_eq!(chain_attached_file_ids(&[1, 2, 3]), vec![3, 7]);
+        assert!(chain_attached_file_ids(&[1]).is_empty());
+
+        // Nothing attached: no key needed.
+        assert!(
+            resolve_file_keys(None, 4, Some(1), &None)
+                .unwrap()
+                .is_none()
+        );
+        // Files attached by the new message or earlier in the chain need a key.
+        assert!(resolve_file_keys(None, 4, None, &Some(vec![9])).is_err());
+        assert!(resolve_file_keys(None, 4, Some(3), &None).is_err());
+        assert!(resolve_file_keys(Some(vec![key(4)]), 4, Some(3), &None).is_ok());
+        // The key must be wrapped for the job's node.
+        assert!(resolve_file_keys(Some(vec![key(5)]), 4, Some(3), &None).is_err());
+        let too_many: Vec<NodeChatKey> = (0..=MAX_FAILOVER_CHAT_KEYS as u64 + 1).map(key).collect();
+        assert!(validate_file_keys(&too_many, 4).is_err());
+    }
+}
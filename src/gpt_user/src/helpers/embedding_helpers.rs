@@ -0,0 +1,343 @@
+use candid::Principal;
+use gpt_types::{
+    domain::{
+        Chat, ChatId, EmbeddingBatch, EmbeddingInput, FileId, Job, JobId, JobKind, Message,
+        ModelId, ModelStatus, NodeChatKey, NodeId, Role, TextChunk,
+    },
+    error::{CanisterError, CanisterResult},
+};
+use serde_bytes::ByteBuf;
+
+use crate::config::{MAX_EMBEDDING_BATCH_BYTES, MAX_EMBEDDING_BATCH_INPUTS};
+use crate::helpers::generation_helpers::{
+    GenerationParams, create_generation_entities, validate_generation_request,
+};
+use crate::storage::{
+    CHAT_JOBS, CandidWrapper, EMBEDDING_BATCHES, EmbeddingBatchRecord, FILES_METADATA, MODELS,
+    StorableString, get_next_chat_id, get_next_message_id, save_chat, save_message,
+};
+
+/// The batch description of `job`, if it is an embedding batch job.
+pub fn embedding_batch(job: &Job) -> Option<&EmbeddingBatch> {
+    match &job.kind {
+        Some(JobKind::EmbeddingBatch(batch)) => Some(batch),
+        _ => None,
+    }
+}
+
+/// Creates a job of `kind` for an embedding model in a new temporary chat. The chat
+/// holds an empty user message and the placeholder that records the job's usage
+/// and errors.
+pub fn create_embedding_job(
+    owner: Principal,
+    model_id: &ModelId,
+    node_id: NodeId,
+    kind: JobKind,
+    encryption_salt: Vec<u8>,
+    encrypted_chat_key: String,
+    failover_chat_keys: Option<Vec<NodeChatKey>>,
+) -> CanisterResult<(ChatId, JobId)> {
+    let max_context = MODELS.with(|models| {
+        let m = models.borrow();
+        let model = m
+            .get(&StorableString(model_id.clone()))
+            .ok_or(CanisterError::ModelNotFound)?;
+        if model.0.status == ModelStatus::Paused {
+            return Err(CanisterError::InvalidInput(format!(
+                "Model {} is currently paused.",
+                model_id
+            )));
+        }
+        if !model.0.is_embedding {
+            return Err(CanisterError::InvalidInput(format!(
+                "Model {} is not an embedding model.",
+                model_id
+            )));
+        }
+        Ok(model.0.max_context)
+    })?;
+
+    if encryption_salt.len() != 32 {
+        return Err(CanisterError::InvalidInput(
+            "Encryption salt must be 32 bytes".to_string(),
+        ));
+    }
+    validate_generation_request(0, node_id, model_id, &None, None, &failover_chat_keys)?;
+
+    let timestamp = ic_cdk::api::time();
+    let chat_id = get_next_chat_id();
+    let user_message_id = get_next_message_id();
+
+    let user_message = Message {
+        message_id: user_message_id,
+        chat_id,
+        parent_message_id: None,
+        role: Role::User,
+        content: Vec::new(),
+        created_at: timestamp,
+        updated_at: timestamp,
+        error_status: None,
+        attachments: None,
+        attached_file_ids: None,
+        tool_calls: None,
+        tool_results: None,
+        tool_call_id: None,
+        requires_client_action: false,
+        usage: None,
+        citations: Vec::new(),
+    };
+
+    let title = match &kind {
+        JobKind::FileIngestion(ingestion) => format!("File ingestion {}", ingestion.file_id),
+        _ => format!("Embedding batch {}", chat_id),
+    };
+    let gen_params = GenerationParams {
+        chat_id,
+        user_message_id,
+        node_id,
+        model_id,
+        temperature: 0.0,
+        max_completion_tokens: 0,
+        max_context,
+        custom_prompt: None,
+        tools: None,
+        reasoning_effort: None,
+        encrypted_chat_key: Some(encrypted_chat_key),
+        failover_chat_keys: failover_chat_keys.unwrap_or_default(),
+        context_strategy: None,
+        response_schema: None,
+        kind: Some(kind),
+        retrieval: None,
+        file_keys: None,
+    };
+    let (ai_message, job) = create_generation_entities(gen_params, timestamp);
+    let ai_message_id = ai_message.message_id;
+    let job_id = job.job_id;
+
+    let chat = Chat {
+        chat_id,
+        owner,
+        title,
+        message_ids: vec![user_message_id, ai_message_id],
+        job_ids: vec![job_id],
+        active_job_id: Some(job_id),
+        created_at: timestamp,
+        updated_at: timestamp,
+        archived: false,
+        temporary: true,
+        pinned: false,
+        active_leaf_message_id: Some(ai_message_id),
+        encryption_salt,
+    };
+
+    save_message(user_message);
+    save_message(ai_message);
+    CHAT_JOBS.with(|cj| {
+        cj.borrow_mut().insert(job_id, CandidWrapper(job));
+    });
+    save_chat(chat);
+
+    Ok((chat_id, job_id))
+}
+
+/// Checks the count and total size of a batch's inputs, and their ranges when the
+/// embeddings are written to a file.
+pub fn validate_embedding_inputs(
+    inputs: &[EmbeddingInput],
+    target_file_id: Option<FileId>,
+) -> CanisterResult<()> {
+    if inputs.is_empty() || inputs.len() > MAX_EMBEDDING_BATCH_INPUTS {
+        return Err(CanisterError::InvalidInput(format!(
+            "An embedding batch must have between 1 and {} inputs.",
+            MAX_EMBEDDING_BATCH_INPUTS
+        )));
+    }
+    if inputs.iter().any(|input| input.content.is_empty()) {
+        return Err(CanisterError::InvalidInput(
+            "Embedding inputs cannot be empty.".to_string(),
+        ));
+    }
+    let total_bytes: usize = inputs.iter().map(|input| input.content.len()).sum();
+    if total_bytes > MAX_EMBEDDING_BATCH_BYTES {
+        return Err(CanisterError::InvalidInput(format!(
+            "Embedding inputs exceed the maximum total size of {} bytes.",
+            MAX_EMBEDDING_BATCH_BYTES
+        )));
+    }
+    if target_file_id.is_some() && inputs.iter().any(|input| input.start_char > input.end_char) {
+        return Err(CanisterError::InvalidInput(
+            "Embedding input ranges must not end before they start.".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Stores the inputs of a new batch job until the node has embedded them.
+pub fn save_embedding_inputs(job_id: JobId, inputs: Vec<EmbeddingInput>) {
+    let record = EmbeddingBatchRecord {
+        inputs,
+        embeddings: Vec::new(),
+    };
+    EMBEDDING_BATCHES.with(|b| b.borrow_mut().insert(job_id, CandidWrapper(record)));
+}
+
+/// The inputs of a batch job that has not ended yet.
+pub fn embedding_inputs(job_id: JobId) -> Option<Vec<EmbeddingInput>> {
+    EMBEDDING_BATCHES.with(|b| b.borrow().get(&job_id).map(|w| w.0.inputs))
+}
+
+/// Stores the embeddings of a completed batch, dropping its inputs. With a target
+/// file that still exists, the embeddings replace the file's chunks and the batch
+/// record is removed; otherwise they are kept on the record.
+pub fn store_embeddings(
+    job_id: JobId,
+    batch: &EmbeddingBatch,
+    embeddings: Vec<ByteBuf>,
+    now: u64,
+) -> CanisterResult<()> {
+    if embeddings.len() != batch.input_count as usize {
+        return Err(CanisterError::InvalidInput(format!(
+            "Expected {} embeddings, got {}.",
+            batch.input_count,
+            embeddings.len()
+        )));
+    }
+    let mut record = EMBEDDING_BATCHES
+        .with(|b| b.borrow().get(&job_id).map(|w| w.0))
+        .ok_or_else(|| CanisterError::Other(format!("Embedding batch {} has no inputs", job_id)))?;
+
+    let target_file = batch
+        .target_file_id
+        .and_then(|id| FILES_METADATA.with(|f| f.borrow().get(&id).map(|w| (id, w.0))));
+    if let Some((file_id, mut file)) = target_file {
+        file.chunks = record
+            .inputs
+            .iter()
+            .zip(embeddings)
+            .enumerate()
+            .map(|(index, (input, embedding))| TextChunk {
+                chunk_index: index as u32,
+                start_char: input.start_char,
+                end_char: input.end_char,
+                embedding: embedding.into_vec(),
+                content: None,
+            })
+            .collect();
+        file.updated_at = now;
+        FILES_METADATA.with(|f| f.borrow_mut().insert(file_id, CandidWrapper(file)));
+        remove_embedding_batches(&[job_id]);
+        return Ok(());
+    }
+
+    record.inputs = Vec::new();
+    record.embeddings = embeddings;
+    EMBEDDING_BATCHES.with(|b| b.borrow_mut().insert(job_id, CandidWrapper(record)));
+    Ok(())
+}
+
+/// The stored embeddings of a completed batch; empty while it runs, after a failure
+/// and when they were written to a file.
+pub fn stored_embeddings(job_id: JobId) -> Vec<ByteBuf> {
+    EMBEDDING_BATCHES.with(|b| {
+        b.borrow()
+            .get(&job_id)
+            .map(|w| w.0.embeddings)
+            .unwrap_or_default()
+    })
+}
+
+/// Drops the inputs and embeddings of the given jobs.
+pub fn remove_embedding_batches(job_ids: &[JobId]) {
+    EMBEDDING_BATCHES.with(|b| {
+        let mut batches = b.borrow_mut();
+        for job_id in job_ids {
+            batches.remove(job_id);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_support;
+    use gpt_types::domain::FileMetadata;
+
+    fn input(text: &str, start_char: u32) -> EmbeddingInput {
+        EmbeddingInput {
+            content: text.as_bytes().to_vec(),
+            start_char,
+            end_char: start_char + text.len() as u32,
+        }
+    }
+
+    fn file(id: FileId) -> FileMetadata {
+        FileMetadata {
+            name: "notes.md".to_string(),
+            mime_type: "text/markdown".to_string(),
+            content_size_bytes: 11,
+            ..test_support::file(id)
+        }
+    }
+
+    fn embeddings(count: u8) -> Vec<ByteBuf> {
+        (0..count).map(|i| ByteBuf::from(vec![i; 4])).collect()
+    }
+
+    #[test]
+    fn test_embeddings_replace_target_file_chunks() {
+        FILES_METADATA.with(|f| f.borrow_mut().insert(7, CandidWrapper(file(7))));
+        save_embedding_inputs(1, vec![input("hello", 0), input("world", 6)]);
+        let batch = EmbeddingBatch {
+            input_count: 2,
+            target_file_id: Some(7),
+        };
+
+        assert!(store_embeddings(1, &batch, embeddings(1), 5).is_err());
+        store_embeddings(1, &batch, embeddings(2), 5).unwrap();
+
+        let file = FILES_METADATA.with(|f| f.borrow().get(&7).unwrap().0);
+        assert_eq!(file.updated_at, 5);
+        assert_eq!(file.chunks.len(), 2);
+        assert_eq!(
+            file.chunks[1],
+            TextChunk {
+                chunk_index: 1,
+                start_char: 6,
+                end_char: 11,
+                embedding: vec![1; 4],
+                content: None,
+            }
+        );
+        assert!(EMBEDDING_BATCHES.with(|b| b.borrow().get(&1).is_none()));
+    }
+
+    #[test]
+    fn test_embeddings_without_target_stay_on_the_batch() {
+        save_embedding_inputs(2, vec![input("a", 0), input("b", 0), input("c", 0)]);
+        assert_eq!(embedding_inputs(2).map(|inputs| inputs.len()), Some(3));
+        assert!(stored_embeddings(2).is_empty());
+
+        let batch = EmbeddingBatch {
+            input_count: 3,
+            target_file_id: None,
+        };
+        store_embeddings(2, &batch, embeddings(3), 0).unwrap();
+        assert_eq!(embedding_inputs(2), Some(Vec::new()));
+        assert_eq!(stored_embeddings(2), embeddings(3));
+
+        remove_embedding_batches(&[2]);
+        assert!(stored_embeddings(2).is_empty());
+    }
+
+    #[test]
+    fn test_validate_embedding_inputs() {
+        assert!(validate_embedding_inputs(&[], None).is_err());
+        assert!(validate_embedding_inputs(&[input("", 0)], None).is_err());
+        assert!(validate_embedding_inputs(&[input("text", 0)], None).is_ok());
+
+        let mut reversed = input("text", 10);
+        reversed.end_char = 2;
+        assert!(validate_embedding_inputs(std::slice::from_ref(&reversed), None).is_ok());
+        assert!(validate_embedding_inputs(&[reversed], Some(1)).is_err());
+    }
+}
@@ -1,9 +1,12 @@
 use crate::storage::{
-    get_next_job_id, get_next_message_id, StorableString,
+    get_next_job_id, get_next_message_id, save_chat, StorableString,
     CHATS, MODELS, NODES,
 };
 use gpt_types::{
-    domain::{GenerationStatus, Job, Message, ModelId, Role, tool::Tool},
+    domain::{
+        ContextStrategy, GenerationStatus, Job, JobKind, Message, ModelId, NodeChatKey, NodeId,
+        ResponseSchema, Retrieval, Role, tool::Tool,
+    },
     error::{CanisterError, CanisterResult, MessageErrorStatus},
 };
 
@@ -19,6 +22,12 @@ pub struct GenerationParams<'a> {
     pub tools: Option<Vec<Tool>>,
     pub reasoning_effort: Option<String>,
     pub encrypted_chat_key: Option<String>,
+    pub failover_chat_keys: Vec<NodeChatKey>,
+    pub context_strategy: Option<ContextStrategy>,
+    pub response_schema: Option<ResponseSchema>,
+    pub kind: Option<JobKind>,
+    pub retrieval: Option<Retrieval>,
+    pub file_keys: Option<Vec<NodeChatKey>>,
 }
 
 pub fn create_generation_entities(params: GenerationParams, timestamp: u64) -> (Message, Job) {
@@ -34,11 +43,13 @@ pub fn create_generation_entities(params: GenerationParams, timestamp: u64) -> (
         updated_at: timestamp,
         error_status: None,
         attachments: None,
+        attached_file_ids: None,
         tool_calls: None,
         tool_results: None,
         tool_call_id: None,
         requires_client_action: false,
         usage: None,
+        citations: Vec::new(),
     };
 
     let job_id = get_next_job_id();
@@ -66,6 +77,14 @@ pub fn create_generation_entities(params: GenerationParams, timestamp: u64) -> (
         extra_body_json,
         reasoning_effort: params.reasoning_effort,
         encrypted_chat_key: params.encrypted_chat_key,
+        retry_count: 0,
+        node_history: Vec::new(),
+        failover_chat_keys: params.failover_chat_keys,
+        context_strategy: params.context_strategy,
+        response_schema: params.response_schema,
+        kind: params.kind,
+        retrieval: params.retrieval,
+        file_keys: params.file_keys,
     };
 
     (ai_msg, job)
@@ -77,6 +96,7 @@ pub fn validate_generation_request(
     model_id: &str,
     tools: &Option<Vec<Tool>>,
     custom_prompt: Option<&String>,
+    failover_chat_keys: &Option<Vec<NodeChatKey>>,
 ) -> CanisterResult<()> {
     // Check if chat has an active job (skip for new chats where chat_id is 0)
     if chat_id > 0 {
@@ -144,9 +164,66 @@ pub fn validate_generation_request(
         )));
     }
 
+    if let Some(keys) = failover_chat_keys
+        && keys.len() > crate::config::MAX_FAILOVER_CHAT_KEYS
+    {
+        return Err(CanisterError::InvalidInput(format!(
+            "At most {} failover chat keys are accepted.",
+            crate::config::MAX_FAILOVER_CHAT_KEYS
+        )));
+    }
+
     Ok(())
 }
 
+/// Checks a requested response schema: a valid name, and a JSON object within the size
+/// limit. Embedding models produce no text to validate.
+pub fn validate_response_schema(
+    response_schema: &Option<ResponseSchema>,
+    model_id: &str,
+) -> CanisterResult<()> {
+    let Some(response_schema) = response_schema else {
+        return Ok(());
+    };
+
+    let is_embedding = MODELS.with(|m| {
+        m.borrow()
+            .get(&StorableString(model_id.to_string()))
+            .is_some_and(|model| model.0.is_embedding)
+    });
+    if is_embedding {
+        return Err(CanisterError::InvalidInput(
+            "Embedding models do not support response schemas.".to_string(),
+        ));
+    }
+
+    let name = &response_schema.name;
+    if name.is_empty()
+        || name.len() > crate::config::MAX_RESPONSE_SCHEMA_NAME_CHARS
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(CanisterError::InvalidInput(format!(
+            "Response schema name must be 1-{} characters of a-z, A-Z, 0-9, '_' or '-'.",
+            crate::config::MAX_RESPONSE_SCHEMA_NAME_CHARS
+        )));
+    }
+
+    if response_schema.schema_json.len() > crate::config::MAX_RESPONSE_SCHEMA_BYTES {
+        return Err(CanisterError::InvalidInput(format!(
+            "Response schema exceeds maximum allowed size of {} bytes.",
+            crate::config::MAX_RESPONSE_SCHEMA_BYTES
+        )));
+    }
+    match serde_json::from_str::<serde_json::Value>(&response_schema.schema_json) {
+        Ok(serde_json::Value::Object(_)) => Ok(()),
+        _ => Err(CanisterError::InvalidInput(
+            "Response schema must be a JSON object.".to_string(),
+        )),
+    }
+}
+
 pub fn fail_job(job_id: u64, reason: MessageErrorStatus) -> CanisterResult<()> {
     use crate::storage::{CandidWrapper, CHAT_JOBS, MESSAGES};
 
@@ -180,6 +257,9 @@ pub fn fail_job(job_id: u64, reason: MessageErrorStatus) -> CanisterResult<()> {
 
         Ok((chat_id, placeholder_id))
     })?;
+    crate::helpers::node_helpers::revoke_node_reads(job_id);
+    crate::helpers::embedding_helpers::remove_embedding_batches(&[job_id]);
+    crate::helpers::ingestion_helpers::remove_ingested_chunks(&[job_id]);
 
     // Update message
     MESSAGES.with(|m| {
@@ -194,18 +274,92 @@ pub fn fail_job(job_id: u64, reason: MessageErrorStatus) -> CanisterResult<()> {
     });
 
     // Update chat
-    CHATS.with(|c| {
-        let mut chats = c.borrow_mut();
-        if let Some(chat_wrapper) = chats.get(&chat_id) {
-            let mut chat = chat_wrapper.0.clone();
-            if chat.active_job_id == Some(job_id) {
-                chat.active_job_id = None;
-                chat.updated_at = now;
-                chats.insert(chat_id, CandidWrapper(chat));
-            }
-        }
-    });
+    if let Some(mut chat) = CHATS.with(|c| c.borrow().get(&chat_id).map(|w| w.0.clone()))
+        && chat.active_job_id == Some(job_id)
+    {
+        chat.active_job_id = None;
+        chat.updated_at = now;
+        save_chat(chat);
+    }
 
     ic_cdk::println!("[JOB] Failed job {}", job_id);
     Ok(())
 }
+
+/// Fails a job, unless the failure is retryable and another node can take it over.
+pub fn retry_or_fail_job(job_id: u64, reason: MessageErrorStatus) -> CanisterResult<()> {
+    if reason.is_retryable()
+        && let Some(node_id) = reassign_job(job_id, ic_cdk::api::time())?
+    {
+        ic_cdk::println!(
+            "[JOB] Reassigned job {} to node {} after: {}",
+            job_id, node_id, reason
+        );
+        return Ok(());
+    }
+    fail_job(job_id, reason)
+}
+
+/// Moves a pending or in-progress job to the first failover node that is active in
+/// the synced node cache, serves the job's model and has not held the job before.
+/// The job returns to `Pending` with that node's wrapped chat key.
+/// Returns the new node, or `None` when retries are exhausted or no candidate exists.
+pub fn reassign_job(job_id: u64, now: u64) -> CanisterResult<Option<NodeId>> {
+    use crate::storage::{CandidWrapper, CHAT_JOBS, MESSAGES};
+
+    let mut job = CHAT_JOBS
+        .with(|cj| cj.borrow().get(&job_id).map(|w| w.0.clone()))
+        .ok_or(CanisterError::Other(format!("Job {} not found for failover", job_id)))?;
+
+    if !matches!(
+        job.generation_status,
+        GenerationStatus::Pending | GenerationStatus::InProgress
+    ) || job.retry_count >= crate::config::MAX_JOB_RETRIES
+    {
+        return Ok(None);
+    }
+
+    let candidate = job.failover_chat_keys.iter().find(|key| {
+        key.node_id != job.node_id
+            && !job.node_history.contains(&key.node_id)
+            && NODES.with(|n| {
+                n.borrow().get(&key.node_id).is_some_and(|node| {
+                    node.node_principal.is_some() && node.model_id == job.model_id
+                })
+            })
+    });
+    let Some(candidate) = candidate.cloned() else {
+        return Ok(None);
+    };
+
+    job.node_history.push(job.node_id);
+    job.node_id = candidate.node_id;
+    job.encrypted_chat_key = Some(candidate.encrypted_chat_key);
+    job.retry_count += 1;
+    job.generation_status = GenerationStatus::Pending;
+    job.updated_at = now;
+    let placeholder_id = job.placeholder_message_id;
+    CHAT_JOBS.with(|cj| cj.borrow_mut().insert(job_id, CandidWrapper(job)));
+
+    // The previous node loses access to the chain immediately, and the next one
+    // ingests the file from scratch.
+    crate::helpers::node_helpers::revoke_node_reads(job_id);
+    crate::helpers::ingestion_helpers::remove_ingested_chunks(&[job_id]);
+
+    // Clear whatever the previous attempt left on the placeholder.
+    MESSAGES.with(|m| {
+        let mut msgs = m.borrow_mut();
+        if let Some(msg_wrapper) = msgs.get(&placeholder_id) {
+            let mut msg = msg_wrapper.0.clone();
+            msg.content = Vec::new();
+            msg.error_status = None;
+            msg.tool_calls = None;
+            msg.usage = None;
+            msg.requires_client_action = false;
+            msg.updated_at = now;
+            msgs.insert(placeholder_id, CandidWrapper(msg));
+        }
+    });
+
+    Ok(Some(candidate.node_id))
+}
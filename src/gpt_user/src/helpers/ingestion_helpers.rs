@@ -0,0 +1,253 @@
+use gpt_types::{
+    domain::{
+        FileId, FileIngestion, FileMetadata, GenerationStatus, Job, JobId, JobKind, NodeId,
+        TextChunk,
+    },
+    error::{CanisterError, CanisterResult},
+};
+
+use crate::config::{
+    DEFAULT_INGESTION_CHUNK_CHARS, DEFAULT_INGESTION_CHUNK_OVERLAP_CHARS, INGESTIBLE_MIME_TYPES,
+    MAX_INGESTED_CHUNKS, MAX_INGESTION_CHUNK_CHARS, MAX_INGESTION_FILE_BYTES,
+    MIN_INGESTION_CHUNK_CHARS,
+};
+use crate::storage::{CHAT_JOBS, CandidWrapper, FILES_METADATA, INGESTED_CHUNKS, IngestedChunkKey};
+
+/// The ingestion description of `job`, if it is a file ingestion job.
+pub fn file_ingestion(job: &Job) -> Option<&FileIngestion> {
+    match &job.kind {
+        Some(JobKind::FileIngestion(ingestion)) => Some(ingestion),
+        _ => None,
+    }
+}
+
+/// Applies the defaults to the requested chunking and checks that chunks make
+/// progress: the overlap must be smaller than the chunk.
+pub fn resolve_chunking(
+    chunk_size_chars: Option<u32>,
+    chunk_overlap_chars: Option<u32>,
+) -> CanisterResult<(u32, u32)> {
+    let size = chunk_size_chars.unwrap_or(DEFAULT_INGESTION_CHUNK_CHARS);
+    if !(MIN_INGESTION_CHUNK_CHARS..=MAX_INGESTION_CHUNK_CHARS).contains(&size) {
+        return Err(CanisterError::InvalidInput(format!(
+            "Chunk size must be between {} and {} characters.",
+            MIN_INGESTION_CHUNK_CHARS, MAX_INGESTION_CHUNK_CHARS
+        )));
+    }
+    let overlap =
+        chunk_overlap_chars.unwrap_or(DEFAULT_INGESTION_CHUNK_OVERLAP_CHARS.min(size / 2));
+    if overlap >= size {
+        return Err(CanisterError::InvalidInput(
+            "Chunk overlap must be smaller than the chunk size.".to_string(),
+        ));
+    }
+    Ok((size, overlap))
+}
+
+/// Checks that a node can extract text from the file.
+pub fn validate_ingestible_file(file: &FileMetadata) -> CanisterResult<()> {
+    if !INGESTIBLE_MIME_TYPES.contains(&file.mime_type.as_str()) {
+        return Err(CanisterError::UnsupportedMimeType(file.mime_type.clone()));
+    }
+    if file.content_size_bytes == 0 || file.content_size_bytes > MAX_INGESTION_FILE_BYTES {
+        return Err(CanisterError::InvalidInput(format!(
+            "Only files of 1 to {} bytes can be ingested.",
+            MAX_INGESTION_FILE_BYTES
+        )));
+    }
+    Ok(())
+}
+
+/// The in-progress ingestion job `job_id`, if it is assigned to `node_id`.
+pub fn node_ingestion_job(job_id: JobId, node_id: NodeId) -> CanisterResult<(Job, FileIngestion)> {
+    let job = CHAT_JOBS
+        .with(|cj| cj.borrow().get(&job_id).map(|w| w.0.clone()))
+        .ok_or_else(|| CanisterError::Other(format!("Job {} not found", job_id)))?;
+    if job.node_id != node_id {
+        return Err(CanisterError::Unauthorized);
+    }
+    let ingestion = file_ingestion(&job).cloned().ok_or_else(|| {
+        CanisterError::InvalidInput(format!("Job {} is not a file ingestion.", job_id))
+    })?;
+    if job.generation_status != GenerationStatus::InProgress {
+        return Err(CanisterError::InvalidInput(format!(
+            "Job {} is not in progress.",
+            job_id
+        )));
+    }
+    Ok((job, ingestion))
+}
+
+/// Holds chunks written by an ingestion job until it completes. A chunk written
+/// again under the same index replaces the earlier one. Returns the number of
+/// chunks held for the job.
+pub fn stage_ingested_chunks(job_id: JobId, chunks: Vec<TextChunk>) -> CanisterResult<u32> {
+    for chunk in &chunks {
+        if chunk.chunk_index >= MAX_INGESTED_CHUNKS {
+            return Err(CanisterError::InvalidInput(format!(
+                "A file can have at most {} ingested chunks.",
+                MAX_INGESTED_CHUNKS
+            )));
+        }
+        if chunk.embedding.is_empty() || chunk.content.as_ref().is_none_or(|c| c.is_empty()) {
+            return Err(CanisterError::InvalidInput(format!(
+                "Chunk {} needs both content and an embedding.",
+                chunk.chunk_index
+            )));
+        }
+        if chunk.start_char > chunk.end_char {
+            return Err(CanisterError::InvalidInput(format!(
+                "Chunk {} ends before it starts.",
+                chunk.chunk_index
+            )));
+        }
+    }
+
+    INGESTED_CHUNKS.with(|c| {
+        let mut staged = c.borrow_mut();
+        for chunk in chunks {
+            staged.insert((job_id, chunk.chunk_index), CandidWrapper(chunk));
+        }
+        Ok(staged.keys_range(job_range(job_id)).count() as u32)
+    })
+}
+
+/// Replaces the chunks of `file_id` with the ones staged by a completed ingestion
+/// job. The staged chunks must be numbered from 0 without gaps. Nothing is written
+/// if the file was deleted while the job ran.
+pub fn commit_ingested_chunks(job_id: JobId, file_id: FileId, now: u64) -> CanisterResult<()> {
+    let chunks: Vec<TextChunk> = INGESTED_CHUNKS.with(|c| {
+        c.borrow()
+            .range(job_range(job_id))
+            .map(|entry| entry.value().0.clone())
+            .collect()
+    });
+    remove_ingested_chunks(&[job_id]);
+
+    if let Some(missing) = chunks
+        .iter()
+        .enumerate()
+        .find(|(position, chunk)| chunk.chunk_index != *position as u32)
+    {
+        return Err(CanisterError::InvalidInput(format!(
+            "Ingested chunk {} is missing.",
+            missing.0
+        )));
+    }
+
+    if let Some(mut file) = FILES_METADATA.with(|f| f.borrow().get(&file_id).map(|w| w.0)) {
+        file.chunks = chunks;
+        file.updated_at = now;
+        FILES_METADATA.with(|f| f.borrow_mut().insert(file_id, CandidWrapper(file)));
+    }
+    Ok(())
+}
+
+/// Drops the staged chunks of the given jobs.
+pub fn remove_ingested_chunks(job_ids: &[JobId]) {
+    INGESTED_CHUNKS.with(|c| {
+        let mut staged = c.borrow_mut();
+        for job_id in job_ids {
+            let keys: Vec<IngestedChunkKey> = staged.keys_range(job_range(*job_id)).collect();
+            for key in keys {
+                staged.remove(&key);
+            }
+        }
+    });
+}
+
+fn job_range(job_id: JobId) -> std::ops::RangeInclusive<IngestedChunkKey> {
+    (job_id, 0)..=(job_id, u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_support;
+    use serde_bytes::ByteBuf;
+
+    fn chunk(index: u32) -> TextChunk {
+        TextChunk {
+            chunk_index: index,
+            start_char: index * 10,
+            end_char: index * 10 + 12,
+            embedding: vec![index as u8; 4],
+            content: Some(ByteBuf::from(vec![1, 2, 3])),
+        }
+    }
+
+    fn file(id: FileId, mime_type: &str, content_size_bytes: u64) -> FileMetadata {
+        FileMetadata {
+            name: "report.pdf".to_string(),
+            mime_type: mime_type.to_string(),
+            content_size_bytes,
+            chunks: vec![TextChunk {
+                content: None,
+                ..chunk(0)
+            }],
+            ..test_support::file(id)
+        }
+    }
+
+    #[test]
+    fn test_committed_chunks_replace_file_chunks() {
+        FILES_METADATA.with(|f| {
+            f.borrow_mut()
+                .insert(3, CandidWrapper(file(3, "application/pdf", 100)))
+        });
+        assert_eq!(
+            stage_ingested_chunks(9, vec![chunk(1), chunk(0)]).unwrap(),
+            2
+        );
+        assert_eq!(
+            stage_ingested_chunks(9, vec![chunk(2), chunk(1)]).unwrap(),
+            3
+        );
+
+        commit_ingested_chunks(9, 3, 42).unwrap();
+
+        let file = FILES_METADATA.with(|f| f.borrow().get(&3).unwrap().0);
+        assert_eq!(file.updated_at, 42);
+        assert_eq!(file.chunks, vec![chunk(0), chunk(1), chunk(2)]);
+        assert_eq!(INGESTED_CHUNKS.with(|c| c.borrow().len()), 0);
+    }
+
+    #[test]
+    fn test_incomplete_or_invalid_chunks_are_rejected() {
+        let mut empty = chunk(0);
+        empty.content = None;
+        assert!(stage_ingested_chunks(4, vec![empty]).is_err());
+        assert!(stage_ingested_chunks(4, vec![chunk(MAX_INGESTED_CHUNKS)]).is_err());
+
+        FILES_METADATA.with(|f| {
+            f.borrow_mut()
+                .insert(5, CandidWrapper(file(5, "text/html", 100)))
+        });
+        stage_ingested_chunks(4, vec![chunk(0), chunk(2)]).unwrap();
+        assert!(commit_ingested_chunks(4, 5, 1).is_err());
+        let file = FILES_METADATA.with(|f| f.borrow().get(&5).unwrap().0);
+        assert_eq!(file.chunks[0].content, None);
+        assert_eq!(INGESTED_CHUNKS.with(|c| c.borrow().len()), 0);
+    }
+
+    #[test]
+    fn test_chunking_and_file_validation() {
+        assert_eq!(
+            resolve_chunking(None, None).unwrap(),
+            (
+                DEFAULT_INGESTION_CHUNK_CHARS,
+                DEFAULT_INGESTION_CHUNK_OVERLAP_CHARS
+            )
+        );
+        assert_eq!(resolve_chunking(Some(200), None).unwrap(), (200, 100));
+        assert!(resolve_chunking(Some(500), Some(500)).is_err());
+        assert!(resolve_chunking(Some(MAX_INGESTION_CHUNK_CHARS + 1), Some(0)).is_err());
+
+        assert!(validate_ingestible_file(&file(1, "application/pdf", 10)).is_ok());
+        assert!(validate_ingestible_file(&file(1, "image/png", 10)).is_err());
+        assert!(validate_ingestible_file(&file(1, "text/markdown", 0)).is_err());
+        assert!(
+            validate_ingestible_file(&file(1, "text/plain", MAX_INGESTION_FILE_BYTES + 1)).is_err()
+        );
+    }
+}
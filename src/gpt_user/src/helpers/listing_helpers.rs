@@ -0,0 +1,134 @@
+use gpt_types::{
+    api::{ListSortField, ListSortKey, SortDirection},
+    error::{CanisterError, CanisterResult},
+};
+
+use crate::config::MAX_LIST_PAGE_SIZE;
+
+/// Resolved ordering of a listing request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListOrder {
+    pub field: ListSortField,
+    pub descending: bool,
+}
+
+/// Fills in the defaults of a listing request: `default_field`, newest first for
+/// timestamps and alphabetical for titles.
+pub fn resolve_list_order(
+    sort_by: Option<ListSortField>,
+    direction: Option<SortDirection>,
+    default_field: ListSortField,
+) -> ListOrder {
+    let field = sort_by.unwrap_or(default_field);
+    let descending = match direction {
+        Some(direction) => direction == SortDirection::Descending,
+        None => field != ListSortField::Title,
+    };
+    ListOrder { field, descending }
+}
+
+pub fn list_page_limit(limit: Option<u32>) -> usize {
+    limit
+        .unwrap_or(MAX_LIST_PAGE_SIZE)
+        .clamp(1, MAX_LIST_PAGE_SIZE) as usize
+}
+
+pub fn list_sort_key(
+    field: ListSortField,
+    created_at: u64,
+    updated_at: u64,
+    title: &str,
+) -> ListSortKey {
+    match field {
+        ListSortField::UpdatedAt => ListSortKey::Timestamp(updated_at),
+        ListSortField::CreatedAt => ListSortKey::Timestamp(created_at),
+        ListSortField::Title => ListSortKey::Title(title.to_lowercase()),
+    }
+}
+
+/// Rejects a cursor produced by a listing sorted on another field.
+pub fn validate_cursor_key(field: ListSortField, key: &ListSortKey) -> CanisterResult<()> {
+    let matches = matches!(
+        (field, key),
+        (
+            ListSortField::UpdatedAt | ListSortField::CreatedAt,
+            ListSortKey::Timestamp(_)
+        ) | (ListSortField::Title, ListSortKey::Title(_))
+    );
+    if !matches {
+        return Err(CanisterError::InvalidInput(
+            "Cursor does not match the requested sort.".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Orders `items` by position and returns up to `limit` of them following `after`,
+/// together with whether more items remain.
+pub fn paginate<P: Ord, T>(
+    mut items: Vec<(P, T)>,
+    descending: bool,
+    after: Option<&P>,
+    limit: usize,
+) -> (Vec<(P, T)>, bool) {
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    if descending {
+        items.reverse();
+    }
+    let start = after.map_or(0, |after| {
+        items.partition_point(|(pos, _)| {
+            if descending {
+                pos >= after
+            } else {
+                pos <= after
+            }
+        })
+    });
+
+    let mut page: Vec<(P, T)> = items.into_iter().skip(start).take(limit + 1).collect();
+    let has_more = page.len() > limit;
+    page.truncate(limit);
+    (page, has_more)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items() -> Vec<(u64, char)> {
+        vec![(3, 'c'), (1, 'a'), (4, 'd'), (2, 'b'), (5, 'e')]
+    }
+
+    #[test]
+    fn test_paginate_walks_both_directions() {
+        let (page, has_more) = paginate(items(), false, None, 2);
+        assert_eq!(page, vec![(1, 'a'), (2, 'b')]);
+        assert!(has_more);
+
+        let (page, has_more) = paginate(items(), false, Some(&4), 2);
+        assert_eq!(page, vec![(5, 'e')]);
+        assert!(!has_more);
+
+        let (page, has_more) = paginate(items(), true, Some(&4), 2);
+        assert_eq!(page, vec![(3, 'c'), (2, 'b')]);
+        assert!(has_more);
+    }
+
+    #[test]
+    fn test_paginate_cursor_need_not_exist() {
+        // The item under the cursor may have been deleted between pages.
+        let (page, _) = paginate(vec![(1, 'a'), (4, 'd')], false, Some(&2), 10);
+        assert_eq!(page, vec![(4, 'd')]);
+    }
+
+    #[test]
+    fn test_default_order_and_cursor_validation() {
+        let order = resolve_list_order(None, None, ListSortField::UpdatedAt);
+        assert!(order.descending);
+        let order = resolve_list_order(Some(ListSortField::Title), None, ListSortField::UpdatedAt);
+        assert!(!order.descending);
+
+        assert!(validate_cursor_key(ListSortField::CreatedAt, &ListSortKey::Timestamp(1)).is_ok());
+        assert!(validate_cursor_key(ListSortField::Title, &ListSortKey::Timestamp(1)).is_err());
+    }
+}
@@ -1,41 +1,110 @@
 use crate::{
-    config::{ALLOWED_IMAGE_MIME_TYPES, MAX_ATTACHMENT_SIZE_BYTES},
-    storage::{StorableString, CHAT_JOBS, CHATS, MESSAGES, MODELS},
+    config::{MAX_CITATION_TOOL_CALL_ID_CHARS, MAX_MESSAGE_CITATIONS},
+    storage::{
+        CHAT_JOBS, CHATS, CONTEXT_CHECKPOINTS, CandidWrapper, MESSAGE_TREE_BACKFILL, MESSAGES,
+        index_message,
+    },
 };
 use gpt_types::{
-    domain::{Message, ModelId, message::ImageAttachment},
+    domain::{Chat, Citation, CitationSource, Message, MessageId, ModelId, Role},
     error::{CanisterError, CanisterResult},
 };
 
 pub fn is_chat_in_generation(chat_id: u64) -> CanisterResult<bool> {
-    let active = CHATS.with(|c| {
-        c.borrow()
-            .get(&chat_id)
-            .and_then(|w| w.0.active_job_id)
-    });
+    let active = CHATS.with(|c| c.borrow().get(&chat_id).and_then(|w| w.0.active_job_id));
     Ok(active.is_some())
 }
 
-pub fn validate_attachments(
-    attachments: &Option<Vec<ImageAttachment>>,
-    model_id: &ModelId,
-) -> CanisterResult<()> {
-    if let Some(atts) = attachments {
-        let model = MODELS
-            .with(|m| m.borrow().get(&StorableString(model_id.clone())).map(|w| w.0.clone()))
-            .ok_or(CanisterError::ModelNotFound)?;
+/// The chat's active leaf, falling back to its newest message for chats created
+/// before the pointer existed.
+pub fn active_leaf(chat: &Chat) -> Option<MessageId> {
+    chat.active_leaf_message_id
+        .or_else(|| chat.message_ids.iter().max().copied())
+}
 
-        if atts.len() as u32 > model.max_image_attachments {
-            return Err(CanisterError::InvalidInput(format!(
-                "Number of attachments ({}) exceeds the model's limit of {}.",
-                atts.len(),
-                model.max_image_attachments
-            )));
+/// Walks parent links from `leaf_message_id` and returns the chain ids, root first.
+pub fn build_message_chain(leaf_message_id: MessageId) -> Vec<MessageId> {
+    let mut chain = Vec::new();
+    let mut current_message_id = leaf_message_id;
+    loop {
+        let msg_opt = MESSAGES.with(|m| m.borrow().get(&current_message_id).map(|w| w.0.clone()));
+        if let Some(msg) = msg_opt {
+            chain.push(msg.message_id);
+            if let Some(parent_id) = msg.parent_message_id {
+                current_message_id = parent_id;
+            } else {
+                break;
+            }
+        } else {
+            break;
         }
+    }
+    chain.reverse();
+    chain
+}
 
-        validate_attachment_properties(attachments)?;
+/// Applies the newest context checkpoint along `chain`: the messages it covers are
+/// dropped, except system messages, and the checkpoint is returned alongside.
+pub fn apply_context_checkpoint(chain: Vec<MessageId>) -> (Vec<MessageId>, Option<Message>) {
+    let found = CONTEXT_CHECKPOINTS.with(|c| {
+        let checkpoints = c.borrow();
+        chain
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, id)| checkpoints.get(id).map(|w| (index, w.0)))
+    });
+    let Some((covered_index, checkpoint)) = found else {
+        return (chain, None);
+    };
+
+    let mut remaining: Vec<MessageId> = MESSAGES.with(|m| {
+        let messages = m.borrow();
+        chain[..=covered_index]
+            .iter()
+            .copied()
+            .filter(|id| messages.get(id).is_some_and(|w| w.0.role == Role::System))
+            .collect()
+    });
+    remaining.extend_from_slice(&chain[covered_index + 1..]);
+    (remaining, Some(checkpoint))
+}
+
+/// Drops the context checkpoints covering any of `message_ids`.
+pub fn remove_context_checkpoints(message_ids: &[MessageId]) {
+    CONTEXT_CHECKPOINTS.with(|c| {
+        let mut checkpoints = c.borrow_mut();
+        for message_id in message_ids {
+            checkpoints.remove(message_id);
+        }
+    });
+}
+
+/// Adds up to `batch_size` messages written before the chat tree existed to it, and
+/// returns whether any are left.
+pub fn backfill_message_tree(batch_size: usize) -> bool {
+    let Some(next) = MESSAGE_TREE_BACKFILL.with(|b| b.borrow().get().0) else {
+        return false;
+    };
+    let batch: Vec<Message> = MESSAGES.with(|m| {
+        m.borrow()
+            .range(next..)
+            .take(batch_size)
+            .map(|entry| entry.value().0)
+            .collect()
+    });
+    for message in &batch {
+        index_message(message);
     }
-    Ok(())
+
+    let next = batch
+        .last()
+        .filter(|_| batch.len() == batch_size)
+        .map(|message| message.message_id + 1);
+    MESSAGE_TREE_BACKFILL.with(|b| {
+        let _ = b.borrow_mut().set(CandidWrapper(next));
+    });
+    next.is_some()
 }
 
 pub fn find_model_id_for_message(user_message: &Message) -> CanisterResult<ModelId> {
@@ -94,23 +163,138 @@ pub fn find_model_id_for_message(user_message: &Message) -> CanisterResult<Model
     Err(CanisterError::ModelNotFound)
 }
 
-fn validate_attachment_properties(
-    attachments: &Option<Vec<ImageAttachment>>,
-) -> CanisterResult<()> {
-    if let Some(atts) = attachments {
-        let total_size: usize = atts.iter().map(|a| a.data.len()).sum();
-        if total_size > MAX_ATTACHMENT_SIZE_BYTES {
-            return Err(CanisterError::FileSystemLimitExceeded(format!(
-                "Total attachment size {} exceeds limit of {} bytes.",
-                total_size, MAX_ATTACHMENT_SIZE_BYTES
+/// Checks the citations a node reports for an assistant message: a bounded number,
+/// spans that do not end before they start, and tool call ids of a sane length.
+pub fn validate_citations(citations: &[Citation]) -> CanisterResult<()> {
+    if citations.len() > MAX_MESSAGE_CITATIONS {
+        return Err(CanisterError::InvalidInput(format!(
+            "A message can have at most {} citations.",
+            MAX_MESSAGE_CITATIONS
+        )));
+    }
+    for citation in citations {
+        if let Some(span) = citation.span
+            && span.start_char > span.end_char
+        {
+            return Err(CanisterError::InvalidInput(
+                "A citation span ends before it starts.".to_string(),
+            ));
+        }
+        if let CitationSource::ToolResult { tool_call_id } = &citation.source
+            && (tool_call_id.is_empty() || tool_call_id.len() > MAX_CITATION_TOOL_CALL_ID_CHARS)
+        {
+            return Err(CanisterError::InvalidInput(format!(
+                "Cited tool call ids must be 1 to {} characters long.",
+                MAX_CITATION_TOOL_CALL_ID_CHARS
             )));
         }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{MESSAGE_CHILDREN, MESSAGE_TREE, is_message_tree_complete, test_support};
+    use gpt_types::domain::CharSpan;
 
-        for att in atts {
-            if !ALLOWED_IMAGE_MIME_TYPES.contains(&att.mime_type.as_str()) {
-                return Err(CanisterError::UnsupportedMimeType(att.mime_type.clone()));
+    #[test]
+    fn test_newest_checkpoint_replaces_covered_messages() {
+        let roles = [
+            Role::System,
+            Role::User,
+            Role::Assistant,
+            Role::User,
+            Role::Assistant,
+        ];
+        MESSAGES.with(|m| {
+            let mut messages = m.borrow_mut();
+            for (i, role) in roles.into_iter().enumerate() {
+                let id = i as MessageId + 1;
+                let message = Message {
+                    role,
+                    ..test_support::message(id)
+                };
+                messages.insert(id, CandidWrapper(message));
             }
-        }
+        });
+        let chain = build_message_chain(5);
+        assert_eq!(chain, vec![1, 2, 3, 4, 5]);
+
+        // No checkpoint yet: the chain is untouched.
+        let (unchanged, checkpoint) = apply_context_checkpoint(chain.clone());
+        assert_eq!(unchanged, chain);
+        assert!(checkpoint.is_none());
+
+        CONTEXT_CHECKPOINTS.with(|c| {
+            let mut checkpoints = c.borrow_mut();
+            let checkpoint = |id| Message {
+                role: Role::System,
+                ..test_support::message(id)
+            };
+            checkpoints.insert(2, CandidWrapper(checkpoint(100)));
+            checkpoints.insert(3, CandidWrapper(checkpoint(101)));
+        });
+        let (remaining, checkpoint) = apply_context_checkpoint(chain);
+        assert_eq!(remaining, vec![1, 4, 5]);
+        assert_eq!(checkpoint.map(|c| c.message_id), Some(101));
+
+        remove_context_checkpoints(&[2, 3]);
+        assert!(CONTEXT_CHECKPOINTS.with(|c| c.borrow().is_empty()));
+    }
+
+    #[test]
+    fn test_backfill_adds_stored_messages_to_the_tree_in_batches() {
+        MESSAGES.with(|m| {
+            let mut messages = m.borrow_mut();
+            for id in 1..=5 {
+                messages.insert(id, CandidWrapper(test_support::message(id)));
+            }
+        });
+        MESSAGE_TREE_BACKFILL.with(|b| {
+            let _ = b.borrow_mut().set(CandidWrapper(Some(1)));
+        });
+        assert!(!is_message_tree_complete());
+
+        assert!(backfill_message_tree(2));
+        assert_eq!(MESSAGE_TREE.with(|t| t.borrow().len()), 2);
+        assert!(backfill_message_tree(2));
+        assert!(!backfill_message_tree(2));
+        assert!(is_message_tree_complete());
+        assert_eq!(MESSAGE_TREE.with(|t| t.borrow().len()), 5);
+        assert!(MESSAGE_CHILDREN.with(|c| c.borrow().contains_key(&(1, 4, 5))));
+    }
+
+    #[test]
+    fn test_citations_are_validated() {
+        let citation = |source, span| Citation { source, span };
+        let chunk = CitationSource::FileChunk {
+            file_id: 3,
+            chunk_index: 1,
+        };
+        let tool = |id: &str| CitationSource::ToolResult {
+            tool_call_id: id.to_string(),
+        };
+        let span = |start_char, end_char| {
+            Some(CharSpan {
+                start_char,
+                end_char,
+            })
+        };
+
+        assert!(validate_citations(&[]).is_ok());
+        assert!(
+            validate_citations(&[
+                citation(chunk.clone(), span(4, 9)),
+                citation(tool("call_1"), None)
+            ])
+            .is_ok()
+        );
+        assert!(validate_citations(&[citation(chunk.clone(), span(9, 4))]).is_err());
+        assert!(validate_citations(&[citation(tool(""), None)]).is_err());
+        assert!(validate_citations(&[citation(tool(&"x".repeat(300)), None)]).is_err());
+        assert!(
+            validate_citations(&vec![citation(chunk, None); MAX_MESSAGE_CITATIONS + 1]).is_err()
+        );
     }
-    Ok(())
 }
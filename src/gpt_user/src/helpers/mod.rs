@@ -1,4 +1,11 @@
+pub mod attachment_helpers;
+pub mod embedding_helpers;
 pub mod generation_helpers;
+pub mod ingestion_helpers;
+pub mod listing_helpers;
 pub mod message_helpers;
 pub mod node_helpers;
+pub mod retention_helpers;
+pub mod retrieval_helpers;
 pub mod user_helpers;
+pub mod vault_helpers;
@@ -1,24 +1,80 @@
 use gpt_types::{
-    domain::{Chat, GenerationStatus},
+    domain::{FileId, JobId, MessageId, NodeId},
     error::{CanisterError, CanisterResult},
 };
 
-use crate::storage::CHAT_JOBS;
-
-pub fn ensure_no_conflict_incomplete(chat: &Chat, node_id: u64) -> CanisterResult<()> {
-    if let Some(active_job_id) = chat.active_job_id {
-        // Get job from stable storage using CandidWrapper pattern
-        let job = CHAT_JOBS.with(|cj| cj.borrow().get(&active_job_id).map(|w| w.0.clone()));
-        if let Some(job) = job
-            && job.generation_status != GenerationStatus::Completed
-            && job.node_id != node_id
-        {
-            ic_cdk::println!(
-                "Conflict: chat {} has an active job assigned to a different node",
-                chat.chat_id
-            );
-            return Err(CanisterError::Unauthorized);
+use crate::config::JOB_INPROGRESS_TIMEOUT_NS;
+use crate::storage::{CandidWrapper, NodeReadGrant, NODE_READ_GRANTS};
+
+/// Allows `node_id` to read the given message chain and the files it attaches until
+/// the job finishes. The grant lapses on its own once the job would have timed out.
+pub fn grant_node_reads(
+    job_id: JobId,
+    node_id: NodeId,
+    message_ids: Vec<MessageId>,
+    file_ids: Vec<FileId>,
+    now: u64,
+) {
+    let grant = NodeReadGrant {
+        node_id,
+        message_ids,
+        file_ids,
+        expires_at: now.saturating_add(JOB_INPROGRESS_TIMEOUT_NS),
+    };
+    NODE_READ_GRANTS.with(|g| g.borrow_mut().insert(job_id, CandidWrapper(grant)));
+}
+
+/// Revokes node read access for a job. Called when the job completes or fails.
+pub fn revoke_node_reads(job_id: JobId) {
+    NODE_READ_GRANTS.with(|g| g.borrow_mut().remove(&job_id));
+}
+
+/// Drops every grant whose expiry has passed.
+pub fn revoke_expired_node_reads(now: u64) -> usize {
+    NODE_READ_GRANTS.with(|g| {
+        let mut grants = g.borrow_mut();
+        let expired: Vec<JobId> = grants
+            .iter()
+            .filter(|entry| entry.value().expires_at <= now)
+            .map(|entry| *entry.key())
+            .collect();
+        for job_id in &expired {
+            grants.remove(job_id);
         }
+        expired.len()
+    })
+}
+
+/// Returns the live grant of `job_id` if it belongs to `node_id`.
+pub fn get_node_read_grant(job_id: JobId, node_id: NodeId, now: u64) -> CanisterResult<NodeReadGrant> {
+    let grant = NODE_READ_GRANTS
+        .with(|g| g.borrow().get(&job_id).map(|w| w.0.clone()))
+        .ok_or(CanisterError::Unauthorized)?;
+
+    if grant.node_id != node_id || grant.expires_at <= now {
+        return Err(CanisterError::Unauthorized);
+    }
+    Ok(grant)
+}
+
+/// Ensures `message_id` is part of a job chain currently claimed by `node_id`.
+pub fn ensure_node_can_read(node_id: NodeId, message_id: MessageId, now: u64) -> CanisterResult<()> {
+    let allowed = NODE_READ_GRANTS.with(|g| {
+        g.borrow().iter().any(|entry| {
+            let grant = entry.value();
+            grant.node_id == node_id
+                && grant.expires_at > now
+                && grant.message_ids.contains(&message_id)
+        })
+    });
+
+    if !allowed {
+        ic_cdk::println!(
+            "Node {} denied read of message {}: not in a claimed job chain",
+            node_id,
+            message_id
+        );
+        return Err(CanisterError::Unauthorized);
     }
     Ok(())
 }
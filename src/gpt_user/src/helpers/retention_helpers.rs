@@ -0,0 +1,123 @@
+use gpt_types::{
+    domain::{Chat, RetentionPolicy},
+    error::{CanisterError, CanisterResult},
+};
+
+use crate::config::{MAX_RETENTION_TTL_SECS, MAX_TEMPORARY_CHAT_TTL_SECS, MIN_RETENTION_TTL_SECS};
+use crate::storage::get_config;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Returns the owner's retention policy, or the default one if none was set.
+pub fn current_retention_policy() -> RetentionPolicy {
+    get_config().retention_policy.unwrap_or_default()
+}
+
+pub fn validate_retention_policy(policy: &RetentionPolicy) -> CanisterResult<()> {
+    for (label, ttl) in [
+        ("Chat", policy.chat_ttl_secs),
+        ("Archived chat", policy.archived_chat_ttl_secs),
+    ] {
+        if let Some(ttl) = ttl
+            && !(MIN_RETENTION_TTL_SECS..=MAX_RETENTION_TTL_SECS).contains(&ttl)
+        {
+            return Err(CanisterError::InvalidInput(format!(
+                "{} TTL must be between {} and {} seconds.",
+                label, MIN_RETENTION_TTL_SECS, MAX_RETENTION_TTL_SECS
+            )));
+        }
+    }
+
+    // Temporary chats always expire; they are not meant to outlive a session.
+    if !(MIN_RETENTION_TTL_SECS..=MAX_TEMPORARY_CHAT_TTL_SECS)
+        .contains(&policy.temporary_chat_ttl_secs)
+    {
+        return Err(CanisterError::InvalidInput(format!(
+            "Temporary chat TTL must be between {} and {} seconds.",
+            MIN_RETENTION_TTL_SECS, MAX_TEMPORARY_CHAT_TTL_SECS
+        )));
+    }
+    Ok(())
+}
+
+/// Time after which `chat` is due for deletion under `policy`, or `None` if it is kept
+/// forever. Pinned chats are always kept.
+pub fn chat_delete_at(chat: &Chat, policy: &RetentionPolicy) -> Option<u64> {
+    if chat.pinned {
+        return None;
+    }
+    let ttl_secs = if chat.temporary {
+        Some(policy.temporary_chat_ttl_secs)
+    } else if chat.archived {
+        policy.archived_chat_ttl_secs
+    } else {
+        policy.chat_ttl_secs
+    }?;
+    Some(chat.updated_at.saturating_add(ttl_secs.saturating_mul(NANOS_PER_SEC)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    fn chat(updated_at: u64, archived: bool, temporary: bool, pinned: bool) -> Chat {
+        Chat {
+            chat_id: 1,
+            owner: Principal::anonymous(),
+            title: "Chat 1".to_string(),
+            message_ids: Vec::new(),
+            job_ids: Vec::new(),
+            active_job_id: None,
+            created_at: 0,
+            updated_at,
+            archived,
+            temporary,
+            pinned,
+            active_leaf_message_id: None,
+            encryption_salt: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_default_policy_matches_chat_kind() {
+        let policy = RetentionPolicy::default();
+        let day = 24 * 60 * 60 * NANOS_PER_SEC;
+
+        assert_eq!(chat_delete_at(&chat(5, false, false, false), &policy), Some(5 + 7 * day));
+        assert_eq!(chat_delete_at(&chat(5, true, false, false), &policy), Some(5 + 14 * day));
+        assert_eq!(
+            chat_delete_at(&chat(5, false, true, false), &policy),
+            Some(5 + 10 * 60 * NANOS_PER_SEC)
+        );
+    }
+
+    #[test]
+    fn test_pinned_and_keep_forever_chats_are_kept() {
+        let policy = RetentionPolicy {
+            chat_ttl_secs: None,
+            ..RetentionPolicy::default()
+        };
+
+        assert_eq!(chat_delete_at(&chat(5, false, false, false), &policy), None);
+        assert_eq!(chat_delete_at(&chat(5, true, false, true), &policy), None);
+        assert!(chat_delete_at(&chat(5, true, false, false), &policy).is_some());
+    }
+
+    #[test]
+    fn test_policy_validation_bounds() {
+        assert!(validate_retention_policy(&RetentionPolicy::default()).is_ok());
+
+        let too_short = RetentionPolicy {
+            archived_chat_ttl_secs: Some(1),
+            ..RetentionPolicy::default()
+        };
+        assert!(validate_retention_policy(&too_short).is_err());
+
+        let long_temporary = RetentionPolicy {
+            temporary_chat_ttl_secs: MAX_TEMPORARY_CHAT_TTL_SECS + 1,
+            ..RetentionPolicy::default()
+        };
+        assert!(validate_retention_policy(&long_temporary).is_err());
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::BTreeSet;
+
+use candid::Principal;
+use gpt_types::{
+    api::{RetrievalChunk, RetrievalOptions},
+    domain::{FileId, GenerationStatus, Job, JobId, ModelStatus, NodeId, Retrieval},
+    error::{CanisterError, CanisterResult},
+};
+
+use crate::config::{
+    DEFAULT_RETRIEVAL_PASSAGE_TOKENS, DEFAULT_RETRIEVAL_TOP_K, MAX_RETRIEVAL_PASSAGE_TOKENS,
+    MAX_RETRIEVAL_SOURCES, MAX_RETRIEVAL_TOP_K,
+};
+use crate::helpers::attachment_helpers::validate_file_keys;
+use crate::storage::{
+    CHAT_JOBS, FILES_METADATA, FOLDER_CONTENTS_INDEX, FOLDERS, MODELS, StorableString,
+};
+
+/// Checks the requested retrieval of a generation on `model_id` served by `node_id`
+/// and applies the defaults. The files and folders must belong to `owner`, and the
+/// embedding model must be reachable through the same provider endpoint as the
+/// chat model, since the node embeds the query with its own provider client.
+pub fn resolve_retrieval(
+    options: Option<RetrievalOptions>,
+    owner: Principal,
+    model_id: &str,
+    node_id: NodeId,
+) -> CanisterResult<Option<Retrieval>> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
+
+    let source_count = options.file_ids.len() + options.folder_ids.len();
+    if source_count == 0 || source_count > MAX_RETRIEVAL_SOURCES {
+        return Err(CanisterError::InvalidInput(format!(
+            "Retrieval needs 1 to {} files and folders.",
+            MAX_RETRIEVAL_SOURCES
+        )));
+    }
+    for file_id in &options.file_ids {
+        let owned = FILES_METADATA.with(|f| f.borrow().get(file_id).map(|w| w.0.owner == owner));
+        match owned {
+            Some(true) => {}
+            Some(false) => return Err(CanisterError::Unauthorized),
+            None => return Err(CanisterError::FileNotFound),
+        }
+    }
+    for folder_id in &options.folder_ids {
+        let owned = FOLDERS.with(|f| f.borrow().get(folder_id).map(|w| w.0.owner == owner));
+        match owned {
+            Some(true) => {}
+            Some(false) => return Err(CanisterError::Unauthorized),
+            None => return Err(CanisterError::FolderNotFound),
+        }
+    }
+
+    validate_embedding_model(&options.embedding_model_id, model_id)?;
+    let (top_k, max_passage_tokens) = resolve_retrieval_limits(
+        options.top_k,
+        options.max_passage_tokens,
+        options.mmr_lambda,
+    )?;
+
+    validate_file_keys(&options.file_keys, node_id)?;
+
+    Ok(Some(Retrieval {
+        file_ids: options.file_ids,
+        folder_ids: options.folder_ids,
+        embedding_model_id: options.embedding_model_id,
+        top_k,
+        max_passage_tokens,
+        mmr_lambda: options.mmr_lambda,
+        file_keys: options.file_keys,
+    }))
+}
+
+fn validate_embedding_model(embedding_model_id: &str, model_id: &str) -> CanisterResult<()> {
+    let get_model = |id: &str| {
+        MODELS
+            .with(|m| m.borrow().get(&StorableString(id.to_string())).map(|w| w.0))
+            .ok_or(CanisterError::ModelNotFound)
+    };
+    let model = get_model(model_id)?;
+    let embedding_model = get_model(embedding_model_id)?;
+
+    if model.is_embedding {
+        return Err(CanisterError::InvalidInput(
+            "Embedding models do not support retrieval.".to_string(),
+        ));
+    }
+    if !embedding_model.is_embedding {
+        return Err(CanisterError::InvalidInput(format!(
+            "Model {} is not an embedding model.",
+            embedding_model_id
+        )));
+    }
+    if embedding_model.status == ModelStatus::Paused {
+        return Err(CanisterError::InvalidInput(format!(
+            "Model {} is currently paused.",
+            embedding_model_id
+        )));
+    }
+    if embedding_model.provider_endpoint != model.provider_endpoint {
+        return Err(CanisterError::InvalidInput(format!(
+            "Embedding model {} is not served by the provider of model {}.",
+            embedding_model_id, model_id
+        )));
+    }
+    Ok(())
+}
+
+/// Applies the defaults to the passage count and token budget, and checks the MMR
+/// weight lies between 0 and 1.
+pub fn resolve_retrieval_limits(
+    top_k: Option<u32>,
+    max_passage_tokens: Option<u32>,
+    mmr_lambda: Option<f32>,
+) -> CanisterResult<(u32, u32)> {
+    let top_k = top_k.unwrap_or(DEFAULT_RETRIEVAL_TOP_K);
+    if !(1..=MAX_RETRIEVAL_TOP_K).contains(&top_k) {
+        return Err(CanisterError::InvalidInput(format!(
+            "Retrieval can add 1 to {} passages.",
+            MAX_RETRIEVAL_TOP_K
+        )));
+    }
+    let max_passage_tokens = max_passage_tokens.unwrap_or(DEFAULT_RETRIEVAL_PASSAGE_TOKENS);
+    if !(1..=MAX_RETRIEVAL_PASSAGE_TOKENS).contains(&max_passage_tokens) {
+        return Err(CanisterError::InvalidInput(format!(
+            "The passage token budget must be between 1 and {}.",
+            MAX_RETRIEVAL_PASSAGE_TOKENS
+        )));
+    }
+    if mmr_lambda.is_some_and(|lambda| !(0.0..=1.0).contains(&lambda)) {
+        return Err(CanisterError::InvalidInput(
+            "The MMR weight must be between 0 and 1.".to_string(),
+        ));
+    }
+    Ok((top_k, max_passage_tokens))
+}
+
+/// The in-progress retrieval job `job_id`, if it is assigned to `node_id`.
+pub fn node_retrieval_job(job_id: JobId, node_id: NodeId) -> CanisterResult<(Job, Retrieval)> {
+    let job = CHAT_JOBS
+        .with(|cj| cj.borrow().get(&job_id).map(|w| w.0.clone()))
+        .ok_or_else(|| CanisterError::Other(format!("Job {} not found", job_id)))?;
+    if job.node_id != node_id {
+        return Err(CanisterError::Unauthorized);
+    }
+    let retrieval = job.retrieval.clone().ok_or_else(|| {
+        CanisterError::InvalidInput(format!("Job {} does not use retrieval.", job_id))
+    })?;
+    if job.generation_status != GenerationStatus::InProgress {
+        return Err(CanisterError::InvalidInput(format!(
+            "Job {} is not in progress.",
+            job_id
+        )));
+    }
+    Ok((job, retrieval))
+}
+
+/// The files a retrieval searches: the listed files and every file below the listed
+/// folders, in id order. Files that no longer exist or belong to someone other than
+/// `owner` are left out.
+pub fn retrieval_file_ids(retrieval: &Retrieval, owner: Principal) -> Vec<FileId> {
+    let mut file_ids: BTreeSet<FileId> = retrieval.file_ids.iter().copied().collect();
+    let mut visited = BTreeSet::new();
+    let mut pending = retrieval.folder_ids.clone();
+    while let Some(folder_id) = pending.pop() {
+        if !visited.insert(folder_id) {
+            continue;
+        }
+        if let Some(contents) = FOLDER_CONTENTS_INDEX.with(|idx| idx.borrow().get(&folder_id)) {
+            file_ids.extend(contents.0.child_file_ids);
+            pending.extend(contents.0.child_folder_ids);
+        }
+    }
+
+    file_ids
+        .into_iter()
+        .filter(|file_id| {
+            FILES_METADATA.with(|f| f.borrow().get(file_id).is_some_and(|w| w.0.owner == owner))
+        })
+        .collect()
+}
+
+/// Returns the chunks of `file_ids` that carry their text, starting at position
+/// `cursor`, in a page whose encoded size stays within `max_page_bytes`. A page
+/// always carries at least one chunk so the cursor keeps advancing.
+pub fn retrieval_chunk_page(
+    file_ids: &[FileId],
+    cursor: u32,
+    max_page_bytes: usize,
+) -> CanisterResult<(Vec<RetrievalChunk>, Option<u32>)> {
+    let mut chunks = Vec::new();
+    let mut page_bytes = 0usize;
+    let mut position = 0u32;
+    for file_id in file_ids {
+        let Some(file) = FILES_METADATA.with(|f| f.borrow().get(file_id).map(|w| w.0)) else {
+            continue;
+        };
+        for chunk in file.chunks.into_iter().filter(|c| c.content.is_some()) {
+            if position < cursor {
+                position += 1;
+                continue;
+            }
+            let chunk = RetrievalChunk {
+                file_id: *file_id,
+                chunk,
+            };
+            let chunk_bytes = candid::encode_one(&chunk)
+                .map_err(|e| CanisterError::Other(format!("Failed to encode chunk: {}", e)))?
+                .len();
+            if !chunks.is_empty() && page_bytes + chunk_bytes > max_page_bytes {
+                return Ok((chunks, Some(position)));
+            }
+            page_bytes += chunk_bytes;
+            chunks.push(chunk);
+            position += 1;
+        }
+    }
+    Ok((chunks, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{CandidWrapper, FolderContents, test_support};
+    use gpt_types::domain::{FileMetadata, NodeChatKey, TextChunk};
+    use serde_bytes::ByteBuf;
+
+    fn chunk(index: u32, with_content: bool) -> TextChunk {
+        TextChunk {
+            chunk_index: index,
+            start_char: index * 10,
+            end_char: index * 10 + 12,
+            embedding: vec![7; 16],
+            content: with_content.then(|| ByteBuf::from(vec![1; 16])),
+        }
+    }
+
+    fn insert_file(id: FileId, owner: Principal, chunks: Vec<TextChunk>) {
+        let file = FileMetadata {
+            owner,
+            chunks,
+            ..test_support::file(id)
+        };
+        FILES_METADATA.with(|f| f.borrow_mut().insert(id, CandidWrapper(file)));
+    }
+
+    fn insert_folder(id: u64, child_folder_ids: Vec<u64>, child_file_ids: Vec<FileId>) {
+        let contents = FolderContents {
+            child_folder_ids,
+            child_file_ids,
+        };
+        FOLDER_CONTENTS_INDEX.with(|idx| idx.borrow_mut().insert(id, CandidWrapper(contents)));
+    }
+
+    fn retrieval(file_ids: Vec<FileId>, folder_ids: Vec<u64>) -> Retrieval {
+        Retrieval {
+            file_ids,
+            folder_ids,
+            embedding_model_id: "embedder".to_string(),
+            top_k: 5,
+            max_passage_tokens: 100,
+            mmr_lambda: None,
+            file_keys: vec![NodeChatKey {
+                node_id: 1,
+                encrypted_chat_key: "key".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_folders_are_searched_recursively() {
+        let owner = Principal::anonymous();
+        let other = Principal::management_canister();
+        for id in [1, 2, 3, 4] {
+            insert_file(id, owner, Vec::new());
+        }
+        insert_file(5, other, Vec::new());
+        insert_folder(10, vec![11], vec![2]);
+        insert_folder(11, vec![10], vec![3, 5]);
+
+        assert_eq!(
+            retrieval_file_ids(&retrieval(vec![4, 1, 99], vec![10]), owner),
+            vec![1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_chunk_pages_skip_chunks_without_text() {
+        let owner = Principal::anonymous();
+        insert_file(
+            20,
+            owner,
+            vec![chunk(0, true), chunk(1, false), chunk(2, true)],
+        );
+        insert_file(21, owner, vec![chunk(0, true)]);
+
+        let (all, next) = retrieval_chunk_page(&[20, 21], 0, usize::MAX).unwrap();
+        let found: Vec<(FileId, u32)> = all
+            .iter()
+            .map(|c| (c.file_id, c.chunk.chunk_index))
+            .collect();
+        assert_eq!(found, vec![(20, 0), (20, 2), (21, 0)]);
+        assert_eq!(next, None);
+
+        // A page too small for two chunks still carries one.
+        let (first, next) = retrieval_chunk_page(&[20, 21], 0, 1).unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(next, Some(1));
+        let (rest, next) = retrieval_chunk_page(&[20, 21], 2, usize::MAX).unwrap();
+        assert_eq!((rest[0].file_id, rest[0].chunk.chunk_index), (21, 0));
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn test_retrieval_limits() {
+        assert_eq!(
+            resolve_retrieval_limits(None, None, None).unwrap(),
+            (DEFAULT_RETRIEVAL_TOP_K, DEFAULT_RETRIEVAL_PASSAGE_TOKENS)
+        );
+        assert_eq!(
+            resolve_retrieval_limits(Some(3), Some(500), Some(0.5)).unwrap(),
+            (3, 500)
+        );
+        assert!(resolve_retrieval_limits(Some(0), None, None).is_err());
+        assert!(resolve_retrieval_limits(Some(MAX_RETRIEVAL_TOP_K + 1), None, None).is_err());
+        assert!(
+            resolve_retrieval_limits(None, Some(MAX_RETRIEVAL_PASSAGE_TOKENS + 1), None).is_err()
+        );
+        assert!(resolve_retrieval_limits(None, None, Some(1.5)).is_err());
+        assert!(resolve_retrieval_limits(None, None, Some(f32::NAN)).is_err());
+    }
+}
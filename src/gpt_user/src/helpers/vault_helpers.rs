@@ -0,0 +1,93 @@
+use gpt_types::{
+    api::{VaultPage, VaultRecord, VaultSection},
+    error::{CanisterError, CanisterResult},
+};
+use sha2::{Digest, Sha256};
+
+use crate::storage::VAULT_IMPORT;
+
+/// SHA-256 over the Candid encoding of a page's records.
+pub fn page_digest(records: &[VaultRecord]) -> CanisterResult<Vec<u8>> {
+    let bytes = candid::encode_one(records)
+        .map_err(|e| CanisterError::Other(format!("Failed to encode vault page: {}", e)))?;
+    Ok(Sha256::digest(bytes).to_vec())
+}
+
+pub fn is_vault_import_in_progress() -> bool {
+    VAULT_IMPORT.with(|v| v.borrow().get().0.is_some())
+}
+
+pub fn record_section(record: &VaultRecord) -> VaultSection {
+    match record {
+        VaultRecord::Chat(_) => VaultSection::Chats,
+        VaultRecord::Message(_) => VaultSection::Messages,
+        VaultRecord::Job(_) => VaultSection::Jobs,
+        VaultRecord::Folder(_) => VaultSection::Folders,
+        VaultRecord::File(_) => VaultSection::Files,
+        VaultRecord::FileContent(_) => VaultSection::FileContents,
+    }
+}
+
+/// Checks that a page is intact and only carries records of its own section.
+pub fn verify_page(page: &VaultPage) -> CanisterResult<()> {
+    if page_digest(&page.records)? != page.sha256 {
+        return Err(CanisterError::InvalidInput(format!(
+            "Integrity check failed for {:?} page at offset {}.",
+            page.section, page.offset
+        )));
+    }
+    if let Some(record) = page
+        .records
+        .iter()
+        .find(|record| record_section(record) != page.section)
+    {
+        return Err(CanisterError::InvalidInput(format!(
+            "{:?} page at offset {} contains a {:?} record.",
+            page.section,
+            page.offset,
+            record_section(record)
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpt_types::api::VaultFileContent;
+
+    fn content_page(data: Vec<u8>) -> VaultPage {
+        let records = vec![VaultRecord::FileContent(VaultFileContent {
+            file_id: 3,
+            chunk_index: Some(0),
+            data,
+        })];
+        VaultPage {
+            section: VaultSection::FileContents,
+            offset: 0,
+            sha256: page_digest(&records).unwrap(),
+            records,
+        }
+    }
+
+    #[test]
+    fn test_intact_page_verifies() {
+        assert!(verify_page(&content_page(vec![1, 2, 3])).is_ok());
+    }
+
+    #[test]
+    fn test_tampered_page_is_rejected() {
+        let mut page = content_page(vec![1, 2, 3]);
+        if let VaultRecord::FileContent(content) = &mut page.records[0] {
+            content.data.pop();
+        }
+        assert!(verify_page(&page).is_err());
+    }
+
+    #[test]
+    fn test_record_from_other_section_is_rejected() {
+        let mut page = content_page(vec![1, 2, 3]);
+        page.section = VaultSection::Files;
+        assert!(verify_page(&page).is_err());
+    }
+}
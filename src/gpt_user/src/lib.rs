@@ -11,6 +11,7 @@ use gpt_types::prelude::*;
 mod config;
 mod handlers;
 mod helpers;
+mod migrations;
 mod storage;
 mod timers;
 
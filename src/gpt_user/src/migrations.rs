@@ -0,0 +1,532 @@
+// Versioned envelope and migration registry for values kept in stable storage.
+//
+// Every stored value is written as `GPTV` + little-endian u32 record version + Candid
+// payload. Values written before envelopes existed are bare Candid (they start with
+// `DIDL`) and are treated as version 0. Reading a value runs the record migrations of
+// its type from the stored version up to the current one, so old records decode
+// lazily and are stored at the current version the next time they are saved.
+// `run_storage_migrations` only runs the steps that change storage as a whole.
+
+use crate::storage::{
+    CHATS, CHATS_BY_UPDATED, CONFIG, CandidWrapper, CanisterConfig, ChatUpdatedKey,
+    EmbeddingBatchRecord, FolderContents, MESSAGE_TREE_BACKFILL, MESSAGES, MessageTreeRecord,
+    NodeReadGrant, UploadSession, VaultImportSession,
+};
+use candid::{CandidType, Deserialize, Principal};
+use gpt_types::domain::node::LocalNode;
+use gpt_types::domain::{
+    Chat, ChatId, FileId, FileMetadata, Folder, FolderId, GenerationStatus, ImageAttachment, Job,
+    JobId, Message, MessageId, Model, ModelId, NodeId, Role, TextChunk, TokenUsage, ToolCall,
+    ToolResult, tool::Tool,
+};
+use gpt_types::error::MessageErrorStatus;
+use std::borrow::Cow;
+
+const ENVELOPE_MAGIC: &[u8; 4] = b"GPTV";
+const ENVELOPE_HEADER_LEN: usize = 8;
+
+/// Upgrades a Candid payload stored at record version `n` to version `n + 1`.
+pub type RecordMigration = fn(&[u8]) -> Result<Vec<u8>, String>;
+
+/// A type that can be stored behind a versioned envelope.
+pub trait Versioned: CandidType + for<'a> Deserialize<'a> {
+    /// Migration registry for this type: entry `n` upgrades version `n` to `n + 1`.
+    /// Append an entry whenever the type changes shape; never edit existing ones.
+    const MIGRATIONS: &'static [RecordMigration] = &[];
+
+    /// Record version written for new values.
+    fn record_version() -> u32 {
+        Self::MIGRATIONS.len() as u32
+    }
+}
+
+impl Versioned for Folder {}
+impl Versioned for FileMetadata {}
+impl Versioned for FolderContents {}
+impl Versioned for UploadSession {}
+impl Versioned for Option<VaultImportSession> {}
+impl Versioned for LocalNode {}
+impl Versioned for Model {}
+impl Versioned for Vec<u8> {}
+impl Versioned for EmbeddingBatchRecord {}
+impl Versioned for TextChunk {}
+impl Versioned for MessageTreeRecord {}
+impl Versioned for Option<MessageId> {}
+
+impl Versioned for CanisterConfig {
+    const MIGRATIONS: &'static [RecordMigration] = &[config_v0_add_schema_version];
+}
+
+impl Versioned for Chat {
+    const MIGRATIONS: &'static [RecordMigration] = &[chat_v0_add_pinned];
+}
+
+impl Versioned for Job {
+    const MIGRATIONS: &'static [RecordMigration] = &[job_v0_add_failover_state];
+}
+
+impl Versioned for Message {
+    const MIGRATIONS: &'static [RecordMigration] = &[message_v0_add_citations];
+}
+
+impl Versioned for NodeReadGrant {
+    const MIGRATIONS: &'static [RecordMigration] = &[read_grant_v0_add_file_ids];
+}
+
+/// Encodes a value behind an envelope carrying its current record version.
+pub fn encode_record<T: Versioned>(value: &T) -> Vec<u8> {
+    let payload = candid::encode_one(value).expect("Failed to encode");
+    let mut bytes = Vec::with_capacity(ENVELOPE_HEADER_LEN + payload.len());
+    bytes.extend_from_slice(ENVELOPE_MAGIC);
+    bytes.extend_from_slice(&T::record_version().to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+/// Decodes a stored value, migrating it from whatever version it was written at.
+pub fn decode_record<T: Versioned>(bytes: &[u8]) -> Result<T, String> {
+    let (version, payload) = split_envelope(bytes);
+    let current = T::record_version();
+    if version > current {
+        return Err(format!(
+            "record version {} is newer than supported version {}",
+            version, current
+        ));
+    }
+
+    let mut payload = Cow::Borrowed(payload);
+    for migrate in &T::MIGRATIONS[version as usize..] {
+        payload = Cow::Owned(migrate(&payload)?);
+    }
+    candid::decode_one(&payload).map_err(|e| e.to_string())
+}
+
+/// Returns the record version and Candid payload of a stored value.
+fn split_envelope(bytes: &[u8]) -> (u32, &[u8]) {
+    match bytes.strip_prefix(ENVELOPE_MAGIC.as_slice()) {
+        Some(rest) if rest.len() >= 4 => {
+            let (version, payload) = rest.split_at(4);
+            (
+                u32::from_le_bytes(version.try_into().expect("4-byte version")),
+                payload,
+            )
+        }
+        _ => (0, bytes),
+    }
+}
+
+// --- Record Migrations ---
+
+/// `CanisterConfig` as stored before `schema_version` was added.
+#[derive(CandidType, Deserialize)]
+struct CanisterConfigV0 {
+    owner: Option<Principal>,
+    parent_canister: Option<Principal>,
+    registered_at: u64,
+    enc_salt: Option<Vec<u8>>,
+    enc_validator: Option<String>,
+    root_folder_id: Option<FolderId>,
+    next_chat_id: u64,
+    next_message_id: u64,
+    next_job_id: JobId,
+    next_folder_id: FolderId,
+    next_file_id: FileId,
+}
+
+fn config_v0_add_schema_version(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let old: CanisterConfigV0 = candid::decode_one(bytes).map_err(|e| e.to_string())?;
+    let config = CanisterConfig {
+        owner: old.owner,
+        parent_canister: old.parent_canister,
+        registered_at: old.registered_at,
+        enc_salt: old.enc_salt,
+        enc_validator: old.enc_validator,
+        root_folder_id: old.root_folder_id,
+        next_chat_id: old.next_chat_id,
+        next_message_id: old.next_message_id,
+        next_job_id: old.next_job_id,
+        next_folder_id: old.next_folder_id,
+        next_file_id: old.next_file_id,
+        // Storage written before versioning still needs every storage migration.
+        schema_version: 0,
+        retention_policy: None,
+    };
+    candid::encode_one(&config).map_err(|e| e.to_string())
+}
+
+/// `Job` as stored before failover state was added.
+#[derive(CandidType, Deserialize)]
+struct JobV0 {
+    job_id: JobId,
+    chat_id: ChatId,
+    generation_status: GenerationStatus,
+    temperature: f32,
+    max_completion_tokens: u32,
+    max_context: u32,
+    model_id: ModelId,
+    node_id: NodeId,
+    placeholder_message_id: MessageId,
+    custom_prompt: Option<String>,
+    created_at: u64,
+    updated_at: u64,
+    tools: Option<Vec<Tool>>,
+    extra_body_json: Option<String>,
+    reasoning_effort: Option<String>,
+    encrypted_chat_key: Option<String>,
+}
+
+fn job_v0_add_failover_state(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let old: JobV0 = candid::decode_one(bytes).map_err(|e| e.to_string())?;
+    let job = Job {
+        job_id: old.job_id,
+        chat_id: old.chat_id,
+        generation_status: old.generation_status,
+        temperature: old.temperature,
+        max_completion_tokens: old.max_completion_tokens,
+        max_context: old.max_context,
+        model_id: old.model_id,
+        node_id: old.node_id,
+        placeholder_message_id: old.placeholder_message_id,
+        custom_prompt: old.custom_prompt,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        tools: old.tools,
+        extra_body_json: old.extra_body_json,
+        reasoning_effort: old.reasoning_effort,
+        encrypted_chat_key: old.encrypted_chat_key,
+        retry_count: 0,
+        node_history: Vec::new(),
+        failover_chat_keys: Vec::new(),
+        context_strategy: None,
+        response_schema: None,
+        kind: None,
+        retrieval: None,
+        file_keys: None,
+    };
+    candid::encode_one(&job).map_err(|e| e.to_string())
+}
+
+/// `Chat` as stored before pinning was added.
+#[derive(CandidType, Deserialize)]
+struct ChatV0 {
+    chat_id: ChatId,
+    owner: Principal,
+    title: String,
+    message_ids: Vec<MessageId>,
+    job_ids: Vec<JobId>,
+    active_job_id: Option<JobId>,
+    created_at: u64,
+    updated_at: u64,
+    archived: bool,
+    temporary: bool,
+    encryption_salt: Vec<u8>,
+}
+
+fn chat_v0_add_pinned(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let old: ChatV0 = candid::decode_one(bytes).map_err(|e| e.to_string())?;
+    let chat = Chat {
+        chat_id: old.chat_id,
+        owner: old.owner,
+        title: old.title,
+        message_ids: old.message_ids,
+        job_ids: old.job_ids,
+        active_job_id: old.active_job_id,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        archived: old.archived,
+        temporary: old.temporary,
+        pinned: false,
+        active_leaf_message_id: None,
+        encryption_salt: old.encryption_salt,
+    };
+    candid::encode_one(&chat).map_err(|e| e.to_string())
+}
+
+/// `Message` as stored before citations were added.
+#[derive(CandidType, Deserialize)]
+struct MessageV0 {
+    message_id: MessageId,
+    chat_id: ChatId,
+    parent_message_id: Option<MessageId>,
+    role: Role,
+    #[serde(with = "serde_bytes")]
+    content: Vec<u8>,
+    created_at: u64,
+    updated_at: u64,
+    error_status: Option<MessageErrorStatus>,
+    attachments: Option<Vec<ImageAttachment>>,
+    tool_calls: Option<Vec<ToolCall>>,
+    tool_results: Option<Vec<ToolResult>>,
+    tool_call_id: Option<String>,
+    requires_client_action: bool,
+    usage: Option<TokenUsage>,
+}
+
+fn message_v0_add_citations(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let old: MessageV0 = candid::decode_one(bytes).map_err(|e| e.to_string())?;
+    let message = Message {
+        message_id: old.message_id,
+        chat_id: old.chat_id,
+        parent_message_id: old.parent_message_id,
+        role: old.role,
+        content: old.content,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        error_status: old.error_status,
+        attachments: old.attachments,
+        attached_file_ids: None,
+        tool_calls: old.tool_calls,
+        tool_results: old.tool_results,
+        tool_call_id: old.tool_call_id,
+        requires_client_action: old.requires_client_action,
+        usage: old.usage,
+        citations: Vec::new(),
+    };
+    candid::encode_one(&message).map_err(|e| e.to_string())
+}
+
+/// `NodeReadGrant` as stored before grants covered attached files.
+#[derive(CandidType, Deserialize)]
+struct NodeReadGrantV0 {
+    node_id: NodeId,
+    message_ids: Vec<MessageId>,
+    expires_at: u64,
+}
+
+fn read_grant_v0_add_file_ids(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let old: NodeReadGrantV0 = candid::decode_one(bytes).map_err(|e| e.to_string())?;
+    let grant = NodeReadGrant {
+        node_id: old.node_id,
+        message_ids: old.message_ids,
+        file_ids: Vec::new(),
+        expires_at: old.expires_at,
+    };
+    candid::encode_one(&grant).map_err(|e| e.to_string())
+}
+
+// --- Storage Migrations ---
+
+/// Storage-wide migration steps run in `post_upgrade`: entry `n` moves storage
+/// from schema version `n` to `n + 1`.
+const STORAGE_MIGRATIONS: &[fn()] = &[index_chats_by_updated, start_message_tree_backfill];
+
+/// Schema version of storage written by this build.
+pub const STORAGE_SCHEMA_VERSION: u32 = STORAGE_MIGRATIONS.len() as u32;
+
+/// Applies every storage migration newer than the version recorded in `CanisterConfig`.
+pub fn run_storage_migrations() {
+    let from = CONFIG.with(|c| c.borrow().get().schema_version);
+    if from > STORAGE_SCHEMA_VERSION {
+        ic_cdk::println!(
+            "Storage schema version {} is newer than this build ({}); skipping migrations.",
+            from,
+            STORAGE_SCHEMA_VERSION
+        );
+        return;
+    }
+
+    for (version, step) in STORAGE_MIGRATIONS.iter().enumerate().skip(from as usize) {
+        ic_cdk::println!(
+            "Migrating storage schema from version {} to {}.",
+            version,
+            version + 1
+        );
+        step();
+        CONFIG.with(|c| {
+            let mut cell = c.borrow_mut();
+            let mut config = cell.get().0.clone();
+            config.schema_version = version as u32 + 1;
+            let _ = cell.set(CandidWrapper(config));
+        });
+    }
+}
+
+/// Version 0 -> 1: builds the chat recency index.
+fn index_chats_by_updated() {
+    let keys: Vec<ChatUpdatedKey> = CHATS.with(|c| {
+        c.borrow()
+            .iter()
+            .map(|entry| (entry.value().updated_at, *entry.key()))
+            .collect()
+    });
+    CHATS_BY_UPDATED.with(|idx| {
+        let mut idx = idx.borrow_mut();
+        for key in keys {
+            idx.insert(key, ());
+        }
+    });
+}
+
+/// Version 1 -> 2: queues building the chat tree of the stored messages. Messages are
+/// read in batches from a timer (`schedule_message_tree_backfill`), since reading
+/// them all here could exceed the upgrade's instruction limit.
+fn start_message_tree_backfill() {
+    let first = MESSAGES.with(|m| m.borrow().keys().next());
+    MESSAGE_TREE_BACKFILL.with(|b| {
+        let _ = b.borrow_mut().set(CandidWrapper(first));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_config() -> CanisterConfigV0 {
+        CanisterConfigV0 {
+            owner: Some(Principal::anonymous()),
+            parent_canister: None,
+            registered_at: 42,
+            enc_salt: Some(vec![1; 16]),
+            enc_validator: Some("validator".to_string()),
+            root_folder_id: Some(1),
+            next_chat_id: 7,
+            next_message_id: 30,
+            next_job_id: 12,
+            next_folder_id: 2,
+            next_file_id: 5,
+        }
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let contents = FolderContents {
+            child_folder_ids: vec![2, 3],
+            child_file_ids: vec![9],
+        };
+        let bytes = encode_record(&contents);
+        assert!(bytes.starts_with(ENVELOPE_MAGIC));
+
+        let decoded: FolderContents = decode_record(&bytes).unwrap();
+        assert_eq!(decoded.child_folder_ids, vec![2, 3]);
+        assert_eq!(decoded.child_file_ids, vec![9]);
+    }
+
+    #[test]
+    fn test_bare_candid_is_read_as_version_zero() {
+        let bytes = candid::encode_one(vec![1u8, 2, 3]).unwrap();
+        assert_eq!(split_envelope(&bytes).0, 0);
+
+        let decoded: Vec<u8> = decode_record(&bytes).unwrap();
+        assert_eq!(decoded, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_legacy_config_is_migrated_on_read() {
+        let bytes = candid::encode_one(legacy_config()).unwrap();
+
+        let config: CanisterConfig = decode_record(&bytes).unwrap();
+        assert_eq!(config.owner, Some(Principal::anonymous()));
+        assert_eq!(config.next_message_id, 30);
+        assert_eq!(config.next_file_id, 5);
+        assert_eq!(config.schema_version, 0);
+
+        let rewritten = encode_record(&config);
+        assert_eq!(
+            split_envelope(&rewritten).0,
+            CanisterConfig::record_version()
+        );
+    }
+
+    #[test]
+    fn test_legacy_job_gets_empty_failover_state() {
+        let legacy = JobV0 {
+            job_id: 3,
+            chat_id: 1,
+            generation_status: GenerationStatus::Pending,
+            temperature: 0.7,
+            max_completion_tokens: 1024,
+            max_context: 8192,
+            model_id: "model".to_string(),
+            node_id: 4,
+            placeholder_message_id: 9,
+            custom_prompt: None,
+            created_at: 10,
+            updated_at: 11,
+            tools: None,
+            extra_body_json: None,
+            reasoning_effort: None,
+            encrypted_chat_key: Some("key".to_string()),
+        };
+        let bytes = candid::encode_one(legacy).unwrap();
+
+        let job: Job = decode_record(&bytes).unwrap();
+        assert_eq!(job.node_id, 4);
+        assert_eq!(job.encrypted_chat_key.as_deref(), Some("key"));
+        assert_eq!(job.retry_count, 0);
+        assert!(job.node_history.is_empty());
+        assert!(job.failover_chat_keys.is_empty());
+    }
+
+    #[test]
+    fn test_legacy_chat_is_unpinned() {
+        let legacy = ChatV0 {
+            chat_id: 5,
+            owner: Principal::anonymous(),
+            title: "Chat 5".to_string(),
+            message_ids: vec![1, 2],
+            job_ids: vec![1],
+            active_job_id: None,
+            created_at: 10,
+            updated_at: 20,
+            archived: true,
+            temporary: false,
+            encryption_salt: vec![7; 16],
+        };
+        let bytes = candid::encode_one(legacy).unwrap();
+
+        let chat: Chat = decode_record(&bytes).unwrap();
+        assert_eq!(chat.chat_id, 5);
+        assert!(chat.archived);
+        assert!(!chat.pinned);
+        assert_eq!(chat.encryption_salt, vec![7; 16]);
+    }
+
+    #[test]
+    fn test_legacy_message_has_no_citations() {
+        let legacy = MessageV0 {
+            message_id: 8,
+            chat_id: 5,
+            parent_message_id: Some(7),
+            role: Role::Assistant,
+            content: vec![1, 2, 3],
+            created_at: 10,
+            updated_at: 12,
+            error_status: None,
+            attachments: None,
+            tool_calls: None,
+            tool_results: None,
+            tool_call_id: None,
+            requires_client_action: false,
+            usage: None,
+        };
+        let bytes = candid::encode_one(legacy).unwrap();
+
+        let message: Message = decode_record(&bytes).unwrap();
+        assert_eq!(message.message_id, 8);
+        assert_eq!(message.parent_message_id, Some(7));
+        assert_eq!(message.content, vec![1, 2, 3]);
+        assert!(message.citations.is_empty());
+    }
+
+    #[test]
+    fn test_legacy_read_grant_covers_no_files() {
+        let legacy = NodeReadGrantV0 {
+            node_id: 2,
+            message_ids: vec![4, 5],
+            expires_at: 99,
+        };
+        let bytes = candid::encode_one(legacy).unwrap();
+
+        let grant: NodeReadGrant = decode_record(&bytes).unwrap();
+        assert_eq!(grant.message_ids, vec![4, 5]);
+        assert_eq!(grant.expires_at, 99);
+        assert!(grant.file_ids.is_empty());
+    }
+
+    #[test]
+    fn test_newer_record_version_is_rejected() {
+        let mut bytes = encode_record(&FolderContents::default());
+        bytes[4..ENVELOPE_HEADER_LEN].copy_from_slice(&99u32.to_le_bytes());
+        assert!(decode_record::<FolderContents>(&bytes).is_err());
+    }
+}
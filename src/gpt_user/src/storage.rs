@@ -1,6 +1,12 @@
+use crate::migrations::{STORAGE_SCHEMA_VERSION, Versioned, decode_record, encode_record};
 use candid::{CandidType, Deserialize, Principal};
+use gpt_types::api::{VaultManifest, VaultSectionProgress};
 use gpt_types::domain::node::LocalNode;
-use gpt_types::domain::{Chat, FileId, FileMetadata, Folder, FolderId, Job, JobId, Message, Model};
+use gpt_types::domain::{
+    Chat, ChatId, EmbeddingInput, FileId, FileMetadata, Folder, FolderId, Job, JobId, Message,
+    MessageId, Model, RetentionPolicy, Role, TextChunk,
+};
+use gpt_types::error::MessageErrorStatus;
 use gpt_types::prelude::NodeId;
 use ic_stable_structures::{
     DefaultMemoryImpl, StableBTreeMap, StableCell, Storable,
@@ -23,12 +29,25 @@ const MEMORY_ID_FILES_CONTENT: MemoryId = MemoryId::new(6);
 const MEMORY_ID_NODES: MemoryId = MemoryId::new(7);
 const MEMORY_ID_MODELS: MemoryId = MemoryId::new(8);
 const MEMORY_ID_FOLDER_INDEX: MemoryId = MemoryId::new(9);
-
-type Memory = VirtualMemory<DefaultMemoryImpl>;
+const MEMORY_ID_NODE_READ_GRANTS: MemoryId = MemoryId::new(10);
+const MEMORY_ID_UPLOAD_SESSIONS: MemoryId = MemoryId::new(11);
+const MEMORY_ID_FILE_CONTENT_CHUNKS: MemoryId = MemoryId::new(12);
+const MEMORY_ID_VAULT_IMPORT: MemoryId = MemoryId::new(13);
+const MEMORY_ID_VAULT_IMPORT_IDS: MemoryId = MemoryId::new(14);
+const MEMORY_ID_CHATS_BY_UPDATED: MemoryId = MemoryId::new(15);
+const MEMORY_ID_CONTEXT_CHECKPOINTS: MemoryId = MemoryId::new(16);
+const MEMORY_ID_EMBEDDING_BATCHES: MemoryId = MemoryId::new(17);
+const MEMORY_ID_INGESTED_CHUNKS: MemoryId = MemoryId::new(18);
+const MEMORY_ID_MESSAGE_TREE: MemoryId = MemoryId::new(19);
+const MEMORY_ID_MESSAGE_CHILDREN: MemoryId = MemoryId::new(20);
+const MEMORY_ID_MESSAGE_TREE_BACKFILL: MemoryId = MemoryId::new(21);
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
 
 // --- Storable Wrappers ---
 
 /// Generic wrapper to make any Candid-serializable type Storable.
+/// Values are stored behind a versioned envelope (see `crate::migrations`).
 #[derive(Default, Clone, Debug)]
 pub struct CandidWrapper<T>(pub T)
 where
@@ -36,18 +55,18 @@ where
 
 impl<T> Storable for CandidWrapper<T>
 where
-    T: CandidType + for<'a> Deserialize<'a>,
+    T: Versioned,
 {
     fn to_bytes(&self) -> Cow<'_, [u8]> {
-        Cow::Owned(candid::encode_one(&self.0).expect("Failed to encode"))
+        Cow::Owned(encode_record(&self.0))
     }
 
     fn into_bytes(self) -> Vec<u8> {
-        candid::encode_one(&self.0).expect("Failed to encode")
+        encode_record(&self.0)
     }
 
     fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
-        Self(candid::decode_one(&bytes).expect("Failed to decode"))
+        Self(decode_record(&bytes).unwrap_or_else(|e| panic!("Failed to decode: {}", e)))
     }
 
     const BOUND: Bound = Bound::Unbounded;
@@ -123,6 +142,10 @@ pub struct CanisterConfig {
     pub next_folder_id: FolderId,
     /// Next file ID counter
     pub next_file_id: FileId,
+    /// Storage schema version; migrations newer than this run in post_upgrade
+    pub schema_version: u32,
+    /// Chat retention policy set by the owner (None = default policy)
+    pub retention_policy: Option<RetentionPolicy>,
 }
 
 impl Default for CanisterConfig {
@@ -139,6 +162,9 @@ impl Default for CanisterConfig {
             next_job_id: 1,
             next_folder_id: 1,
             next_file_id: 1,
+            // Fresh storage is written in the current layout.
+            schema_version: STORAGE_SCHEMA_VERSION,
+            retention_policy: None,
         }
     }
 }
@@ -152,6 +178,95 @@ pub struct FolderContents {
     pub child_file_ids: Vec<FileId>,
 }
 
+// --- Node Read Grant Value ---
+
+/// Messages a node may read while it processes a claimed job.
+/// Recorded by `claim_job` and revoked when the job completes or times out.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct NodeReadGrant {
+    pub node_id: NodeId,
+    /// The job's message chain, root first
+    pub message_ids: Vec<MessageId>,
+    /// Files attached by the messages of the chain
+    pub file_ids: Vec<FileId>,
+    /// Reads are rejected after this time even if the grant was never revoked
+    pub expires_at: u64,
+}
+
+// --- Upload Session Value ---
+
+/// Key of a stored content chunk: (file_id, chunk index)
+pub type FileChunkKey = (FileId, u32);
+
+/// An open chunked upload. The session id doubles as the file id of the result,
+/// and received chunks are written straight to `FILE_CONTENT_CHUNKS`.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct UploadSession {
+    pub name: String,
+    pub parent_folder_id: FolderId,
+    pub mime_type: String,
+    pub total_size_bytes: u64,
+    pub chunk_count: u32,
+    pub received_chunks: u32,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// --- Chat Index Key ---
+
+/// Key of the chat recency index: (updated_at, chat_id)
+pub type ChatUpdatedKey = (u64, ChatId);
+
+// --- Vault Import Session Value ---
+
+/// Key of an id remapped during a vault import: (record kind, id in the archive)
+pub type VaultIdKey = (u8, u64);
+
+/// A vault import in progress. Imported records get fresh ids; the mapping from
+/// archive ids lives in `VAULT_IMPORT_IDS` until the import is committed or aborted.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct VaultImportSession {
+    pub manifest: VaultManifest,
+    pub sections: Vec<VaultSectionProgress>,
+    pub started_at: u64,
+    pub updated_at: u64,
+}
+
+// --- Embedding Batch Value ---
+
+/// The inputs of an embedding batch job until it ends, then the embeddings unless
+/// they were written to the target file.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default)]
+pub struct EmbeddingBatchRecord {
+    pub inputs: Vec<EmbeddingInput>,
+    /// Encrypted embeddings in input order
+    pub embeddings: Vec<serde_bytes::ByteBuf>,
+}
+
+// --- Ingested Chunk Key ---
+
+/// Key of a chunk written by an ingestion job: (job_id, chunk index)
+pub type IngestedChunkKey = (JobId, u32);
+
+// --- Message Tree Values ---
+
+/// Key of the message tree: (chat_id, message_id)
+pub type MessageTreeKey = (ChatId, MessageId);
+
+/// Key of the message children index: (chat_id, parent_message_id, message_id)
+pub type MessageChildKey = (ChatId, MessageId, MessageId);
+
+/// What a chat tree shows of a message, kept apart from its content so trees are
+/// listed without reading messages.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct MessageTreeRecord {
+    pub parent_message_id: Option<MessageId>,
+    pub role: Role,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub error_status: Option<MessageErrorStatus>,
+}
+
 // --- Storage Definition ---
 
 thread_local! {
@@ -175,6 +290,12 @@ thread_local! {
         StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_CHATS)))
     );
 
+    /// Chat recency index: (updated_at, chat_id) -> ().
+    /// Written only through `save_chat` and `remove_chat`.
+    pub static CHATS_BY_UPDATED: RefCell<StableBTreeMap<ChatUpdatedKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_CHATS_BY_UPDATED)))
+    );
+
     /// Message storage: message_id -> Message
     pub static MESSAGES: RefCell<StableBTreeMap<u64, CandidWrapper<Message>, Memory>> = RefCell::new(
         StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_MESSAGES)))
@@ -214,6 +335,150 @@ thread_local! {
     pub static FOLDER_CONTENTS_INDEX: RefCell<StableBTreeMap<FolderId, CandidWrapper<FolderContents>, Memory>> = RefCell::new(
         StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_FOLDER_INDEX)))
     );
+
+    /// Open chunked uploads: upload_id (= file_id) -> UploadSession
+    pub static UPLOAD_SESSIONS: RefCell<StableBTreeMap<FileId, CandidWrapper<UploadSession>, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_UPLOAD_SESSIONS)))
+    );
+
+    /// Chunked file content: (file_id, chunk index) -> bytes.
+    /// Files uploaded in one call keep their content in FILES_CONTENT instead.
+    pub static FILE_CONTENT_CHUNKS: RefCell<StableBTreeMap<FileChunkKey, CandidWrapper<Vec<u8>>, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_FILE_CONTENT_CHUNKS)))
+    );
+
+    /// Node read grants for in-progress jobs: job_id -> NodeReadGrant
+    pub static NODE_READ_GRANTS: RefCell<StableBTreeMap<JobId, CandidWrapper<NodeReadGrant>, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_NODE_READ_GRANTS)))
+    );
+
+    /// The vault import in progress, if any
+    pub static VAULT_IMPORT: RefCell<StableCell<CandidWrapper<Option<VaultImportSession>>, Memory>> = RefCell::new(
+        StableCell::new(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_VAULT_IMPORT)),
+            CandidWrapper(None),
+        )
+    );
+
+    /// Ids assigned by the vault import in progress: (kind, archive id) -> new id
+    pub static VAULT_IMPORT_IDS: RefCell<StableBTreeMap<VaultIdKey, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_VAULT_IMPORT_IDS)))
+    );
+
+    /// Context summaries: id of the last message covered -> checkpoint message.
+    /// Checkpoints are not part of any chat's message list and are not exported.
+    pub static CONTEXT_CHECKPOINTS: RefCell<StableBTreeMap<MessageId, CandidWrapper<Message>, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_CONTEXT_CHECKPOINTS)))
+    );
+
+    /// Embedding batch inputs and results: job_id -> EmbeddingBatchRecord
+    pub static EMBEDDING_BATCHES: RefCell<StableBTreeMap<JobId, CandidWrapper<EmbeddingBatchRecord>, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_EMBEDDING_BATCHES)))
+    );
+
+    /// Chunks written by running ingestion jobs until they replace the file's chunks
+    pub static INGESTED_CHUNKS: RefCell<StableBTreeMap<IngestedChunkKey, CandidWrapper<TextChunk>, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_INGESTED_CHUNKS)))
+    );
+
+    /// Tree records of every message, by chat: (chat_id, message_id) -> MessageTreeRecord
+    pub static MESSAGE_TREE: RefCell<StableBTreeMap<MessageTreeKey, CandidWrapper<MessageTreeRecord>, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_MESSAGE_TREE)))
+    );
+
+    /// Replies of every message: (chat_id, parent_message_id, message_id) -> ()
+    pub static MESSAGE_CHILDREN: RefCell<StableBTreeMap<MessageChildKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_MESSAGE_CHILDREN)))
+    );
+
+    /// The next message to add to `MESSAGE_TREE` after an upgrade that introduced it;
+    /// `None` once every message is in the tree.
+    pub static MESSAGE_TREE_BACKFILL: RefCell<StableCell<CandidWrapper<Option<MessageId>>, Memory>> = RefCell::new(
+        StableCell::new(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MEMORY_ID_MESSAGE_TREE_BACKFILL)),
+            CandidWrapper(None),
+        )
+    );
+}
+
+// --- Helper Functions for CHATS Access ---
+
+/// Inserts or replaces a chat and keeps `CHATS_BY_UPDATED` in sync.
+pub fn save_chat(chat: Chat) {
+    let chat_id = chat.chat_id;
+    let updated_at = chat.updated_at;
+    let previous = CHATS.with(|c| c.borrow_mut().insert(chat_id, CandidWrapper(chat)));
+    CHATS_BY_UPDATED.with(|idx| {
+        let mut idx = idx.borrow_mut();
+        if let Some(previous) = previous {
+            idx.remove(&(previous.updated_at, chat_id));
+        }
+        idx.insert((updated_at, chat_id), ());
+    });
+}
+
+/// Removes a chat and its `CHATS_BY_UPDATED` entry.
+pub fn remove_chat(chat_id: ChatId) -> Option<Chat> {
+    let removed = CHATS.with(|c| c.borrow_mut().remove(&chat_id)).map(|w| w.0);
+    if let Some(chat) = &removed {
+        CHATS_BY_UPDATED.with(|idx| idx.borrow_mut().remove(&(chat.updated_at, chat_id)));
+    }
+    removed
+}
+
+// --- Helper Functions for MESSAGES Access ---
+
+/// Inserts or replaces a message and keeps `MESSAGE_TREE` and `MESSAGE_CHILDREN` in sync.
+pub fn save_message(message: Message) {
+    index_message(&message);
+    MESSAGES.with(|m| {
+        m.borrow_mut()
+            .insert(message.message_id, CandidWrapper(message))
+    });
+}
+
+/// Removes a message and its tree entries. Its replies keep their parent id and are
+/// shown as roots.
+pub fn remove_message(message_id: MessageId) -> Option<Message> {
+    let removed = MESSAGES
+        .with(|m| m.borrow_mut().remove(&message_id))
+        .map(|w| w.0);
+    if let Some(message) = &removed {
+        MESSAGE_TREE.with(|t| t.borrow_mut().remove(&(message.chat_id, message_id)));
+        if let Some(parent) = message.parent_message_id {
+            MESSAGE_CHILDREN.with(|c| {
+                c.borrow_mut()
+                    .remove(&(message.chat_id, parent, message_id))
+            });
+        }
+    }
+    removed
+}
+
+/// Writes the tree entries of a message. A message never changes chat or parent.
+pub fn index_message(message: &Message) {
+    let record = MessageTreeRecord {
+        parent_message_id: message.parent_message_id,
+        role: message.role.clone(),
+        created_at: message.created_at,
+        updated_at: message.updated_at,
+        error_status: message.error_status.clone(),
+    };
+    MESSAGE_TREE.with(|t| {
+        t.borrow_mut()
+            .insert((message.chat_id, message.message_id), CandidWrapper(record))
+    });
+    if let Some(parent) = message.parent_message_id {
+        MESSAGE_CHILDREN.with(|c| {
+            c.borrow_mut()
+                .insert((message.chat_id, parent, message.message_id), ())
+        });
+    }
+}
+
+/// Whether every message written before `MESSAGE_TREE` existed has been added to it.
+pub fn is_message_tree_complete() -> bool {
+    MESSAGE_TREE_BACKFILL.with(|b| b.borrow().get().0.is_none())
 }
 
 // --- Helper Functions for CONFIG Access ---
@@ -334,7 +599,73 @@ pub fn set_root_folder_id(folder_id: FolderId) {
     });
 }
 
+/// Sets the chat retention policy
+pub fn update_retention_policy(policy: RetentionPolicy) {
+    CONFIG.with(|c| {
+        let mut cell = c.borrow_mut();
+        let mut config = cell.get().0.clone();
+        config.retention_policy = Some(policy);
+        let _ = cell.set(CandidWrapper(config));
+    });
+}
+
+/// Replaces the vault salt and validator (used when a vault import is committed)
+pub fn set_vault_credentials(enc_salt: Vec<u8>, enc_validator: String) {
+    CONFIG.with(|c| {
+        let mut cell = c.borrow_mut();
+        let mut config = cell.get().0.clone();
+        config.enc_salt = Some(enc_salt);
+        config.enc_validator = Some(enc_validator);
+        let _ = cell.set(CandidWrapper(config));
+    });
+}
+
 /// Gets the full canister config (for whoami, etc.)
 pub fn get_config() -> CanisterConfig {
     CONFIG.with(|c| c.borrow().get().0.clone())
 }
+
+#[cfg(test)]
+pub mod test_support {
+    //! Records for tests, which adjust the fields they care about with struct update
+    //! syntax.
+
+    use super::*;
+
+    /// An empty user message in chat 1 that replies to the message before it.
+    pub fn message(message_id: MessageId) -> Message {
+        Message {
+            message_id,
+            chat_id: 1,
+            parent_message_id: message_id.checked_sub(1).filter(|id| *id > 0),
+            role: Role::User,
+            content: Vec::new(),
+            created_at: 0,
+            updated_at: 0,
+            error_status: None,
+            attachments: None,
+            attached_file_ids: None,
+            tool_calls: None,
+            tool_results: None,
+            tool_call_id: None,
+            requires_client_action: false,
+            usage: None,
+            citations: Vec::new(),
+        }
+    }
+
+    /// An empty text file owned by the anonymous principal in folder 0.
+    pub fn file(id: FileId) -> FileMetadata {
+        FileMetadata {
+            id,
+            owner: Principal::anonymous(),
+            name: format!("file-{}.txt", id),
+            parent_folder_id: 0,
+            mime_type: "text/plain".to_string(),
+            content_size_bytes: 10,
+            chunks: Vec::new(),
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+}
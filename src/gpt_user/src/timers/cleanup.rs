@@ -1,6 +1,11 @@
 use crate::handlers::chat::delete::delete_chat_internal;
-use crate::helpers::generation_helpers::fail_job;
+use crate::handlers::file_system::upload::discard_abandoned_uploads;
+use crate::config::JOB_INPROGRESS_TIMEOUT_NS;
+use crate::helpers::generation_helpers::retry_or_fail_job;
 use crate::helpers::message_helpers::is_chat_in_generation;
+use crate::helpers::node_helpers::revoke_expired_node_reads;
+use crate::helpers::retention_helpers::{chat_delete_at, current_retention_policy};
+use crate::helpers::vault_helpers::is_vault_import_in_progress;
 use crate::storage::{CHAT_JOBS, CHATS};
 use candid::Principal;
 use gpt_types::{domain::GenerationStatus, error::MessageErrorStatus};
@@ -10,7 +15,6 @@ pub async fn time_out_stale_jobs() {
     ic_cdk::println!("[TASK] Starting: Time out stale jobs...");
     let now = api::time();
     const PENDING_TIMEOUT_NS: u64 = 2 * 60 * 1_000_000_000;
-    const INPROGRESS_TIMEOUT_NS: u64 = 5 * 60 * 1_000_000_000;
 
     let stale_job_ids: Vec<u64> = CHAT_JOBS.with(|cj_ref| {
         let jobs = cj_ref.borrow();
@@ -20,7 +24,7 @@ pub async fn time_out_stale_jobs() {
             let age = now.saturating_sub(job.updated_at);
             let is_stale = match job.generation_status {
                 GenerationStatus::Pending => age > PENDING_TIMEOUT_NS,
-                GenerationStatus::InProgress => age > INPROGRESS_TIMEOUT_NS,
+                GenerationStatus::InProgress => age > JOB_INPROGRESS_TIMEOUT_NS,
                 _ => false,
             };
             if is_stale {
@@ -30,6 +34,12 @@ pub async fn time_out_stale_jobs() {
         result
     });
 
+    // Grants of jobs that vanished without completing (e.g. deleted chats) lapse here.
+    let revoked = revoke_expired_node_reads(now);
+    if revoked > 0 {
+        ic_cdk::println!("[TASK] Revoked {} expired node read grants.", revoked);
+    }
+
     if stale_job_ids.is_empty() {
         ic_cdk::println!("[TASK] Completed: No stale jobs found.");
         return;
@@ -41,7 +51,7 @@ pub async fn time_out_stale_jobs() {
     );
 
     for job_id in stale_job_ids {
-        if let Err(e) = fail_job(job_id, MessageErrorStatus::Timeout) {
+        if let Err(e) = retry_or_fail_job(job_id, MessageErrorStatus::Timeout) {
             ic_cdk::println!("[TASK] WARN: Could not time out job {}: {:?}", job_id, e);
         }
     }
@@ -50,24 +60,20 @@ pub async fn time_out_stale_jobs() {
 
 pub async fn cleanup_old_chats() {
     ic_cdk::println!("[TASK] Starting: Cleanup of old chats...");
+    // Imported chats may be past the policy's age already; wait for the import to end.
+    if is_vault_import_in_progress() {
+        ic_cdk::println!("[TASK] Skipped: A vault import is in progress.");
+        return;
+    }
     let current_time = api::time();
-    const TEMP_CHAT_TTL_NS: u64 = 10 * 60 * 1_000_000_000;
-    const SEVEN_DAYS_NS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
-    const FOURTEEN_DAYS_NS: u64 = 14 * 24 * 60 * 60 * 1_000_000_000;
+    let policy = current_retention_policy();
 
     let to_delete: Vec<(Principal, u64)> = CHATS.with(|c| {
         let chats = c.borrow();
         let mut result = Vec::new();
         for entry in chats.iter() {
             let chat = &entry.value().0;
-            let cutoff = if chat.temporary {
-                current_time.saturating_sub(TEMP_CHAT_TTL_NS)
-            } else if chat.archived {
-                current_time.saturating_sub(FOURTEEN_DAYS_NS)
-            } else {
-                current_time.saturating_sub(SEVEN_DAYS_NS)
-            };
-            if chat.updated_at < cutoff {
+            if chat_delete_at(chat, &policy).is_some_and(|delete_at| delete_at < current_time) {
                 result.push((chat.owner, chat.chat_id));
             }
         }
@@ -93,3 +99,12 @@ pub async fn cleanup_old_chats() {
     }
     ic_cdk::println!("[TASK] Completed: Finished chat cleanup cycle.");
 }
+
+pub async fn cleanup_abandoned_uploads() {
+    ic_cdk::println!("[TASK] Starting: Cleanup of abandoned uploads...");
+    let discarded = discard_abandoned_uploads(api::time());
+    ic_cdk::println!(
+        "[TASK] Completed: Discarded {} abandoned upload sessions.",
+        discarded
+    );
+}
@@ -1,4 +1,6 @@
 use super::{cleanup, sync};
+use crate::config::MESSAGE_TREE_BACKFILL_BATCH_SIZE;
+use crate::helpers::message_helpers::backfill_message_tree;
 use ic_cdk_timers::{set_timer, set_timer_interval};
 use std::time::Duration;
 
@@ -21,6 +23,18 @@ async fn perform_initial_sync() {
     ic_cdk::println!("Finished Initial Canister Sync");
 }
 
+/// Builds the chat tree of messages written before it existed, one batch per timer
+/// call so no call runs into the instruction limit.
+pub fn schedule_message_tree_backfill() {
+    set_timer(Duration::ZERO, || {
+        if backfill_message_tree(MESSAGE_TREE_BACKFILL_BATCH_SIZE) {
+            schedule_message_tree_backfill();
+        } else {
+            ic_cdk::println!("Finished building the chat tree.");
+        }
+    });
+}
+
 pub fn setup_periodic_tasks_timer() {
     ic_cdk::println!(
         "Setting up periodic tasks timer to run every {} seconds.",
@@ -38,6 +52,7 @@ async fn perform_periodic_tasks() {
     sync::sync_models_with_index().await;
     cleanup::time_out_stale_jobs().await;
     cleanup::cleanup_old_chats().await;
+    cleanup::cleanup_abandoned_uploads().await;
 
     ic_cdk::println!("Finished Periodic Canister Tasks");
 }
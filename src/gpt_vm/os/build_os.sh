@@ -140,6 +140,8 @@ readonly OUTPUT_DIR="/output"
 readonly GPT_NODE_BIN="/workspace/gpt_node"
 readonly INIT_SCRIPT="/workspace/init"
 readonly UDHCP_SCRIPT="/workspace/udhcpc.script"
+readonly TOKENIZER_SRC_DIR="/workspace/tokenizers"
+readonly TOKENIZER_DEST_DIR="/usr/local/share/gpt_node/tokenizers"
 
 # Required Modules (paths relative to kernel module tree root)
 readonly MODULES="
@@ -234,6 +236,26 @@ main() {
 		warn "DHCP helper script ${UDHCP_SCRIPT} missing; network auto-config may fail."
 	fi
 
+	# HuggingFace tokenizers for context budgeting. Only tokenizer.json files are
+	# installed, and each must match its digest pinned in SHA256SUMS; models without
+	# one fall back to estimated token counts.
+	local tokenizer_file tokenizer_name pinned_digest
+	if [[ -d ${TOKENIZER_SRC_DIR} ]]; then
+		for tokenizer_file in "${TOKENIZER_SRC_DIR}"/*/tokenizer.json; do
+			[[ -f ${tokenizer_file} ]] || continue
+			tokenizer_name=$(basename "$(dirname "${tokenizer_file}")")
+			pinned_digest=$(awk -v path="${tokenizer_name}/tokenizer.json" \
+				'$2 == path { print $1 }' "${TOKENIZER_SRC_DIR}/SHA256SUMS" 2>/dev/null) || true
+			[[ -n ${pinned_digest} ]] ||
+				die "Tokenizer ${tokenizer_name} has no digest pinned in SHA256SUMS."
+			[[ $(hash_file "${tokenizer_file}") == "${pinned_digest}" ]] ||
+				die "Tokenizer ${tokenizer_name} does not match its pinned digest."
+			info "Installing tokenizer ${tokenizer_name}..."
+			install -D -m 644 "${tokenizer_file}" \
+				"${ROOTFS_DIR}${TOKENIZER_DEST_DIR}/${tokenizer_name}/tokenizer.json"
+		done
+	fi
+
 	# 4. Extract Kernel & Modules
 	info "Extracting Kernel & Modules..."
 	local kdir mdir